@@ -4,57 +4,153 @@ use common::db::{
 use common::types::*;
 use common::util::*;
 
+use crate::budget::{Budgets, CampaignBudget, Delivery, Pacer};
+use crate::context::{
+    context_dimensions, with_context, ContextConfig, GeoDatabase, RequestContext, CONTEXT_PREFIX,
+};
+use crate::frequency::{
+    CapKey, CapLevel, FrequencyCaps, ImpressionEvent, ImpressionStore, InMemoryImpressionStore,
+};
+use crate::schedule::{flight_status, Dayparting, ScheduleStatus};
+use crate::sync_health::SyncHealth;
+
+use filter::catalog::{DimensionCatalog, Violation};
+use filter::derived::DerivedDimensions;
+use filter::explain::Explanation;
 use filter::filter::TargetFilter;
 use filter::filterable::Filterable;
 use filter::index::FilterIndex;
+use filter::overlap::{analyze, OverlapReport};
+use filter::segment::resolve_segments;
 use filter::serde as TargetFilterSerde;
+use filter::taxonomy::Taxonomy;
+use filter::user_list::{insert_user_lists, UserList};
 use integrations::integrations::Integrations;
-use prisma_client_rust::chrono::{DateTime, FixedOffset};
+use prisma_client_rust::chrono::{DateTime, FixedOffset, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
-pub struct AdGroup {
+pub struct AdGroup<'a> {
     pub data: ad_group::Data,
+    // filters of loaded segments, to resolve InSegment.
+    pub segments: &'a HashMap<String, TargetFilter>,
+}
+
+/**
+ * filter that fail to compile must not be served as untargeted,
+ * so empty Or(never match) is used instead of None(match all).
+ */
+fn compile_filter(kind: &str, id: &str, value: &serde_json::Value) -> Option<TargetFilter> {
+    match TargetFilterSerde::from_jsonlogic(value) {
+        Ok(target_filter) => Some(target_filter),
+        Err(e) => {
+            println!("{} {} has invalid filter: {}", kind, id, e);
+            Some(TargetFilter::Or { fields: vec![] })
+        }
+    }
+}
+/**
+ * Filter stored as JSON string. string that is not JSON fails the same way as filter
+ * that fails to compile, so that it matches nobody instead of everyone.
+ */
+fn parse_filter(kind: &str, id: &str, s: &str) -> TargetFilter {
+    let filter = match serde_json::from_str::<serde_json::Value>(s) {
+        Ok(value) => compile_filter(kind, id, &value),
+        Err(e) => {
+            println!("{} {} has invalid filter: {}", kind, id, e);
+            None
+        }
+    };
+    filter.unwrap_or(TargetFilter::Or { fields: vec![] })
+}
+/**
+ * InSegment is inlined with loaded segments. reference to missing segment or
+ * segment cycle fails the same way as filter that fail to compile.
+ */
+fn resolve_filter(
+    kind: &str,
+    id: &str,
+    filter: TargetFilter,
+    segments: &HashMap<String, TargetFilter>,
+) -> Option<TargetFilter> {
+    match resolve_segments(&filter, segments) {
+        Ok(target_filter) => Some(target_filter),
+        Err(e) => {
+            println!("{} {} has invalid filter: {}", kind, id, e);
+            Some(TargetFilter::Or { fields: vec![] })
+        }
+    }
+}
+/**
+ * Filter of Segment.where, before InSegment on it is resolved.
+ * segment without where has no condition, so it is everyone.
+ */
+pub fn segment_filter(segment: &segment::Data) -> TargetFilter {
+    match &segment.r#where {
+        None => TargetFilter::And { fields: vec![] },
+        Some(s) => parse_filter("segment", &segment.id, s),
+    }
 }
 
-impl Filterable for AdGroup {
+impl<'a> AdGroup<'a> {
+    // filter as it is stored, InSegment is not resolved yet.
+    pub fn target_filter(&self) -> Option<TargetFilter> {
+        self.data
+            .filter
+            .as_ref()
+            .map(|s| parse_filter("ad_group", &self.data.id, s))
+    }
+}
+impl<'a> Filterable for AdGroup<'a> {
     fn id(&self) -> String {
         String::from(&self.data.id)
     }
 
     fn filter(&self) -> Option<TargetFilter> {
-        match &self.data.filter {
-            None => None,
-            Some(s) => {
-                let value: serde_json::Value = serde_json::from_str(&s).ok()?;
-
-                TargetFilterSerde::from_jsonlogic(&value)
-            }
-        }
+        let filter = self.target_filter()?;
+        resolve_filter("ad_group", &self.data.id, filter, self.segments)
     }
 }
 
-pub struct AdSet {
+pub struct AdSet<'a> {
     pub data: ad_set::Data,
+    // filters of loaded segments, to resolve InSegment.
+    pub segments: &'a HashMap<String, TargetFilter>,
 }
 
-impl Filterable for AdSet {
+impl<'a> AdSet<'a> {
+    // filter as it is stored, InSegment is not resolved yet.
+    pub fn target_filter(&self) -> Option<TargetFilter> {
+        // loaded segment is newer than the one fetched with ad set.
+        if let Some(segment_id) = &self.data.segment_id {
+            if self.segments.contains_key(segment_id) {
+                return Some(TargetFilter::InSegment {
+                    segment_id: segment_id.clone(),
+                });
+            }
+        }
+        match self.data.segment() {
+            Ok(None) => None,
+            Ok(Some(segment)) => segment
+                .r#where
+                .as_ref()
+                .map(|s| parse_filter("ad_set", &self.data.id, s)),
+            Err(e) => {
+                println!("ad_set {} has invalid filter: {}", self.data.id, e);
+                Some(TargetFilter::Or { fields: vec![] })
+            }
+        }
+    }
+}
+impl<'a> Filterable for AdSet<'a> {
     fn id(&self) -> String {
         String::from(&self.data.id)
     }
 
     fn filter(&self) -> Option<TargetFilter> {
-        match &self.data.segment().ok()? {
-            None => None,
-            Some(segment) => match &segment.r#where {
-                None => None,
-                Some(s) => {
-                    let value: serde_json::Value = serde_json::from_str(s).ok()?;
-
-                    TargetFilterSerde::from_jsonlogic(&value)
-                }
-            },
-        }
+        let filter = self.target_filter()?;
+        resolve_filter("ad_set", &self.data.id, filter, self.segments)
     }
 }
 #[derive(Debug, Clone, Deserialize)]
@@ -97,6 +193,49 @@ pub struct AdSetSearchResult<'a> {
     pub ad_sets: Vec<AdSetContent<'a>>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusCheck {
+    pub level: String,
+    pub id: String,
+    // None when it doesn't exist on ad_state.
+    pub status: Option<String>,
+    pub is_active: bool,
+}
+impl StatusCheck {
+    fn new(level: &str, id: &str, status: Option<&String>, is_active: bool) -> Self {
+        StatusCheck {
+            level: String::from(level),
+            id: String::from(id),
+            status: status.cloned(),
+            is_active,
+        }
+    }
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplainResult {
+    pub placement_id: String,
+    pub ad_group_id: String,
+    // campaign of ad group is on the placement.
+    pub on_placement: bool,
+    pub status_checks: Vec<StatusCheck>,
+    // None when placement has no filter index.
+    pub filter: Option<Explanation>,
+    // flight and dayparting of campaign at the time of explain, None without campaign.
+    pub schedule: Option<ScheduleStatus>,
+}
+
+/**
+ * filter that is not indexed because it doesn't agree with dimension catalog of its service.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FilterViolations {
+    // ad_group or segment.
+    pub kind: String,
+    pub id: String,
+    pub service_id: String,
+    pub violations: Vec<Violation>,
+}
+
 #[derive(Debug, Clone)]
 pub struct UpdateInfo {
     pub services: DateTime<FixedOffset>,
@@ -106,6 +245,8 @@ pub struct UpdateInfo {
     pub creatives: DateTime<FixedOffset>,
     pub contents: DateTime<FixedOffset>,
     pub ad_sets: DateTime<FixedOffset>,
+    pub segments: DateTime<FixedOffset>,
+    pub customsets: DateTime<FixedOffset>,
     pub content_types: DateTime<FixedOffset>,
     pub integrations: DateTime<FixedOffset>,
 }
@@ -119,6 +260,8 @@ impl Default for UpdateInfo {
             creatives: Default::default(),
             contents: Default::default(),
             ad_sets: Default::default(),
+            segments: Default::default(),
+            customsets: Default::default(),
             content_types: Default::default(),
             integrations: Default::default(),
         }
@@ -131,9 +274,41 @@ pub struct AdState {
     pub campaigns: HashMap<String, campaign::Data>,
     pub ad_groups: HashMap<String, ad_group::Data>,
     pub creatives: HashMap<String, HashMap<String, creative::Data>>,
+    // creative id -> ad group id that it is on.
+    pub creative_ad_groups: HashMap<String, String>,
     pub contents: HashMap<String, content::Data>,
     pub content_types: HashMap<String, content_type::Data>,
     pub segments: HashMap<String, segment::Data>,
+    // segment_filter of segments, InSegment on ad groups/ad sets is resolved against it.
+    pub segment_filters: HashMap<String, TargetFilter>,
+    // Customset id -> user ids of it, for InUserList. Arc so that cloning AdState is cheap.
+    pub user_lists: HashMap<String, Arc<UserList>>,
+    // Service id -> value taxonomy on its details, shared by indices of its placements.
+    pub taxonomies: HashMap<String, Arc<Taxonomy>>,
+    // placement id -> derived dimensions on its CREATIVE_FETCHER/AD_SET_FETCHER integration.
+    pub derived_dimensions: HashMap<String, Arc<DerivedDimensions>>,
+    pub ad_set_derived_dimensions: HashMap<String, Arc<DerivedDimensions>>,
+    // placement id -> context dimensions switched on for it.
+    pub contexts: HashMap<String, Arc<ContextConfig>>,
+    // placement id -> frequency caps on its fetcher integrations.
+    pub frequency_caps: HashMap<String, Arc<FrequencyCaps>>,
+    // impressions of users, shared by every AdState cloned from this one.
+    pub impression_store: Arc<dyn ImpressionStore>,
+    // placement id -> dayparting of its campaigns.
+    pub daypartings: HashMap<String, Arc<Dayparting>>,
+    // campaigns after their flight, ad groups of them are not indexed.
+    pub ended_campaigns: HashSet<String>,
+    // placement id -> budgets of its campaigns.
+    pub budgets: HashMap<String, Arc<Budgets>>,
+    // delivery and throttles of campaigns with budget, shared by every AdState cloned from
+    // this one, so that feedback during a load isn't lost when the loaded one replaces it.
+    pub pacer: Arc<Mutex<Pacer>>,
+    // Service id -> dimension catalog that its filters are validated against.
+    pub catalogs: HashMap<String, Arc<DimensionCatalog>>,
+    // (version, catalog) derived from the latest version of UserFeature.
+    pub user_feature_catalog: Option<(String, Arc<DimensionCatalog>)>,
+    // (kind, id) -> violations of filters that are not indexed.
+    pub filter_violations: HashMap<(String, String), FilterViolations>,
     pub ad_sets: HashMap<String, ad_set::Data>,
     pub update_info: UpdateInfo,
     pub filter_index: HashMap<String, FilterIndex>,
@@ -143,6 +318,8 @@ pub struct AdState {
     pub creatives_stat: HashMap<String, Stat>,
     pub ad_sets_stat: HashMap<String, Stat>,
     pub integrations: Integrations,
+    // health of ad meta sync, shared by every AdState cloned from this one.
+    pub sync_health: Arc<SyncHealth>,
 }
 impl Default for AdState {
     fn default() -> Self {
@@ -152,9 +329,25 @@ impl Default for AdState {
             campaigns: Default::default(),
             ad_groups: Default::default(),
             creatives: Default::default(),
+            creative_ad_groups: Default::default(),
             contents: Default::default(),
             content_types: Default::default(),
             segments: Default::default(),
+            segment_filters: Default::default(),
+            user_lists: Default::default(),
+            taxonomies: Default::default(),
+            derived_dimensions: Default::default(),
+            ad_set_derived_dimensions: Default::default(),
+            contexts: Default::default(),
+            frequency_caps: Default::default(),
+            impression_store: Arc::new(InMemoryImpressionStore::default()),
+            daypartings: Default::default(),
+            ended_campaigns: Default::default(),
+            budgets: Default::default(),
+            pacer: Default::default(),
+            catalogs: Default::default(),
+            user_feature_catalog: Default::default(),
+            filter_violations: Default::default(),
             ad_sets: Default::default(),
             update_info: Default::default(),
             filter_index: Default::default(),
@@ -163,6 +356,7 @@ impl Default for AdState {
             creatives_stat: Default::default(),
             ad_sets_stat: Default::default(),
             integrations: Integrations::default(),
+            sync_health: Arc::new(SyncHealth::default()),
             // clients: Default::default(),
             // functions: Default::default(),
         }
@@ -187,14 +381,95 @@ impl AdState {
     pub fn init() -> Self {
         AdState::default()
     }
+    /**
+     * violations of filter against catalog of service. placement is given for ad groups,
+     * so that its derived dimensions are accepted. context and taxonomy dimensions are
+     * not on user features, so they are accepted as well.
+     */
+    pub fn validate_filter(
+        &self,
+        service_id: &str,
+        placement_id: Option<&str>,
+        filter: &TargetFilter,
+    ) -> Vec<Violation> {
+        let catalog = match self.catalogs.get(service_id) {
+            None => return vec![],
+            Some(catalog) => catalog,
+        };
+        let mut known = HashSet::new();
+        if let Some(placement_id) = placement_id {
+            for derived_dimensions in [&self.derived_dimensions, &self.ad_set_derived_dimensions] {
+                if let Some(derived) = derived_dimensions.get(placement_id) {
+                    known.extend(derived.targets());
+                }
+            }
+        }
+        if let Some(taxonomy) = self.taxonomies.get(service_id) {
+            known.extend(taxonomy.dimensions());
+        }
+        catalog.validate(filter, |dimension| {
+            dimension.starts_with(CONTEXT_PREFIX) || known.contains(dimension)
+        })
+    }
+    /**
+     * filters of service that are not indexed, ordered by kind and id.
+     */
+    pub fn service_filter_violations(&self, service_id: &str) -> Vec<&FilterViolations> {
+        let mut violations: Vec<&FilterViolations> = self
+            .filter_violations
+            .values()
+            .filter(|violations| violations.service_id == service_id)
+            .collect();
+        violations.sort_by(|a, b| (&a.kind, &a.id).cmp(&(&b.kind, &b.id)));
+        violations
+    }
 
     pub fn set_integrations(&mut self, integrations: Integrations) {
         self.integrations = integrations;
     }
+    /**
+     * user lists that user of request is in are added to user_info, for InUserList.
+     */
+    fn with_user_lists(&self, mut user_info: UserInfo, user_id: Option<&str>) -> UserInfo {
+        insert_user_lists(&mut user_info, user_id, &self.user_lists);
+        user_info
+    }
+    /**
+     * user_info json of request with context dimensions(ctx.*) of placement,
+     * to be given to search/search_ad_sets.
+     */
+    pub fn context_user_info(
+        &self,
+        placement_id: &str,
+        context: &RequestContext,
+        geo: Option<&GeoDatabase>,
+        user_info_json: &serde_json::Value,
+    ) -> serde_json::Value {
+        let dimensions = match self.contexts.get(placement_id) {
+            Some(config) => context_dimensions(config, context, &Utc::now(), geo),
+            None => UserInfo::new(),
+        };
+        with_context(user_info_json, &dimensions)
+    }
+    /**
+     * dimensions derived from raw user_info of request on placement(ex: age band from birth year).
+     */
+    fn with_derived_dimensions(
+        derived_dimensions: &HashMap<String, Arc<DerivedDimensions>>,
+        placement_id: &str,
+        mut user_info: UserInfo,
+    ) -> UserInfo {
+        if let Some(derived_dimensions) = derived_dimensions.get(placement_id) {
+            let now = Utc::now().with_timezone(&FixedOffset::east_opt(0).unwrap());
+            derived_dimensions.apply(&mut user_info, &now);
+        }
+        user_info
+    }
     pub async fn search_ad_sets(
         &self,
         _service_id: &str,
         placement_id: &str,
+        user_id: Option<&str>,
         user_info_json: &serde_json::Value,
         top_k: Option<usize>,
     ) -> Option<AdSetSearchResult> {
@@ -202,17 +477,39 @@ impl AdState {
         let placement = self.placements.get(placement_id)?;
         let content_type = self.content_types.get(&placement.content_type_id)?;
 
-        let user_info = parse_user_info(user_info_json).unwrap();
+        let user_info = Self::with_derived_dimensions(
+            &self.ad_set_derived_dimensions,
+            placement_id,
+            parse_user_info(user_info_json).unwrap(),
+        );
+        let user_info = self.with_user_lists(user_info, user_id);
 
-        let ad_sets = self
+        let mut ad_sets = self
             .integrations
             .fetch_ad_sets(&self.ad_set_index, &self.ad_sets, placement_id, &user_info)
             .await
             .unwrap_or(Vec::new());
+        // capped ad sets are dropped before ranking, so they don't take slots of top_k.
+        let now = Utc::now();
+        ad_sets.retain(|ad_set| {
+            !self.is_capped(
+                placement_id,
+                user_id,
+                &[CapKey::new(CapLevel::AdSet, &ad_set.id)],
+                &now,
+            )
+        });
+        let match_scores = Self::match_scores(
+            &self.ad_set_index,
+            placement_id,
+            &user_info,
+            ad_sets.iter().copied().map(|ad_set| ad_set.id.as_str()),
+        );
         let top_ad_sets = self.integrations.rank_ad_sets(
             placement_id,
             &self.ad_sets_stat,
             ad_sets,
+            &match_scores,
             top_k.unwrap_or(1),
         );
 
@@ -237,12 +534,18 @@ impl AdState {
         &self,
         _service_id: &str,
         placement_id: &str,
+        user_id: Option<&str>,
         user_info_json: &serde_json::Value,
         top_k: Option<usize>,
     ) -> SearchResult {
-        let user_info = parse_user_info(user_info_json).unwrap();
+        let user_info = Self::with_derived_dimensions(
+            &self.derived_dimensions,
+            placement_id,
+            parse_user_info(user_info_json).unwrap(),
+        );
+        let user_info = self.with_user_lists(user_info, user_id);
 
-        let creatives_map = self
+        let mut creatives_map = self
             .integrations
             .fetch_creatives(
                 &self.filter_index,
@@ -252,9 +555,33 @@ impl AdState {
             )
             .await
             .unwrap_or(HashMap::new());
+        // ad groups out of schedule, budget or capped are dropped before ranking,
+        // so they don't take slots of top_k.
+        let now = Utc::now();
+        let out_of_budget = self.campaigns_out_of_budget(creatives_map.keys().copied(), &now);
+        creatives_map.retain(|ad_group_id, _| {
+            self.is_ad_group_serving(ad_group_id, &now)
+                && self
+                    .get_ad_group(ad_group_id)
+                    .map(|ad_group| !out_of_budget.contains(ad_group.campaign_id.as_str()))
+                    .unwrap_or(true)
+                && !self.is_capped(
+                    placement_id,
+                    user_id,
+                    &self.ad_group_cap_keys(ad_group_id),
+                    &now,
+                )
+        });
 
+        let match_scores = Self::match_scores(
+            &self.filter_index,
+            placement_id,
+            &user_info,
+            creatives_map.keys().copied(),
+        );
         let creatives = self.ad_group_ids_to_creatives_with_contents(creatives_map);
-        let ad_group_creatives = self.ad_group_creatives(placement_id, creatives, top_k);
+        let ad_group_creatives =
+            self.ad_group_creatives(placement_id, creatives, &match_scores, top_k);
         let campaign_ad_groups = self.campaign_ad_groups(ad_group_creatives);
         let matched_ads = self.placement_campaigns(campaign_ad_groups);
 
@@ -264,6 +591,166 @@ impl AdState {
         }
     }
 
+    /**
+     * flight of campaign, then dayparting of its placement at now.
+     */
+    pub fn campaign_schedule(
+        &self,
+        campaign: &campaign::Data,
+        now: &DateTime<Utc>,
+    ) -> ScheduleStatus {
+        let status = flight_status(campaign.started_at.as_ref(), campaign.end_at.as_ref(), now);
+        let is_off_schedule = self
+            .daypartings
+            .get(&campaign.placement_id)
+            .map(|dayparting| !dayparting.is_serving(&campaign.id, now))
+            .unwrap_or(false);
+        if status == ScheduleStatus::Serving && is_off_schedule {
+            ScheduleStatus::OffSchedule
+        } else {
+            status
+        }
+    }
+    fn is_ad_group_serving(&self, ad_group_id: &str, now: &DateTime<Utc>) -> bool {
+        self.get_ad_group(ad_group_id)
+            .and_then(|ad_group| self.get_campaign(ad_group))
+            .map(|campaign| self.campaign_schedule(campaign, now) == ScheduleStatus::Serving)
+            .unwrap_or(false)
+    }
+    pub fn campaign_budget(&self, campaign: &campaign::Data) -> Option<&CampaignBudget> {
+        self.budgets
+            .get(&campaign.placement_id)
+            .and_then(|budgets| budgets.get(&campaign.id))
+    }
+    /**
+     * campaigns of ad groups that don't serve on this request, because they are exhausted
+     * or don't pass their throttle. pacer is locked once for all of them, and throttle is
+     * drawn once per campaign, not per ad group.
+     */
+    fn campaigns_out_of_budget<'a, I>(
+        &'a self,
+        ad_group_ids: I,
+        now: &DateTime<Utc>,
+    ) -> HashSet<&'a str>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let budgets: HashMap<&str, &CampaignBudget> = ad_group_ids
+            .filter_map(|ad_group_id| self.get_ad_group(ad_group_id))
+            .filter_map(|ad_group| self.get_campaign(ad_group))
+            .filter_map(|campaign| {
+                self.campaign_budget(campaign)
+                    .map(|budget| (campaign.id.as_str(), budget))
+            })
+            .collect();
+        if budgets.is_empty() {
+            return HashSet::new();
+        }
+        let pacer = self.pacer.lock().unwrap();
+        budgets
+            .into_iter()
+            .filter(|(campaign_id, budget)| {
+                !pacer.serves(budget, campaign_id, now, rand::random::<f64>())
+            })
+            .map(|(campaign_id, _)| campaign_id)
+            .collect()
+    }
+    /**
+     * throttles of campaigns are adjusted to their delivery so far.
+     */
+    pub fn update_throttles(&self, campaign_ids: &HashSet<String>, now: &DateTime<Utc>) {
+        let mut pacer = self.pacer.lock().unwrap();
+        for campaign_id in campaign_ids {
+            let budget = self
+                .campaigns
+                .get(campaign_id)
+                .and_then(|campaign| self.campaign_budget(campaign))
+                .cloned()
+                .unwrap_or_default();
+            pacer.update_throttle(&budget, campaign_id, now);
+        }
+    }
+    /**
+     * the earliest end_at after now among campaigns, when their ad groups leave index.
+     */
+    pub fn next_campaign_end(&self, now: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.campaigns
+            .values()
+            .filter_map(|campaign| campaign.end_at)
+            .map(|end_at| end_at.with_timezone(&Utc))
+            .filter(|end_at| end_at > now)
+            .min()
+    }
+    /**
+     * user of request already saw any of keys as many times as frequency caps of placement allow.
+     * request without user id can't be counted, so it is never capped.
+     */
+    fn is_capped(
+        &self,
+        placement_id: &str,
+        user_id: Option<&str>,
+        keys: &[CapKey],
+        now: &DateTime<Utc>,
+    ) -> bool {
+        let (caps, user_id) = match (self.frequency_caps.get(placement_id), user_id) {
+            (Some(caps), Some(user_id)) => (caps, user_id),
+            _ => return false,
+        };
+        keys.iter()
+            .any(|key| caps.is_capped(self.impression_store.as_ref(), user_id, key, now))
+    }
+    fn ad_group_cap_keys(&self, ad_group_id: &str) -> Vec<CapKey> {
+        let mut keys = vec![CapKey::new(CapLevel::AdGroup, ad_group_id)];
+        if let Some(ad_group) = self.get_ad_group(ad_group_id) {
+            keys.push(CapKey::new(CapLevel::Campaign, &ad_group.campaign_id));
+        }
+        keys
+    }
+    /**
+     * impressions from event server. which of event is ad set, or creative that is counted
+     * on its ad group and campaign. returns the number of impressions recorded.
+     */
+    pub fn record_impressions(&self, events: &[ImpressionEvent]) -> usize {
+        let now = Utc::now();
+        let mut recorded = 0;
+        for event in events.iter().filter(|event| event.what == "impression") {
+            let at = match event.timestamp(&now) {
+                None => continue,
+                Some(at) => at,
+            };
+            let keys = if self.ad_sets.contains_key(&event.which) {
+                vec![CapKey::new(CapLevel::AdSet, &event.which)]
+            } else {
+                match self.creative_ad_groups.get(&event.which) {
+                    None => continue,
+                    Some(ad_group_id) => self.ad_group_cap_keys(ad_group_id),
+                }
+            };
+            for key in keys.iter() {
+                self.impression_store.record(&event.who, key, &at, &now);
+            }
+            recorded += 1;
+        }
+        recorded
+    }
+
+    /**
+     * match score of soft targeting(Prefer) for each id on index of placement.
+     */
+    fn match_scores<'a, I>(
+        index: &HashMap<String, FilterIndex>,
+        placement_id: &str,
+        user_info: &UserInfo,
+        ids: I,
+    ) -> HashMap<&'a str, f64>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        index
+            .get(placement_id)
+            .map(|index| index.match_scores(user_info, ids))
+            .unwrap_or_default()
+    }
     fn ad_group_ids_to_creatives_with_contents<'a>(
         &'a self,
         ad_group_id_creatives: HashMap<&'a str, &'a HashMap<String, creative::Data>>,
@@ -297,6 +784,7 @@ impl AdState {
         &'a self,
         placement_id: &str,
         creatives: Vec<CreativeWithContent<'a>>,
+        match_scores: &HashMap<&str, f64>,
         top_k: Option<usize>,
     ) -> Vec<AdGroupCreatives<'a>> {
         let mut aggr = Vec::new();
@@ -306,6 +794,7 @@ impl AdState {
             placement_id,
             &self.creatives_stat,
             creatives,
+            match_scores,
             top_k.unwrap_or(1),
         );
 
@@ -379,6 +868,97 @@ impl AdState {
         aggr
     }
 
+    /**
+     * Why ad group is or isn't served to user on placement.
+     * status of placement/campaign/ad group/creatives and filter explanation from index.
+     */
+    pub fn explain(
+        &self,
+        placement_id: &str,
+        ad_group_id: &str,
+        user_id: Option<&str>,
+        user_info_json: &serde_json::Value,
+    ) -> ExplainResult {
+        let user_info = Self::with_derived_dimensions(
+            &self.derived_dimensions,
+            placement_id,
+            parse_user_info(user_info_json).unwrap_or_default(),
+        );
+        let user_info = self.with_user_lists(user_info, user_id);
+        let mut status_checks = Vec::new();
+
+        let placement = self.placements.get(placement_id);
+        status_checks.push(StatusCheck::new(
+            "placement",
+            placement_id,
+            placement.map(|placement| &placement.status),
+            placement.map(is_active_placement).unwrap_or(false),
+        ));
+
+        let ad_group = self.get_ad_group(ad_group_id);
+        let campaign = ad_group.and_then(|ad_group| self.get_campaign(ad_group));
+        if let Some(ad_group) = ad_group {
+            status_checks.push(StatusCheck::new(
+                "campaign",
+                &ad_group.campaign_id,
+                campaign.map(|campaign| &campaign.status),
+                campaign.map(is_active_campaign).unwrap_or(false),
+            ));
+        }
+        status_checks.push(StatusCheck::new(
+            "ad_group",
+            ad_group_id,
+            ad_group.map(|ad_group| &ad_group.status),
+            ad_group.map(is_active_ad_group).unwrap_or(false),
+        ));
+
+        if let Some(creatives) = self.creatives.get(ad_group_id) {
+            let mut creatives: Vec<&creative::Data> = creatives.values().collect();
+            creatives.sort_by(|a, b| a.id.cmp(&b.id));
+            for creative in creatives {
+                status_checks.push(StatusCheck::new(
+                    "creative",
+                    &creative.id,
+                    Some(&creative.status),
+                    is_active_creative(creative),
+                ));
+            }
+        }
+
+        ExplainResult {
+            placement_id: String::from(placement_id),
+            ad_group_id: String::from(ad_group_id),
+            on_placement: campaign
+                .map(|campaign| campaign.placement_id == placement_id)
+                .unwrap_or(false),
+            status_checks,
+            filter: self
+                .filter_index
+                .get(placement_id)
+                .map(|index| index.explain(&user_info, ad_group_id)),
+            schedule: campaign.map(|campaign| self.campaign_schedule(campaign, &Utc::now())),
+        }
+    }
+
+    /**
+     * Overlap between ad groups on placement. sampled users get derived dimensions
+     * of placement first, as they would on search. None when placement has no index.
+     */
+    pub fn placement_overlap(
+        &self,
+        placement_id: &str,
+        sample: Vec<UserInfo>,
+    ) -> Option<OverlapReport> {
+        let index = self.filter_index.get(placement_id)?;
+        let sample: Vec<UserInfo> = sample
+            .into_iter()
+            .map(|user_info| {
+                Self::with_derived_dimensions(&self.derived_dimensions, placement_id, user_info)
+            })
+            .collect();
+        Some(analyze(index, &sample))
+    }
+
     pub fn update_creative_feedback(&mut self, creative_feedbacks: &Vec<CreativeFeedback>) {
         let creatives_stat = &mut self.creatives_stat;
         let creatives = &self.creatives;
@@ -396,6 +976,34 @@ impl AdState {
                     .merge(stat);
             }
         }
+        self.update_delivery(creative_feedbacks, &Utc::now());
+    }
+    /**
+     * feedback counts on delivery of campaigns with budget, until next sync sets totals
+     * from CreativeStat. positive is click, negative is impression without click.
+     */
+    fn update_delivery(&self, creative_feedbacks: &Vec<CreativeFeedback>, now: &DateTime<Utc>) {
+        let mut campaign_ids = HashSet::new();
+        for CreativeFeedback {
+            ad_group_id, stat, ..
+        } in creative_feedbacks
+        {
+            let campaign_id = match self
+                .get_ad_group(ad_group_id)
+                .and_then(|ad_group| self.get_campaign(ad_group))
+            {
+                Some(campaign) if self.campaign_budget(campaign).is_some() => campaign.id.clone(),
+                _ => continue,
+            };
+            let clicks = stat.positive_counts as u64;
+            let delivery = Delivery::new(clicks + stat.negative_counts as u64, clicks);
+            self.pacer
+                .lock()
+                .unwrap()
+                .add_delivery(&campaign_id, &delivery, now);
+            campaign_ids.insert(campaign_id);
+        }
+        self.update_throttles(&campaign_ids, now);
     }
 
     pub fn update_ad_set_feedback(&mut self, ad_set_feedbacks: &Vec<AdSetFeedback>) {
@@ -1,24 +1,42 @@
-use crate::ad_state::{AdGroup, AdSet, AdState};
+use crate::ad_state::{segment_filter, AdGroup, AdSet, AdState, FilterViolations};
+use crate::budget::{Budgets, Delivery};
+use crate::context::ContextConfig;
+use crate::frequency::FrequencyCaps;
+use crate::meta_source::{LiveIds, MetaSource};
+use crate::schedule::{flight_status, Dayparting, ScheduleStatus};
+use crate::sync_health::{retry, RetryPolicy, SyncEntity, SyncError, SyncHealth};
+use chrono_tz::Tz;
 use common::db::provider;
 use common::{
     db::{
-        ad_group, ad_set, campaign, content, content_type, creative, integration, placement,
-        service, PrismaClient,
+        ad_group, ad_set, campaign, content, content_type, creative, customset, integration,
+        placement, segment, service, PrismaClient,
     },
     util::{is_active_ad_group, is_active_ad_set},
 };
-use filter::index::FilterIndex;
+use filter::catalog::{Dimension, DimensionCatalog, DimensionType, Violation};
+use filter::derived::DerivedDimensions;
+use filter::filter::TargetFilter;
+use filter::index::{FilterIndex, IndexBackend};
+use filter::segment::{dependent_segment_ids, resolve_segments};
+use filter::taxonomy::Taxonomy;
+use filter::user_list::UserList;
 use integrations::integrations::Integrations;
 use prisma_client_rust::{
-    chrono::{DateTime, FixedOffset},
-    Direction,
+    chrono::{DateTime, FixedOffset, NaiveDate, Utc},
+    raw, Direction, PrismaValue, QueryError, Raw,
+};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::Arc,
 };
-use std::{collections::HashMap, sync::Arc};
 
 async fn fetch_services(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<service::Data> {
+) -> Result<Vec<service::Data>, QueryError> {
     client
         .service()
         .find_many(vec![service::updated_at::gt(last_updated_at)])
@@ -26,12 +44,11 @@ async fn fetch_services(
         .order_by(service::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 pub async fn fetch_placements(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<placement::Data> {
+) -> Result<Vec<placement::Data>, QueryError> {
     client
         .placement()
         .find_many(vec![placement::updated_at::gt(last_updated_at)])
@@ -39,98 +56,209 @@ pub async fn fetch_placements(
         .order_by(placement::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 async fn fetch_campaigns(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<campaign::Data> {
+) -> Result<Vec<campaign::Data>, QueryError> {
     client
         .campaign()
         .find_many(vec![campaign::updated_at::gt(last_updated_at)])
         .order_by(campaign::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 async fn fetch_ad_groups(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<ad_group::Data> {
+) -> Result<Vec<ad_group::Data>, QueryError> {
     client
         .ad_group()
         .find_many(vec![ad_group::updated_at::gt(last_updated_at)])
         .order_by(ad_group::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 async fn fetch_creatives(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<creative::Data> {
+) -> Result<Vec<creative::Data>, QueryError> {
     client
         .creative()
         .find_many(vec![creative::updated_at::gt(last_updated_at)])
         .order_by(creative::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 async fn fetch_contents(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<content::Data> {
+) -> Result<Vec<content::Data>, QueryError> {
     client
         .content()
         .find_many(vec![content::updated_at::gt(last_updated_at)])
         .order_by(content::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 async fn fetch_content_types(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<content_type::Data> {
+) -> Result<Vec<content_type::Data>, QueryError> {
     client
         .content_type()
         .find_many(vec![content_type::updated_at::gt(last_updated_at)])
         .order_by(content_type::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 pub async fn fetch_providers(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<provider::Data> {
+) -> Result<Vec<provider::Data>, QueryError> {
     client
         .provider()
         .find_many(vec![provider::updated_at::gt(last_updated_at)])
         .order_by(provider::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 
-// async fn fetch_segments(
-//     client: Arc<PrismaClient>,
-//     last_updated_at: DateTime<FixedOffset>,
-// ) -> Vec<segment::Data> {
-//     client
-//         .segment()
-//         .find_many(vec![segment::updated_at::gt(last_updated_at)])
-//         .order_by(segment::updated_at::order(Direction::Desc))
-//         .exec()
-//         .await
-//         .unwrap()
-// }
+async fn fetch_segments(
+    client: Arc<PrismaClient>,
+    last_updated_at: DateTime<FixedOffset>,
+) -> Result<Vec<segment::Data>, QueryError> {
+    client
+        .segment()
+        .find_many(vec![segment::updated_at::gt(last_updated_at)])
+        // service of segment is on its integration, for dimension catalog.
+        .with(segment::integration::fetch())
+        .order_by(segment::updated_at::order(Direction::Desc))
+        .exec()
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+struct CampaignDeliveryRow {
+    #[serde(rename = "campaignId")]
+    campaign_id: String,
+    #[serde(rename = "dailyImpressions")]
+    daily_impressions: i64,
+    #[serde(rename = "dailyClicks")]
+    daily_clicks: i64,
+    #[serde(rename = "lifetimeImpressions")]
+    lifetime_impressions: i64,
+    #[serde(rename = "lifetimeClicks")]
+    lifetime_clicks: i64,
+}
+/**
+ * (daily, lifetime) delivery of campaigns from daily CreativeStat, today is in UTC.
+ */
+async fn fetch_campaign_deliveries(
+    client: Arc<PrismaClient>,
+    campaign_ids: Vec<String>,
+    today: DateTime<FixedOffset>,
+) -> Result<HashMap<String, (Delivery, Delivery)>, QueryError> {
+    let sql = r#"
+            SELECT  ag."campaignId" AS "campaignId",
+                    COALESCE(SUM(CASE WHEN s."time" >= $2 THEN s."impressionCount" ELSE 0 END), 0)::bigint AS "dailyImpressions",
+                    COALESCE(SUM(CASE WHEN s."time" >= $2 THEN s."clickCount" ELSE 0 END), 0)::bigint AS "dailyClicks",
+                    COALESCE(SUM(s."impressionCount"), 0)::bigint AS "lifetimeImpressions",
+                    COALESCE(SUM(s."clickCount"), 0)::bigint AS "lifetimeClicks"
+            FROM    "CreativeStat" s
+                    JOIN "Creative" c ON c."id" = s."creativeId"
+                    JOIN "AdGroup" ag ON ag."id" = c."adGroupId"
+            WHERE   s."timeUnit" = 'day' AND ag."campaignId" = ANY($1)
+            GROUP BY ag."campaignId"
+        "#;
+    let campaign_ids = campaign_ids.into_iter().map(PrismaValue::String).collect();
+    let rows: Vec<CampaignDeliveryRow> = client
+        ._query_raw(Raw::new(
+            sql,
+            vec![
+                PrismaValue::List(campaign_ids),
+                PrismaValue::DateTime(today),
+            ],
+        ))
+        .exec()
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let daily = Delivery::new(row.daily_impressions as u64, row.daily_clicks as u64);
+            let lifetime =
+                Delivery::new(row.lifetime_impressions as u64, row.lifetime_clicks as u64);
+            (row.campaign_id, (daily, lifetime))
+        })
+        .collect())
+}
+#[derive(Debug, Deserialize)]
+struct UserFeatureVersion {
+    version: Option<String>,
+}
+#[derive(Debug, Deserialize)]
+struct UserFeatureKey {
+    name: String,
+    #[serde(rename = "isNumber")]
+    is_number: bool,
+}
+async fn fetch_user_feature_version(
+    client: Arc<PrismaClient>,
+) -> Result<Option<String>, QueryError> {
+    let versions: Vec<UserFeatureVersion> = client
+        ._query_raw(raw!(
+            r#"SELECT MAX("version") AS "version" FROM "UserFeature""#
+        ))
+        .exec()
+        .await?;
+    Ok(versions.into_iter().find_map(|v| v.version))
+}
+/**
+ * keys of features on version. type is number when every value of key is json number,
+ * unknown otherwise, since numbers are often stored as strings.
+ */
+async fn fetch_user_feature_catalog(
+    client: Arc<PrismaClient>,
+    version: &str,
+) -> Result<DimensionCatalog, QueryError> {
+    let sql = r#"
+            SELECT  "key" AS "name",
+                    BOOL_AND(JSONB_TYPEOF("value") = 'number') AS "isNumber"
+            FROM    "UserFeature", JSONB_EACH("feature")
+            WHERE   "version" = $1
+            GROUP BY "key"
+        "#;
+    let keys: Vec<UserFeatureKey> = client
+        ._query_raw(Raw::new(
+            sql,
+            vec![PrismaValue::String(String::from(version))],
+        ))
+        .exec()
+        .await?;
+    let mut catalog = DimensionCatalog::default();
+    for key in keys {
+        let dimension_type = Some(DimensionType::Number).filter(|_| key.is_number);
+        catalog.insert(Dimension::new(&key.name, dimension_type));
+    }
+    Ok(catalog)
+}
+
+async fn fetch_customsets(
+    client: Arc<PrismaClient>,
+    last_updated_at: DateTime<FixedOffset>,
+) -> Result<Vec<customset::Data>, QueryError> {
+    client
+        .customset()
+        .find_many(vec![customset::updated_at::gt(last_updated_at)])
+        .order_by(customset::updated_at::order(Direction::Desc))
+        .exec()
+        .await
+}
 
 async fn fetch_ad_sets(
     client: Arc<PrismaClient>,
     last_updated_at: DateTime<FixedOffset>,
-) -> Vec<ad_set::Data> {
+) -> Result<Vec<ad_set::Data>, QueryError> {
     client
         .ad_set()
         .find_many(vec![ad_set::updated_at::gt(last_updated_at)])
@@ -138,7 +266,6 @@ async fn fetch_ad_sets(
         .order_by(ad_set::updated_at::order(Direction::Desc))
         .exec()
         .await
-        .unwrap()
 }
 // async fn fetch_integrations(
 //     client: Arc<PrismaClient>,
@@ -174,6 +301,202 @@ pub fn update_services(ad_state: &mut AdState, new_services: &Vec<service::Data>
     for service in new_services {
         services.insert(service.id.clone(), service.clone());
     }
+    for service in new_services {
+        // service with invalid taxonomy is served without expansion.
+        match load_taxonomy(service) {
+            Ok(Some(taxonomy)) => {
+                ad_state
+                    .taxonomies
+                    .insert(service.id.clone(), Arc::new(taxonomy));
+            }
+            Ok(None) => {
+                ad_state.taxonomies.remove(&service.id);
+            }
+            Err(e) => {
+                println!("taxonomy of service {} is not loaded: {}", service.id, e);
+                ad_state.taxonomies.remove(&service.id);
+            }
+        }
+    }
+    let placement_ids: Vec<String> = ad_state.placements.keys().cloned().collect();
+    for placement_id in placement_ids {
+        set_taxonomy(ad_state, &placement_id);
+    }
+    let service_ids: HashSet<String> = new_services
+        .iter()
+        .map(|service| service.id.clone())
+        .collect();
+    set_catalogs(ad_state, &service_ids);
+}
+/**
+ * dimension catalog of service is on details. dimensions are given explicitly, derived
+ * from the latest version of UserFeature, or both(explicit ones win).
+ * filters of services without catalog are not validated.
+ * ex) {"dimensionCatalog": {"fromUserFeature": true,
+ *      "dimensions": {"country": {"type": "string", "values": ["KR", "JP"]}}}}
+ */
+fn load_catalog(
+    service: &service::Data,
+    user_feature_catalog: Option<&DimensionCatalog>,
+) -> Result<Option<DimensionCatalog>, String> {
+    let config = match service.details.get("dimensionCatalog") {
+        None => return Ok(None),
+        Some(config) => config,
+    };
+    let mut catalog = DimensionCatalog::default();
+    let from_user_feature = config
+        .get("fromUserFeature")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    if let (true, Some(user_feature_catalog)) = (from_user_feature, user_feature_catalog) {
+        catalog.extend(user_feature_catalog.clone());
+    }
+    if let Some(dimensions) = config.get("dimensions") {
+        catalog.extend(DimensionCatalog::from_json(dimensions).map_err(|e| e.to_string())?);
+    }
+    // every dimension would be unknown on empty catalog, ex) no UserFeature yet.
+    Ok(Some(catalog).filter(|catalog| !catalog.is_empty()))
+}
+/**
+ * catalogs of services are reloaded, and their ad groups/segments are validated again.
+ */
+fn set_catalogs(ad_state: &mut AdState, service_ids: &HashSet<String>) {
+    for service_id in service_ids {
+        let service = match ad_state.services.get(service_id) {
+            None => continue,
+            Some(service) => service,
+        };
+        let user_feature_catalog = ad_state
+            .user_feature_catalog
+            .as_ref()
+            .map(|(_, catalog)| catalog.as_ref());
+        // service with invalid catalog is served without validation.
+        match load_catalog(service, user_feature_catalog) {
+            Ok(Some(catalog)) => {
+                ad_state
+                    .catalogs
+                    .insert(service_id.clone(), Arc::new(catalog));
+            }
+            Ok(None) => {
+                ad_state.catalogs.remove(service_id);
+            }
+            Err(e) => {
+                println!(
+                    "dimension catalog of service {} is not loaded: {}",
+                    service_id, e
+                );
+                ad_state.catalogs.remove(service_id);
+            }
+        }
+    }
+    let segments: Vec<segment::Data> = ad_state
+        .segments
+        .values()
+        .filter(|segment| {
+            segment_service_id(segment)
+                .map(|service_id| service_ids.contains(service_id))
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect();
+    if !segments.is_empty() {
+        reload_segments(ad_state, &segments);
+    }
+    let ad_groups: Vec<ad_group::Data> = ad_state
+        .ad_groups
+        .values()
+        .filter(|ad_group| {
+            ad_group_service_id(ad_state, ad_group)
+                .map(|service_id| service_ids.contains(service_id))
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect();
+    index_ad_groups(ad_state, &ad_groups);
+}
+fn segment_service_id(segment: &segment::Data) -> Option<&String> {
+    segment
+        .integration()
+        .ok()
+        .map(|integration| &integration.service_id)
+}
+fn ad_group_service_id<'a>(ad_state: &'a AdState, ad_group: &ad_group::Data) -> Option<&'a String> {
+    ad_state.get_placement(ad_group)?.service_id.as_ref()
+}
+/**
+ * violations are kept to be reported, and removed once filter is valid again.
+ * returns whether filter has any.
+ */
+fn set_violations(
+    ad_state: &mut AdState,
+    kind: &str,
+    id: &str,
+    service_id: &str,
+    violations: Vec<Violation>,
+) -> bool {
+    let key = (String::from(kind), String::from(id));
+    if violations.is_empty() {
+        ad_state.filter_violations.remove(&key);
+        return false;
+    }
+    let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+    println!(
+        "{} {} has invalid filter: {}",
+        kind,
+        id,
+        messages.join(", ")
+    );
+    ad_state.filter_violations.insert(
+        key,
+        FilterViolations {
+            kind: String::from(kind),
+            id: String::from(id),
+            service_id: String::from(service_id),
+            violations,
+        },
+    );
+    true
+}
+/**
+ * taxonomy of service is on details, either inline or on a json file.
+ * ex) {"taxonomy": {"region": {"APAC": {"country": {"KR": {}}}}}}
+ * or {"taxonomyPath": "/data/taxonomies/geo.json"}
+ */
+fn load_taxonomy(service: &service::Data) -> Result<Option<Taxonomy>, String> {
+    if let Some(taxonomy) = service.details.get("taxonomy") {
+        return Taxonomy::from_json(taxonomy)
+            .map(Some)
+            .map_err(|e| e.to_string());
+    }
+    let path = service.details.get("taxonomyPath");
+    match path.and_then(|path| path.as_str()) {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let value: serde_json::Value =
+                serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+            Taxonomy::from_json(&value)
+                .map(Some)
+                .map_err(|e| format!("{}: {}", path, e))
+        }
+        None => Ok(None),
+    }
+}
+fn placement_taxonomy(ad_state: &AdState, placement_id: &str) -> Option<Arc<Taxonomy>> {
+    ad_state
+        .placements
+        .get(placement_id)
+        .and_then(|placement| placement.service_id.as_ref())
+        .and_then(|service_id| ad_state.taxonomies.get(service_id))
+        .cloned()
+}
+fn set_taxonomy(ad_state: &mut AdState, placement_id: &str) {
+    let taxonomy = placement_taxonomy(ad_state, placement_id);
+    if let Some(index) = ad_state.filter_index.get_mut(placement_id) {
+        index.set_taxonomy(taxonomy.clone());
+    }
+    if let Some(index) = ad_state.ad_set_index.get_mut(placement_id) {
+        index.set_taxonomy(taxonomy);
+    }
 }
 pub fn update_placements(ad_state: &mut AdState, new_placements: &Vec<placement::Data>) -> () {
     let placements = &mut ad_state.placements;
@@ -185,6 +508,216 @@ pub fn update_placements(ad_state: &mut AdState, new_placements: &Vec<placement:
     for placement in new_placements {
         placements.insert(placement.id.clone(), placement.clone());
     }
+    // backend can be changed on placement after its indices are built.
+    for placement in new_placements {
+        let backend = index_backend(ad_state, &placement.id, "CREATIVE_FETCHER");
+        if let Some(index) = ad_state.filter_index.get_mut(&placement.id) {
+            index.set_backend(backend);
+        }
+        let backend = index_backend(ad_state, &placement.id, "AD_SET_FETCHER");
+        if let Some(index) = ad_state.ad_set_index.get_mut(&placement.id) {
+            index.set_backend(backend);
+        }
+        set_taxonomy(ad_state, &placement.id);
+
+        let derived = derived_dimensions(ad_state, &placement.id, "CREATIVE_FETCHER");
+        set_derived_dimensions(&mut ad_state.derived_dimensions, &placement.id, derived);
+        let derived = derived_dimensions(ad_state, &placement.id, "AD_SET_FETCHER");
+        set_derived_dimensions(
+            &mut ad_state.ad_set_derived_dimensions,
+            &placement.id,
+            derived,
+        );
+
+        match context_config(ad_state, &placement.id) {
+            Some(config) if !config.is_empty() => {
+                ad_state
+                    .contexts
+                    .insert(placement.id.clone(), Arc::new(config));
+            }
+            _ => {
+                ad_state.contexts.remove(&placement.id);
+            }
+        }
+        let caps = frequency_caps(ad_state, &placement.id);
+        if caps.is_empty() {
+            ad_state.frequency_caps.remove(&placement.id);
+        } else {
+            ad_state
+                .frequency_caps
+                .insert(placement.id.clone(), Arc::new(caps));
+        }
+        match dayparting(ad_state, &placement.id) {
+            Some(dayparting) if !dayparting.is_empty() => {
+                ad_state
+                    .daypartings
+                    .insert(placement.id.clone(), Arc::new(dayparting));
+            }
+            _ => {
+                ad_state.daypartings.remove(&placement.id);
+            }
+        }
+        match budgets(ad_state, &placement.id) {
+            Some(budgets) if !budgets.is_empty() => {
+                ad_state
+                    .budgets
+                    .insert(placement.id.clone(), Arc::new(budgets));
+            }
+            _ => {
+                ad_state.budgets.remove(&placement.id);
+            }
+        }
+    }
+}
+/**
+ * budgets of campaigns are on details.budgets of placement's CREATIVE_FETCHER.
+ * ex) {"budgets": {"campaign_1": {"dailyImpressions": 1000, "lifetimeClicks": 300}}}
+ */
+fn budgets(ad_state: &AdState, placement_id: &str) -> Option<Budgets> {
+    let value = ad_state
+        .placements
+        .get(placement_id)
+        .and_then(|placement| placement.integrations.as_ref())
+        .and_then(|integrations| {
+            integrations
+                .iter()
+                .find(|i| i.provide == "CREATIVE_FETCHER")
+        })
+        .and_then(|integration| integration.details.get("budgets"))?;
+    // invalid budgets are logged and campaigns are not limited.
+    Budgets::from_json(value)
+        .map_err(|e| println!("budgets of {} are not loaded: {}", placement_id, e))
+        .ok()
+}
+/**
+ * dayparting of campaigns is on details.dayparting of placement's CREATIVE_FETCHER,
+ * in timezone of its context unless it has its own.
+ * ex) {"dayparting": {"timezone": "Asia/Seoul", "campaigns": {"campaign_1": {"mon": [[9, 18]]}}}}
+ */
+fn dayparting(ad_state: &AdState, placement_id: &str) -> Option<Dayparting> {
+    let value = ad_state
+        .placements
+        .get(placement_id)
+        .and_then(|placement| placement.integrations.as_ref())
+        .and_then(|integrations| {
+            integrations
+                .iter()
+                .find(|i| i.provide == "CREATIVE_FETCHER")
+        })
+        .and_then(|integration| integration.details.get("dayparting"))?;
+    let timezone = context_config(ad_state, placement_id)
+        .map(|config| config.timezone)
+        .unwrap_or(Tz::UTC);
+    // invalid dayparting is logged and campaigns serve all day.
+    Dayparting::from_json(value, timezone)
+        .map_err(|e| println!("dayparting of {} is not loaded: {}", placement_id, e))
+        .ok()
+}
+/**
+ * frequency caps are on details.frequencyCaps of placement's CREATIVE_FETCHER(campaign and
+ * ad group) and AD_SET_FETCHER(ad set), caps of both are applied.
+ * ex) {"frequencyCaps": [{"level": "campaign", "impressions": 3, "windowSeconds": 86400}]}
+ */
+fn frequency_caps(ad_state: &AdState, placement_id: &str) -> FrequencyCaps {
+    let mut caps = FrequencyCaps::default();
+    let integrations = match ad_state
+        .placements
+        .get(placement_id)
+        .and_then(|placement| placement.integrations.as_ref())
+    {
+        None => return caps,
+        Some(integrations) => integrations,
+    };
+    for provide in ["CREATIVE_FETCHER", "AD_SET_FETCHER"] {
+        let value = integrations
+            .iter()
+            .find(|i| i.provide == provide)
+            .and_then(|integration| integration.details.get("frequencyCaps"));
+        if let Some(value) = value {
+            // invalid caps are logged and placement is served without them.
+            match FrequencyCaps::from_json(value) {
+                Ok(other) => caps.extend(other),
+                Err(e) => println!(
+                    "frequency caps of {} on {} are not loaded: {}",
+                    placement_id, provide, e
+                ),
+            }
+        }
+    }
+    caps
+}
+/**
+ * context dimensions are switched on details.context of placement's CREATIVE_FETCHER,
+ * or AD_SET_FETCHER when placement only serves ad sets.
+ * ex) {"context": {"timezone": "Asia/Seoul", "time": true, "device": true, "geo": true}}
+ */
+fn context_config(ad_state: &AdState, placement_id: &str) -> Option<ContextConfig> {
+    let integrations = ad_state
+        .placements
+        .get(placement_id)
+        .and_then(|placement| placement.integrations.as_ref())?;
+    let value = ["CREATIVE_FETCHER", "AD_SET_FETCHER"]
+        .iter()
+        .flat_map(|provide| integrations.iter().find(|i| i.provide == *provide))
+        .find_map(|integration| integration.details.get("context"))?;
+    ContextConfig::from_json(value)
+        .map_err(|e| println!("context of {} is not loaded: {}", placement_id, e))
+        .ok()
+}
+fn set_derived_dimensions(
+    derived_dimensions: &mut HashMap<String, Arc<DerivedDimensions>>,
+    placement_id: &str,
+    derived: Option<DerivedDimensions>,
+) {
+    match derived {
+        Some(derived) if !derived.is_empty() => {
+            derived_dimensions.insert(placement_id.to_string(), Arc::new(derived));
+        }
+        _ => {
+            derived_dimensions.remove(placement_id);
+        }
+    }
+}
+/**
+ * FilterIndex backend is configured per placement on details.indexBackend
+ * of its fetcher integration. ex) {"indexBackend": "bitmap"}
+ */
+fn index_backend(ad_state: &AdState, placement_id: &str, provide: &str) -> IndexBackend {
+    ad_state
+        .placements
+        .get(placement_id)
+        .and_then(|placement| placement.integrations.as_ref())
+        .and_then(|integrations| integrations.iter().find(|i| i.provide == provide))
+        .and_then(|integration| integration.details.get("indexBackend"))
+        .and_then(|value| value.as_str())
+        .and_then(IndexBackend::parse)
+        .unwrap_or_default()
+}
+/**
+ * derived dimensions are configured per placement on details.derivedDimensions
+ * of its fetcher integration, like indexBackend.
+ * ex) {"derivedDimensions": [{"type": "lowercase", "source": "city"}]}
+ */
+fn derived_dimensions(
+    ad_state: &AdState,
+    placement_id: &str,
+    provide: &str,
+) -> Option<DerivedDimensions> {
+    let value = ad_state
+        .placements
+        .get(placement_id)
+        .and_then(|placement| placement.integrations.as_ref())
+        .and_then(|integrations| integrations.iter().find(|i| i.provide == provide))
+        .and_then(|integration| integration.details.get("derivedDimensions"))?;
+    // invalid configuration is logged and placement is served with raw user_info.
+    DerivedDimensions::from_json(value)
+        .map_err(|e| {
+            println!(
+                "derived dimensions of {} are not loaded: {}",
+                placement_id, e
+            )
+        })
+        .ok()
 }
 pub fn update_campaigns(ad_state: &mut AdState, new_campaigns: &Vec<campaign::Data>) -> () {
     let campaigns = &mut ad_state.campaigns;
@@ -195,6 +728,38 @@ pub fn update_campaigns(ad_state: &mut AdState, new_campaigns: &Vec<campaign::Da
     for campaign in new_campaigns {
         campaigns.insert(campaign.id.clone(), campaign.clone());
     }
+    update_flights(ad_state, &Utc::now());
+}
+/**
+ * ad groups of campaigns that ended by now leave filter index, and are indexed again
+ * when end_at of campaign is moved later. campaigns before their flight stay on index,
+ * since they are checked on each request.
+ */
+pub fn update_flights(ad_state: &mut AdState, now: &DateTime<Utc>) {
+    let ended_campaigns: HashSet<String> = ad_state
+        .campaigns
+        .values()
+        .filter(|campaign| {
+            let status = flight_status(campaign.started_at.as_ref(), campaign.end_at.as_ref(), now);
+            status == ScheduleStatus::AfterFlight
+        })
+        .map(|campaign| campaign.id.clone())
+        .collect();
+    let changed: HashSet<String> = ended_campaigns
+        .symmetric_difference(&ad_state.ended_campaigns)
+        .cloned()
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+    ad_state.ended_campaigns = ended_campaigns;
+    let ad_groups: Vec<ad_group::Data> = ad_state
+        .ad_groups
+        .values()
+        .filter(|ad_group| changed.contains(&ad_group.campaign_id))
+        .cloned()
+        .collect();
+    index_ad_groups(ad_state, &ad_groups);
 }
 fn ad_group_grouped_by_placement(
     ad_state: &AdState,
@@ -235,29 +800,63 @@ pub fn update_ad_groups(ad_state: &mut AdState, new_ad_groups: &Vec<ad_group::Da
     for ad_group in new_ad_groups {
         ad_groups.insert(ad_group.id.clone(), ad_group.clone());
     }
+    index_ad_groups(ad_state, new_ad_groups);
+}
+fn index_ad_groups(ad_state: &mut AdState, new_ad_groups: &Vec<ad_group::Data>) -> () {
     let placement_ad_groups = ad_group_grouped_by_placement(ad_state, new_ad_groups);
     for (placement_id, ad_groups) in placement_ad_groups.iter() {
+        // ad group that violates catalog is not indexed, instead of matching nobody silently.
+        let mut invalid_ad_groups = HashSet::new();
+        for ad_group in ad_groups {
+            let service_id = match ad_group_service_id(ad_state, ad_group) {
+                None => continue,
+                Some(service_id) => service_id.clone(),
+            };
+            let filter = AdGroup {
+                data: ad_group.clone(),
+                segments: &ad_state.segment_filters,
+            }
+            .target_filter();
+            let violations = filter
+                .map(|filter| ad_state.validate_filter(&service_id, Some(placement_id), &filter))
+                .unwrap_or_default();
+            if set_violations(ad_state, "ad_group", &ad_group.id, &service_id, violations) {
+                invalid_ad_groups.insert(ad_group.id.clone());
+            }
+        }
+        let backend = index_backend(ad_state, placement_id, "CREATIVE_FETCHER");
+        let taxonomy = placement_taxonomy(ad_state, placement_id);
         let index = ad_state
             .filter_index
             .entry(placement_id.clone())
-            .or_insert_with(|| FilterIndex::default());
+            .or_insert_with(|| FilterIndex::with_backend(backend));
+        index.set_taxonomy(taxonomy);
 
         let mut ad_groups_to_insert = Vec::new();
         let mut ad_groups_to_delete = Vec::new();
 
         for ad_group in ad_groups {
-            if is_active_ad_group(ad_group) {
+            let is_ended = ad_state.ended_campaigns.contains(&ad_group.campaign_id);
+            if is_active_ad_group(ad_group)
+                && !is_ended
+                && !invalid_ad_groups.contains(&ad_group.id)
+            {
                 ad_groups_to_insert.push(AdGroup {
                     data: ad_group.clone(),
+                    segments: &ad_state.segment_filters,
                 });
             } else {
                 ad_groups_to_delete.push(AdGroup {
                     data: ad_group.clone(),
+                    segments: &ad_state.segment_filters,
                 });
             }
         }
 
-        index.update(&ad_groups_to_insert, &ad_groups_to_delete);
+        let rejected = index.update(&ad_groups_to_insert, &ad_groups_to_delete);
+        for (ad_group_id, e) in rejected {
+            println!("ad_group {} is not indexed: {}", ad_group_id, e);
+        }
     }
 }
 pub fn update_creatives(ad_state: &mut AdState, new_creatives: &Vec<creative::Data>) -> () {
@@ -270,6 +869,19 @@ pub fn update_creatives(ad_state: &mut AdState, new_creatives: &Vec<creative::Da
         //TODO
         // ad_state.ranker.add_arm(&Creative { data: creative.clone() });
 
+        // creative moved to another ad group leaves the old one.
+        let moved_from = ad_state
+            .creative_ad_groups
+            .insert(creative.id.clone(), creative.ad_group_id.clone())
+            .filter(|ad_group_id| *ad_group_id != creative.ad_group_id);
+        if let Some(ad_group_id) = moved_from {
+            if let Some(old_creatives) = creatives.get_mut(&ad_group_id) {
+                old_creatives.remove(&creative.id);
+                if old_creatives.is_empty() {
+                    creatives.remove(&ad_group_id);
+                }
+            }
+        }
         creatives
             .entry(creative.ad_group_id.clone())
             .or_insert_with(|| HashMap::new())
@@ -299,16 +911,132 @@ pub fn update_content_types(
         content_types.insert(content_type.id.clone(), content_type.clone());
     }
 }
-// pub fn update_segments(ad_state: &mut AdState, new_segments: &Vec<segment::Data>) -> () {
-//     let segments = &mut ad_state.segments;
-//     if let Some(latest_updated) = new_segments.first() {
-//         let update_info = &mut ad_state.update_info;
-//         update_info.segments = latest_updated.updated_at;
-//     }
-//     for segment in new_segments {
-//         segments.insert(segment.id.clone(), segment.clone());
-//     }
-// }
+/**
+ * Segments are inlined into filters of ad groups/ad sets on indexing, so the ones
+ * that depend on changed segments, directly or through other segments, are re-indexed.
+ */
+pub fn update_segments(ad_state: &mut AdState, new_segments: &Vec<segment::Data>) -> () {
+    let segments = &mut ad_state.segments;
+    if let Some(latest_updated) = new_segments.first() {
+        let update_info = &mut ad_state.update_info;
+        update_info.segments = latest_updated.updated_at;
+    }
+    for segment in new_segments {
+        segments.insert(segment.id.clone(), segment.clone());
+    }
+    reload_segments(ad_state, new_segments);
+}
+/**
+ * segment that violates catalog of its service never matches, like segment that fails
+ * to compile, so ad groups/ad sets in it are not served to anyone by mistake.
+ */
+fn reload_segments(ad_state: &mut AdState, new_segments: &Vec<segment::Data>) -> () {
+    for segment in new_segments {
+        let mut filter = segment_filter(segment);
+        if let Some(service_id) = segment_service_id(segment) {
+            let violations = ad_state.validate_filter(service_id, None, &filter);
+            if set_violations(ad_state, "segment", &segment.id, service_id, violations) {
+                filter = TargetFilter::Or { fields: vec![] };
+            }
+        }
+        ad_state.segment_filters.insert(segment.id.clone(), filter);
+    }
+    let changed: HashSet<&String> = new_segments.iter().map(|segment| &segment.id).collect();
+    for segment_id in changed.iter() {
+        let filter = TargetFilter::InSegment {
+            segment_id: segment_id.to_string(),
+        };
+        if let Err(e) = resolve_segments(&filter, &ad_state.segment_filters) {
+            println!("segment {} has invalid filter: {}", segment_id, e);
+        }
+    }
+    index_segment_dependents(ad_state, &changed);
+}
+/**
+ * ad groups/ad sets that depend on changed segments, directly or through other segments.
+ */
+fn index_segment_dependents(ad_state: &mut AdState, changed: &HashSet<&String>) {
+    let depends_on_changed = |filter: Option<TargetFilter>| {
+        filter
+            .map(|filter| {
+                dependent_segment_ids(&filter, &ad_state.segment_filters)
+                    .iter()
+                    .any(|segment_id| changed.contains(segment_id))
+            })
+            .unwrap_or(false)
+    };
+
+    let dependent_ad_groups: Vec<ad_group::Data> = ad_state
+        .ad_groups
+        .values()
+        .filter(|ad_group| {
+            let ad_group = AdGroup {
+                data: (*ad_group).clone(),
+                segments: &ad_state.segment_filters,
+            };
+            depends_on_changed(ad_group.target_filter())
+        })
+        .cloned()
+        .collect();
+    let dependent_ad_sets: Vec<ad_set::Data> = ad_state
+        .ad_sets
+        .values()
+        .filter(|ad_set| {
+            let ad_set = AdSet {
+                data: (*ad_set).clone(),
+                segments: &ad_state.segment_filters,
+            };
+            depends_on_changed(ad_set.target_filter())
+        })
+        .cloned()
+        .collect();
+
+    index_ad_groups(ad_state, &dependent_ad_groups);
+    index_ad_sets(ad_state, &dependent_ad_sets);
+}
+/**
+ * user ids of Customset are on details, either inline or on a file with one id per line.
+ * ex) {"userIds": ["u1", "u2"]} or {"path": "/data/customsets/vip.txt"}
+ */
+fn load_user_list(customset: &customset::Data) -> Result<UserList, String> {
+    if let Some(user_ids) = customset.details.get("userIds") {
+        let user_ids = user_ids
+            .as_array()
+            .ok_or_else(|| String::from("userIds is not an array"))?;
+        return Ok(UserList::new(
+            user_ids
+                .iter()
+                .flat_map(|user_id| user_id.as_str())
+                .map(String::from),
+        ));
+    }
+    match customset.details.get("path").and_then(|path| path.as_str()) {
+        Some(path) => std::fs::read_to_string(path)
+            .map(|text| UserList::parse(&text))
+            .map_err(|e| format!("{}: {}", path, e)),
+        None => Err(String::from("details has neither userIds nor path")),
+    }
+}
+/**
+ * InUserList is indexed by list id only, so changed lists are swapped in without re-indexing.
+ * file on path is read again when its Customset is updated.
+ */
+pub fn update_customsets(ad_state: &mut AdState, new_customsets: &Vec<customset::Data>) -> () {
+    if let Some(latest_updated) = new_customsets.first() {
+        let update_info = &mut ad_state.update_info;
+        update_info.customsets = latest_updated.updated_at;
+    }
+    for customset in new_customsets {
+        // list that fails to load is empty, so that nobody is matched by it.
+        let user_list = load_user_list(customset).unwrap_or_else(|e| {
+            println!("customset {} is not loaded: {}", customset.id, e);
+            UserList::default()
+        });
+        ad_state
+            .user_lists
+            .insert(customset.id.clone(), Arc::new(user_list));
+    }
+}
 pub fn update_ad_sets(ad_state: &mut AdState, new_ad_sets: &Vec<ad_set::Data>) -> () {
     let ad_sets = &mut ad_state.ad_sets;
     if let Some(latest_updated) = new_ad_sets.first() {
@@ -319,13 +1047,18 @@ pub fn update_ad_sets(ad_state: &mut AdState, new_ad_sets: &Vec<ad_set::Data>) -
     for ad_set in new_ad_sets {
         ad_sets.insert(ad_set.id.clone(), ad_set.clone());
     }
-
+    index_ad_sets(ad_state, new_ad_sets);
+}
+fn index_ad_sets(ad_state: &mut AdState, new_ad_sets: &Vec<ad_set::Data>) -> () {
     let placement_ad_sets = ad_set_grouped_by_placement(ad_state, new_ad_sets);
     for (placement_id, ad_sets) in placement_ad_sets.iter() {
+        let backend = index_backend(ad_state, placement_id, "AD_SET_FETCHER");
+        let taxonomy = placement_taxonomy(ad_state, placement_id);
         let index = ad_state
             .ad_set_index
             .entry(placement_id.clone())
-            .or_insert_with(|| FilterIndex::default());
+            .or_insert_with(|| FilterIndex::with_backend(backend));
+        index.set_taxonomy(taxonomy);
 
         let mut inserts = Vec::new();
         let mut deletes = Vec::new();
@@ -334,15 +1067,150 @@ pub fn update_ad_sets(ad_state: &mut AdState, new_ad_sets: &Vec<ad_set::Data>) -
             if is_active_ad_set(ad_set) {
                 inserts.push(AdSet {
                     data: ad_set.clone(),
+                    segments: &ad_state.segment_filters,
                 });
             } else {
                 deletes.push(AdSet {
                     data: ad_set.clone(),
+                    segments: &ad_state.segment_filters,
                 });
             }
         }
 
-        index.update(&inserts, &deletes);
+        let rejected = index.update(&inserts, &deletes);
+        for (ad_set_id, e) in rejected {
+            println!("ad_set {} is not indexed: {}", ad_set_id, e);
+        }
+    }
+}
+// removes entries of map that are not live, returns their ids.
+fn retain_live<V>(map: &mut HashMap<String, V>, live_ids: &HashSet<String>) -> HashSet<String> {
+    let deleted: HashSet<String> = map
+        .keys()
+        .filter(|id| !live_ids.contains(*id))
+        .cloned()
+        .collect();
+    map.retain(|id, _| !deleted.contains(id));
+    deleted
+}
+/**
+ * rows that are gone from source are removed from every map and index.
+ * ad groups/ad sets leave indices first, since their placements are needed to find them.
+ */
+pub fn remove_deleted(ad_state: &mut AdState, live_ids: &LiveIds) {
+    let deleted_ad_groups: Vec<ad_group::Data> = ad_state
+        .ad_groups
+        .values()
+        .filter(|ad_group| !live_ids.ad_groups.contains(&ad_group.id))
+        .cloned()
+        .collect();
+    for (placement_id, ad_groups) in ad_group_grouped_by_placement(ad_state, &deleted_ad_groups) {
+        if let Some(index) = ad_state.filter_index.get_mut(&placement_id) {
+            let deletes = ad_groups
+                .into_iter()
+                .map(|data| AdGroup {
+                    data,
+                    segments: &ad_state.segment_filters,
+                })
+                .collect();
+            index.update(&Vec::new(), &deletes);
+        }
+    }
+    let deleted_ad_sets: Vec<ad_set::Data> = ad_state
+        .ad_sets
+        .values()
+        .filter(|ad_set| !live_ids.ad_sets.contains(&ad_set.id))
+        .cloned()
+        .collect();
+    for (placement_id, ad_sets) in ad_set_grouped_by_placement(ad_state, &deleted_ad_sets) {
+        if let Some(index) = ad_state.ad_set_index.get_mut(&placement_id) {
+            let deletes = ad_sets
+                .into_iter()
+                .map(|data| AdSet {
+                    data,
+                    segments: &ad_state.segment_filters,
+                })
+                .collect();
+            index.update(&Vec::new(), &deletes);
+        }
+    }
+
+    let ad_group_ids = retain_live(&mut ad_state.ad_groups, &live_ids.ad_groups);
+    // creatives of deleted ad group are deleted with it.
+    retain_live(&mut ad_state.creatives, &live_ids.ad_groups);
+    for creatives in ad_state.creatives.values_mut() {
+        creatives.retain(|creative_id, _| live_ids.creatives.contains(creative_id));
+    }
+    ad_state
+        .creatives
+        .retain(|_, creatives| !creatives.is_empty());
+    let creatives = &ad_state.creatives;
+    ad_state
+        .creative_ad_groups
+        .retain(|creative_id, ad_group_id| {
+            creatives
+                .get(ad_group_id)
+                .map(|creatives| creatives.contains_key(creative_id))
+                .unwrap_or(false)
+        });
+    retain_live(&mut ad_state.creatives_stat, &live_ids.creatives);
+    let ad_set_ids = retain_live(&mut ad_state.ad_sets, &live_ids.ad_sets);
+    retain_live(&mut ad_state.ad_sets_stat, &live_ids.ad_sets);
+
+    for campaign_id in retain_live(&mut ad_state.campaigns, &live_ids.campaigns) {
+        ad_state.ended_campaigns.remove(&campaign_id);
+        ad_state.pacer.lock().unwrap().remove(&campaign_id);
+    }
+    for placement_id in retain_live(&mut ad_state.placements, &live_ids.placements) {
+        ad_state.filter_index.remove(&placement_id);
+        ad_state.ad_set_index.remove(&placement_id);
+        ad_state.derived_dimensions.remove(&placement_id);
+        ad_state.ad_set_derived_dimensions.remove(&placement_id);
+        ad_state.contexts.remove(&placement_id);
+        ad_state.frequency_caps.remove(&placement_id);
+        ad_state.daypartings.remove(&placement_id);
+        ad_state.budgets.remove(&placement_id);
+    }
+    let service_ids = retain_live(&mut ad_state.services, &live_ids.services);
+    for service_id in service_ids.iter() {
+        ad_state.catalogs.remove(service_id);
+        ad_state.taxonomies.remove(service_id);
+    }
+    retain_live(&mut ad_state.contents, &live_ids.contents);
+    retain_live(&mut ad_state.content_types, &live_ids.content_types);
+    retain_live(&mut ad_state.user_lists, &live_ids.customsets);
+
+    let segment_ids = retain_live(&mut ad_state.segments, &live_ids.segments);
+    retain_live(&mut ad_state.segment_filters, &live_ids.segments);
+    ad_state.filter_violations.retain(|(kind, id), violations| {
+        let is_deleted = match kind.as_str() {
+            "ad_group" => ad_group_ids.contains(id),
+            "ad_set" => ad_set_ids.contains(id),
+            "segment" => segment_ids.contains(id),
+            _ => false,
+        };
+        !is_deleted && !service_ids.contains(&violations.service_id)
+    });
+    // InSegment on deleted segment matches nobody from now on.
+    if !segment_ids.is_empty() {
+        index_segment_dependents(ad_state, &segment_ids.iter().collect());
+    }
+}
+/**
+ * deletions are found by comparing against ids on source, after rows updated since
+ * last sync are applied. state is kept as it is when ids are not fetched.
+ */
+pub async fn reconcile(ad_state: &mut AdState, source: &dyn MetaSource) {
+    let live_ids = retry(
+        &ad_state.sync_health,
+        SyncEntity::LiveIds,
+        &RetryPolicy::default(),
+        || source.live_ids(),
+    )
+    .await;
+    match live_ids {
+        Ok(live_ids) => remove_deleted(ad_state, &live_ids),
+        Err(e) => println!("live ids are not fetched: {}", e),
     }
 }
 // pub async fn update_providers(ad_state: &mut AdState, new_providers: &Vec<provider::Data>) -> () {
@@ -394,95 +1262,256 @@ pub async fn fetch_and_update_services(
     ad_state: &mut AdState,
     client: Arc<PrismaClient>,
     last_updated_at: Option<DateTime<FixedOffset>>,
-) -> () {
+) -> Result<(), SyncError> {
     let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.services);
-    let new_services = fetch_services(client, last_updated_at_value).await;
+    let new_services = fetch_with_retry(&ad_state.sync_health, SyncEntity::Services, || {
+        fetch_services(client.clone(), last_updated_at_value)
+    })
+    .await?;
     println!("[new_services]: {:?}", new_services.len());
     update_services(ad_state, &new_services);
+    fetch_and_update_user_feature_catalog(ad_state, client).await;
+    Ok(())
+}
+/**
+ * fetch that fails is retried with backoff, and recorded on sync health of entity.
+ */
+async fn fetch_with_retry<T, F, Fut>(
+    health: &SyncHealth,
+    entity: SyncEntity,
+    fetch: F,
+) -> Result<T, SyncError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, QueryError>>,
+{
+    retry(health, entity, &RetryPolicy::default(), fetch)
+        .await
+        .map_err(|error| SyncError { entity, error })
+}
+/**
+ * catalog from UserFeature is derived again only when a new version is loaded.
+ * previous one is kept when it fails to be fetched.
+ */
+async fn fetch_and_update_user_feature_catalog(ad_state: &mut AdState, client: Arc<PrismaClient>) {
+    let version = match fetch_user_feature_version(client.clone()).await {
+        Ok(Some(version)) => version,
+        Ok(None) => return,
+        Err(e) => {
+            println!("user feature version is not fetched: {}", e);
+            return;
+        }
+    };
+    let current_version = ad_state
+        .user_feature_catalog
+        .as_ref()
+        .map(|(version, _)| version);
+    if current_version == Some(&version) {
+        return;
+    }
+    match fetch_user_feature_catalog(client, &version).await {
+        Ok(catalog) => update_user_feature_catalog(ad_state, &version, catalog),
+        Err(e) => println!("user feature catalog of {} is not fetched: {}", version, e),
+    }
+}
+/**
+ * delivery of campaigns with budget is set from CreativeStat on each sync.
+ * previous one is kept when it fails to be fetched.
+ */
+async fn fetch_and_update_deliveries(ad_state: &mut AdState, client: Arc<PrismaClient>) {
+    let campaign_ids: Vec<String> = ad_state
+        .budgets
+        .values()
+        .flat_map(|budgets| budgets.campaigns.keys().cloned())
+        .collect();
+    if campaign_ids.is_empty() {
+        return;
+    }
+    let now = Utc::now();
+    let today = now.date_naive();
+    let today_at = today
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(FixedOffset::east_opt(0).unwrap())
+        .unwrap();
+    let deliveries = fetch_with_retry(&ad_state.sync_health, SyncEntity::Deliveries, || {
+        fetch_campaign_deliveries(client.clone(), campaign_ids.clone(), today_at)
+    })
+    .await;
+    match deliveries {
+        Ok(deliveries) => update_deliveries(ad_state, &campaign_ids, today, &deliveries, &now),
+        Err(e) => println!("{}", e),
+    }
+}
+/**
+ * campaign without any CreativeStat yet has delivered nothing.
+ */
+pub fn update_deliveries(
+    ad_state: &mut AdState,
+    campaign_ids: &[String],
+    today: NaiveDate,
+    deliveries: &HashMap<String, (Delivery, Delivery)>,
+    now: &DateTime<Utc>,
+) {
+    {
+        let mut pacer = ad_state.pacer.lock().unwrap();
+        for campaign_id in campaign_ids {
+            let (daily, lifetime) = deliveries.get(campaign_id).cloned().unwrap_or_default();
+            pacer.set_delivery(campaign_id, today, daily, lifetime);
+        }
+    }
+    let campaign_ids: HashSet<String> = campaign_ids.iter().cloned().collect();
+    ad_state.update_throttles(&campaign_ids, now);
+}
+pub fn update_user_feature_catalog(
+    ad_state: &mut AdState,
+    version: &str,
+    catalog: DimensionCatalog,
+) {
+    ad_state.user_feature_catalog = Some((String::from(version), Arc::new(catalog)));
+    let service_ids: HashSet<String> = ad_state
+        .services
+        .values()
+        .filter(|service| {
+            service
+                .details
+                .get("dimensionCatalog")
+                .and_then(|config| config.get("fromUserFeature"))
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false)
+        })
+        .map(|service| service.id.clone())
+        .collect();
+    set_catalogs(ad_state, &service_ids);
 }
 pub async fn fetch_and_update_placements(
     ad_state: &mut AdState,
     client: Arc<PrismaClient>,
     last_updated_at: Option<DateTime<FixedOffset>>,
-) -> Vec<placement::Data> {
+) -> Result<Vec<placement::Data>, SyncError> {
     let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.placements);
-    let new_placements = fetch_placements(client, last_updated_at_value).await;
+    let new_placements = fetch_with_retry(&ad_state.sync_health, SyncEntity::Placements, || {
+        fetch_placements(client.clone(), last_updated_at_value)
+    })
+    .await?;
     println!("[new_placements]: {:?}", new_placements.len());
     update_placements(ad_state, &new_placements);
 
-    new_placements
+    Ok(new_placements)
 }
 
 pub async fn fetch_and_update_campaigns(
     ad_state: &mut AdState,
     client: Arc<PrismaClient>,
     last_updated_at: Option<DateTime<FixedOffset>>,
-) -> () {
+) -> Result<(), SyncError> {
     let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.campaigns);
-    let new_campaigns = fetch_campaigns(client, last_updated_at_value).await;
+    let new_campaigns = fetch_with_retry(&ad_state.sync_health, SyncEntity::Campaigns, || {
+        fetch_campaigns(client.clone(), last_updated_at_value)
+    })
+    .await?;
     println!("[new_campaigns]: {:?}", new_campaigns.len());
     update_campaigns(ad_state, &new_campaigns);
+    Ok(())
 }
 pub async fn fetch_and_update_ad_groups(
     ad_state: &mut AdState,
     client: Arc<PrismaClient>,
     last_updated_at: Option<DateTime<FixedOffset>>,
-) -> () {
+) -> Result<(), SyncError> {
     let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.ad_groups);
-    let new_ad_groups = fetch_ad_groups(client, last_updated_at_value).await;
+    let new_ad_groups = fetch_with_retry(&ad_state.sync_health, SyncEntity::AdGroups, || {
+        fetch_ad_groups(client.clone(), last_updated_at_value)
+    })
+    .await?;
     println!("[new_ad_groups]: {:?}", new_ad_groups.len());
     update_ad_groups(ad_state, &new_ad_groups);
+    Ok(())
 }
 pub async fn fetch_and_update_creatives(
     ad_state: &mut AdState,
     client: Arc<PrismaClient>,
     last_updated_at: Option<DateTime<FixedOffset>>,
-) -> () {
+) -> Result<(), SyncError> {
     let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.creatives);
-    let new_creatives = fetch_creatives(client, last_updated_at_value).await;
+    let new_creatives = fetch_with_retry(&ad_state.sync_health, SyncEntity::Creatives, || {
+        fetch_creatives(client.clone(), last_updated_at_value)
+    })
+    .await?;
     println!("[new_creatives]: {:?}", new_creatives.len());
     update_creatives(ad_state, &new_creatives);
+    Ok(())
 }
 pub async fn fetch_and_update_contents(
     ad_state: &mut AdState,
     client: Arc<PrismaClient>,
     last_updated_at: Option<DateTime<FixedOffset>>,
-) -> () {
+) -> Result<(), SyncError> {
     let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.contents);
-    let new_contents = fetch_contents(client, last_updated_at_value).await;
+    let new_contents = fetch_with_retry(&ad_state.sync_health, SyncEntity::Contents, || {
+        fetch_contents(client.clone(), last_updated_at_value)
+    })
+    .await?;
     println!("[new_contents]: {:?}", new_contents.len());
     update_contents(ad_state, &new_contents);
+    Ok(())
 }
 pub async fn fetch_and_update_content_types(
     ad_state: &mut AdState,
     client: Arc<PrismaClient>,
     last_updated_at: Option<DateTime<FixedOffset>>,
-) -> () {
+) -> Result<(), SyncError> {
     let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.content_types);
-    let new_content_types = fetch_content_types(client, last_updated_at_value).await;
+    let new_content_types =
+        fetch_with_retry(&ad_state.sync_health, SyncEntity::ContentTypes, || {
+            fetch_content_types(client.clone(), last_updated_at_value)
+        })
+        .await?;
     println!("[new_content_typess]: {:?}", new_content_types.len());
     update_content_types(ad_state, &new_content_types);
+    Ok(())
+}
+pub async fn fetch_and_update_segments(
+    ad_state: &mut AdState,
+    client: Arc<PrismaClient>,
+    last_updated_at: Option<DateTime<FixedOffset>>,
+) -> Result<(), SyncError> {
+    let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.segments);
+    let fetched = fetch_with_retry(&ad_state.sync_health, SyncEntity::Segments, || {
+        fetch_segments(client.clone(), last_updated_at_value)
+    })
+    .await?;
+    println!("[new_segments]: {:?}", fetched.len());
+    update_segments(ad_state, &fetched);
+    Ok(())
+}
+pub async fn fetch_and_update_customsets(
+    ad_state: &mut AdState,
+    client: Arc<PrismaClient>,
+    last_updated_at: Option<DateTime<FixedOffset>>,
+) -> Result<(), SyncError> {
+    let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.customsets);
+    let fetched = fetch_with_retry(&ad_state.sync_health, SyncEntity::Customsets, || {
+        fetch_customsets(client.clone(), last_updated_at_value)
+    })
+    .await?;
+    println!("[new_customsets]: {:?}", fetched.len());
+    update_customsets(ad_state, &fetched);
+    Ok(())
 }
-// pub async fn fetch_and_update_segments(
-//     ad_state: &mut AdState,
-//     client: Arc<PrismaClient>,
-//     last_updated_at: Option<DateTime<FixedOffset>>,
-// ) -> () {
-//     let last_updated_at_value =
-//         last_updated_at.unwrap_or(ad_state.update_info.segments);
-//     let fetched = fetch_segments(client, last_updated_at_value).await;
-//     println!("[new_segments]: {:?}", fetched.len());
-//     update_segments(ad_state, &fetched);
-// }
 pub async fn fetch_and_update_ad_sets(
     ad_state: &mut AdState,
     client: Arc<PrismaClient>,
     last_updated_at: Option<DateTime<FixedOffset>>,
-) -> () {
+) -> Result<(), SyncError> {
     let last_updated_at_value = last_updated_at.unwrap_or(ad_state.update_info.ad_sets);
-    let fetched = fetch_ad_sets(client, last_updated_at_value).await;
+    let fetched = fetch_with_retry(&ad_state.sync_health, SyncEntity::AdSets, || {
+        fetch_ad_sets(client.clone(), last_updated_at_value)
+    })
+    .await?;
     println!("[new_ad_sets]: {:?}", fetched.len());
     update_ad_sets(ad_state, &fetched);
+    Ok(())
 }
 
 // async fn fetch_and_update_providers(
@@ -507,21 +1536,34 @@ pub async fn fetch_and_update_ad_sets(
 //     self.update_integrations(new_integrations);
 // }
 
-pub async fn load(ad_state: &mut AdState, client: Arc<PrismaClient>) {
-    fetch_and_update_services(ad_state, client.clone(), None).await;
-    let placements = fetch_and_update_placements(ad_state, client.clone(), None).await;
-    fetch_and_update_campaigns(ad_state, client.clone(), None).await;
-    fetch_and_update_ad_groups(ad_state, client.clone(), None).await;
-    fetch_and_update_creatives(ad_state, client.clone(), None).await;
-    fetch_and_update_contents(ad_state, client.clone(), None).await;
-    fetch_and_update_content_types(ad_state, client.clone(), None).await;
-    fetch_and_update_ad_sets(ad_state, client.clone(), None).await;
+/**
+ * fetches are applied on ad_state one by one. it is left half updated on SyncError, so
+ * caller should keep serving the previous one instead.
+ */
+pub async fn load(ad_state: &mut AdState, client: Arc<PrismaClient>) -> Result<(), SyncError> {
+    fetch_and_update_services(ad_state, client.clone(), None).await?;
+    let placements = fetch_and_update_placements(ad_state, client.clone(), None).await?;
+    fetch_and_update_campaigns(ad_state, client.clone(), None).await?;
+    // before ad groups/ad sets, so that their filters are resolved with loaded segments.
+    fetch_and_update_segments(ad_state, client.clone(), None).await?;
+    fetch_and_update_ad_groups(ad_state, client.clone(), None).await?;
+    fetch_and_update_creatives(ad_state, client.clone(), None).await?;
+    fetch_and_update_contents(ad_state, client.clone(), None).await?;
+    fetch_and_update_content_types(ad_state, client.clone(), None).await?;
+    fetch_and_update_ad_sets(ad_state, client.clone(), None).await?;
+    fetch_and_update_customsets(ad_state, client.clone(), None).await?;
+    fetch_and_update_deliveries(ad_state, client.clone()).await;
+    reconcile(ad_state, client.as_ref()).await;
 
     // integrations
     let last_updated_at_value = ad_state.update_info.integrations;
-    let providers = fetch_providers(client, last_updated_at_value).await;
+    let providers = fetch_with_retry(&ad_state.sync_health, SyncEntity::Providers, || {
+        fetch_providers(client.clone(), last_updated_at_value)
+    })
+    .await?;
     let integrations = Integrations::new(&placements, &providers).await;
     ad_state.set_integrations(integrations);
 
     println!("{:?}", ad_state);
+    Ok(())
 }
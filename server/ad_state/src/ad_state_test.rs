@@ -1,16 +1,31 @@
-use super::AdState;
+use super::{AdState, CreativeFeedback};
+
+use crate::context::RequestContext;
+use crate::frequency::ImpressionEvent;
+use crate::meta_source::{LiveIds, MetaSource};
+use crate::schedule::ScheduleStatus;
 
 use crate::ad_state_builder::{
-    update_ad_groups, update_campaigns, update_content_types, update_contents, update_creatives,
-    update_placements, update_services,
+    reconcile, update_ad_groups, update_campaigns, update_content_types, update_contents,
+    update_creatives, update_customsets, update_deliveries, update_placements, update_segments,
+    update_services, update_user_feature_catalog,
 };
+use crate::budget::Delivery;
+use async_trait::async_trait;
 use common::{
-    db::{ad_group, campaign, content, content_type, creative, placement, service},
+    db::{
+        ad_group, campaign, content, content_type, creative, customset, integration, placement,
+        segment, service,
+    },
     types::{AdGroupCreatives, CreativeWithContent},
+    util::parse_user_info,
 };
+use filter::catalog::{Dimension, DimensionCatalog, DimensionType};
+use filter::filter::TargetFilter;
 use integrations::integrations::Integrations;
 use lazy_static::lazy_static;
-use prisma_client_rust::chrono::FixedOffset;
+use prisma_client_rust::chrono::{Duration, FixedOffset, Utc};
+use prisma_client_rust::QueryError;
 use serde_json::json;
 use std::collections::{HashMap, HashSet};
 
@@ -172,7 +187,8 @@ async fn test_ad_state_and_search_result_after_update() {
         let ad_group_id_creatives = HashMap::from([(ad_group_id.as_str(), &inner)]);
         let creatives = ad_state.ad_group_ids_to_creatives_with_contents(ad_group_id_creatives);
 
-        let ad_group_creatives = ad_state.ad_group_creatives(&placement_id, creatives, None);
+        let ad_group_creatives =
+            ad_state.ad_group_creatives(&placement_id, creatives, &HashMap::new(), None);
 
         let campaign_ad_groups = ad_state.campaign_ad_groups(ad_group_creatives);
 
@@ -201,7 +217,7 @@ async fn test_ad_state_and_search_result_after_update() {
         // filter {"in": [{"var": "age"}, ["10"]]}
 
         let search_result = ad_state
-            .search(&SERVICE.id, &placement_id, &user_info_json, None)
+            .search(&SERVICE.id, &placement_id, None, &user_info_json, None)
             .await;
         assert_eq!(search_result.matched_ads.len() > 0, true);
     }
@@ -232,7 +248,8 @@ async fn test_ad_state_and_search_result_after_update() {
 
         let creatives = ad_state.ad_group_ids_to_creatives_with_contents(ad_group_id_creatives);
 
-        let ad_group_creatives = ad_state.ad_group_creatives(&placement_id, creatives, None);
+        let ad_group_creatives =
+            ad_state.ad_group_creatives(&placement_id, creatives, &HashMap::new(), None);
 
         for AdGroupCreatives {
             ad_group,
@@ -251,7 +268,7 @@ async fn test_ad_state_and_search_result_after_update() {
         // after update, ad_group should not matched since filter changed from
         // age.10 to age.30 and user_info has age.10
         let search_result = ad_state
-            .search(&SERVICE.id, &placement_id, &user_info_json, None)
+            .search(&SERVICE.id, &placement_id, None, &user_info_json, None)
             .await;
         assert_eq!(search_result.matched_ads.len() == 0, true);
     }
@@ -270,7 +287,7 @@ async fn test_update_ad_group_filter_and_status_change() {
 
     // result should contain AD_GROUP since age.10
     let search_result = ad_state
-        .search(&SERVICE.id, &PLACEMENT.id, &user_info_json, None)
+        .search(&SERVICE.id, &PLACEMENT.id, None, &user_info_json, None)
         .await;
     assert_eq!(search_result.matched_ads.len() > 0, true);
 
@@ -287,7 +304,7 @@ async fn test_update_ad_group_filter_and_status_change() {
     // after update, AD_GROUP should be excluded from result since
     // our index suppose to exlude filters_to_delete.
     let search_result = ad_state
-        .search(&SERVICE.id, &PLACEMENT.id, &user_info_json, None)
+        .search(&SERVICE.id, &PLACEMENT.id, None, &user_info_json, None)
         .await;
     assert_eq!(search_result.matched_ads.len() == 0, true);
 
@@ -302,7 +319,787 @@ async fn test_update_ad_group_filter_and_status_change() {
     // after update, AD_GROUP should be included in result.
     // since ad_group's status has been change back to published.
     let search_result = ad_state
-        .search(&SERVICE.id, &PLACEMENT.id, &user_info_json, None)
+        .search(&SERVICE.id, &PLACEMENT.id, None, &user_info_json, None)
         .await;
     assert_eq!(search_result.matched_ads.len() > 0, true);
 }
+
+#[test]
+fn test_explain() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+
+    let result = ad_state.explain(&PLACEMENT.id, &AD_GROUP.id, None, &json!({"age": ["20"]}));
+    assert_eq!(result.on_placement, true);
+    let levels: Vec<(&str, bool)> = result
+        .status_checks
+        .iter()
+        .map(|check| (check.level.as_str(), check.is_active))
+        .collect();
+    assert_eq!(
+        levels,
+        vec![
+            ("placement", true),
+            ("campaign", true),
+            ("ad_group", true),
+            ("creative", true)
+        ]
+    );
+    let explanation = result.filter.unwrap();
+    assert_eq!(explanation.matched, false);
+    assert_eq!(explanation.target_keys[0].dimensions[0].dimension, "age");
+    assert_eq!(explanation.target_keys[0].dimensions[0].passed, false);
+
+    // archived ad group is removed from index too.
+    update_ad_groups(
+        &mut ad_state,
+        &vec![ad_group::Data {
+            status: String::from("archieved"),
+            ..AD_GROUP.clone()
+        }],
+    );
+    let result = ad_state.explain(&PLACEMENT.id, &AD_GROUP.id, None, &json!({"age": ["10"]}));
+    assert_eq!(
+        result.status_checks[2].status,
+        Some(String::from("archieved"))
+    );
+    assert_eq!(result.status_checks[2].is_active, false);
+    assert_eq!(result.filter.unwrap().indexed, false);
+}
+
+fn segment_of(id: &str, r#where: &str) -> segment::Data {
+    segment::Data {
+        id: String::from(id),
+        integration: None,
+        integration_id: String::from("integration_1"),
+        name: String::from(id),
+        description: None,
+        r#where: Some(String::from(r#where)),
+        population: None,
+        status: String::from("CREATED"),
+        created_at: *NOW,
+        updated_at: *NOW,
+        ad_sets: None,
+    }
+}
+
+#[test]
+fn test_ad_group_in_segment() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    let is_matched = |ad_state: &AdState, user_info_json: serde_json::Value| {
+        let user_info = parse_user_info(&user_info_json).unwrap();
+        ad_state.filter_index[&PLACEMENT.id]
+            .search(&user_info)
+            .contains(AD_GROUP.id.as_str())
+    };
+
+    update_segments(
+        &mut ad_state,
+        &vec![
+            segment_of("teens", r#"{"in": [{"var": "age"}, ["10"]]}"#),
+            segment_of(
+                "korean_teens",
+                r#"{"and": [{"in_segment": ["teens"]}, {"==": [{"var": "country"}, "KR"]}]}"#,
+            ),
+        ],
+    );
+    update_ad_groups(
+        &mut ad_state,
+        &vec![ad_group::Data {
+            filter: Some(String::from(r#"{"in_segment": ["korean_teens"]}"#)),
+            ..AD_GROUP.clone()
+        }],
+    );
+    assert!(is_matched(&ad_state, json!({"age": "10", "country": "KR"})));
+    assert!(!is_matched(
+        &ad_state,
+        json!({"age": "20", "country": "KR"})
+    ));
+
+    // ad group is re-indexed when a segment it depends on through other segment changes.
+    update_segments(
+        &mut ad_state,
+        &vec![segment_of("teens", r#"{"in": [{"var": "age"}, ["20"]]}"#)],
+    );
+    assert!(!is_matched(
+        &ad_state,
+        json!({"age": "10", "country": "KR"})
+    ));
+    assert!(is_matched(&ad_state, json!({"age": "20", "country": "KR"})));
+
+    // cycle is never matched instead of being served as untargeted.
+    update_segments(
+        &mut ad_state,
+        &vec![segment_of("teens", r#"{"in_segment": ["korean_teens"]}"#)],
+    );
+    assert!(!is_matched(
+        &ad_state,
+        json!({"age": "20", "country": "KR"})
+    ));
+    assert_eq!(ad_state.segments.len(), 2);
+}
+
+#[tokio::test]
+async fn test_ad_group_in_user_list() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    let customset = customset::Data {
+        id: String::from("customset_1"),
+        name: String::from("vip"),
+        description: None,
+        service: None,
+        service_id: Some(SERVICE.id.clone()),
+        created_by: None,
+        creator_id: String::from(""),
+        details: json!({"userIds": ["u1", "u2"]}),
+        status: String::from("CREATED"),
+        created_at: *NOW,
+        updated_at: *NOW,
+    };
+    update_customsets(&mut ad_state, &vec![customset.clone()]);
+    update_ad_groups(
+        &mut ad_state,
+        &vec![ad_group::Data {
+            filter: Some(String::from(r#"{"in_user_list": ["customset_1"]}"#)),
+            ..AD_GROUP.clone()
+        }],
+    );
+    let matched = |ad_state: &AdState, user_id: Option<&str>| {
+        ad_state
+            .explain(&PLACEMENT.id, &AD_GROUP.id, user_id, &json!({}))
+            .filter
+            .unwrap()
+            .matched
+    };
+    assert!(matched(&ad_state, Some("u1")));
+    assert!(!matched(&ad_state, Some("u3")));
+    assert!(!matched(&ad_state, None));
+
+    let search_result = ad_state
+        .search(&SERVICE.id, &PLACEMENT.id, Some("u2"), &json!({}), None)
+        .await;
+    assert_eq!(search_result.matched_ads.len(), 1);
+
+    // updated list replaces the previous one without re-indexing ad group.
+    update_customsets(
+        &mut ad_state,
+        &vec![customset::Data {
+            details: json!({"userIds": ["u3"]}),
+            ..customset
+        }],
+    );
+    assert!(!matched(&ad_state, Some("u1")));
+    assert!(matched(&ad_state, Some("u3")));
+}
+
+#[test]
+fn test_ad_group_with_taxonomy() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    update_ad_groups(
+        &mut ad_state,
+        &vec![ad_group::Data {
+            filter: Some(String::from(r#"{"==": [{"var": "region"}, "APAC"]}"#)),
+            ..AD_GROUP.clone()
+        }],
+    );
+    let matched = |ad_state: &AdState, user_info_json: serde_json::Value| {
+        ad_state
+            .explain(&PLACEMENT.id, &AD_GROUP.id, None, &user_info_json)
+            .filter
+            .unwrap()
+            .matched
+    };
+    assert!(!matched(&ad_state, json!({"city": "Seoul"})));
+
+    // taxonomy on service is applied to indices that already exist.
+    update_services(
+        &mut ad_state,
+        &vec![service::Data {
+            details: json!({
+                "taxonomy": {"region": {"APAC": {"country": {"KR": {"city": {"Seoul": {}}}}}}}
+            }),
+            ..SERVICE.clone()
+        }],
+    );
+    assert!(matched(&ad_state, json!({"city": "Seoul"})));
+    assert!(matched(&ad_state, json!({"country": "KR"})));
+    assert!(!matched(&ad_state, json!({"country": "FR"})));
+
+    // invalid taxonomy is dropped instead of keeping the previous one.
+    update_services(
+        &mut ad_state,
+        &vec![service::Data {
+            details: json!({"taxonomy": {"region": ["APAC"]}}),
+            ..SERVICE.clone()
+        }],
+    );
+    assert!(ad_state.taxonomies.is_empty());
+    assert!(!matched(&ad_state, json!({"city": "Seoul"})));
+}
+
+#[test]
+fn test_ad_group_with_derived_dimensions() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    update_ad_groups(
+        &mut ad_state,
+        &vec![ad_group::Data {
+            filter: Some(String::from(
+                r#"{"and": [{"==": [{"var": "age_band"}, "20-30"]}, {"==": [{"var": "city"}, "seoul"]}]}"#,
+            )),
+            ..AD_GROUP.clone()
+        }],
+    );
+    let matched = |ad_state: &AdState| {
+        ad_state
+            .explain(
+                &PLACEMENT.id,
+                &AD_GROUP.id,
+                None,
+                &json!({"age": 25, "city": "Seoul"}),
+            )
+            .filter
+            .unwrap()
+            .matched
+    };
+    assert!(!matched(&ad_state));
+
+    let fetcher = integration::Data {
+        id: String::from("integration_1"),
+        name: String::from("fetcher"),
+        description: None,
+        provide: String::from("CREATIVE_FETCHER"),
+        provider: None,
+        provider_id: None,
+        details: json!({
+            "derivedDimensions": [
+                {"type": "bucketize", "source": "age", "target": "age_band", "boundaries": [20, 30]},
+                {"type": "lowercase", "source": "city"}
+            ]
+        }),
+        status: String::from("published"),
+        created_at: *NOW,
+        updated_at: *NOW,
+        service: None,
+        service_id: SERVICE.id.clone(),
+        placements: None,
+        segments: None,
+    };
+    update_placements(
+        &mut ad_state,
+        &vec![placement::Data {
+            integrations: Some(vec![fetcher.clone()]),
+            ..PLACEMENT.clone()
+        }],
+    );
+    assert!(matched(&ad_state));
+    assert!(!ad_state
+        .ad_set_derived_dimensions
+        .contains_key(&PLACEMENT.id));
+
+    // invalid configuration falls back to raw user_info.
+    update_placements(
+        &mut ad_state,
+        &vec![placement::Data {
+            integrations: Some(vec![integration::Data {
+                details: json!({"derivedDimensions": [{"type": "bucketize", "source": "age"}]}),
+                ..fetcher
+            }]),
+            ..PLACEMENT.clone()
+        }],
+    );
+    assert!(!matched(&ad_state));
+}
+
+#[test]
+fn test_context_user_info() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    let context = RequestContext::default();
+    let user_info_json = json!({"age": "10", "ctx.hour": "99"});
+
+    // placement without context gets none, and ctx sent by caller is dropped.
+    assert_eq!(
+        ad_state.context_user_info(&PLACEMENT.id, &context, None, &user_info_json),
+        json!({"age": "10"})
+    );
+
+    update_placements(
+        &mut ad_state,
+        &vec![placement::Data {
+            integrations: Some(vec![integration::Data {
+                id: String::from("integration_1"),
+                name: String::from("fetcher"),
+                description: None,
+                provide: String::from("AD_SET_FETCHER"),
+                provider: None,
+                provider_id: None,
+                details: json!({"context": {"timezone": "Asia/Seoul", "time": true}}),
+                status: String::from("published"),
+                created_at: *NOW,
+                updated_at: *NOW,
+                service: None,
+                service_id: SERVICE.id.clone(),
+                placements: None,
+                segments: None,
+            }]),
+            ..PLACEMENT.clone()
+        }],
+    );
+    let user_info = parse_user_info(&ad_state.context_user_info(
+        &PLACEMENT.id,
+        &context,
+        None,
+        &user_info_json,
+    ))
+    .unwrap();
+    assert_eq!(user_info["ctx.hour"].len(), 1);
+    assert_ne!(user_info["ctx.hour"], HashSet::from([String::from("99")]));
+    assert!(user_info.contains_key("ctx.weekday"));
+    assert!(!user_info.contains_key("ctx.device"));
+}
+
+#[test]
+fn test_filter_violations() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    let is_indexed = |ad_state: &AdState| {
+        ad_state
+            .explain(&PLACEMENT.id, &AD_GROUP.id, None, &json!({}))
+            .filter
+            .unwrap()
+            .indexed
+    };
+    update_ad_groups(
+        &mut ad_state,
+        &vec![ad_group::Data {
+            filter: Some(String::from(r#"{"==": [{"var": "contry"}, "KR"]}"#)),
+            ..AD_GROUP.clone()
+        }],
+    );
+    // filters are not validated until service has a catalog.
+    assert!(is_indexed(&ad_state));
+    assert!(ad_state.service_filter_violations(&SERVICE.id).is_empty());
+
+    // ad group indexed before is validated again with new catalog.
+    update_services(
+        &mut ad_state,
+        &vec![service::Data {
+            details: json!({
+                "dimensionCatalog": {
+                    "fromUserFeature": true,
+                    "dimensions": {"country": {"values": ["KR", "JP"]}}
+                }
+            }),
+            ..SERVICE.clone()
+        }],
+    );
+    assert!(!is_indexed(&ad_state));
+    let violations = ad_state.service_filter_violations(&SERVICE.id);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].kind, "ad_group");
+    assert_eq!(
+        violations[0].violations[0].to_string(),
+        "unknown dimension contry, did you mean country?"
+    );
+
+    // dimensions derived from UserFeature are known to services that use them.
+    let mut user_feature_catalog = DimensionCatalog::default();
+    user_feature_catalog.insert(Dimension::new("age", Some(DimensionType::Number)));
+    update_user_feature_catalog(&mut ad_state, "20261018000000", user_feature_catalog);
+    update_ad_groups(
+        &mut ad_state,
+        &vec![ad_group::Data {
+            filter: Some(String::from(
+                r#"{"and": [{"==": [{"var": "country"}, "KR"]}, {">": [{"var": "age"}, 10]}]}"#,
+            )),
+            ..AD_GROUP.clone()
+        }],
+    );
+    assert!(is_indexed(&ad_state));
+    assert!(ad_state.service_filter_violations(&SERVICE.id).is_empty());
+
+    // segment that violates catalog never matches.
+    let segment = segment::Data {
+        integration: Some(Box::new(integration::Data {
+            id: String::from("integration_1"),
+            name: String::from("segments"),
+            description: None,
+            provide: String::from("SEGMENT"),
+            provider: None,
+            provider_id: None,
+            details: json!({}),
+            status: String::from("published"),
+            created_at: *NOW,
+            updated_at: *NOW,
+            service: None,
+            service_id: SERVICE.id.clone(),
+            placements: None,
+            segments: None,
+        })),
+        ..segment_of("koreans", r#"{"==": [{"var": "country"}, "US"]}"#)
+    };
+    update_segments(&mut ad_state, &vec![segment]);
+    assert_eq!(
+        ad_state.segment_filters["koreans"],
+        TargetFilter::Or { fields: vec![] }
+    );
+    let violations = ad_state.service_filter_violations(&SERVICE.id);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].kind, "segment");
+}
+
+#[test]
+fn test_ad_group_with_invalid_filter_json() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    let is_indexed_for = |ad_state: &AdState, user_info_json: serde_json::Value| {
+        let user_info = parse_user_info(&user_info_json).unwrap();
+        ad_state.filter_index[&PLACEMENT.id]
+            .search(&user_info)
+            .contains(AD_GROUP.id.as_str())
+    };
+    assert!(is_indexed_for(&ad_state, json!({"age": "10"})));
+
+    // filter that is not JSON matches nobody instead of everyone.
+    update_ad_groups(
+        &mut ad_state,
+        &vec![ad_group::Data {
+            filter: Some(String::from(r#"{"in": [{"var": "age"}, ["10"]]"#)),
+            ..AD_GROUP.clone()
+        }],
+    );
+    assert!(!is_indexed_for(&ad_state, json!({"age": "10"})));
+    assert!(!is_indexed_for(&ad_state, json!({})));
+}
+
+async fn is_matched(ad_state: &AdState, user_id: Option<&str>) -> bool {
+    let search_result = ad_state
+        .search(
+            &SERVICE.id,
+            &PLACEMENT.id,
+            user_id,
+            &json!({"age": "10"}),
+            None,
+        )
+        .await;
+    search_result.matched_ads.len() > 0
+}
+#[tokio::test]
+async fn test_frequency_cap() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    update_placements(
+        &mut ad_state,
+        &vec![placement::Data {
+            integrations: Some(vec![integration::Data {
+                id: String::from("integration_1"),
+                name: String::from("fetcher"),
+                description: None,
+                provide: String::from("CREATIVE_FETCHER"),
+                provider: None,
+                provider_id: None,
+                details: json!({"frequencyCaps": [
+                    {"level": "campaign", "impressions": 2, "windowSeconds": 3600}
+                ]}),
+                status: String::from("published"),
+                created_at: *NOW,
+                updated_at: *NOW,
+                service: None,
+                service_id: SERVICE.id.clone(),
+                placements: None,
+                segments: None,
+            }]),
+            ..PLACEMENT.clone()
+        }],
+    );
+    let impression = |which: &str| ImpressionEvent {
+        when: NOW.timestamp_millis() as u64,
+        who: String::from("user_1"),
+        what: String::from("impression"),
+        which: String::from(which),
+    };
+
+    // click and unknown creative are not counted.
+    let recorded = ad_state.record_impressions(&[
+        impression(&CREATIVE.id),
+        ImpressionEvent {
+            what: String::from("click"),
+            ..impression(&CREATIVE.id)
+        },
+        impression("creative_2"),
+    ]);
+    assert_eq!(recorded, 1);
+    assert_eq!(is_matched(&ad_state, Some("user_1")).await, true);
+
+    // impression dated far in the future is dropped.
+    let future = ImpressionEvent {
+        when: (NOW.timestamp_millis() + Duration::days(1).num_milliseconds()) as u64,
+        ..impression(&CREATIVE.id)
+    };
+    assert_eq!(ad_state.record_impressions(&[future]), 0);
+    assert_eq!(is_matched(&ad_state, Some("user_1")).await, true);
+
+    // impressions are shared with ad state cloned on sync.
+    AdState::from(&ad_state).record_impressions(&[impression(&CREATIVE.id)]);
+    assert_eq!(is_matched(&ad_state, Some("user_1")).await, false);
+    assert_eq!(is_matched(&ad_state, Some("user_2")).await, true);
+    assert_eq!(is_matched(&ad_state, None).await, true);
+}
+
+#[tokio::test]
+async fn test_campaign_schedule() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    let explain =
+        |ad_state: &AdState| ad_state.explain(&PLACEMENT.id, &AD_GROUP.id, None, &json!({}));
+
+    // campaign before its flight stays on index, but doesn't serve.
+    update_campaigns(
+        &mut ad_state,
+        &vec![campaign::Data {
+            started_at: Some(*NOW + Duration::hours(1)),
+            ..CAMPAIGN.clone()
+        }],
+    );
+    assert_eq!(is_matched(&ad_state, None).await, false);
+    let result = explain(&ad_state);
+    assert_eq!(result.schedule, Some(ScheduleStatus::BeforeFlight));
+    assert_eq!(result.filter.unwrap().indexed, true);
+
+    // ended campaign leaves index.
+    update_campaigns(
+        &mut ad_state,
+        &vec![campaign::Data {
+            end_at: Some(*NOW - Duration::hours(1)),
+            ..CAMPAIGN.clone()
+        }],
+    );
+    assert_eq!(is_matched(&ad_state, None).await, false);
+    let result = explain(&ad_state);
+    assert_eq!(result.schedule, Some(ScheduleStatus::AfterFlight));
+    assert_eq!(result.filter.unwrap().indexed, false);
+
+    // and comes back when its flight is extended.
+    let extended = campaign::Data {
+        end_at: Some(*NOW + Duration::days(1)),
+        ..CAMPAIGN.clone()
+    };
+    update_campaigns(&mut ad_state, &vec![extended.clone()]);
+    assert_eq!(is_matched(&ad_state, None).await, true);
+    assert_eq!(
+        ad_state.next_campaign_end(&NOW.with_timezone(&Utc)),
+        extended.end_at.map(|end_at| end_at.with_timezone(&Utc))
+    );
+
+    // campaign without any hours on dayparting is off schedule all week.
+    update_placements(
+        &mut ad_state,
+        &vec![placement::Data {
+            integrations: Some(vec![integration::Data {
+                id: String::from("integration_1"),
+                name: String::from("fetcher"),
+                description: None,
+                provide: String::from("CREATIVE_FETCHER"),
+                provider: None,
+                provider_id: None,
+                details: json!({"dayparting": {"campaigns": {"campaign_1": {}}}}),
+                status: String::from("published"),
+                created_at: *NOW,
+                updated_at: *NOW,
+                service: None,
+                service_id: SERVICE.id.clone(),
+                placements: None,
+                segments: None,
+            }]),
+            ..PLACEMENT.clone()
+        }],
+    );
+    assert_eq!(is_matched(&ad_state, None).await, false);
+    assert_eq!(
+        explain(&ad_state).schedule,
+        Some(ScheduleStatus::OffSchedule)
+    );
+}
+
+#[tokio::test]
+async fn test_campaign_budget() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    update_placements(
+        &mut ad_state,
+        &vec![placement::Data {
+            integrations: Some(vec![integration::Data {
+                id: String::from("integration_1"),
+                name: String::from("fetcher"),
+                description: None,
+                provide: String::from("CREATIVE_FETCHER"),
+                provider: None,
+                provider_id: None,
+                details: json!({"budgets": {
+                    "campaign_1": {"lifetimeImpressions": 100, "pacing": "asap"}
+                }}),
+                status: String::from("published"),
+                created_at: *NOW,
+                updated_at: *NOW,
+                service: None,
+                service_id: SERVICE.id.clone(),
+                placements: None,
+                segments: None,
+            }]),
+            ..PLACEMENT.clone()
+        }],
+    );
+    let now = Utc::now();
+    let campaign_ids = vec![CAMPAIGN.id.clone()];
+    let deliveries = HashMap::from([(
+        CAMPAIGN.id.clone(),
+        (Delivery::new(10, 1), Delivery::new(90, 5)),
+    )]);
+    update_deliveries(
+        &mut ad_state,
+        &campaign_ids,
+        now.date_naive(),
+        &deliveries,
+        &now,
+    );
+    assert_eq!(is_matched(&ad_state, None).await, true);
+
+    // feedback counts on delivery until next sync, exhausted campaign is excluded.
+    let feedbacks: Vec<CreativeFeedback> = serde_json::from_value(json!([{
+        "ad_group_id": AD_GROUP.id,
+        "creative_id": CREATIVE.id,
+        "stat": {"positive_counts": 1, "negative_counts": 9}
+    }]))
+    .unwrap();
+    ad_state.update_creative_feedback(&feedbacks);
+    assert_eq!(is_matched(&ad_state, None).await, false);
+
+    // campaign without CreativeStat has delivered nothing.
+    update_deliveries(
+        &mut ad_state,
+        &campaign_ids,
+        now.date_naive(),
+        &HashMap::new(),
+        &now,
+    );
+    assert_eq!(is_matched(&ad_state, None).await, true);
+
+    // feedback on serving state while a load is in flight is kept by the loaded one.
+    let loading = ad_state.clone();
+    ad_state.update_creative_feedback(&feedbacks);
+    assert_eq!(
+        loading.pacer.lock().unwrap().delivery(&CAMPAIGN.id, &now),
+        (Delivery::new(10, 1), Delivery::new(10, 1))
+    );
+}
+
+struct InMemoryMetaSource {
+    live_ids: LiveIds,
+}
+#[async_trait]
+impl MetaSource for InMemoryMetaSource {
+    async fn live_ids(&self) -> Result<LiveIds, QueryError> {
+        Ok(self.live_ids.clone())
+    }
+}
+fn live_ids_of(ad_state: &AdState) -> LiveIds {
+    let ids = |keys: Vec<&String>| keys.into_iter().cloned().collect();
+    LiveIds {
+        services: ids(ad_state.services.keys().collect()),
+        placements: ids(ad_state.placements.keys().collect()),
+        campaigns: ids(ad_state.campaigns.keys().collect()),
+        ad_groups: ids(ad_state.ad_groups.keys().collect()),
+        creatives: ids(ad_state.creatives.values().flat_map(|c| c.keys()).collect()),
+        contents: ids(ad_state.contents.keys().collect()),
+        content_types: ids(ad_state.content_types.keys().collect()),
+        segments: ids(ad_state.segments.keys().collect()),
+        customsets: ids(ad_state.user_lists.keys().collect()),
+        ad_sets: ids(ad_state.ad_sets.keys().collect()),
+    }
+}
+
+#[tokio::test]
+async fn test_reconcile_deleted() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    update_segments(
+        &mut ad_state,
+        &vec![segment_of(
+            "segment_1",
+            r#"{"in": [{"var": "age"}, ["10"]]}"#,
+        )],
+    );
+    let ad_group_2 = ad_group::Data {
+        id: String::from("ad_group_2"),
+        filter: Some(String::from(r#"{"in_segment": ["segment_1"]}"#)),
+        ..AD_GROUP.clone()
+    };
+    update_ad_groups(&mut ad_state, &vec![ad_group_2.clone()]);
+    update_creatives(
+        &mut ad_state,
+        &vec![creative::Data {
+            id: String::from("creative_2"),
+            ad_group_id: ad_group_2.id.clone(),
+            ..CREATIVE.clone()
+        }],
+    );
+    let indexed = |ad_state: &AdState, ad_group_id: &str| {
+        ad_state
+            .explain(&PLACEMENT.id, ad_group_id, None, &json!({"age": ["10"]}))
+            .filter
+            .map(|explanation| explanation.matched)
+            .unwrap_or(false)
+    };
+    assert_eq!(indexed(&ad_state, &AD_GROUP.id), true);
+    assert_eq!(indexed(&ad_state, &ad_group_2.id), true);
+
+    // nothing is removed while everything is live.
+    let source = InMemoryMetaSource {
+        live_ids: live_ids_of(&ad_state),
+    };
+    reconcile(&mut ad_state, &source).await;
+    assert_eq!(ad_state.ad_groups.len(), 2);
+
+    // AD_GROUP with its creative and segment_1 are deleted.
+    let mut live_ids = live_ids_of(&ad_state);
+    live_ids.ad_groups.remove(&AD_GROUP.id);
+    live_ids.creatives.remove(&CREATIVE.id);
+    live_ids.segments.remove("segment_1");
+    reconcile(&mut ad_state, &InMemoryMetaSource { live_ids }).await;
+
+    assert_eq!(ad_state.get_ad_group(&AD_GROUP.id).is_none(), true);
+    assert_eq!(ad_state.creatives.contains_key(&AD_GROUP.id), false);
+    assert_eq!(ad_state.segments.is_empty(), true);
+    assert_eq!(indexed(&ad_state, &AD_GROUP.id), false);
+    // InSegment on deleted segment matches nobody.
+    assert_eq!(indexed(&ad_state, &ad_group_2.id), false);
+
+    // placement is deleted with its index.
+    let mut live_ids = live_ids_of(&ad_state);
+    live_ids.placements.remove(&PLACEMENT.id);
+    reconcile(&mut ad_state, &InMemoryMetaSource { live_ids }).await;
+    assert_eq!(ad_state.placements.is_empty(), true);
+    assert_eq!(ad_state.filter_index.contains_key(&PLACEMENT.id), false);
+}
+
+#[test]
+fn test_creative_moved_to_another_ad_group() {
+    let mut ad_state = AdState::default();
+    init_test_ad_state(&mut ad_state);
+    update_creatives(
+        &mut ad_state,
+        &vec![creative::Data {
+            ad_group_id: String::from("ad_group_2"),
+            ..CREATIVE.clone()
+        }],
+    );
+    assert_eq!(ad_state.creatives.contains_key(&AD_GROUP.id), false);
+    assert_eq!(
+        ad_state.creatives["ad_group_2"].contains_key(&CREATIVE.id),
+        true
+    );
+    assert_eq!(ad_state.creative_ad_groups[&CREATIVE.id], "ad_group_2");
+}
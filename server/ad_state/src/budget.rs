@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use prisma_client_rust::chrono::{DateTime, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// throttle is adjusted at most once per this many seconds, so rate is observed over a while.
+const MIN_PACING_INTERVAL_SECONDS: i64 = 60;
+// campaign that is far ahead still gets some traffic, to keep observing its delivery rate.
+const MIN_SERVE_RATE: f64 = 0.01;
+const SECONDS_OF_DAY: i64 = 86400;
+
+/**
+ * How daily goal of campaign is spent. even spreads it over the day, asap serves
+ * until it is exhausted.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pacing {
+    #[default]
+    Even,
+    Asap,
+}
+
+/**
+ * Impression and click goals of campaign, either of them can be left open.
+ * days are in UTC, as CreativeStat is aggregated.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CampaignBudget {
+    pub daily_impressions: Option<u64>,
+    pub daily_clicks: Option<u64>,
+    pub lifetime_impressions: Option<u64>,
+    pub lifetime_clicks: Option<u64>,
+    #[serde(default)]
+    pub pacing: Pacing,
+}
+impl CampaignBudget {
+    pub fn is_exhausted(&self, daily: &Delivery, lifetime: &Delivery) -> bool {
+        let reached =
+            |goal: Option<u64>, delivered: u64| goal.map(|goal| delivered >= goal).unwrap_or(false);
+        reached(self.daily_impressions, daily.impressions)
+            || reached(self.daily_clicks, daily.clicks)
+            || reached(self.lifetime_impressions, lifetime.impressions)
+            || reached(self.lifetime_clicks, lifetime.clicks)
+    }
+    // (delivered, goal) of today that even pacing follows, impressions first.
+    fn daily_goal(&self, daily: &Delivery) -> Option<(u64, u64)> {
+        match (self.daily_impressions, self.daily_clicks) {
+            (Some(goal), _) => Some((daily.impressions, goal)),
+            (None, Some(goal)) => Some((daily.clicks, goal)),
+            (None, None) => None,
+        }
+    }
+}
+
+/**
+ * Budgets of campaigns on placement, on details.budgets of its CREATIVE_FETCHER.
+ * ex) {"budgets": {"campaign_1": {"dailyImpressions": 1000, "lifetimeClicks": 300, "pacing": "even"}}}
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Budgets {
+    pub campaigns: HashMap<String, CampaignBudget>,
+}
+impl Budgets {
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let campaigns: HashMap<String, CampaignBudget> =
+            serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+        for (campaign_id, budget) in campaigns.iter() {
+            let goals = [
+                budget.daily_impressions,
+                budget.daily_clicks,
+                budget.lifetime_impressions,
+                budget.lifetime_clicks,
+            ];
+            if goals.contains(&Some(0)) {
+                return Err(format!(
+                    "goals of campaign {} must be positive",
+                    campaign_id
+                ));
+            }
+        }
+        Ok(Budgets { campaigns })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.campaigns.is_empty()
+    }
+    pub fn get(&self, campaign_id: &str) -> Option<&CampaignBudget> {
+        self.campaigns.get(campaign_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Delivery {
+    pub impressions: u64,
+    pub clicks: u64,
+}
+impl Delivery {
+    pub fn new(impressions: u64, clicks: u64) -> Self {
+        Delivery {
+            impressions,
+            clicks,
+        }
+    }
+    fn merge(&mut self, other: &Delivery) {
+        self.impressions += other.impressions;
+        self.clicks += other.clicks;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct CampaignDelivery {
+    // day that daily is counted on.
+    day: Option<NaiveDate>,
+    daily: Delivery,
+    lifetime: Delivery,
+}
+
+#[derive(Debug, Clone)]
+struct Throttle {
+    serve_rate: f64,
+    observed_at: DateTime<Utc>,
+    delivered: u64,
+}
+
+/**
+ * Delivery of campaigns and throttles of even paced ones.
+ * totals are set from CreativeStat on each sync, and feedback adds to them in between.
+ * throttle is the probability that campaign serves on a request. it is adjusted by
+ * the ratio of rate needed to spend the rest of goal by end of day, to rate observed
+ * since last adjustment.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Pacer {
+    deliveries: HashMap<String, CampaignDelivery>,
+    throttles: HashMap<String, Throttle>,
+}
+impl Pacer {
+    pub fn set_delivery(
+        &mut self,
+        campaign_id: &str,
+        day: NaiveDate,
+        daily: Delivery,
+        lifetime: Delivery,
+    ) {
+        self.deliveries.insert(
+            String::from(campaign_id),
+            CampaignDelivery {
+                day: Some(day),
+                daily,
+                lifetime,
+            },
+        );
+    }
+    pub fn add_delivery(&mut self, campaign_id: &str, delivery: &Delivery, now: &DateTime<Utc>) {
+        let today = now.date_naive();
+        let campaign_delivery = self
+            .deliveries
+            .entry(String::from(campaign_id))
+            .or_default();
+        if campaign_delivery.day != Some(today) {
+            campaign_delivery.day = Some(today);
+            campaign_delivery.daily = Delivery::default();
+        }
+        campaign_delivery.daily.merge(delivery);
+        campaign_delivery.lifetime.merge(delivery);
+    }
+    // (daily, lifetime) delivery of campaign at now.
+    pub fn delivery(&self, campaign_id: &str, now: &DateTime<Utc>) -> (Delivery, Delivery) {
+        match self.deliveries.get(campaign_id) {
+            None => Default::default(),
+            Some(delivery) if delivery.day == Some(now.date_naive()) => {
+                (delivery.daily, delivery.lifetime)
+            }
+            Some(delivery) => (Delivery::default(), delivery.lifetime),
+        }
+    }
+    pub fn is_exhausted(
+        &self,
+        budget: &CampaignBudget,
+        campaign_id: &str,
+        now: &DateTime<Utc>,
+    ) -> bool {
+        let (daily, lifetime) = self.delivery(campaign_id, now);
+        budget.is_exhausted(&daily, &lifetime)
+    }
+    pub fn remove(&mut self, campaign_id: &str) {
+        self.deliveries.remove(campaign_id);
+        self.throttles.remove(campaign_id);
+    }
+    pub fn serve_rate(&self, campaign_id: &str) -> f64 {
+        self.throttles
+            .get(campaign_id)
+            .map(|throttle| throttle.serve_rate)
+            .unwrap_or(1.0)
+    }
+    /**
+     * campaign serves on request when it is not exhausted, and draw in [0, 1) is under
+     * its throttle when it is even paced.
+     */
+    pub fn serves(
+        &self,
+        budget: &CampaignBudget,
+        campaign_id: &str,
+        now: &DateTime<Utc>,
+        draw: f64,
+    ) -> bool {
+        if self.is_exhausted(budget, campaign_id, now) {
+            return false;
+        }
+        budget.pacing == Pacing::Asap || draw < self.serve_rate(campaign_id)
+    }
+    pub fn update_throttle(
+        &mut self,
+        budget: &CampaignBudget,
+        campaign_id: &str,
+        now: &DateTime<Utc>,
+    ) {
+        let (daily, _) = self.delivery(campaign_id, now);
+        let (delivered, goal) = match budget.daily_goal(&daily) {
+            Some(daily_goal) if budget.pacing == Pacing::Even => daily_goal,
+            _ => {
+                self.throttles.remove(campaign_id);
+                return;
+            }
+        };
+        let throttle = self
+            .throttles
+            .entry(String::from(campaign_id))
+            .or_insert_with(|| Throttle {
+                serve_rate: 1.0,
+                observed_at: *now,
+                delivered,
+            });
+        // each day starts unthrottled.
+        if throttle.observed_at.date_naive() != now.date_naive() {
+            *throttle = Throttle {
+                serve_rate: 1.0,
+                observed_at: *now,
+                delivered,
+            };
+            return;
+        }
+        let interval = (*now - throttle.observed_at).num_seconds();
+        if interval < MIN_PACING_INTERVAL_SECONDS {
+            return;
+        }
+        let remaining_seconds =
+            (SECONDS_OF_DAY - now.num_seconds_from_midnight() as i64).max(1) as f64;
+        let needed_rate = goal.saturating_sub(delivered) as f64 / remaining_seconds;
+        let observed_rate = delivered.saturating_sub(throttle.delivered) as f64 / interval as f64;
+
+        throttle.serve_rate = if observed_rate > 0.0 {
+            throttle.serve_rate * needed_rate / observed_rate
+        } else {
+            // nothing delivered while throttled, open it up.
+            throttle.serve_rate * 2.0
+        }
+        .clamp(MIN_SERVE_RATE, 1.0);
+        throttle.observed_at = *now;
+        throttle.delivered = delivered;
+    }
+}
+
+#[cfg(test)]
+#[path = "./budget_test.rs"]
+mod budget_test;
@@ -0,0 +1,107 @@
+use super::*;
+
+use prisma_client_rust::chrono::{Duration, TimeZone};
+use serde_json::json;
+
+fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2023, 6, 5, hour, minute, 0).unwrap()
+}
+
+#[test]
+fn test_from_json() {
+    let budgets = Budgets::from_json(&json!({
+        "campaign_1": {"dailyImpressions": 1000, "lifetimeClicks": 300},
+        "campaign_2": {"lifetimeImpressions": 5000, "pacing": "asap"}
+    }))
+    .unwrap();
+    let budget = budgets.get("campaign_1").unwrap();
+    assert_eq!(budget.daily_impressions, Some(1000));
+    assert_eq!(budget.pacing, Pacing::Even);
+    assert_eq!(budgets.get("campaign_2").unwrap().pacing, Pacing::Asap);
+
+    assert!(Budgets::from_json(&json!({"campaign_1": {"dailyImpressions": 0}})).is_err());
+    assert!(Budgets::from_json(&json!({"campaign_1": {"pacing": "slow"}})).is_err());
+}
+
+#[test]
+fn test_exhausted() {
+    let budget = CampaignBudget {
+        daily_impressions: Some(100),
+        lifetime_clicks: Some(10),
+        ..Default::default()
+    };
+    let mut pacer = Pacer::default();
+    pacer.set_delivery(
+        "campaign_1",
+        at(0, 0).date_naive(),
+        Delivery::new(90, 2),
+        Delivery::new(900, 8),
+    );
+    assert!(!pacer.is_exhausted(&budget, "campaign_1", &at(12, 0)));
+
+    pacer.add_delivery("campaign_1", &Delivery::new(10, 0), &at(12, 0));
+    assert!(pacer.is_exhausted(&budget, "campaign_1", &at(12, 0)));
+    assert!(!pacer.serves(&budget, "campaign_1", &at(12, 0), 0.0));
+
+    // daily delivery starts over on next day, lifetime doesn't.
+    let tomorrow = at(12, 0) + Duration::days(1);
+    assert!(!pacer.is_exhausted(&budget, "campaign_1", &tomorrow));
+    pacer.add_delivery("campaign_1", &Delivery::new(1, 2), &tomorrow);
+    assert_eq!(
+        pacer.delivery("campaign_1", &tomorrow),
+        (Delivery::new(1, 2), Delivery::new(911, 10))
+    );
+    assert!(pacer.is_exhausted(&budget, "campaign_1", &tomorrow));
+
+    // campaign without delivery yet.
+    assert!(!pacer.is_exhausted(&budget, "campaign_2", &at(12, 0)));
+}
+
+#[test]
+fn test_even_pacing() {
+    // 1440 impressions a day is 1 per minute.
+    let budget = CampaignBudget {
+        daily_impressions: Some(1440),
+        ..Default::default()
+    };
+    let mut pacer = Pacer::default();
+    let day = at(0, 0).date_naive();
+    pacer.set_delivery("campaign_1", day, Delivery::default(), Delivery::default());
+    pacer.update_throttle(&budget, "campaign_1", &at(0, 0));
+    assert_eq!(pacer.serve_rate("campaign_1"), 1.0);
+
+    // 4 per minute unthrottled is 4 times faster than needed.
+    pacer.set_delivery(
+        "campaign_1",
+        day,
+        Delivery::new(40, 0),
+        Delivery::new(40, 0),
+    );
+    pacer.update_throttle(&budget, "campaign_1", &at(0, 10));
+    let serve_rate = pacer.serve_rate("campaign_1");
+    assert!(serve_rate > 0.2 && serve_rate < 0.3);
+    assert!(pacer.serves(&budget, "campaign_1", &at(0, 10), 0.1));
+    assert!(!pacer.serves(&budget, "campaign_1", &at(0, 10), 0.5));
+
+    // not adjusted again until rate is observed for a while.
+    pacer.update_throttle(&budget, "campaign_1", &at(0, 10));
+    assert_eq!(pacer.serve_rate("campaign_1"), serve_rate);
+
+    // nothing delivered while throttled opens it up.
+    pacer.update_throttle(&budget, "campaign_1", &at(0, 20));
+    assert_eq!(pacer.serve_rate("campaign_1"), serve_rate * 2.0);
+
+    // each day starts unthrottled.
+    let tomorrow = at(0, 30) + Duration::days(1);
+    pacer.update_throttle(&budget, "campaign_1", &tomorrow);
+    assert_eq!(pacer.serve_rate("campaign_1"), 1.0);
+
+    // asap is never throttled.
+    let asap = CampaignBudget {
+        pacing: Pacing::Asap,
+        ..budget.clone()
+    };
+    pacer.update_throttle(&asap, "campaign_1", &at(0, 40));
+    assert_eq!(pacer.serve_rate("campaign_1"), 1.0);
+    assert!(pacer.serves(&asap, "campaign_1", &at(0, 40), 0.99));
+}
@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+
+use chrono_tz::Tz;
+use common::types::UserInfo;
+use maxminddb::{geoip2, Reader};
+use prisma_client_rust::chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use woothee::parser::Parser;
+
+/**
+ * Prefix of dimensions that are injected from the request itself instead of sent by caller.
+ * dimensions with this prefix sent on user_info are dropped, so caller can't fake them.
+ */
+pub const CONTEXT_PREFIX: &str = "ctx.";
+
+/**
+ * Which context dimensions placement gets, on details.context of its fetcher integration.
+ * ex) {"context": {"timezone": "Asia/Seoul", "time": true, "device": true, "geo": true}}
+ * geo looks up client ip, that is peer address of request. X-Forwarded-For is honored
+ * only when peer is one of TRUSTED_PROXIES of api server, see TrustedProxies.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextConfig {
+    // ctx.hour(0-23) and ctx.weekday(mon-sun) in timezone.
+    pub time: bool,
+    pub timezone: Tz,
+    // ctx.device, ctx.os, ctx.browser from User-Agent.
+    pub device: bool,
+    // ctx.country, ctx.region from client ip.
+    pub geo: bool,
+}
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            time: false,
+            timezone: Tz::UTC,
+            device: false,
+            geo: false,
+        }
+    }
+}
+#[derive(Deserialize)]
+struct ContextConfigJson {
+    #[serde(default)]
+    time: bool,
+    timezone: Option<String>,
+    #[serde(default)]
+    device: bool,
+    #[serde(default)]
+    geo: bool,
+}
+impl ContextConfig {
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let config: ContextConfigJson =
+            serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+        let timezone = match config.timezone {
+            None => Tz::UTC,
+            Some(timezone) => timezone
+                .parse::<Tz>()
+                .map_err(|_| format!("unknown timezone {}", timezone))?,
+        };
+        Ok(ContextConfig {
+            time: config.time,
+            timezone,
+            device: config.device,
+            geo: config.geo,
+        })
+    }
+    pub fn is_empty(&self) -> bool {
+        !self.time && !self.device && !self.geo
+    }
+}
+
+/**
+ * What api server knows about request, other than its body.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub user_agent: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+/**
+ * Proxies that api server is deployed behind, from TRUSTED_PROXIES(comma separated ips).
+ * X-Forwarded-For is set by caller as it likes, so it is only read when request comes
+ * from one of these, and none by default.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrustedProxies {
+    ips: HashSet<IpAddr>,
+}
+impl TrustedProxies {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut ips = HashSet::new();
+        for ip in s.split(',').map(str::trim).filter(|ip| !ip.is_empty()) {
+            let ip = ip
+                .parse::<IpAddr>()
+                .map_err(|_| format!("invalid trusted proxy {}", ip))?;
+            ips.insert(ip);
+        }
+        Ok(TrustedProxies { ips })
+    }
+    /**
+     * ip of client. proxies append to X-Forwarded-For, so it is read from the right,
+     * skipping trusted proxies, and the first one that is not trusted is the client.
+     */
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let peer = peer?;
+        if !self.ips.contains(&peer) {
+            return Some(peer);
+        }
+        let mut client = peer;
+        for addr in forwarded_for.unwrap_or("").rsplit(',') {
+            match parse_ip(addr.trim()) {
+                Some(ip) if self.ips.contains(&ip) => client = ip,
+                Some(ip) => return Some(ip),
+                None => break,
+            }
+        }
+        Some(client)
+    }
+}
+
+/**
+ * ip of client as given by proxy headers or peer address, with or without port.
+ */
+pub fn parse_ip(addr: &str) -> Option<IpAddr> {
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+/**
+ * Local MaxMind-format(GeoIP2/GeoLite2 City) database.
+ */
+pub struct GeoDatabase {
+    reader: Reader<Vec<u8>>,
+}
+impl GeoDatabase {
+    pub fn open(path: &str) -> Result<Self, String> {
+        Reader::open_readfile(path)
+            .map(|reader| GeoDatabase { reader })
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+    // (country, region). region is ISO 3166-2 code like KR-11.
+    pub fn lookup(&self, ip: IpAddr) -> (Option<String>, Option<String>) {
+        let city: geoip2::City = match self.reader.lookup(ip) {
+            Ok(city) => city,
+            Err(_) => return (None, None),
+        };
+        let country = city
+            .country
+            .and_then(|country| country.iso_code)
+            .map(String::from);
+        let region = city
+            .subdivisions
+            .and_then(|subdivisions| subdivisions.into_iter().next())
+            .and_then(|subdivision| subdivision.iso_code)
+            .zip(country.as_ref())
+            .map(|(subdivision, country)| format!("{}-{}", country, subdivision));
+        (country, region)
+    }
+}
+impl std::fmt::Debug for GeoDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GeoDatabase({})", self.reader.metadata.database_type)
+    }
+}
+
+fn insert(user_info: &mut UserInfo, dimension: &str, value: Option<String>) {
+    if let Some(value) = value.filter(|value| !value.is_empty() && value != "UNKNOWN") {
+        user_info.insert(
+            format!("{}{}", CONTEXT_PREFIX, dimension),
+            HashSet::from([value]),
+        );
+    }
+}
+
+/**
+ * context dimensions that are switched on by config. dimension that can't be
+ * known(ex: no User-Agent, ip not on database) is left out, so Missing can target it.
+ */
+pub fn context_dimensions(
+    config: &ContextConfig,
+    context: &RequestContext,
+    now: &DateTime<Utc>,
+    geo: Option<&GeoDatabase>,
+) -> UserInfo {
+    let mut user_info = UserInfo::new();
+    if config.time {
+        let local = now.with_timezone(&config.timezone);
+        insert(&mut user_info, "hour", Some(local.hour().to_string()));
+        let weekday = local.weekday().to_string().to_lowercase();
+        insert(&mut user_info, "weekday", Some(weekday));
+    }
+    if config.device {
+        if let Some(result) = context
+            .user_agent
+            .as_deref()
+            .and_then(|user_agent| Parser::new().parse(user_agent))
+        {
+            insert(&mut user_info, "device", Some(result.category.to_string()));
+            insert(&mut user_info, "os", Some(result.os.to_string()));
+            insert(&mut user_info, "browser", Some(result.name.to_string()));
+        }
+    }
+    if config.geo {
+        if let (Some(geo), Some(ip)) = (geo, context.ip) {
+            let (country, region) = geo.lookup(ip);
+            insert(&mut user_info, "country", country);
+            insert(&mut user_info, "region", region);
+        }
+    }
+    user_info
+}
+
+/**
+ * user_info json of request with context dimensions. ctx.* sent by caller is replaced,
+ * and values are arrays of string as parse_user_info reads them.
+ */
+pub fn with_context(user_info_json: &Value, dimensions: &UserInfo) -> Value {
+    let mut user_info = match user_info_json {
+        Value::Object(user_info) => user_info.clone(),
+        _ => serde_json::Map::new(),
+    };
+    user_info.retain(|dimension, _| !dimension.starts_with(CONTEXT_PREFIX));
+    for (dimension, values) in dimensions {
+        let mut values: Vec<&String> = values.iter().collect();
+        values.sort();
+        user_info.insert(dimension.clone(), serde_json::json!(values));
+    }
+    Value::Object(user_info)
+}
+
+#[cfg(test)]
+#[path = "./context_test.rs"]
+mod context_test;
@@ -0,0 +1,128 @@
+use super::*;
+
+use serde_json::json;
+
+const CHROME_ON_WINDOWS: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+fn now() -> DateTime<Utc> {
+    // sunday 23:30 in UTC, monday 08:30 in Asia/Seoul.
+    DateTime::parse_from_rfc3339("2026-10-18T23:30:00Z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+fn values(user_info: &UserInfo, dimension: &str) -> Option<Vec<String>> {
+    user_info
+        .get(dimension)
+        .map(|values| values.iter().cloned().collect())
+}
+
+#[test]
+fn test_context_config() {
+    let config =
+        ContextConfig::from_json(&json!({"timezone": "Asia/Seoul", "time": true})).unwrap();
+    assert_eq!(config.timezone, Tz::Asia__Seoul);
+    assert!(config.time && !config.device && !config.geo);
+    assert!(ContextConfig::from_json(&json!({})).unwrap().is_empty());
+    assert_eq!(
+        ContextConfig::from_json(&json!({"timezone": "Mars/Olympus"})),
+        Err(String::from("unknown timezone Mars/Olympus"))
+    );
+}
+
+#[test]
+fn test_context_dimensions() {
+    let context = RequestContext {
+        user_agent: Some(String::from(CHROME_ON_WINDOWS)),
+        ip: Some("1.1.1.1".parse().unwrap()),
+    };
+    let config = ContextConfig {
+        time: true,
+        timezone: Tz::Asia__Seoul,
+        device: true,
+        geo: true,
+    };
+    let dimensions = context_dimensions(&config, &context, &now(), None);
+    assert_eq!(
+        values(&dimensions, "ctx.hour"),
+        Some(vec![String::from("8")])
+    );
+    assert_eq!(
+        values(&dimensions, "ctx.weekday"),
+        Some(vec![String::from("mon")])
+    );
+    assert_eq!(
+        values(&dimensions, "ctx.device"),
+        Some(vec![String::from("pc")])
+    );
+    assert_eq!(
+        values(&dimensions, "ctx.browser"),
+        Some(vec![String::from("Chrome")])
+    );
+    assert!(dimensions.contains_key("ctx.os"));
+    // geo is left out without database.
+    assert!(!dimensions.contains_key("ctx.country"));
+
+    // each group is switched on its own, and unknown values are left out.
+    let config = ContextConfig {
+        device: true,
+        ..Default::default()
+    };
+    let context = RequestContext {
+        user_agent: Some(String::from("curl-like-unknown-agent")),
+        ip: None,
+    };
+    let dimensions = context_dimensions(&config, &context, &now(), None);
+    assert!(!dimensions.contains_key("ctx.hour"));
+    assert!(!dimensions.contains_key("ctx.device"));
+}
+
+#[test]
+fn test_with_context() {
+    let dimensions =
+        UserInfo::from([(String::from("ctx.hour"), HashSet::from([String::from("8")]))]);
+    assert_eq!(
+        with_context(
+            &json!({"age": "10", "ctx.hour": "3", "ctx.country": "KR"}),
+            &dimensions
+        ),
+        json!({"age": "10", "ctx.hour": ["8"]})
+    );
+    assert_eq!(with_context(&json!(null), &UserInfo::new()), json!({}));
+}
+
+#[test]
+fn test_parse_ip() {
+    assert_eq!(parse_ip("1.2.3.4"), Some("1.2.3.4".parse().unwrap()));
+    assert_eq!(parse_ip("1.2.3.4:8080"), Some("1.2.3.4".parse().unwrap()));
+    assert_eq!(parse_ip("[::1]:8080"), Some("::1".parse().unwrap()));
+    assert_eq!(parse_ip("unknown"), None);
+}
+
+#[test]
+fn test_trusted_proxies() {
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+    let trusted = TrustedProxies::parse("10.0.0.1, 10.0.0.2").unwrap();
+    assert!(TrustedProxies::parse("10.0.0.0/8").is_err());
+    assert_eq!(
+        TrustedProxies::parse("").unwrap(),
+        TrustedProxies::default()
+    );
+
+    // header from peer that is not trusted is ignored.
+    let peer = Some(ip("1.2.3.4"));
+    assert_eq!(
+        TrustedProxies::default().client_ip(peer, Some("5.6.7.8")),
+        peer
+    );
+    assert_eq!(trusted.client_ip(peer, Some("5.6.7.8")), peer);
+
+    // behind trusted proxies, the rightmost ip that is not trusted is the client.
+    let proxy = Some(ip("10.0.0.1"));
+    assert_eq!(
+        trusted.client_ip(proxy, Some("9.9.9.9, 5.6.7.8, 10.0.0.2")),
+        Some(ip("5.6.7.8"))
+    );
+    assert_eq!(trusted.client_ip(proxy, None), proxy);
+    assert_eq!(trusted.client_ip(proxy, Some("unknown")), proxy);
+    assert_eq!(trusted.client_ip(None, Some("5.6.7.8")), None);
+}
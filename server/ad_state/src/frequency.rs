@@ -0,0 +1,213 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use prisma_client_rust::chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// users without impression in retention are swept once per this many records.
+const SWEEP_EVERY: usize = 10000;
+// clock of event server can be this much ahead of api server.
+const MAX_CLOCK_SKEW_SECONDS: i64 = 60;
+
+/**
+ * Level of ad that impressions are counted on.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapLevel {
+    Campaign,
+    AdGroup,
+    AdSet,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CapKey {
+    pub level: CapLevel,
+    pub id: String,
+}
+impl CapKey {
+    pub fn new(level: CapLevel, id: &str) -> Self {
+        CapKey {
+            level,
+            id: String::from(id),
+        }
+    }
+}
+
+/**
+ * At most `impressions` per user in the last `window_seconds`, on ad of level.
+ * cap without id applies to every ad of level on placement, each counted on its own.
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrequencyCap {
+    pub level: CapLevel,
+    pub id: Option<String>,
+    pub impressions: usize,
+    pub window_seconds: i64,
+}
+impl FrequencyCap {
+    fn applies_to(&self, key: &CapKey) -> bool {
+        self.level == key.level && self.id.as_ref().map(|id| *id == key.id).unwrap_or(true)
+    }
+}
+
+/**
+ * Frequency caps of placement, on details.frequencyCaps of its fetcher integrations.
+ * ex) {"frequencyCaps": [{"level": "campaign", "impressions": 3, "windowSeconds": 86400},
+ *      {"level": "ad_set", "id": "ad_set_1", "impressions": 1, "windowSeconds": 3600}]}
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrequencyCaps {
+    pub caps: Vec<FrequencyCap>,
+}
+impl FrequencyCaps {
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let caps: Vec<FrequencyCap> =
+            serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+        for cap in caps.iter() {
+            if cap.impressions == 0 || cap.window_seconds <= 0 {
+                return Err(format!(
+                    "impressions and windowSeconds of {:?} cap must be positive",
+                    cap.level
+                ));
+            }
+        }
+        Ok(FrequencyCaps { caps })
+    }
+    pub fn extend(&mut self, other: FrequencyCaps) {
+        self.caps.extend(other.caps);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.caps.is_empty()
+    }
+    /**
+     * user already has as many impressions on ad of key as any cap on it allows.
+     */
+    pub fn is_capped(
+        &self,
+        store: &dyn ImpressionStore,
+        user_id: &str,
+        key: &CapKey,
+        now: &DateTime<Utc>,
+    ) -> bool {
+        self.caps
+            .iter()
+            .filter(|cap| cap.applies_to(key))
+            .any(|cap| {
+                let since = *now - Duration::seconds(cap.window_seconds);
+                store.count_since(user_id, key, &since) >= cap.impressions
+            })
+    }
+}
+
+/**
+ * Impression event as it is published to event server. `which` is id of creative or ad set.
+ */
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImpressionEvent {
+    // epoch time in millis.
+    pub when: u64,
+    pub who: String,
+    pub what: String,
+    pub which: String,
+}
+impl ImpressionEvent {
+    // time of event. event from the future is dropped, `when` is given by caller as it likes.
+    pub fn timestamp(&self, now: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        Utc.timestamp_millis_opt(self.when as i64)
+            .single()
+            .filter(|at| *at <= *now + Duration::seconds(MAX_CLOCK_SKEW_SECONDS))
+    }
+}
+
+/**
+ * Impressions of users that frequency caps are checked against.
+ * in-process by default, a shared store can implement it for multiple api servers.
+ */
+pub trait ImpressionStore: std::fmt::Debug + Send + Sync {
+    // impressions older than retention are expired relative to now, not to at.
+    fn record(&self, user_id: &str, key: &CapKey, at: &DateTime<Utc>, now: &DateTime<Utc>);
+    // impressions of user on key at or after since.
+    fn count_since(&self, user_id: &str, key: &CapKey, since: &DateTime<Utc>) -> usize;
+}
+
+#[derive(Debug, Default)]
+struct Impressions {
+    // user id -> key -> impression times in millis, ascending.
+    users: HashMap<String, HashMap<CapKey, VecDeque<i64>>>,
+    records: usize,
+}
+
+/**
+ * ImpressionStore on memory of api server. impressions older than retention are dropped,
+ * so window longer than retention only counts the retained ones.
+ */
+#[derive(Debug)]
+pub struct InMemoryImpressionStore {
+    retention: Duration,
+    impressions: Mutex<Impressions>,
+}
+impl Default for InMemoryImpressionStore {
+    fn default() -> Self {
+        Self::new(Duration::days(30))
+    }
+}
+impl InMemoryImpressionStore {
+    pub fn new(retention: Duration) -> Self {
+        InMemoryImpressionStore {
+            retention,
+            impressions: Mutex::new(Impressions::default()),
+        }
+    }
+    pub fn user_count(&self) -> usize {
+        self.impressions.lock().unwrap().users.len()
+    }
+}
+impl ImpressionStore for InMemoryImpressionStore {
+    fn record(&self, user_id: &str, key: &CapKey, at: &DateTime<Utc>, now: &DateTime<Utc>) {
+        let expired = (*now - self.retention).timestamp_millis();
+        let at = at.timestamp_millis();
+        if at < expired {
+            return;
+        }
+        let mut impressions = self.impressions.lock().unwrap();
+
+        let times = impressions
+            .users
+            .entry(String::from(user_id))
+            .or_default()
+            .entry(key.clone())
+            .or_default();
+        // events can arrive out of order.
+        let position = times.partition_point(|time| *time <= at);
+        times.insert(position, at);
+        while times.front().map(|time| *time < expired).unwrap_or(false) {
+            times.pop_front();
+        }
+
+        impressions.records += 1;
+        if impressions.records >= SWEEP_EVERY {
+            impressions.records = 0;
+            for keys in impressions.users.values_mut() {
+                keys.retain(|_, times| times.back().map(|time| *time >= expired).unwrap_or(false));
+            }
+            impressions.users.retain(|_, keys| !keys.is_empty());
+        }
+    }
+    fn count_since(&self, user_id: &str, key: &CapKey, since: &DateTime<Utc>) -> usize {
+        let since = since.timestamp_millis();
+        let impressions = self.impressions.lock().unwrap();
+        impressions
+            .users
+            .get(user_id)
+            .and_then(|keys| keys.get(key))
+            .map(|times| times.len() - times.partition_point(|time| *time < since))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+#[path = "./frequency_test.rs"]
+mod frequency_test;
@@ -0,0 +1,113 @@
+use super::*;
+
+use serde_json::json;
+
+fn at(seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(1700000000 + seconds, 0).unwrap()
+}
+
+#[test]
+fn test_from_json() {
+    let caps = FrequencyCaps::from_json(&json!([
+        {"level": "campaign", "impressions": 3, "windowSeconds": 86400},
+        {"level": "ad_set", "id": "ad_set_1", "impressions": 1, "windowSeconds": 3600}
+    ]))
+    .unwrap();
+    assert_eq!(caps.caps.len(), 2);
+    assert_eq!(caps.caps[1].level, CapLevel::AdSet);
+    assert_eq!(caps.caps[1].id, Some(String::from("ad_set_1")));
+
+    assert!(FrequencyCaps::from_json(&json!({"level": "campaign"})).is_err());
+    assert!(FrequencyCaps::from_json(
+        &json!([{"level": "creative", "impressions": 1, "windowSeconds": 60}])
+    )
+    .is_err());
+    assert!(FrequencyCaps::from_json(
+        &json!([{"level": "ad_group", "impressions": 0, "windowSeconds": 60}])
+    )
+    .is_err());
+}
+
+#[test]
+fn test_in_memory_store() {
+    let store = InMemoryImpressionStore::new(Duration::seconds(100));
+    let key = CapKey::new(CapLevel::AdGroup, "ad_group_1");
+    store.record("user_1", &key, &at(10), &at(10));
+    store.record("user_1", &key, &at(30), &at(30));
+    // out of order.
+    store.record("user_1", &key, &at(20), &at(20));
+    store.record("user_2", &key, &at(20), &at(20));
+
+    assert_eq!(store.count_since("user_1", &key, &at(0)), 3);
+    assert_eq!(store.count_since("user_1", &key, &at(20)), 2);
+    assert_eq!(store.count_since("user_1", &key, &at(31)), 0);
+    assert_eq!(
+        store.count_since(
+            "user_1",
+            &CapKey::new(CapLevel::Campaign, "ad_group_1"),
+            &at(0)
+        ),
+        0
+    );
+    assert_eq!(store.count_since("user_3", &key, &at(0)), 0);
+
+    // impressions older than retention are dropped.
+    store.record("user_1", &key, &at(125), &at(125));
+    assert_eq!(store.count_since("user_1", &key, &at(0)), 2);
+    assert_eq!(store.user_count(), 2);
+}
+
+#[test]
+fn test_is_capped() {
+    let caps = FrequencyCaps::from_json(&json!([
+        {"level": "ad_group", "impressions": 2, "windowSeconds": 60},
+        {"level": "campaign", "id": "campaign_1", "impressions": 3, "windowSeconds": 3600}
+    ]))
+    .unwrap();
+    let store = InMemoryImpressionStore::default();
+    let ad_group = CapKey::new(CapLevel::AdGroup, "ad_group_1");
+    let campaign = CapKey::new(CapLevel::Campaign, "campaign_1");
+    let other_campaign = CapKey::new(CapLevel::Campaign, "campaign_2");
+    for seconds in [0, 30] {
+        store.record("user_1", &ad_group, &at(seconds), &at(seconds));
+        store.record("user_1", &campaign, &at(seconds), &at(seconds));
+        store.record("user_1", &other_campaign, &at(seconds), &at(seconds));
+    }
+
+    assert!(caps.is_capped(&store, "user_1", &ad_group, &at(40)));
+    // window is rolling, impression at 0 is out of it.
+    assert!(!caps.is_capped(&store, "user_1", &ad_group, &at(61)));
+    assert!(!caps.is_capped(&store, "user_2", &ad_group, &at(40)));
+
+    assert!(!caps.is_capped(&store, "user_1", &campaign, &at(40)));
+    store.record("user_1", &campaign, &at(40), &at(40));
+    assert!(caps.is_capped(&store, "user_1", &campaign, &at(50)));
+    // cap with id only applies to that campaign.
+    store.record("user_1", &other_campaign, &at(40), &at(40));
+    assert!(!caps.is_capped(&store, "user_1", &other_campaign, &at(50)));
+}
+
+#[test]
+fn test_future_impressions() {
+    let event = |seconds: i64| ImpressionEvent {
+        when: at(seconds).timestamp_millis() as u64,
+        who: String::from("user_1"),
+        what: String::from("impression"),
+        which: String::from("creative_1"),
+    };
+    // a bit ahead is clock skew, far ahead is dropped.
+    assert_eq!(event(30).timestamp(&at(0)), Some(at(30)));
+    assert_eq!(event(86400).timestamp(&at(0)), None);
+
+    // retention is counted from now, so impression with future time doesn't expire others.
+    let store = InMemoryImpressionStore::new(Duration::seconds(100));
+    let key = CapKey::new(CapLevel::AdGroup, "ad_group_1");
+    store.record("user_1", &key, &at(10), &at(10));
+    store.record("user_1", &key, &at(1000000), &at(20));
+    assert_eq!(store.count_since("user_1", &key, &at(0)), 2);
+
+    // impression already out of retention is not recorded.
+    store.record("user_2", &key, &at(10), &at(200));
+    assert_eq!(store.count_since("user_2", &key, &at(0)), 0);
+    assert_eq!(store.user_count(), 1);
+}
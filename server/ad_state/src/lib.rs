@@ -1,2 +1,8 @@
 pub mod ad_state;
 pub mod ad_state_builder;
+pub mod budget;
+pub mod context;
+pub mod frequency;
+pub mod meta_source;
+pub mod schedule;
+pub mod sync_health;
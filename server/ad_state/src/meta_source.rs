@@ -0,0 +1,85 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use common::db::PrismaClient;
+use prisma_client_rust::{raw, QueryError};
+use serde::Deserialize;
+
+/**
+ * Ids of ad meta rows that exist on source at a moment. incremental sync only sees rows
+ * updated after the last one, so rows that are gone are found by comparing against these.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LiveIds {
+    pub services: HashSet<String>,
+    pub placements: HashSet<String>,
+    pub campaigns: HashSet<String>,
+    pub ad_groups: HashSet<String>,
+    pub creatives: HashSet<String>,
+    pub contents: HashSet<String>,
+    pub content_types: HashSet<String>,
+    pub segments: HashSet<String>,
+    pub customsets: HashSet<String>,
+    pub ad_sets: HashSet<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LiveId {
+    entity: String,
+    id: String,
+}
+
+impl LiveIds {
+    fn insert(&mut self, entity: &str, id: String) {
+        let ids = match entity {
+            "Service" => &mut self.services,
+            "Placement" => &mut self.placements,
+            "Campaign" => &mut self.campaigns,
+            "AdGroup" => &mut self.ad_groups,
+            "Creative" => &mut self.creatives,
+            "Content" => &mut self.contents,
+            "ContentType" => &mut self.content_types,
+            "Segment" => &mut self.segments,
+            "Customset" => &mut self.customsets,
+            "AdSet" => &mut self.ad_sets,
+            _ => return,
+        };
+        ids.insert(id);
+    }
+}
+
+/**
+ * Source that deletions of ad meta are reconciled against.
+ */
+#[async_trait]
+pub trait MetaSource: Send + Sync {
+    async fn live_ids(&self) -> Result<LiveIds, QueryError>;
+}
+
+#[async_trait]
+impl MetaSource for PrismaClient {
+    async fn live_ids(&self) -> Result<LiveIds, QueryError> {
+        let ids: Vec<LiveId> = self
+            ._query_raw(raw!(
+                r#"
+            SELECT 'Service' AS "entity", "id" FROM "Service"
+            UNION ALL SELECT 'Placement', "id" FROM "Placement"
+            UNION ALL SELECT 'Campaign', "id" FROM "Campaign"
+            UNION ALL SELECT 'AdGroup', "id" FROM "AdGroup"
+            UNION ALL SELECT 'Creative', "id" FROM "Creative"
+            UNION ALL SELECT 'Content', "id" FROM "Content"
+            UNION ALL SELECT 'ContentType', "id" FROM "ContentType"
+            UNION ALL SELECT 'Segment', "id" FROM "Segment"
+            UNION ALL SELECT 'Customset', "id" FROM "Customset"
+            UNION ALL SELECT 'AdSet', "id" FROM "AdSet"
+        "#
+            ))
+            .exec()
+            .await?;
+        let mut live_ids = LiveIds::default();
+        for LiveId { entity, id } in ids {
+            live_ids.insert(&entity, id);
+        }
+        Ok(live_ids)
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use chrono_tz::Tz;
+use prisma_client_rust::chrono::{DateTime, Datelike, FixedOffset, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/**
+ * Where campaign is in its flight and schedule at a moment.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleStatus {
+    BeforeFlight,
+    AfterFlight,
+    // in flight, but outside of hours on dayparting.
+    OffSchedule,
+    Serving,
+}
+
+/**
+ * flight of campaign is [started_at, end_at), either side is open when not given.
+ */
+pub fn flight_status(
+    started_at: Option<&DateTime<FixedOffset>>,
+    end_at: Option<&DateTime<FixedOffset>>,
+    now: &DateTime<Utc>,
+) -> ScheduleStatus {
+    if started_at
+        .map(|started_at| now < started_at)
+        .unwrap_or(false)
+    {
+        ScheduleStatus::BeforeFlight
+    } else if end_at.map(|end_at| now >= end_at).unwrap_or(false) {
+        ScheduleStatus::AfterFlight
+    } else {
+        ScheduleStatus::Serving
+    }
+}
+
+/**
+ * Hours of week that campaign serves. weekday that is not given doesn't serve.
+ * ex) {"mon": [[9, 18]], "sat": [[0, 24]]} serves 09:00-18:00 on monday and all day on saturday.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WeeklySchedule {
+    // bit h of weekday(from monday) is set when hour h serves.
+    hours: [u32; 7],
+}
+impl WeeklySchedule {
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let weekdays: HashMap<String, Vec<(u32, u32)>> =
+            serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+        let mut schedule = WeeklySchedule::default();
+        for (weekday, ranges) in weekdays {
+            let day = weekday
+                .parse::<Weekday>()
+                .map_err(|_| format!("unknown weekday {}", weekday))?
+                .num_days_from_monday() as usize;
+            for (start, end) in ranges {
+                if start >= end || end > 24 {
+                    return Err(format!("invalid hours [{}, {}) on {}", start, end, weekday));
+                }
+                for hour in start..end {
+                    schedule.hours[day] |= 1 << hour;
+                }
+            }
+        }
+        Ok(schedule)
+    }
+    pub fn is_serving<T: Datelike + Timelike>(&self, local: &T) -> bool {
+        let day = local.weekday().num_days_from_monday() as usize;
+        self.hours[day] & (1 << local.hour()) != 0
+    }
+}
+
+#[derive(Deserialize)]
+struct DaypartingJson {
+    timezone: Option<String>,
+    campaigns: HashMap<String, Value>,
+}
+
+/**
+ * Dayparting of campaigns on placement, on details.dayparting of its CREATIVE_FETCHER.
+ * hours are in timezone of dayparting, or of placement's context when it is not given.
+ * ex) {"dayparting": {"timezone": "Asia/Seoul", "campaigns": {"campaign_1": {"mon": [[9, 18]]}}}}
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dayparting {
+    pub timezone: Tz,
+    pub campaigns: HashMap<String, WeeklySchedule>,
+}
+impl Dayparting {
+    pub fn from_json(value: &Value, default_timezone: Tz) -> Result<Self, String> {
+        let config: DaypartingJson =
+            serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+        let timezone = match config.timezone {
+            None => default_timezone,
+            Some(timezone) => timezone
+                .parse::<Tz>()
+                .map_err(|_| format!("unknown timezone {}", timezone))?,
+        };
+        let mut campaigns = HashMap::new();
+        for (campaign_id, value) in config.campaigns {
+            let schedule = WeeklySchedule::from_json(&value)
+                .map_err(|e| format!("campaign {}: {}", campaign_id, e))?;
+            campaigns.insert(campaign_id, schedule);
+        }
+        Ok(Dayparting {
+            timezone,
+            campaigns,
+        })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.campaigns.is_empty()
+    }
+    // campaign without schedule serves all day.
+    pub fn is_serving(&self, campaign_id: &str, now: &DateTime<Utc>) -> bool {
+        match self.campaigns.get(campaign_id) {
+            None => true,
+            Some(schedule) => schedule.is_serving(&now.with_timezone(&self.timezone)),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./schedule_test.rs"]
+mod schedule_test;
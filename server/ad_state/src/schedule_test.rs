@@ -0,0 +1,77 @@
+use super::*;
+
+use prisma_client_rust::chrono::TimeZone;
+use serde_json::json;
+
+fn utc(rfc3339: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(rfc3339)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+#[test]
+fn test_flight_status() {
+    let started_at = DateTime::parse_from_rfc3339("2023-06-01T00:00:00+09:00").unwrap();
+    let end_at = DateTime::parse_from_rfc3339("2023-07-01T00:00:00+09:00").unwrap();
+    let status = |now: &str| flight_status(Some(&started_at), Some(&end_at), &utc(now));
+
+    assert_eq!(status("2023-05-31T14:59:59Z"), ScheduleStatus::BeforeFlight);
+    assert_eq!(status("2023-05-31T15:00:00Z"), ScheduleStatus::Serving);
+    assert_eq!(status("2023-06-30T14:59:59Z"), ScheduleStatus::Serving);
+    assert_eq!(status("2023-06-30T15:00:00Z"), ScheduleStatus::AfterFlight);
+
+    // open ended.
+    assert_eq!(
+        flight_status(None, None, &utc("2023-06-30T15:00:00Z")),
+        ScheduleStatus::Serving
+    );
+    assert_eq!(
+        flight_status(Some(&started_at), None, &utc("2030-01-01T00:00:00Z")),
+        ScheduleStatus::Serving
+    );
+}
+
+#[test]
+fn test_weekly_schedule() {
+    let schedule =
+        WeeklySchedule::from_json(&json!({"mon": [[9, 12], [13, 18]], "Sat": [[0, 24]]})).unwrap();
+    // 2023-06-05 is monday.
+    let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2023, 6, day, hour, 30, 0).unwrap();
+    assert!(!schedule.is_serving(&at(5, 8)));
+    assert!(schedule.is_serving(&at(5, 9)));
+    assert!(!schedule.is_serving(&at(5, 12)));
+    assert!(schedule.is_serving(&at(5, 17)));
+    assert!(!schedule.is_serving(&at(5, 18)));
+    assert!(!schedule.is_serving(&at(6, 10)));
+    assert!(schedule.is_serving(&at(10, 0)));
+    assert!(schedule.is_serving(&at(10, 23)));
+
+    assert!(WeeklySchedule::from_json(&json!({"someday": [[0, 1]]})).is_err());
+    assert!(WeeklySchedule::from_json(&json!({"mon": [[9, 9]]})).is_err());
+    assert!(WeeklySchedule::from_json(&json!({"mon": [[20, 25]]})).is_err());
+}
+
+#[test]
+fn test_dayparting() {
+    let dayparting = Dayparting::from_json(
+        &json!({"timezone": "Asia/Seoul", "campaigns": {"campaign_1": {"mon": [[9, 18]]}}}),
+        Tz::UTC,
+    )
+    .unwrap();
+    // monday 09:30 in Seoul is 00:30 in UTC.
+    assert!(dayparting.is_serving("campaign_1", &utc("2023-06-05T00:30:00Z")));
+    assert!(!dayparting.is_serving("campaign_1", &utc("2023-06-05T09:30:00Z")));
+    assert!(dayparting.is_serving("campaign_2", &utc("2023-06-05T09:30:00Z")));
+
+    // timezone of placement by default.
+    let dayparting = Dayparting::from_json(
+        &json!({"campaigns": {"campaign_1": {"mon": [[9, 18]]}}}),
+        Tz::Asia__Seoul,
+    )
+    .unwrap();
+    assert_eq!(dayparting.timezone, Tz::Asia__Seoul);
+
+    assert!(
+        Dayparting::from_json(&json!({"timezone": "Mars/Base", "campaigns": {}}), Tz::UTC).is_err()
+    );
+}
@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration as StdDuration;
+
+use prisma_client_rust::chrono::{DateTime, Duration, Utc};
+use prisma_client_rust::QueryError;
+use serde::Serialize;
+
+/**
+ * What ad meta sync fetches, each is retried and reported on its own.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncEntity {
+    Services,
+    Placements,
+    Campaigns,
+    Segments,
+    AdGroups,
+    Creatives,
+    Contents,
+    ContentTypes,
+    AdSets,
+    Customsets,
+    Providers,
+    Deliveries,
+    LiveIds,
+}
+impl fmt::Display for SyncEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SyncEntity::Services => "services",
+            SyncEntity::Placements => "placements",
+            SyncEntity::Campaigns => "campaigns",
+            SyncEntity::Segments => "segments",
+            SyncEntity::AdGroups => "ad_groups",
+            SyncEntity::Creatives => "creatives",
+            SyncEntity::Contents => "contents",
+            SyncEntity::ContentTypes => "content_types",
+            SyncEntity::AdSets => "ad_sets",
+            SyncEntity::Customsets => "customsets",
+            SyncEntity::Providers => "providers",
+            SyncEntity::Deliveries => "deliveries",
+            SyncEntity::LiveIds => "live_ids",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/**
+ * Fetch that still fails after retries. state being loaded is dropped, and the last
+ * good one keeps serving.
+ */
+#[derive(Debug)]
+pub struct SyncError {
+    pub entity: SyncEntity,
+    pub error: QueryError,
+}
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} are not fetched: {}", self.entity, self.error)
+    }
+}
+impl std::error::Error for SyncError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EntityHealth {
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+    // failures since last success, 0 when the last fetch succeeded.
+    pub consecutive_failures: u32,
+    pub failures: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    // when the last whole load was applied to serving state.
+    pub last_loaded_at: Option<DateTime<Utc>>,
+    // serving state is older than it should be, or never loaded.
+    pub stale: bool,
+    pub entities: BTreeMap<SyncEntity, EntityHealth>,
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    last_loaded_at: Option<DateTime<Utc>>,
+    entities: BTreeMap<SyncEntity, EntityHealth>,
+}
+
+/**
+ * Health of ad meta sync, shared by every AdState cloned from the one it is created on.
+ * state is stale when it isn't loaded for stale_after, that is a few sync periods.
+ */
+#[derive(Debug)]
+pub struct SyncHealth {
+    stale_after: Duration,
+    health: Mutex<Health>,
+}
+impl Default for SyncHealth {
+    fn default() -> Self {
+        Self::new(Duration::minutes(3))
+    }
+}
+impl SyncHealth {
+    pub fn new(stale_after: Duration) -> Self {
+        SyncHealth {
+            stale_after,
+            health: Mutex::new(Health::default()),
+        }
+    }
+    pub fn record_success(&self, entity: SyncEntity, now: &DateTime<Utc>) {
+        let mut health = self.health.lock().unwrap();
+        let entity_health = health.entities.entry(entity).or_default();
+        entity_health.last_success_at = Some(*now);
+        entity_health.consecutive_failures = 0;
+    }
+    pub fn record_failure(&self, entity: SyncEntity, error: &str, now: &DateTime<Utc>) {
+        let mut health = self.health.lock().unwrap();
+        let entity_health = health.entities.entry(entity).or_default();
+        entity_health.last_failure_at = Some(*now);
+        entity_health.consecutive_failures += 1;
+        entity_health.failures += 1;
+        entity_health.last_error = Some(String::from(error));
+    }
+    pub fn record_load(&self, now: &DateTime<Utc>) {
+        self.health.lock().unwrap().last_loaded_at = Some(*now);
+    }
+    pub fn is_stale(&self, now: &DateTime<Utc>) -> bool {
+        let health = self.health.lock().unwrap();
+        Self::is_stale_at(&health, self.stale_after, now)
+    }
+    pub fn report(&self, now: &DateTime<Utc>) -> SyncReport {
+        let health = self.health.lock().unwrap();
+        SyncReport {
+            last_loaded_at: health.last_loaded_at,
+            stale: Self::is_stale_at(&health, self.stale_after, now),
+            entities: health.entities.clone(),
+        }
+    }
+    fn is_stale_at(health: &Health, stale_after: Duration, now: &DateTime<Utc>) -> bool {
+        health
+            .last_loaded_at
+            .map(|last_loaded_at| *now - last_loaded_at > stale_after)
+            .unwrap_or(true)
+    }
+}
+
+/**
+ * Fetch is tried up to attempts times, waiting backoff that doubles from initial_backoff
+ * up to max_backoff in between.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub initial_backoff: StdDuration,
+    pub max_backoff: StdDuration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            initial_backoff: StdDuration::from_millis(200),
+            max_backoff: StdDuration::from_secs(5),
+        }
+    }
+}
+impl RetryPolicy {
+    // backoff after retry-th failure, from 0.
+    pub fn backoff(&self, retry: u32) -> StdDuration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/**
+ * every attempt is recorded on health of entity, the last error is returned when
+ * all of them fail.
+ */
+pub async fn retry<T, E, F, Fut>(
+    health: &SyncHealth,
+    entity: SyncEntity,
+    policy: &RetryPolicy,
+    mut fetch: F,
+) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut failures = 0;
+    loop {
+        match fetch().await {
+            Ok(value) => {
+                health.record_success(entity, &Utc::now());
+                return Ok(value);
+            }
+            Err(e) => {
+                health.record_failure(entity, &e.to_string(), &Utc::now());
+                failures += 1;
+                if failures >= policy.attempts {
+                    return Err(e);
+                }
+                println!(
+                    "{} are not fetched, retry {}/{}: {}",
+                    entity,
+                    failures,
+                    policy.attempts - 1,
+                    e
+                );
+                tokio::time::sleep(policy.backoff(failures - 1)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./sync_health_test.rs"]
+mod sync_health_test;
@@ -0,0 +1,75 @@
+use super::*;
+
+use std::cell::Cell;
+
+fn policy() -> RetryPolicy {
+    RetryPolicy {
+        attempts: 3,
+        initial_backoff: StdDuration::from_millis(1),
+        max_backoff: StdDuration::from_millis(2),
+    }
+}
+
+#[tokio::test]
+async fn test_retry() {
+    let health = SyncHealth::default();
+    let calls = Cell::new(0);
+    let result: Result<usize, String> = retry(&health, SyncEntity::AdGroups, &policy(), || {
+        calls.set(calls.get() + 1);
+        let calls = calls.get();
+        async move {
+            if calls < 3 {
+                Err(format!("connection reset {}", calls))
+            } else {
+                Ok(calls)
+            }
+        }
+    })
+    .await;
+    assert_eq!(result, Ok(3));
+
+    let report = health.report(&Utc::now());
+    let ad_groups = &report.entities[&SyncEntity::AdGroups];
+    assert_eq!(ad_groups.failures, 2);
+    assert_eq!(ad_groups.consecutive_failures, 0);
+    assert_eq!(
+        ad_groups.last_error,
+        Some(String::from("connection reset 2"))
+    );
+    assert!(ad_groups.last_success_at.is_some());
+
+    // gives up after attempts.
+    let calls = Cell::new(0);
+    let result: Result<(), String> = retry(&health, SyncEntity::Creatives, &policy(), || {
+        calls.set(calls.get() + 1);
+        async { Err(String::from("timeout")) }
+    })
+    .await;
+    assert_eq!(result, Err(String::from("timeout")));
+    assert_eq!(calls.get(), 3);
+    let report = health.report(&Utc::now());
+    let creatives = &report.entities[&SyncEntity::Creatives];
+    assert_eq!(creatives.consecutive_failures, 3);
+    assert_eq!(creatives.last_success_at, None);
+}
+
+#[test]
+fn test_backoff() {
+    let policy = RetryPolicy::default();
+    assert_eq!(policy.backoff(0), StdDuration::from_millis(200));
+    assert_eq!(policy.backoff(2), StdDuration::from_millis(800));
+    assert_eq!(policy.backoff(10), StdDuration::from_secs(5));
+}
+
+#[test]
+fn test_stale() {
+    let health = SyncHealth::new(Duration::minutes(3));
+    let now = Utc::now();
+    // never loaded.
+    assert!(health.is_stale(&now));
+
+    health.record_load(&now);
+    assert!(!health.is_stale(&(now + Duration::minutes(3))));
+    assert!(health.is_stale(&(now + Duration::minutes(4))));
+    assert_eq!(health.report(&now).last_loaded_at, Some(now));
+}
@@ -1,20 +1,27 @@
 use actix_cors::Cors;
 use actix_web::{
     get,
+    http::header,
     middleware::Logger,
     post,
     web::{self},
-    App, HttpResponse, HttpServer, Responder,
+    App, HttpRequest, HttpResponse, HttpServer, Responder,
 };
 use ad_state::{
     ad_state::{AdSetFeedback, AdState, CreativeFeedback},
     ad_state_builder::load,
+    context::{GeoDatabase, RequestContext, TrustedProxies},
+    frequency::ImpressionEvent,
+    sync_health::{SyncError, SyncHealth},
 };
 use arc_swap::ArcSwap;
 use common::db::{self, PrismaClient};
 use dotenv::dotenv;
+use filter::{dsl, segment::resolve_segments, serde as TargetFilterSerde};
+use integrations::user_feature::{count_population, sample_user_features};
+use prisma_client_rust::chrono::{self, Utc};
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::{collections::HashSet, env, sync::Arc, time::Duration};
 use tokio::{runtime::Builder, time};
 
@@ -27,28 +34,60 @@ struct UserFeatureRequest {
 struct Request {
     service_id: String,
     placement_id: String,
+    // checked against user lists(Customset) for InUserList.
+    user_id: Option<String>,
     user_info: Value,
     top_k: Option<usize>,
 }
 
+#[derive(Deserialize)]
+struct ExplainRequest {
+    placement_id: String,
+    ad_group_id: String,
+    user_id: Option<String>,
+    user_info: Value,
+}
+
+#[derive(Deserialize)]
+struct ParseFilterRequest {
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct PopulationRequest {
+    version: String,
+    // jsonlogic, as it is stored on AdGroup.filter.
+    filter: Value,
+}
+
+#[derive(Deserialize)]
+struct OverlapQuery {
+    // version of UserFeature to sample, the latest one by default.
+    version: Option<String>,
+    sample_size: Option<usize>,
+}
+
 #[derive(Deserialize)]
 struct SMSRequest {
     placement_id: String,
     payload: Value,
 }
 // copy prev shared state into new struct on heap. then atomically replace Arc using ArcSwap
+// prev keeps serving when load fails, new one is half updated.
 async fn load_ad_meta(
     data: web::Data<ArcSwap<Arc<AdState>>>,
     client: web::Data<PrismaClient>,
-) -> () {
+) -> Result<(), SyncError> {
     let prev = data.load();
     let mut new_ad_state = AdState {
         ..prev.as_ref().as_ref().clone()
     };
 
-    load(&mut new_ad_state, client.clone().into_inner()).await;
+    load(&mut new_ad_state, client.clone().into_inner()).await?;
+    new_ad_state.sync_health.record_load(&Utc::now());
     let new_ad_state_arc = Arc::new(Arc::new(new_ad_state));
     data.store(new_ad_state_arc);
+    Ok(())
 }
 
 pub async fn load_ad_meta_periodic(
@@ -56,10 +95,24 @@ pub async fn load_ad_meta_periodic(
     client: web::Data<PrismaClient>,
     ad_meta_sync_period_millis: u64,
 ) {
-    let mut interval = time::interval(Duration::from_millis(ad_meta_sync_period_millis));
+    let period = Duration::from_millis(ad_meta_sync_period_millis);
     loop {
-        interval.tick().await;
-        load_ad_meta(data.clone(), client.clone()).await;
+        if let Err(e) = load_ad_meta(data.clone(), client.clone()).await {
+            println!(
+                "[ERROR]: ad meta is not loaded, previous one keeps serving: {}",
+                e
+            );
+        }
+        // wake up early when a campaign ends before next period, so it leaves index on time.
+        let now = Utc::now();
+        let until_next_end = data
+            .load()
+            .next_campaign_end(&now)
+            .and_then(|end_at| (end_at - now).to_std().ok());
+        let wait = until_next_end
+            .map(|until_next_end| until_next_end.min(period))
+            .unwrap_or(period);
+        time::sleep(wait).await;
     }
 }
 
@@ -68,22 +121,56 @@ async fn update_ad_meta(
     data: web::Data<ArcSwap<Arc<AdState>>>,
     client: web::Data<PrismaClient>,
 ) -> impl Responder {
-    load_ad_meta(data.clone(), client.clone()).await;
-
-    HttpResponse::Ok().json(true)
+    match load_ad_meta(data.clone(), client.clone()).await {
+        Ok(()) => HttpResponse::Ok().json(true),
+        Err(e) => HttpResponse::ServiceUnavailable().json(e.to_string()),
+    }
+}
+// last success and failures of each entity on ad meta sync, 503 when ad meta is stale.
+#[get("/health/sync")]
+async fn sync_health(data: web::Data<ArcSwap<Arc<AdState>>>) -> impl Responder {
+    let report = data.load().sync_health.report(&Utc::now());
+    if report.stale {
+        HttpResponse::ServiceUnavailable().json(report)
+    } else {
+        HttpResponse::Ok().json(report)
+    }
 }
 
+// User-Agent and client ip(peer, X-Forwarded-For only from trusted proxies) for context dimensions.
+fn request_context(http_request: &HttpRequest, trusted_proxies: &TrustedProxies) -> RequestContext {
+    let header_value = |name: header::HeaderName| {
+        http_request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let user_agent = header_value(header::USER_AGENT).map(String::from);
+    let peer = http_request.peer_addr().map(|addr| addr.ip());
+    let ip = trusted_proxies.client_ip(peer, header_value(header::X_FORWARDED_FOR));
+    RequestContext { user_agent, ip }
+}
 #[post("/search_ad_sets")]
 async fn search_ad_sets(
     data: web::Data<ArcSwap<Arc<AdState>>>,
+    geo: web::Data<Option<GeoDatabase>>,
+    trusted_proxies: web::Data<TrustedProxies>,
+    http_request: HttpRequest,
     request: web::Json<Request>,
 ) -> impl Responder {
     let ad_state = data.load();
+    let user_info = ad_state.context_user_info(
+        &request.placement_id,
+        &request_context(&http_request, &trusted_proxies),
+        geo.get_ref().as_ref(),
+        &request.user_info,
+    );
     let ad_set_search_result = ad_state
         .search_ad_sets(
             &request.service_id,
             &request.placement_id,
-            &request.user_info,
+            request.user_id.as_deref(),
+            &user_info,
             request.top_k,
         )
         .await;
@@ -93,14 +180,24 @@ async fn search_ad_sets(
 #[post("/search")]
 async fn search(
     data: web::Data<ArcSwap<Arc<AdState>>>,
+    geo: web::Data<Option<GeoDatabase>>,
+    trusted_proxies: web::Data<TrustedProxies>,
+    http_request: HttpRequest,
     request: web::Json<Request>,
 ) -> impl Responder {
     let ad_state = data.load();
+    let user_info = ad_state.context_user_info(
+        &request.placement_id,
+        &request_context(&http_request, &trusted_proxies),
+        geo.get_ref().as_ref(),
+        &request.user_info,
+    );
     let matched_ad_groups = ad_state
         .search(
             &request.service_id,
             &request.placement_id,
-            &request.user_info,
+            request.user_id.as_deref(),
+            &user_info,
             request.top_k,
         )
         .await;
@@ -137,6 +234,89 @@ async fn all_dimensions(
         }
     }
 }
+// filters of service that are not indexed because they don't agree with its dimension catalog.
+#[get("/filters/violations/{service_id}")]
+async fn filter_violations(
+    data: web::Data<ArcSwap<Arc<AdState>>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let service_id = path.into_inner();
+    let ad_state = data.load();
+
+    HttpResponse::Ok().json(ad_state.service_filter_violations(&service_id))
+}
+#[post("/explain")]
+async fn explain(
+    data: web::Data<ArcSwap<Arc<AdState>>>,
+    request: web::Json<ExplainRequest>,
+) -> impl Responder {
+    let result = data.load().explain(
+        &request.placement_id,
+        &request.ad_group_id,
+        request.user_id.as_deref(),
+        &request.user_info,
+    );
+
+    HttpResponse::Ok().json(result)
+}
+// validate targeting text written by hand. filter is returned as jsonlogic to be stored.
+#[post("/filters/parse")]
+async fn parse_filter(request: web::Json<ParseFilterRequest>) -> impl Responder {
+    match dsl::from_dsl(&request.text) {
+        Err(e) => HttpResponse::BadRequest().json(e),
+        Ok(filter) => HttpResponse::Ok().json(json!({
+            "text": dsl::to_dsl(&filter),
+            "jsonlogic": TargetFilterSerde::to_jsonlogic(&filter),
+        })),
+    }
+}
+// reach of targeting before publishing: users of UserFeature version that match filter.
+#[post("/filters/population")]
+async fn filter_population(
+    data: web::Data<ArcSwap<Arc<AdState>>>,
+    client: web::Data<PrismaClient>,
+    request: web::Json<PopulationRequest>,
+) -> impl Responder {
+    let filter = match TargetFilterSerde::from_jsonlogic(&request.filter) {
+        Ok(filter) => filter,
+        Err(e) => return HttpResponse::BadRequest().json(e.to_string()),
+    };
+    // segments are inlined with the ones loaded on ad_state.
+    let filter = match resolve_segments(&filter, &data.load().segment_filters) {
+        Ok(filter) => filter,
+        Err(e) => return HttpResponse::BadRequest().json(e.to_string()),
+    };
+    match count_population(&client, &request.version, &filter).await {
+        Ok(population) => HttpResponse::Ok().json(json!({
+            "version": request.version,
+            "population": population,
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(e.to_string()),
+    }
+}
+// ad groups on placement that compete for the same users, with overlap on sampled users.
+#[get("/placements/{placement_id}/overlap")]
+async fn placement_overlap(
+    data: web::Data<ArcSwap<Arc<AdState>>>,
+    client: web::Data<PrismaClient>,
+    path: web::Path<String>,
+    query: web::Query<OverlapQuery>,
+) -> impl Responder {
+    let placement_id = path.into_inner();
+    // sampling sorts every row of the version, so sample is kept small.
+    let sample_size = query.sample_size.unwrap_or(1000).min(10000);
+    let sample = match sample_user_features(&client, query.version.as_deref(), sample_size).await {
+        Ok(sample) => sample,
+        Err(e) => return HttpResponse::InternalServerError().json(e.to_string()),
+    };
+    // pairwise analysis is cpu bound, so it runs off the worker thread.
+    let ad_state = data.load_full();
+    match web::block(move || ad_state.placement_overlap(&placement_id, sample)).await {
+        Ok(None) => HttpResponse::NotFound().json(false),
+        Ok(Some(report)) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::InternalServerError().json(e.to_string()),
+    }
+}
 #[post("/update_feedback")]
 async fn update_feedback(
     data: web::Data<ArcSwap<Arc<AdState>>>,
@@ -168,6 +348,16 @@ async fn update_ad_set_feedback(
     data.store(Arc::new(Arc::new(new_ad_state)));
     HttpResponse::Ok().json(true)
 }
+// impressions forwarded by event server, counted for frequency caps.
+#[post("/impressions")]
+async fn record_impressions(
+    data: web::Data<ArcSwap<Arc<AdState>>>,
+    request: web::Json<Vec<ImpressionEvent>>,
+) -> impl Responder {
+    let recorded = data.load().record_impressions(&request);
+
+    HttpResponse::Ok().json(json!({ "recorded": recorded }))
+}
 #[post("/send_sms")]
 async fn send_sms(
     data: web::Data<ArcSwap<Arc<AdState>>>,
@@ -192,10 +382,36 @@ async fn main() -> std::io::Result<()> {
         .map(|s| s.parse::<u64>().unwrap_or(60000))
         .unwrap_or(60000);
 
+    // optional MaxMind-format database for ctx.country/ctx.region.
+    let geo = web::Data::new(env::var("GEOIP_DATABASE_PATH").ok().and_then(|path| {
+        GeoDatabase::open(&path)
+            .map_err(|e| println!("geoip database is not loaded: {}", e))
+            .ok()
+    }));
+
+    // X-Forwarded-For is trusted only from these, peer address is client ip otherwise.
+    // invalid list trusts no proxy, so that forwarded ip is never taken from wrong one.
+    let trusted_proxies = web::Data::new(
+        env::var("TRUSTED_PROXIES")
+            .ok()
+            .and_then(|s| {
+                TrustedProxies::parse(&s)
+                    .map_err(|e| println!("TRUSTED_PROXIES is not loaded: {}", e))
+                    .ok()
+            })
+            .unwrap_or_default(),
+    );
+
     let prisma = Arc::new(db::new_client_with_url(&database_url).await.unwrap());
     let client = web::Data::from(prisma);
 
-    let state = Arc::new(ArcSwap::new(Arc::new(Arc::new(AdState::default()))));
+    // ad meta is stale when it is not loaded for 3 sync periods.
+    let stale_after = chrono::Duration::milliseconds(3 * ad_meta_sync_period_millis as i64);
+    let initial_ad_state = AdState {
+        sync_health: Arc::new(SyncHealth::new(stale_after)),
+        ..AdState::default()
+    };
+    let state = Arc::new(ArcSwap::new(Arc::new(Arc::new(initial_ad_state))));
     let ad_state = web::Data::from(state);
 
     let rt = Builder::new_multi_thread()
@@ -216,13 +432,22 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(ad_state.clone())
             .app_data(client.clone())
+            .app_data(geo.clone())
+            .app_data(trusted_proxies.clone())
             .service(search)
             .service(search_ad_sets)
             .service(user_info)
             .service(update_ad_meta)
+            .service(sync_health)
             .service(all_dimensions)
+            .service(explain)
+            .service(parse_filter)
+            .service(filter_population)
+            .service(filter_violations)
+            .service(placement_overlap)
             .service(update_feedback)
             .service(update_ad_set_feedback)
+            .service(record_impressions)
             .service(send_sms)
             .wrap(cors)
             .wrap(logger)
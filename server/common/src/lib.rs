@@ -1,4 +1,5 @@
 #![recursion_limit = "256"]
+#[cfg(feature = "db")]
 pub mod db;
 pub mod types;
 pub mod util;
@@ -1,24 +1,49 @@
+#[cfg(feature = "stat")]
 use rand::SeedableRng;
+#[cfg(feature = "stat")]
 use rand_chacha::ChaCha8Rng;
+#[cfg(feature = "stat")]
 use rand_distr::{Beta, Distribution};
-use serde::{Deserialize, Serialize};
+#[cfg(feature = "stat")]
+use serde::Deserialize;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 
+#[cfg(feature = "db")]
 use crate::db::{ad_group, ad_set, campaign, content, creative, placement};
 
+/**
+ * Reserved keys of filter index. they are kept apart from user values,
+ * so user value like "empty" can't collide with them.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum ValueKind {
+    Value,
+    // dimension is not constrained by positive predicate.
+    Empty,
+    // user has any value on dimension.
+    Exists,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct DimValue {
     pub dimension: String,
     pub value: String,
     pub is_not: bool,
+    pub kind: ValueKind,
 }
 impl DimValue {
     pub fn debug(&self) -> String {
+        let value = match self.kind {
+            ValueKind::Value => self.value.clone(),
+            ValueKind::Empty => String::from("<empty>"),
+            ValueKind::Exists => String::from("<exists>"),
+        };
         format!(
             "{is_not}.{dimension}.{value}",
             is_not = self.is_not,
             dimension = self.dimension,
-            value = self.value
+            value = value
         )
     }
     pub fn new(dim: &str, value: &str, is_not: bool) -> Self {
@@ -26,16 +51,35 @@ impl DimValue {
             dimension: String::from(dim),
             value: String::from(value),
             is_not,
+            kind: ValueKind::Value,
+        }
+    }
+    pub fn empty(dim: &str) -> Self {
+        DimValue {
+            dimension: String::from(dim),
+            value: String::new(),
+            is_not: false,
+            kind: ValueKind::Empty,
+        }
+    }
+    pub fn exists(dim: &str, is_not: bool) -> Self {
+        DimValue {
+            dimension: String::from(dim),
+            value: String::new(),
+            is_not,
+            kind: ValueKind::Exists,
         }
     }
 }
 pub type UserInfo = HashMap<String, HashSet<String>>;
 
+#[cfg(feature = "stat")]
 #[derive(Debug, Clone, Deserialize)]
 pub struct Stat {
     pub positive_counts: u32,
     pub negative_counts: u32,
 }
+#[cfg(feature = "stat")]
 impl Default for Stat {
     fn default() -> Self {
         Self {
@@ -44,6 +88,7 @@ impl Default for Stat {
         }
     }
 }
+#[cfg(feature = "stat")]
 impl Stat {
     pub fn score(&self) -> Option<f32> {
         let alpha = 1.0 + (self.positive_counts as f32);
@@ -69,26 +114,31 @@ impl Stat {
     }
 }
 
+#[cfg(feature = "db")]
 #[derive(Serialize, Debug)]
 pub struct AdSetWithContent<'a> {
     pub ad_set: &'a ad_set::Data,
     pub content: &'a content::Data,
 }
+#[cfg(feature = "db")]
 #[derive(Serialize, Debug)]
 pub struct CreativeWithContent<'a> {
     pub creative: &'a creative::Data,
     pub content: &'a content::Data,
 }
+#[cfg(feature = "db")]
 #[derive(Serialize, Debug)]
 pub struct AdGroupCreatives<'a> {
     pub ad_group: &'a ad_group::Data,
     pub creatives: Vec<CreativeWithContent<'a>>,
 }
+#[cfg(feature = "db")]
 #[derive(Serialize, Debug)]
 pub struct CampaignAdGroups<'a> {
     pub campaign: &'a campaign::Data,
     pub ad_groups: Vec<AdGroupCreatives<'a>>,
 }
+#[cfg(feature = "db")]
 #[derive(Serialize, Debug)]
 pub struct PlacementCampaigns<'a> {
     pub placement: &'a placement::Data,
@@ -1,12 +1,10 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::{
-    db::{
-        ad_group, ad_set, campaign, content, content_type, creative, integration, placement,
-        provider,
-    },
-    types::UserInfo,
+#[cfg(feature = "db")]
+use crate::db::{
+    ad_group, ad_set, campaign, content, content_type, creative, integration, placement, provider,
 };
+use crate::types::UserInfo;
 
 pub const USER_FEATURE_SQL_TEMPLATE: &str =
     r#"SELECT * FROM "UserFeature" WHERE "cubeHistoryId" = '{}' AND "userId" = '{}'"#;
@@ -46,30 +44,39 @@ pub fn parse_user_info(value: &serde_json::Value) -> Option<UserInfo> {
     Some(user_info)
 }
 
+#[cfg(feature = "db")]
 pub fn is_active_placement(placement: &placement::Data) -> bool {
     placement.status.to_lowercase() == "published"
 }
+#[cfg(feature = "db")]
 pub fn is_active_campaign(campaign: &campaign::Data) -> bool {
     campaign.status.to_lowercase() == "published"
 }
+#[cfg(feature = "db")]
 pub fn is_active_ad_group(ad_group: &ad_group::Data) -> bool {
     ad_group.status.to_lowercase() == "published"
 }
+#[cfg(feature = "db")]
 pub fn is_active_creative(creative: &creative::Data) -> bool {
     creative.status.to_lowercase() == "published"
 }
+#[cfg(feature = "db")]
 pub fn is_active_content(content: &content::Data) -> bool {
     content.status.to_lowercase() == "published"
 }
+#[cfg(feature = "db")]
 pub fn is_active_content_type(content_type: &content_type::Data) -> bool {
     content_type.status.to_lowercase() == "published"
 }
+#[cfg(feature = "db")]
 pub fn is_active_integration(integration: &integration::Data) -> bool {
     integration.status.to_lowercase() == "published"
 }
+#[cfg(feature = "db")]
 pub fn is_active_provider(provider: &provider::Data) -> bool {
     provider.status.to_lowercase() == "published"
 }
+#[cfg(feature = "db")]
 pub fn is_active_ad_set(ad_set: &ad_set::Data) -> bool {
     ad_set.status.to_lowercase() == "published"
 }
@@ -13,6 +13,7 @@ use std::{
 use tokio::runtime::Runtime;
 
 use crate::{
+    impression_processor::ImpressionProcessor,
     message_send_processor::MessageSendProcessor,
     stat_processor::StatProcessor,
     util::{init_consumer, init_future_producer},
@@ -155,3 +156,30 @@ pub async fn initialize_message_send_processor_runner(
         processor,
     );
 }
+
+pub fn initialize_impression_processor_runner(
+    rt: &Runtime,
+    kafka_configs: &Option<HashMap<String, String>>,
+    input_topic: &str,
+    group_id: &str,
+    client: Arc<PrismaClient>,
+    api_urls: Vec<String>,
+) -> () {
+    let consumer = match kafka_configs {
+        None => None,
+        Some(configs) => Some(init_consumer(group_id, configs)),
+    };
+    let processor = Box::new(ImpressionProcessor {
+        http_client: reqwest::Client::new(),
+        api_urls,
+    });
+
+    ProcessorRunner::new(
+        rt,
+        consumer,
+        Arc::new(None),
+        input_topic,
+        client.clone(),
+        processor,
+    );
+}
@@ -0,0 +1,50 @@
+use crate::{handler::Processor, publisher::Event};
+use async_trait::async_trait;
+use rdkafka::{message::BorrowedMessage, Message};
+
+/**
+ * Forwards impression events to api servers, where they are counted for frequency caps.
+ * each api server keeps its own counts in process, so every one of them gets every impression.
+ */
+pub struct ImpressionProcessor {
+    pub http_client: reqwest::Client,
+    // ex) http://localhost:8080
+    pub api_urls: Vec<String>,
+}
+
+impl ImpressionProcessor {
+    fn parse_impression(m: &BorrowedMessage<'_>) -> Option<Event> {
+        let bytes = m.payload()?;
+
+        let event: Event = serde_json::from_slice(bytes).ok()?;
+        if event.what == "impression" {
+            Some(event)
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl Processor for ImpressionProcessor {
+    async fn process(&self, messages: &Vec<BorrowedMessage<'_>>) -> Vec<bool> {
+        let impressions: Vec<Event> = messages.iter().flat_map(Self::parse_impression).collect();
+
+        if !impressions.is_empty() {
+            for api_url in self.api_urls.iter() {
+                let response = self
+                    .http_client
+                    .post(format!("{}/impressions", api_url))
+                    .json(&impressions)
+                    .send()
+                    .await;
+                // impression that is lost is under counted, it never blocks other api servers.
+                if let Err(error) = response.and_then(|response| response.error_for_status()) {
+                    println!("[ERROR]: {:?}", error);
+                }
+            }
+        }
+
+        messages.iter().map(|_| true).collect()
+    }
+}
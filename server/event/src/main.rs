@@ -1,4 +1,5 @@
 pub mod handler;
+pub mod impression_processor;
 pub mod message_send_processor;
 pub mod meta;
 pub mod processor;
@@ -25,7 +26,10 @@ use serde_json::json;
 use tokio::runtime::Builder;
 
 use crate::{
-    handler::{initialize_message_send_processor_runner, initialize_stat_processor_runner},
+    handler::{
+        initialize_impression_processor_runner, initialize_message_send_processor_runner,
+        initialize_stat_processor_runner,
+    },
     publisher::Event,
 };
 use duckdb::{Connection, Result};
@@ -183,6 +187,23 @@ async fn main() -> std::io::Result<()> {
     initialize_stat_processor_runner(&rt, &kafka_configs, topic, &group_id, client.clone());
     initialize_message_send_processor_runner(&rt, &kafka_configs, "sms", &group_id, client.clone())
         .await;
+    // api servers that count impressions for frequency caps, comma separated.
+    if let Some(api_urls) = envs.get("AD_SERVER_URLS") {
+        let api_urls = api_urls
+            .split(',')
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        // own group, so that stat processor still gets every event.
+        initialize_impression_processor_runner(
+            &rt,
+            &kafka_configs,
+            topic,
+            &format!("{}-impression", group_id),
+            client.clone(),
+            api_urls,
+        );
+    }
     // Processor::new(&rt, client, group_id, topic, &kafka_configs);
 
     HttpServer::new(move || {
@@ -0,0 +1,303 @@
+use common::types::{DimValue, UserInfo};
+use roaring::RoaringBitmap;
+use serde_json::json;
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::filter::{TargetFilter, TargetKey};
+use crate::normalize::Literal;
+use crate::range::{parse_number, BitmapRangeIndex};
+
+/**
+ * Dense u32 numbers for values in the order they are first seen.
+ * number of removed value is reused by next interned value. caller must remove
+ * every posting of a number before removing its value, otherwise the posting
+ * would point to another filter.
+ */
+#[derive(Debug, Clone)]
+pub struct Interner<T> {
+    numbers: HashMap<T, u32>,
+    values: Vec<Option<T>>,
+    free: Vec<u32>,
+}
+impl<T> Default for Interner<T> {
+    fn default() -> Self {
+        Self {
+            numbers: Default::default(),
+            values: Default::default(),
+            free: Default::default(),
+        }
+    }
+}
+impl<T: Hash + Eq + Clone> Interner<T> {
+    pub fn get<Q>(&self, value: &Q) -> Option<u32>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.numbers.get(value).copied()
+    }
+    pub fn intern(&mut self, value: T) -> u32 {
+        if let Some(number) = self.numbers.get(&value) {
+            return *number;
+        }
+        let number = match self.free.pop() {
+            Some(number) => {
+                self.values[number as usize] = Some(value.clone());
+                number
+            }
+            None => {
+                self.values.push(Some(value.clone()));
+                (self.values.len() - 1) as u32
+            }
+        };
+        self.numbers.insert(value, number);
+        number
+    }
+    pub fn remove<Q>(&mut self, value: &Q) -> Option<u32>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let number = self.numbers.remove(value)?;
+        self.values[number as usize] = None;
+        self.free.push(number);
+        Some(number)
+    }
+    pub fn resolve(&self, number: u32) -> Option<&T> {
+        self.values.get(number as usize)?.as_ref()
+    }
+    pub fn len(&self) -> usize {
+        self.numbers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.numbers.is_empty()
+    }
+}
+
+/**
+ * Same postings as FilterIndex with HashSet backend, but each target key is
+ * interned to u32 and posting lists are RoaringBitmap.
+ * intersection, union and negative subtraction are bitmap operations and
+ * ids are only resolved back to string for matched target keys.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct BitmapIndex {
+    // filter id -> filter number.
+    pub ids: Interner<String>,
+    // (filter number, seq of target key) -> target key number stored on bitmaps.
+    pub target_keys: Interner<(u32, u32)>,
+    pub index: HashMap<DimValue, RoaringBitmap>,
+    pub range_index: HashMap<(String, bool), BitmapRangeIndex>,
+    pub residuals: HashMap<u32, Vec<Literal>>,
+}
+
+impl BitmapIndex {
+    fn target_key_numbers(&mut self, id: &str, target_keys: &[TargetKey]) -> Vec<u32> {
+        let filter_number = self.ids.intern(id.to_string());
+        (0..target_keys.len())
+            .map(|seq| self.target_keys.intern((filter_number, seq as u32)))
+            .collect()
+    }
+    pub fn insert(
+        &mut self,
+        all_dimensions: &HashMap<String, HashSet<String>>,
+        id: &str,
+        target_keys: &[TargetKey],
+    ) {
+        let numbers = self.target_key_numbers(id, target_keys);
+        let index_keys = TargetFilter::build_index_keys_with_seqs(all_dimensions, target_keys);
+        self.insert_numbers(&numbers, index_keys);
+
+        for (dr, seq) in TargetFilter::build_range_keys_with_seqs(target_keys) {
+            self.range_index
+                .entry((dr.dimension, dr.is_not))
+                .or_default()
+                .insert(dr.interval, numbers[seq]);
+        }
+        for (seq, target_key) in target_keys.iter().enumerate() {
+            if !target_key.residuals.is_empty() {
+                self.residuals
+                    .insert(numbers[seq], target_key.residuals.clone());
+            }
+        }
+    }
+    pub fn insert_index_keys(
+        &mut self,
+        id: &str,
+        target_keys: &[TargetKey],
+        index_keys: Vec<(DimValue, usize)>,
+    ) {
+        let numbers = self.target_key_numbers(id, target_keys);
+        self.insert_numbers(&numbers, index_keys);
+    }
+    fn insert_numbers(&mut self, numbers: &[u32], index_keys: Vec<(DimValue, usize)>) {
+        for (dv, seq) in index_keys {
+            self.index.entry(dv).or_default().insert(numbers[seq]);
+        }
+    }
+    pub fn remove(
+        &mut self,
+        all_dimensions: &HashMap<String, HashSet<String>>,
+        id: &str,
+        target_keys: &[TargetKey],
+    ) {
+        // filter that has never been indexed has nothing to remove.
+        let filter_number = match self.ids.get(id) {
+            Some(filter_number) => filter_number,
+            None => return,
+        };
+        let numbers: Vec<Option<u32>> = (0..target_keys.len())
+            .map(|seq| self.target_keys.get(&(filter_number, seq as u32)))
+            .collect();
+
+        for (dv, seq) in TargetFilter::build_index_keys_with_seqs(all_dimensions, target_keys) {
+            if let (Some(number), Some(bitmap)) = (numbers[seq], self.index.get_mut(&dv)) {
+                bitmap.remove(number);
+            }
+        }
+        for (dr, seq) in TargetFilter::build_range_keys_with_seqs(target_keys) {
+            let range_index = self.range_index.get_mut(&(dr.dimension, dr.is_not));
+            if let (Some(number), Some(range_index)) = (numbers[seq], range_index) {
+                range_index.remove(&dr.interval, &number);
+            }
+        }
+        for number in numbers.iter().flatten() {
+            self.residuals.remove(number);
+        }
+
+        // postings are gone, so numbers of the filter can be reused.
+        for seq in 0..target_keys.len() {
+            self.target_keys.remove(&(filter_number, seq as u32));
+        }
+        self.ids.remove(id);
+    }
+    pub fn remove_dimension(&mut self, dimension: &str) {
+        self.index.retain(|dv, _bitmap| dv.dimension != dimension);
+        self.range_index
+            .retain(|(range_dimension, _is_not), _ranges| range_dimension != dimension);
+    }
+    pub fn cleanup(&mut self) {
+        self.index.retain(|_dv, bitmap| !bitmap.is_empty());
+        self.range_index.retain(|_key, ranges| !ranges.is_empty());
+    }
+    pub fn rebuild(&mut self) {
+        for ranges in self.range_index.values_mut() {
+            ranges.rebuild();
+        }
+    }
+    /**
+     * string internal id of target key number, only for debugging.
+     */
+    pub fn internal_id(&self, number: u32) -> Option<String> {
+        let (filter_number, seq) = self.target_keys.resolve(number)?;
+        let id = self.ids.resolve(*filter_number)?;
+        Some(TargetFilter::internal_id(id, *seq as usize))
+    }
+    fn debug_bitmap(&self, bitmap: &RoaringBitmap) -> Vec<String> {
+        let mut internal_ids: Vec<String> =
+            bitmap.iter().flat_map(|n| self.internal_id(n)).collect();
+        internal_ids.sort();
+        internal_ids
+    }
+    pub fn debug_index(&self) -> serde_json::Value {
+        let mut index = HashMap::new();
+        for (dim_value, bitmap) in self.index.iter() {
+            index.insert(dim_value.debug(), self.debug_bitmap(bitmap));
+        }
+        json!(index)
+    }
+    pub fn debug_range_index(&self) -> serde_json::Value {
+        let mut index = HashMap::new();
+        for ((dimension, is_not), range_index) in self.range_index.iter() {
+            for (interval, bitmap) in range_index.postings.iter() {
+                let key = format!("{is_not}.{dimension}.{}", interval.debug());
+                index.insert(key, self.debug_bitmap(bitmap));
+            }
+        }
+        json!(index)
+    }
+
+    fn dimension_candidates(
+        &self,
+        user_info: &UserInfo,
+        dimension: &String,
+        is_not: bool,
+    ) -> RoaringBitmap {
+        let mut union = RoaringBitmap::new();
+        // reserved empty key need to be lookup. only positive postings have it.
+        if !is_not {
+            if let Some(bitmap) = self.index.get(&DimValue::empty(dimension)) {
+                union |= bitmap;
+            }
+        }
+        if let Some(values) = user_info.get(dimension) {
+            if !values.is_empty() {
+                if let Some(bitmap) = self.index.get(&DimValue::exists(dimension, is_not)) {
+                    union |= bitmap;
+                }
+            }
+            for value in values {
+                let dim_value = DimValue::new(dimension, value, is_not);
+                if let Some(bitmap) = self.index.get(&dim_value) {
+                    union |= bitmap;
+                }
+            }
+            // numeric values are looked up on range index.
+            if let Some(range_index) = self.range_index.get(&(dimension.clone(), is_not)) {
+                for number in values.iter().flat_map(|value| parse_number(value)) {
+                    for bitmap in range_index.search(number) {
+                        union |= bitmap;
+                    }
+                }
+            }
+        }
+        union
+    }
+    fn check_residuals(&self, number: u32, user_info: &UserInfo) -> bool {
+        match self.residuals.get(&number) {
+            None => true,
+            Some(literals) => literals.iter().all(|literal| literal.apply(user_info)),
+        }
+    }
+    pub fn search<'a>(
+        &'a self,
+        all_dimensions: &HashMap<String, HashSet<String>>,
+        user_info: &UserInfo,
+    ) -> HashSet<&'a str> {
+        let mut positive_candidates: Option<RoaringBitmap> = None;
+        for (dimension, _ids) in all_dimensions.iter() {
+            let dim_candidates = self.dimension_candidates(user_info, dimension, false);
+            let intersections = match positive_candidates {
+                Some(prev) => prev & dim_candidates,
+                None => dim_candidates,
+            };
+            let is_empty = intersections.is_empty();
+            positive_candidates = Some(intersections);
+            if is_empty {
+                break;
+            }
+        }
+
+        let mut matched = positive_candidates.unwrap_or_default();
+        for (dimension, _ids) in all_dimensions.iter() {
+            if matched.is_empty() {
+                break;
+            }
+            matched -= self.dimension_candidates(user_info, dimension, true);
+        }
+
+        matched
+            .iter()
+            .filter(|number| self.check_residuals(*number, user_info))
+            .flat_map(|number| self.target_keys.resolve(number))
+            .flat_map(|(filter_number, _seq)| self.ids.resolve(*filter_number))
+            .map(|id| id.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[path = "./bitmap_test.rs"]
+mod bitmap_test;
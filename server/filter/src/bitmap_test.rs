@@ -0,0 +1,173 @@
+use super::*;
+
+use crate::index::{FilterIndex, IndexBackend};
+use crate::test_util::*;
+use proptest::prelude::*;
+
+#[test]
+fn test_interner() {
+    let mut interner = Interner::default();
+    assert_eq!(interner.intern(String::from("ad_1")), 0);
+    assert_eq!(interner.intern(String::from("ad_2")), 1);
+    assert_eq!(interner.intern(String::from("ad_1")), 0);
+    assert_eq!(interner.get("ad_2"), Some(1));
+    assert_eq!(interner.get("ad_3"), None);
+    assert_eq!(interner.resolve(1).map(|id| id.as_str()), Some("ad_2"));
+    assert_eq!(interner.len(), 2);
+
+    // number of removed value is reused.
+    assert_eq!(interner.remove("ad_1"), Some(0));
+    assert_eq!(interner.remove("ad_1"), None);
+    assert_eq!(interner.resolve(0), None);
+    assert_eq!(interner.len(), 1);
+    assert_eq!(interner.intern(String::from("ad_3")), 0);
+    assert_eq!(interner.intern(String::from("ad_4")), 2);
+}
+
+#[test]
+fn test_bitmap_search() {
+    let filters = [
+        TestFilter {
+            id: String::from("ad_1"),
+            filter: Some(TargetFilter::And {
+                fields: vec![
+                    select("gender", "F"),
+                    TargetFilter::Not {
+                        field: Box::new(select("region", "seoul")),
+                    },
+                ],
+            }),
+        },
+        TestFilter {
+            id: String::from("ad_2"),
+            filter: Some(TargetFilter::Or {
+                fields: vec![
+                    select("gender", "M"),
+                    TargetFilter::Gte {
+                        dimension: String::from("age"),
+                        value: 30.0,
+                    },
+                ],
+            }),
+        },
+    ];
+    let mut index = FilterIndex::with_backend(IndexBackend::Bitmap);
+    index.update(&filters, &[]);
+
+    // ids with underscore are returned as is.
+    let user_info = user_info_of(&[("gender", &["F"]), ("age", &["35"])]);
+    assert_eq!(index.search(&user_info), HashSet::from(["ad_1", "ad_2"]));
+
+    let user_info = user_info_of(&[("gender", &["F"]), ("region", &["seoul"])]);
+    assert_eq!(index.search(&user_info), HashSet::new());
+
+    index.update(&[], &[filters[0].clone()]);
+    let user_info = user_info_of(&[("gender", &["F"]), ("age", &["35"])]);
+    assert_eq!(index.search(&user_info), HashSet::from(["ad_2"]));
+    assert!(index.bitmap.residuals.is_empty());
+}
+
+#[test]
+fn test_bitmap_frees_numbers_of_deleted_filters() {
+    let mut index = FilterIndex::with_backend(IndexBackend::Bitmap);
+    for round in 0..10 {
+        let filter = TestFilter {
+            id: format!("ad_{}", round),
+            filter: Some(TargetFilter::Or {
+                fields: vec![select("gender", "M"), select("region", "seoul")],
+            }),
+        };
+        index.update(std::slice::from_ref(&filter), &[]);
+        index.update(std::slice::from_ref(&filter), &[]);
+        let user_info = user_info_of(&[("gender", &["M"])]);
+        assert_eq!(
+            index.search(&user_info),
+            HashSet::from([filter.id.as_str()])
+        );
+
+        index.update(&[], &[filter]);
+        assert!(index.bitmap.ids.is_empty());
+        assert!(index.bitmap.target_keys.is_empty());
+    }
+}
+
+#[test]
+fn test_set_backend_reindex() {
+    let filters = [
+        TestFilter {
+            id: String::from("ad_1"),
+            filter: Some(TargetFilter::And {
+                fields: vec![
+                    select("interests", "L1"),
+                    select("interests", "L2"),
+                    TargetFilter::Lt {
+                        dimension: String::from("age"),
+                        value: 20.0,
+                    },
+                ],
+            }),
+        },
+        TestFilter {
+            id: String::from("ad_2"),
+            filter: Some(select("gender", "M")),
+        },
+    ];
+    let mut index = FilterIndex::default();
+    index.update(&filters, &[]);
+
+    let user_infos = [
+        user_info_of(&[("interests", &["L1", "L2"]), ("age", &["10"])]),
+        user_info_of(&[("interests", &["L1"]), ("age", &["10"])]),
+        user_info_of(&[("gender", &["M"])]),
+    ];
+    let expected: Vec<HashSet<String>> = user_infos
+        .iter()
+        .map(|user_info| {
+            index
+                .search(user_info)
+                .into_iter()
+                .map(String::from)
+                .collect()
+        })
+        .collect();
+
+    index.set_backend(IndexBackend::Bitmap);
+    assert!(index.index.is_empty());
+    assert!(index.range_index.is_empty());
+    for (user_info, expected) in user_infos.iter().zip(expected.iter()) {
+        let result: HashSet<String> = index
+            .search(user_info)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(&result, expected);
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_bitmap_backend_same_as_hash_set_backend(
+        steps in prop::collection::vec(step_strategy(), 1..5),
+        user_infos in prop::collection::vec(user_info_strategy(), 1..8),
+    ) {
+        let mut hash_set_index = FilterIndex::with_backend(IndexBackend::HashSet);
+        let mut bitmap_index = FilterIndex::with_backend(IndexBackend::Bitmap);
+
+        for step in steps {
+            let (inserts, deletes) = to_updates(step);
+            hash_set_index.update(&inserts, &deletes);
+            bitmap_index.update(&inserts, &deletes);
+
+            prop_assert_eq!(&hash_set_index.non_filter_ids, &bitmap_index.non_filter_ids);
+            for user_info in &user_infos {
+                prop_assert_eq!(
+                    hash_set_index.search(user_info),
+                    bitmap_index.search(user_info),
+                    "user_info: {:?}, filters: {:?}",
+                    user_info,
+                    hash_set_index.filters
+                );
+            }
+        }
+    }
+}
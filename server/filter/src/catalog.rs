@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, HashSet};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::filter::TargetFilter;
+use crate::range::parse_number;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatalogError {
+    NotObject,
+    InvalidDimension { name: String, message: String },
+}
+impl std::fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatalogError::NotObject => write!(f, "dimension catalog is not an object"),
+            CatalogError::InvalidDimension { name, message } => {
+                write!(f, "dimension {}: {}", name, message)
+            }
+        }
+    }
+}
+impl std::error::Error for CatalogError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DimensionType {
+    String,
+    Number,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DimensionSpec {
+    #[serde(rename = "type")]
+    dimension_type: Option<DimensionType>,
+    values: Option<HashSet<String>>,
+    pattern: Option<String>,
+    description: Option<String>,
+}
+
+/**
+ * Known dimension of a service. values and pattern are checked only when given,
+ * and type is unknown(None) when values can be both.
+ */
+#[derive(Debug, Clone)]
+pub struct Dimension {
+    pub name: String,
+    pub dimension_type: Option<DimensionType>,
+    pub values: Option<HashSet<String>>,
+    pub pattern: Option<Regex>,
+    pub description: Option<String>,
+}
+impl Dimension {
+    pub fn new(name: &str, dimension_type: Option<DimensionType>) -> Self {
+        Dimension {
+            name: String::from(name),
+            dimension_type,
+            values: None,
+            pattern: None,
+            description: None,
+        }
+    }
+    fn is_valid_value(&self, value: &str) -> bool {
+        let is_valid_type = match self.dimension_type {
+            Some(DimensionType::Number) => parse_number(value).is_some(),
+            _ => true,
+        };
+        is_valid_type
+            && self
+                .values
+                .as_ref()
+                .map(|values| values.contains(value))
+                .unwrap_or(true)
+            && self
+                .pattern
+                .as_ref()
+                .map(|pattern| pattern.is_match(value))
+                .unwrap_or(true)
+    }
+}
+
+/**
+ * Something in a filter that no user of the service can have.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Violation {
+    UnknownDimension {
+        dimension: String,
+        // closest known dimension, for typos.
+        suggestion: Option<String>,
+    },
+    InvalidValue {
+        dimension: String,
+        value: String,
+    },
+    // range predicate on string dimension.
+    NotNumber {
+        dimension: String,
+    },
+}
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::UnknownDimension {
+                dimension,
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "unknown dimension {}, did you mean {}?",
+                dimension, suggestion
+            ),
+            Violation::UnknownDimension { dimension, .. } => {
+                write!(f, "unknown dimension {}", dimension)
+            }
+            Violation::InvalidValue { dimension, value } => {
+                write!(f, "{} is not a valid value of {}", value, dimension)
+            }
+            Violation::NotNumber { dimension } => {
+                write!(f, "range on {} that is not a number", dimension)
+            }
+        }
+    }
+}
+
+/**
+ * Dimensions of a service that filters are validated against, so that typos like
+ * `contry` are reported instead of being indexed as a dimension nobody has.
+ * ex) {"country": {"type": "string", "values": ["KR", "JP"], "description": "ISO 3166"},
+ *      "age": {"type": "number"}, "zipcode": {"pattern": "[0-9]{5}"}}
+ */
+#[derive(Debug, Clone, Default)]
+pub struct DimensionCatalog {
+    // ordered, so that suggestion for typo is stable.
+    pub dimensions: BTreeMap<String, Dimension>,
+}
+impl DimensionCatalog {
+    pub fn from_json(value: &Value) -> Result<Self, CatalogError> {
+        let mut catalog = DimensionCatalog::default();
+        for (name, spec) in value.as_object().ok_or(CatalogError::NotObject)? {
+            let invalid = |message: String| CatalogError::InvalidDimension {
+                name: name.clone(),
+                message,
+            };
+            let spec: DimensionSpec =
+                serde_json::from_value(spec.clone()).map_err(|e| invalid(e.to_string()))?;
+            // pattern has to match the whole value.
+            let pattern = spec
+                .pattern
+                .map(|pattern| Regex::new(&format!("^(?:{})$", pattern)))
+                .transpose()
+                .map_err(|e| invalid(e.to_string()))?;
+            catalog.insert(Dimension {
+                name: name.clone(),
+                dimension_type: spec.dimension_type,
+                values: spec.values,
+                pattern,
+                description: spec.description,
+            });
+        }
+        Ok(catalog)
+    }
+    pub fn insert(&mut self, dimension: Dimension) {
+        self.dimensions.insert(dimension.name.clone(), dimension);
+    }
+    /**
+     * dimensions of other replace the ones with the same name.
+     */
+    pub fn extend(&mut self, other: DimensionCatalog) {
+        self.dimensions.extend(other.dimensions);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.dimensions.is_empty()
+    }
+    pub fn get(&self, name: &str) -> Option<&Dimension> {
+        self.dimensions.get(name)
+    }
+    /**
+     * violations of every predicate in filter, in the order they appear.
+     * dimensions that is_known accepts(ex: derived, context) are not checked.
+     * InSegment is not resolved, segments are validated on their own.
+     */
+    pub fn validate<F>(&self, filter: &TargetFilter, is_known: F) -> Vec<Violation>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut violations = Vec::new();
+        let mut op = |current_filter: &TargetFilter| {
+            let (dimension, values, is_range): (&String, Vec<&String>, bool) = match current_filter
+            {
+                TargetFilter::In {
+                    dimension,
+                    valid_values,
+                } => (dimension, valid_values.iter().collect(), false),
+                TargetFilter::Select {
+                    dimension,
+                    valid_value,
+                } => (dimension, vec![valid_value], false),
+                TargetFilter::ContainsAll { dimension, values }
+                | TargetFilter::ContainsAtLeast {
+                    dimension, values, ..
+                } => (dimension, values.iter().collect(), false),
+                TargetFilter::Gt { dimension, .. }
+                | TargetFilter::Gte { dimension, .. }
+                | TargetFilter::Lt { dimension, .. }
+                | TargetFilter::Lte { dimension, .. }
+                | TargetFilter::Between { dimension, .. } => (dimension, vec![], true),
+                TargetFilter::Exists { dimension } | TargetFilter::Missing { dimension } => {
+                    (dimension, vec![], false)
+                }
+                _ => return,
+            };
+            if is_known(dimension) {
+                return;
+            }
+            let spec = match self.get(dimension) {
+                None => {
+                    violations.push(Violation::UnknownDimension {
+                        dimension: dimension.clone(),
+                        suggestion: self.suggest(dimension),
+                    });
+                    return;
+                }
+                Some(spec) => spec,
+            };
+            if is_range && spec.dimension_type == Some(DimensionType::String) {
+                violations.push(Violation::NotNumber {
+                    dimension: dimension.clone(),
+                });
+            }
+            let mut values = values;
+            values.sort();
+            for value in values {
+                if !spec.is_valid_value(value) {
+                    violations.push(Violation::InvalidValue {
+                        dimension: dimension.clone(),
+                        value: value.clone(),
+                    });
+                }
+            }
+        };
+        TargetFilter::traverse(filter, &mut op);
+        violations
+    }
+    /**
+     * closest dimension within 2 edits, only when it is the only one that close.
+     */
+    fn suggest(&self, dimension: &str) -> Option<String> {
+        let mut closest: Option<(usize, &String)> = None;
+        let mut is_tied = false;
+        for name in self.dimensions.keys() {
+            let distance = edit_distance(dimension, name);
+            if distance > 2 {
+                continue;
+            }
+            match closest {
+                Some((min, _)) if distance > min => (),
+                Some((min, _)) if distance == min => is_tied = true,
+                _ => {
+                    closest = Some((distance, name));
+                    is_tied = false;
+                }
+            }
+        }
+        closest.filter(|_| !is_tied).map(|(_, name)| name.clone())
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+#[path = "./catalog_test.rs"]
+mod catalog_test;
@@ -0,0 +1,119 @@
+use super::*;
+
+use crate::serde::from_jsonlogic;
+use crate::test_util::select;
+use serde_json::json;
+
+fn catalog() -> DimensionCatalog {
+    DimensionCatalog::from_json(&json!({
+        "country": {"type": "string", "values": ["KR", "JP"], "description": "ISO 3166"},
+        "age": {"type": "number"},
+        "zipcode": {"pattern": "[0-9]{5}"},
+        "city": {}
+    }))
+    .unwrap()
+}
+fn validate(filter: &TargetFilter) -> Vec<Violation> {
+    catalog().validate(filter, |dimension| dimension.starts_with("ctx."))
+}
+
+#[test]
+fn test_from_json() {
+    let catalog = catalog();
+    let country = catalog.get("country").unwrap();
+    assert_eq!(country.dimension_type, Some(DimensionType::String));
+    assert_eq!(country.description, Some(String::from("ISO 3166")));
+    assert_eq!(catalog.get("city").unwrap().dimension_type, None);
+
+    assert_eq!(
+        DimensionCatalog::from_json(&json!([])).unwrap_err(),
+        CatalogError::NotObject
+    );
+    assert!(DimensionCatalog::from_json(&json!({"age": {"type": "date"}})).is_err());
+    assert!(DimensionCatalog::from_json(&json!({"zipcode": {"pattern": "[0-9"}})).is_err());
+}
+
+#[test]
+fn test_validate() {
+    let filter = from_jsonlogic(&json!({"and": [
+        {"in": [{"var": "country"}, ["KR", "US"]]},
+        {">=": [{"var": "age"}, 20]},
+        {"==": [{"var": "zipcode"}, "0612"]},
+        {"!": {"==": [{"var": "city"}, "Seoul"]}},
+        {"==": [{"var": "ctx.device"}, "pc"]}
+    ]}))
+    .unwrap();
+    assert_eq!(
+        validate(&filter),
+        vec![
+            Violation::InvalidValue {
+                dimension: String::from("country"),
+                value: String::from("US"),
+            },
+            Violation::InvalidValue {
+                dimension: String::from("zipcode"),
+                value: String::from("0612"),
+            },
+        ]
+    );
+
+    // typo is reported with the closest dimension.
+    assert_eq!(
+        validate(&select("contry", "KR")),
+        vec![Violation::UnknownDimension {
+            dimension: String::from("contry"),
+            suggestion: Some(String::from("country")),
+        }]
+    );
+    assert_eq!(
+        validate(&TargetFilter::Exists {
+            dimension: String::from("gender"),
+        }),
+        vec![Violation::UnknownDimension {
+            dimension: String::from("gender"),
+            suggestion: None,
+        }]
+    );
+
+    // range on string, and value that is not a number.
+    let filter = from_jsonlogic(&json!({"or": [
+        {"<": [{"var": "country"}, 3]},
+        {"==": [{"var": "age"}, "ten"]}
+    ]}))
+    .unwrap();
+    assert_eq!(
+        validate(&filter),
+        vec![
+            Violation::NotNumber {
+                dimension: String::from("country"),
+            },
+            Violation::InvalidValue {
+                dimension: String::from("age"),
+                value: String::from("ten"),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_violation_display() {
+    let violation = Violation::UnknownDimension {
+        dimension: String::from("contry"),
+        suggestion: Some(String::from("country")),
+    };
+    assert_eq!(
+        violation.to_string(),
+        "unknown dimension contry, did you mean country?"
+    );
+    assert_eq!(
+        serde_json::to_value(&violation).unwrap(),
+        json!({"type": "unknown_dimension", "dimension": "contry", "suggestion": "country"})
+    );
+}
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(edit_distance("contry", "country"), 1);
+    assert_eq!(edit_distance("", "age"), 3);
+    assert_eq!(edit_distance("age", "age"), 0);
+}
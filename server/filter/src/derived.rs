@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate};
+use common::types::UserInfo;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::range::parse_number;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DerivedError {
+    NotArray,
+    InvalidTransform { index: usize, message: String },
+}
+impl std::fmt::Display for DerivedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DerivedError::NotArray => write!(f, "derived dimensions is not an array"),
+            DerivedError::InvalidTransform { index, message } => {
+                write!(f, "derived dimension {}: {}", index, message)
+            }
+        }
+    }
+}
+impl std::error::Error for DerivedError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateUnit {
+    Days,
+    Months,
+    Years,
+}
+
+/**
+ * One step of DerivedDimensions. values of source are transformed one by one into
+ * target(source itself when target is not given). value that can't be transformed
+ * is dropped.
+ */
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Transform {
+    Lowercase {
+        source: String,
+        target: Option<String>,
+    },
+    // number into the bucket between boundaries. [20, 30] -> "<20", "20-30", ">=30".
+    Bucketize {
+        source: String,
+        target: Option<String>,
+        boundaries: Vec<f64>,
+        labels: Option<Vec<String>>,
+    },
+    Map {
+        source: String,
+        target: Option<String>,
+        table: HashMap<String, String>,
+        default: Option<String>,
+    },
+    // full units elapsed from date(yyyy-mm-dd, yyyy or rfc3339) to now.
+    DateDiff {
+        source: String,
+        target: Option<String>,
+        unit: DateUnit,
+    },
+}
+impl Transform {
+    fn source(&self) -> &str {
+        match self {
+            Transform::Lowercase { source, .. }
+            | Transform::Bucketize { source, .. }
+            | Transform::Map { source, .. }
+            | Transform::DateDiff { source, .. } => source,
+        }
+    }
+    fn target(&self) -> &str {
+        let target = match self {
+            Transform::Lowercase { target, .. }
+            | Transform::Bucketize { target, .. }
+            | Transform::Map { target, .. }
+            | Transform::DateDiff { target, .. } => target,
+        };
+        target.as_deref().unwrap_or(self.source())
+    }
+    fn validate(&self) -> Result<(), String> {
+        if let Transform::Bucketize {
+            boundaries, labels, ..
+        } = self
+        {
+            if boundaries.is_empty() {
+                return Err(String::from("boundaries is empty"));
+            }
+            if boundaries.iter().any(|b| !b.is_finite())
+                || boundaries.windows(2).any(|w| w[0] >= w[1])
+            {
+                return Err(String::from("boundaries are not strictly increasing"));
+            }
+            if let Some(labels) = labels {
+                if labels.len() != boundaries.len() + 1 {
+                    return Err(format!(
+                        "{} labels for {} buckets",
+                        labels.len(),
+                        boundaries.len() + 1
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+    fn transform(&self, value: &str, now: &DateTime<FixedOffset>) -> Option<String> {
+        match self {
+            Transform::Lowercase { .. } => Some(value.to_lowercase()),
+            Transform::Bucketize {
+                boundaries, labels, ..
+            } => {
+                let number = parse_number(value)?;
+                let bucket = boundaries.partition_point(|boundary| *boundary <= number);
+                match labels {
+                    Some(labels) => labels.get(bucket).cloned(),
+                    None => Some(bucket_label(boundaries, bucket)),
+                }
+            }
+            Transform::Map { table, default, .. } => table.get(value).or(default.as_ref()).cloned(),
+            Transform::DateDiff { unit, .. } => {
+                let date = parse_date(value, now)?;
+                Some(date_diff(date, now.date_naive(), *unit).to_string())
+            }
+        }
+    }
+}
+fn bucket_label(boundaries: &[f64], bucket: usize) -> String {
+    if bucket == 0 {
+        format!("<{}", boundaries[0])
+    } else if bucket == boundaries.len() {
+        format!(">={}", boundaries[bucket - 1])
+    } else {
+        format!("{}-{}", boundaries[bucket - 1], boundaries[bucket])
+    }
+}
+fn parse_date(value: &str, now: &DateTime<FixedOffset>) -> Option<NaiveDate> {
+    let value = value.trim();
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Some(date_time.with_timezone(now.offset()).date_naive());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(date);
+    }
+    // birth year alone counts from the first day of the year.
+    if value.len() == 4 && value.chars().all(|c| c.is_ascii_digit()) {
+        return NaiveDate::from_ymd_opt(value.parse().ok()?, 1, 1);
+    }
+    None
+}
+// negative when from is after to.
+fn date_diff(from: NaiveDate, to: NaiveDate, unit: DateUnit) -> i64 {
+    if from > to {
+        return -date_diff(to, from, unit);
+    }
+    let months = || {
+        let months =
+            (to.year() as i64 - from.year() as i64) * 12 + to.month() as i64 - from.month() as i64;
+        if to.day() < from.day() {
+            months - 1
+        } else {
+            months
+        }
+    };
+    match unit {
+        DateUnit::Days => (to - from).num_days(),
+        DateUnit::Months => months(),
+        DateUnit::Years => months() / 12,
+    }
+}
+
+/**
+ * Per placement pipeline that derives dimensions(ex: age band, tenure) from raw user
+ * attributes, so filters can target buckets instead of exact raw values.
+ * transforms run in order, so later ones can use targets of earlier ones.
+ * ex) [{"type": "date_diff", "source": "birth_year", "target": "age", "unit": "years"},
+ *      {"type": "bucketize", "source": "age", "target": "age_band", "boundaries": [20, 30, 40]}]
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DerivedDimensions {
+    pub transforms: Vec<Transform>,
+}
+impl DerivedDimensions {
+    pub fn from_json(value: &Value) -> Result<Self, DerivedError> {
+        let mut transforms = Vec::new();
+        for (index, transform) in value
+            .as_array()
+            .ok_or(DerivedError::NotArray)?
+            .iter()
+            .enumerate()
+        {
+            let invalid = |message: String| DerivedError::InvalidTransform { index, message };
+            let transform: Transform =
+                serde_json::from_value(transform.clone()).map_err(|e| invalid(e.to_string()))?;
+            transform.validate().map_err(invalid)?;
+            transforms.push(transform);
+        }
+        Ok(DerivedDimensions { transforms })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+    // dimensions that transforms write, filters can use them as well as raw ones.
+    pub fn targets(&self) -> HashSet<String> {
+        self.transforms
+            .iter()
+            .map(|transform| String::from(transform.target()))
+            .collect()
+    }
+    /**
+     * target is overwritten with transformed values, and removed when none of them
+     * could be transformed. transform with missing source leaves target as is.
+     */
+    pub fn apply(&self, user_info: &mut UserInfo, now: &DateTime<FixedOffset>) {
+        for transform in &self.transforms {
+            let values = match user_info.get(transform.source()) {
+                None => continue,
+                Some(values) => values,
+            };
+            let derived: HashSet<String> = values
+                .iter()
+                .flat_map(|value| transform.transform(value, now))
+                .collect();
+            if derived.is_empty() {
+                user_info.remove(transform.target());
+            } else {
+                user_info.insert(String::from(transform.target()), derived);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./derived_test.rs"]
+mod derived_test;
@@ -0,0 +1,129 @@
+use super::*;
+
+use crate::test_util::user_info_of;
+use serde_json::json;
+
+fn now() -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339("2026-10-18T10:00:00+09:00").unwrap()
+}
+fn derive(config: Value, user_info: UserInfo) -> UserInfo {
+    let mut user_info = user_info;
+    DerivedDimensions::from_json(&config)
+        .unwrap()
+        .apply(&mut user_info, &now());
+    user_info
+}
+
+#[test]
+fn test_transforms() {
+    assert_eq!(
+        derive(
+            json!([{"type": "lowercase", "source": "city"}]),
+            user_info_of(&[("city", &["Seoul", "BUSAN"])])
+        ),
+        user_info_of(&[("city", &["seoul", "busan"])])
+    );
+    assert_eq!(
+        derive(
+            json!([
+                {"type": "bucketize", "source": "age", "target": "age_band", "boundaries": [20, 30.5]},
+                {"type": "bucketize", "source": "score", "boundaries": [10], "labels": ["low", "high"]}
+            ]),
+            user_info_of(&[("age", &["15", "20", "30.5", "x"]), ("score", &["10"])])
+        ),
+        user_info_of(&[
+            ("age", &["15", "20", "30.5", "x"]),
+            ("age_band", &["<20", "20-30.5", ">=30.5"]),
+            ("score", &["high"]),
+        ])
+    );
+    assert_eq!(
+        derive(
+            json!([
+                {"type": "map", "source": "os", "target": "platform", "table": {"ios": "mobile", "android": "mobile"}, "default": "other"},
+                {"type": "map", "source": "tier", "table": {"1": "gold"}}
+            ]),
+            user_info_of(&[("os", &["ios", "linux"]), ("tier", &["2"])])
+        ),
+        user_info_of(&[
+            ("os", &["ios", "linux"]),
+            ("platform", &["mobile", "other"])
+        ])
+    );
+}
+
+#[test]
+fn test_date_diff() {
+    let config = json!([
+        {"type": "date_diff", "source": "birth", "target": "age", "unit": "years"},
+        {"type": "date_diff", "source": "signup", "target": "tenure", "unit": "months"},
+        {"type": "date_diff", "source": "last_visit", "target": "days", "unit": "days"}
+    ]);
+    assert_eq!(
+        derive(
+            config.clone(),
+            user_info_of(&[
+                ("birth", &["1990-10-19"]),
+                ("signup", &["2026-08-18T23:00:00Z"]),
+                ("last_visit", &["2026-10-20"]),
+            ])
+        )
+        .into_iter()
+        .filter(|(dimension, _)| ["age", "tenure", "days"].contains(&dimension.as_str()))
+        .collect::<UserInfo>(),
+        // timestamp is counted on its date at the offset of now(2026-08-19 +09:00).
+        user_info_of(&[("age", &["35"]), ("tenure", &["1"]), ("days", &["-2"])])
+    );
+    // birth year alone, and value that is not a date.
+    assert_eq!(
+        derive(config, user_info_of(&[("birth", &["1990", "unknown"])]))["age"],
+        HashSet::from([String::from("36")])
+    );
+}
+
+#[test]
+fn test_chained_transforms() {
+    let config = json!([
+        {"type": "date_diff", "source": "birth_year", "target": "age", "unit": "years"},
+        {"type": "bucketize", "source": "age", "target": "age_band", "boundaries": [20, 30, 40]}
+    ]);
+    assert_eq!(
+        derive(config.clone(), user_info_of(&[("birth_year", &["2000"])]))["age_band"],
+        HashSet::from([String::from("20-30")])
+    );
+    // target with nothing derived is removed, and missing source leaves target as is.
+    assert!(!derive(
+        config.clone(),
+        user_info_of(&[("birth_year", &["?"]), ("age", &["99"])])
+    )
+    .contains_key("age"));
+    assert_eq!(
+        derive(config, user_info_of(&[("age_band", &["<20"])])),
+        user_info_of(&[("age_band", &["<20"])])
+    );
+}
+
+#[test]
+fn test_invalid_derived_dimensions() {
+    let error = |config: Value| DerivedDimensions::from_json(&config).unwrap_err();
+    assert_eq!(error(json!({})), DerivedError::NotArray);
+    assert_eq!(
+        error(json!([
+            {"type": "lowercase", "source": "city"},
+            {"type": "bucketize", "source": "age", "boundaries": [30, 20]}
+        ])),
+        DerivedError::InvalidTransform {
+            index: 1,
+            message: String::from("boundaries are not strictly increasing")
+        }
+    );
+    assert_eq!(
+        error(json!([{"type": "bucketize", "source": "age", "boundaries": [20], "labels": ["a"]}]))
+            .to_string(),
+        "derived dimension 0: 1 labels for 2 buckets"
+    );
+    assert!(matches!(
+        error(json!([{"type": "round", "source": "age"}])),
+        DerivedError::InvalidTransform { index: 0, .. }
+    ));
+}
@@ -0,0 +1,729 @@
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::filter::TargetFilter;
+
+const KEYWORDS: [&str; 13] = [
+    "and",
+    "or",
+    "not",
+    "in",
+    "between",
+    "true",
+    "false",
+    "exists",
+    "missing",
+    "segment",
+    "user_list",
+    "prefer",
+    "contains",
+];
+// nesting of parentheses, `not` and `prefer` deeper than this is rejected, so that
+// parsing doesn't overflow stack.
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseError {
+    // 1-based position of the token that failed.
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.message, self.line, self.column
+        )
+    }
+}
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Comma,
+    // =, !=, >, >=, <, <=
+    Op(String),
+    // bare word. keywords are matched by parser.
+    Word(String),
+    // `quoted` dimension, never a keyword.
+    Quoted(String),
+    Str(String),
+    // raw text of number, so that `age = 10` keeps "10" as value.
+    Number(String),
+    Eof,
+}
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::LParen => String::from("`(`"),
+            Token::RParen => String::from("`)`"),
+            Token::Comma => String::from("`,`"),
+            Token::Op(op) => format!("`{}`", op),
+            Token::Word(word) => format!("`{}`", word),
+            Token::Quoted(name) => format!("`{}`", name),
+            Token::Str(value) => format!("string {:?}", value),
+            Token::Number(number) => format!("number {}", number),
+            Token::Eof => String::from("end of input"),
+        }
+    }
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self {
+            Token::Word(word) => word.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+    fn error(&self, line: usize, column: usize, message: String) -> ParseError {
+        ParseError {
+            line,
+            column,
+            message,
+        }
+    }
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+    fn take_while<F: Fn(char) -> bool>(&mut self, text: &mut String, f: F) {
+        while let Some(c) = self.chars.peek() {
+            if !f(*c) {
+                break;
+            }
+            text.push(*c);
+            self.bump();
+        }
+    }
+    fn escaped(&mut self, quote: char, line: usize, column: usize) -> Result<String, ParseError> {
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                None => {
+                    return Err(self.error(line, column, format!("unterminated {}", quote)));
+                }
+                Some(c) if c == quote => return Ok(text),
+                Some('\\') => match self.bump() {
+                    Some(c) if c == quote || c == '\\' => text.push(c),
+                    _ => {
+                        return Err(self.error(
+                            self.line,
+                            self.column - 1,
+                            String::from("invalid escape"),
+                        ))
+                    }
+                },
+                Some(c) => text.push(c),
+            }
+        }
+    }
+    fn number(&mut self, mut text: String) -> String {
+        if self.chars.peek() == Some(&'i') {
+            self.take_while(&mut text, |c| c.is_ascii_alphabetic());
+            return text;
+        }
+        self.take_while(&mut text, |c| c.is_ascii_digit() || c == '.');
+        if let Some('e') | Some('E') = self.chars.peek() {
+            text.push(self.bump().unwrap());
+            if let Some('+') | Some('-') = self.chars.peek() {
+                text.push(self.bump().unwrap());
+            }
+            self.take_while(&mut text, |c| c.is_ascii_digit());
+        }
+        text
+    }
+    fn tokenize(mut self) -> Result<Vec<(Token, usize, usize)>, ParseError> {
+        let mut tokens = Vec::new();
+        loop {
+            self.take_while(&mut String::new(), |c| c.is_whitespace());
+            let (line, column) = (self.line, self.column);
+            let c = match self.chars.peek() {
+                None => {
+                    tokens.push((Token::Eof, line, column));
+                    return Ok(tokens);
+                }
+                Some(c) => *c,
+            };
+            let token = match c {
+                '(' | ')' | ',' => {
+                    self.bump();
+                    match c {
+                        '(' => Token::LParen,
+                        ')' => Token::RParen,
+                        _ => Token::Comma,
+                    }
+                }
+                '=' | '!' | '>' | '<' => {
+                    self.bump();
+                    let mut op = c.to_string();
+                    if self.chars.peek() == Some(&'=') {
+                        op.push('=');
+                        self.bump();
+                    }
+                    if op == "!" {
+                        return Err(self.error(line, column, String::from("expected `!=`")));
+                    }
+                    Token::Op(op)
+                }
+                '"' => {
+                    self.bump();
+                    Token::Str(self.escaped('"', line, column)?)
+                }
+                '`' => {
+                    self.bump();
+                    Token::Quoted(self.escaped('`', line, column)?)
+                }
+                '-' => {
+                    self.bump();
+                    match self.chars.peek() {
+                        Some(c) if c.is_ascii_digit() || *c == 'i' => {
+                            Token::Number(self.number(String::from("-")))
+                        }
+                        _ => {
+                            return Err(self.error(line, column, String::from("expected number")));
+                        }
+                    }
+                }
+                c if c.is_ascii_digit() => Token::Number(self.number(String::new())),
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let mut word = String::new();
+                    self.take_while(&mut word, |c| {
+                        c.is_ascii_alphanumeric() || c == '_' || c == '.'
+                    });
+                    Token::Word(word)
+                }
+                c => {
+                    return Err(self.error(line, column, format!("unexpected character `{}`", c)));
+                }
+            };
+            tokens.push((token, line, column));
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize, usize)>,
+    position: usize,
+    depth: usize,
+}
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+    fn peek_next(&self) -> &Token {
+        let position = (self.position + 1).min(self.tokens.len() - 1);
+        &self.tokens[position].0
+    }
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if self.position + 1 < self.tokens.len() {
+            self.position += 1;
+        }
+        token
+    }
+    fn error(&self, expected: &str) -> ParseError {
+        let (token, line, column) = &self.tokens[self.position];
+        ParseError {
+            line: *line,
+            column: *column,
+            message: format!("expected {}, found {}", expected, token.describe()),
+        }
+    }
+    fn expect(&mut self, token: Token, expected: &str) -> Result<(), ParseError> {
+        if *self.peek() != token {
+            return Err(self.error(expected));
+        }
+        self.next();
+        Ok(())
+    }
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if !self.peek().is_keyword(keyword) {
+            return Err(self.error(&format!("`{}`", keyword)));
+        }
+        self.next();
+        Ok(())
+    }
+    fn parse(&mut self) -> Result<TargetFilter, ParseError> {
+        let filter = self.or()?;
+        if *self.peek() != Token::Eof {
+            return Err(self.error("`and`, `or` or end of input"));
+        }
+        Ok(filter)
+    }
+    fn or(&mut self) -> Result<TargetFilter, ParseError> {
+        let mut fields = vec![self.and()?];
+        while self.peek().is_keyword("or") {
+            self.next();
+            fields.push(self.and()?);
+        }
+        Ok(if fields.len() == 1 {
+            fields.pop().unwrap()
+        } else {
+            TargetFilter::Or { fields }
+        })
+    }
+    fn and(&mut self) -> Result<TargetFilter, ParseError> {
+        let mut fields = vec![self.unary()?];
+        while self.peek().is_keyword("and") {
+            self.next();
+            fields.push(self.unary()?);
+        }
+        Ok(if fields.len() == 1 {
+            fields.pop().unwrap()
+        } else {
+            TargetFilter::And { fields }
+        })
+    }
+    // every nesting goes through here, so depth is counted once per level.
+    fn unary(&mut self) -> Result<TargetFilter, ParseError> {
+        if self.depth >= MAX_DEPTH {
+            let (_, line, column) = &self.tokens[self.position];
+            return Err(ParseError {
+                line: *line,
+                column: *column,
+                message: format!("nesting is deeper than {}", MAX_DEPTH),
+            });
+        }
+        self.depth += 1;
+        let filter = if self.peek().is_keyword("not") {
+            self.next();
+            self.unary().map(|field| TargetFilter::Not {
+                field: Box::new(field),
+            })
+        } else {
+            self.primary()
+        };
+        self.depth -= 1;
+        filter
+    }
+    fn primary(&mut self) -> Result<TargetFilter, ParseError> {
+        if *self.peek() == Token::LParen {
+            self.next();
+            let filter = self.or()?;
+            self.expect(Token::RParen, "`)`")?;
+            return Ok(filter);
+        }
+        if self.peek().is_keyword("true") {
+            self.next();
+            return Ok(TargetFilter::And { fields: vec![] });
+        }
+        if self.peek().is_keyword("false") {
+            self.next();
+            return Ok(TargetFilter::Or { fields: vec![] });
+        }
+        let is_function = *self.peek_next() == Token::LParen;
+        if is_function && (self.peek().is_keyword("exists") || self.peek().is_keyword("missing")) {
+            let exists = self.peek().is_keyword("exists");
+            self.next();
+            self.next();
+            let dimension = self.dimension()?;
+            self.expect(Token::RParen, "`)`")?;
+            return Ok(if exists {
+                TargetFilter::Exists { dimension }
+            } else {
+                TargetFilter::Missing { dimension }
+            });
+        }
+        if is_function && self.peek().is_keyword("segment") {
+            let segment_id = self.id_argument("segment id string")?;
+            return Ok(TargetFilter::InSegment { segment_id });
+        }
+        if is_function && self.peek().is_keyword("user_list") {
+            let list_id = self.id_argument("user list id string")?;
+            return Ok(TargetFilter::InUserList { list_id });
+        }
+        if is_function && self.peek().is_keyword("prefer") {
+            self.next();
+            self.next();
+            let field = self.or()?;
+            self.expect(Token::Comma, "`,`")?;
+            let position = self.position;
+            let weight = self.number()?;
+            if !TargetFilter::is_valid_weight(weight) {
+                self.position = position;
+                return Err(self.error("positive weight"));
+            }
+            self.expect(Token::RParen, "`)`")?;
+            return Ok(TargetFilter::Prefer {
+                field: Box::new(field),
+                weight,
+            });
+        }
+        let dimension = self.dimension()?;
+        self.predicate(dimension)
+    }
+    // string argument of `name("id")`, peek is on name.
+    fn id_argument(&mut self, expected: &str) -> Result<String, ParseError> {
+        self.next();
+        self.next();
+        let id = match self.peek().clone() {
+            Token::Str(id) => {
+                self.next();
+                id
+            }
+            _ => return Err(self.error(expected)),
+        };
+        self.expect(Token::RParen, "`)`")?;
+        Ok(id)
+    }
+    fn dimension(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            Token::Word(word) if !is_keyword(&word) => {
+                self.next();
+                Ok(word)
+            }
+            Token::Quoted(name) => {
+                self.next();
+                Ok(name)
+            }
+            _ => Err(self.error("dimension")),
+        }
+    }
+    fn value(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            Token::Str(value) | Token::Number(value) => {
+                self.next();
+                Ok(value)
+            }
+            _ => Err(self.error("string or number")),
+        }
+    }
+    fn number(&mut self) -> Result<f64, ParseError> {
+        let number = match self.peek() {
+            Token::Number(number) => number.parse::<f64>().ok(),
+            Token::Word(word) if word.eq_ignore_ascii_case("inf") => Some(f64::INFINITY),
+            _ => None,
+        };
+        match number {
+            Some(number) => {
+                self.next();
+                Ok(number)
+            }
+            None => Err(self.error("number")),
+        }
+    }
+    fn count(&mut self) -> Result<usize, ParseError> {
+        let count = match self.peek() {
+            Token::Number(number) => number.parse::<usize>().ok(),
+            _ => None,
+        };
+        match count {
+            Some(count) => {
+                self.next();
+                Ok(count)
+            }
+            None => Err(self.error("count")),
+        }
+    }
+    fn values(&mut self) -> Result<HashSet<String>, ParseError> {
+        self.expect(Token::LParen, "`(`")?;
+        let mut values = HashSet::new();
+        if *self.peek() == Token::RParen {
+            self.next();
+            return Ok(values);
+        }
+        loop {
+            values.insert(self.value()?);
+            match self.peek() {
+                Token::Comma => self.next(),
+                Token::RParen => {
+                    self.next();
+                    return Ok(values);
+                }
+                _ => return Err(self.error("`,` or `)`")),
+            };
+        }
+    }
+    fn predicate(&mut self, dimension: String) -> Result<TargetFilter, ParseError> {
+        if self.peek().is_keyword("in") {
+            self.next();
+            return Ok(TargetFilter::In {
+                dimension,
+                valid_values: self.values()?,
+            });
+        }
+        if self.peek().is_keyword("not") {
+            self.next();
+            self.expect_keyword("in")?;
+            return Ok(TargetFilter::Not {
+                field: Box::new(TargetFilter::In {
+                    dimension,
+                    valid_values: self.values()?,
+                }),
+            });
+        }
+        if self.peek().is_keyword("contains") {
+            self.next();
+            if self.peek().is_keyword("all") {
+                self.next();
+                return Ok(TargetFilter::ContainsAll {
+                    dimension,
+                    values: self.values()?,
+                });
+            }
+            if !self.peek().is_keyword("at") {
+                return Err(self.error("`all` or `at least`"));
+            }
+            self.next();
+            self.expect_keyword("least")?;
+            let n = self.count()?;
+            self.expect_keyword("of")?;
+            return Ok(TargetFilter::ContainsAtLeast {
+                dimension,
+                values: self.values()?,
+                n,
+            });
+        }
+        if self.peek().is_keyword("between") {
+            self.next();
+            let min = self.number()?;
+            self.expect_keyword("and")?;
+            let max = self.number()?;
+            return Ok(TargetFilter::Between {
+                dimension,
+                min,
+                max,
+            });
+        }
+        let op = match self.peek() {
+            Token::Op(op) => op.clone(),
+            _ => return Err(self.error("`in`, `not in`, `contains`, `between` or comparison")),
+        };
+        self.next();
+        match op.as_str() {
+            "=" | "!=" => {
+                let select = TargetFilter::Select {
+                    dimension,
+                    valid_value: self.value()?,
+                };
+                Ok(if op == "=" {
+                    select
+                } else {
+                    TargetFilter::Not {
+                        field: Box::new(select),
+                    }
+                })
+            }
+            _ => {
+                let value = self.number()?;
+                Ok(match op.as_str() {
+                    ">" => TargetFilter::Gt { dimension, value },
+                    ">=" => TargetFilter::Gte { dimension, value },
+                    "<" => TargetFilter::Lt { dimension, value },
+                    _ => TargetFilter::Lte { dimension, value },
+                })
+            }
+        }
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    KEYWORDS
+        .iter()
+        .any(|keyword| keyword.eq_ignore_ascii_case(word))
+}
+
+/**
+ * Human readable text form of TargetFilter.
+ *
+ * ```text
+ * country in ("KR", "US") and not (age = "10s" or age = "20s")
+ * age between 20 and 30 or income >= 1000.5
+ * exists(region) and `user.tier` != "gold"
+ * segment("korean_adults") and not user_list("churned")
+ * country = "KR" and prefer(os = "ios", 2.5)
+ * tags contains all ("a", "b") and movies contains at least 3 of ("m1", "m2", "m3", "m4")
+ * ```
+ *
+ * `not` binds tighter than `and`, and `and` binds tighter than `or`.
+ * keywords are case insensitive. dimension that is a keyword or has other than
+ * [A-Za-z0-9_.] is quoted with backtick, values are double quoted strings or numbers.
+ */
+pub fn from_dsl(input: &str) -> Result<TargetFilter, ParseError> {
+    let tokens = Lexer::new(input).tokenize()?;
+    Parser {
+        tokens,
+        position: 0,
+        depth: 0,
+    }
+    .parse()
+}
+
+fn quote(text: &str, quote: char) -> String {
+    let mut quoted = String::from(quote);
+    for c in text.chars() {
+        if c == quote || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push(quote);
+    quoted
+}
+fn dimension_to_dsl(dimension: &str) -> String {
+    let is_plain = dimension
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && dimension
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    if is_plain && !is_keyword(dimension) {
+        dimension.to_string()
+    } else {
+        quote(dimension, '`')
+    }
+}
+fn values_to_dsl(values: &HashSet<String>) -> String {
+    let mut values: Vec<&String> = values.iter().collect();
+    values.sort();
+    let values: Vec<String> = values.iter().map(|value| quote(value, '"')).collect();
+    format!("({})", values.join(", "))
+}
+// And/Or with single child is printed as the child.
+fn collapse(filter: &TargetFilter) -> &TargetFilter {
+    match filter {
+        TargetFilter::And { fields } | TargetFilter::Or { fields } if fields.len() == 1 => {
+            collapse(&fields[0])
+        }
+        _ => filter,
+    }
+}
+fn is_compound(filter: &TargetFilter) -> bool {
+    match collapse(filter) {
+        TargetFilter::And { fields } | TargetFilter::Or { fields } => fields.len() > 1,
+        _ => false,
+    }
+}
+fn operand_to_dsl(filter: &TargetFilter) -> String {
+    if is_compound(filter) {
+        format!("({})", to_dsl(filter))
+    } else {
+        to_dsl(filter)
+    }
+}
+
+/**
+ * Canonical text of filter. values of In are sorted, nested And/Or are always
+ * parenthesized, so from_dsl(to_dsl(f)) is f except And/Or with single child.
+ */
+pub fn to_dsl(filter: &TargetFilter) -> String {
+    match collapse(filter) {
+        TargetFilter::In {
+            dimension,
+            valid_values,
+        } => format!(
+            "{} in {}",
+            dimension_to_dsl(dimension),
+            values_to_dsl(valid_values)
+        ),
+        TargetFilter::Select {
+            dimension,
+            valid_value,
+        } => format!(
+            "{} = {}",
+            dimension_to_dsl(dimension),
+            quote(valid_value, '"')
+        ),
+        TargetFilter::And { fields } if fields.is_empty() => String::from("true"),
+        TargetFilter::Or { fields } if fields.is_empty() => String::from("false"),
+        TargetFilter::And { fields } => {
+            let fields: Vec<String> = fields.iter().map(operand_to_dsl).collect();
+            fields.join(" and ")
+        }
+        TargetFilter::Or { fields } => {
+            let fields: Vec<String> = fields.iter().map(operand_to_dsl).collect();
+            fields.join(" or ")
+        }
+        TargetFilter::Not { field } => match collapse(field) {
+            TargetFilter::Select {
+                dimension,
+                valid_value,
+            } => format!(
+                "{} != {}",
+                dimension_to_dsl(dimension),
+                quote(valid_value, '"')
+            ),
+            TargetFilter::In {
+                dimension,
+                valid_values,
+            } => format!(
+                "{} not in {}",
+                dimension_to_dsl(dimension),
+                values_to_dsl(valid_values)
+            ),
+            field => format!("not {}", operand_to_dsl(field)),
+        },
+        TargetFilter::Gt { dimension, value } => {
+            format!("{} > {}", dimension_to_dsl(dimension), value)
+        }
+        TargetFilter::Gte { dimension, value } => {
+            format!("{} >= {}", dimension_to_dsl(dimension), value)
+        }
+        TargetFilter::Lt { dimension, value } => {
+            format!("{} < {}", dimension_to_dsl(dimension), value)
+        }
+        TargetFilter::Lte { dimension, value } => {
+            format!("{} <= {}", dimension_to_dsl(dimension), value)
+        }
+        TargetFilter::Between {
+            dimension,
+            min,
+            max,
+        } => format!(
+            "{} between {} and {}",
+            dimension_to_dsl(dimension),
+            min,
+            max
+        ),
+        TargetFilter::Exists { dimension } => format!("exists({})", dimension_to_dsl(dimension)),
+        TargetFilter::Missing { dimension } => {
+            format!("missing({})", dimension_to_dsl(dimension))
+        }
+        TargetFilter::InSegment { segment_id } => format!("segment({})", quote(segment_id, '"')),
+        TargetFilter::InUserList { list_id } => format!("user_list({})", quote(list_id, '"')),
+        TargetFilter::ContainsAll { dimension, values } => format!(
+            "{} contains all {}",
+            dimension_to_dsl(dimension),
+            values_to_dsl(values)
+        ),
+        TargetFilter::ContainsAtLeast {
+            dimension,
+            values,
+            n,
+        } => format!(
+            "{} contains at least {} of {}",
+            dimension_to_dsl(dimension),
+            n,
+            values_to_dsl(values)
+        ),
+        TargetFilter::Prefer { field, weight } => format!("prefer({}, {})", to_dsl(field), weight),
+    }
+}
+
+#[cfg(test)]
+#[path = "./dsl_test.rs"]
+mod dsl_test;
@@ -0,0 +1,313 @@
+use super::*;
+
+use crate::filter::Filter;
+use crate::test_util::{select, user_info_of, user_info_strategy, FilterDomain};
+use proptest::prelude::*;
+
+fn in_values(dimension: &str, values: &[&str]) -> TargetFilter {
+    TargetFilter::In {
+        dimension: String::from(dimension),
+        valid_values: values.iter().map(|v| String::from(*v)).collect(),
+    }
+}
+fn not(filter: TargetFilter) -> TargetFilter {
+    TargetFilter::Not {
+        field: Box::new(filter),
+    }
+}
+// what from_dsl(to_dsl(f)) gives back: And/Or with single child is the child.
+fn collapse_all(filter: &TargetFilter) -> TargetFilter {
+    match filter {
+        TargetFilter::And { fields } | TargetFilter::Or { fields } if fields.len() == 1 => {
+            collapse_all(&fields[0])
+        }
+        TargetFilter::And { fields } => TargetFilter::And {
+            fields: fields.iter().map(collapse_all).collect(),
+        },
+        TargetFilter::Or { fields } => TargetFilter::Or {
+            fields: fields.iter().map(collapse_all).collect(),
+        },
+        TargetFilter::Not { field } => not(collapse_all(field)),
+        TargetFilter::Prefer { field, weight } => TargetFilter::Prefer {
+            field: Box::new(collapse_all(field)),
+            weight: *weight,
+        },
+        _ => filter.clone(),
+    }
+}
+
+#[test]
+fn test_from_dsl() {
+    assert_eq!(
+        from_dsl(r#"country in ("KR","US") and not (age = "10s")"#),
+        Ok(TargetFilter::And {
+            fields: vec![
+                in_values("country", &["KR", "US"]),
+                not(select("age", "10s")),
+            ],
+        })
+    );
+    // not binds tighter than and, and binds tighter than or. keywords are case insensitive.
+    assert_eq!(
+        from_dsl(r#"a = "1" OR NOT b = "2" and c != "3""#),
+        Ok(TargetFilter::Or {
+            fields: vec![
+                select("a", "1"),
+                TargetFilter::And {
+                    fields: vec![not(select("b", "2")), not(select("c", "3"))],
+                },
+            ],
+        })
+    );
+    assert_eq!(
+        from_dsl("age between 20 and 30.5 and income >= -1e3"),
+        Ok(TargetFilter::And {
+            fields: vec![
+                TargetFilter::Between {
+                    dimension: String::from("age"),
+                    min: 20.0,
+                    max: 30.5,
+                },
+                TargetFilter::Gte {
+                    dimension: String::from("income"),
+                    value: -1000.0,
+                },
+            ],
+        })
+    );
+    // number is compared as string on equality, as user_info only has string values.
+    assert_eq!(from_dsl("age = 10"), Ok(select("age", "10")));
+    assert_eq!(
+        from_dsl(r#"`user region` not in ("seoul") and exists(gender) or missing(`in`)"#),
+        Ok(TargetFilter::Or {
+            fields: vec![
+                TargetFilter::And {
+                    fields: vec![
+                        not(in_values("user region", &["seoul"])),
+                        TargetFilter::Exists {
+                            dimension: String::from("gender"),
+                        },
+                    ],
+                },
+                TargetFilter::Missing {
+                    dimension: String::from("in"),
+                },
+            ],
+        })
+    );
+    assert_eq!(from_dsl("true"), Ok(TargetFilter::And { fields: vec![] }));
+    assert_eq!(from_dsl("false"), Ok(TargetFilter::Or { fields: vec![] }));
+    assert_eq!(from_dsl("tags in ()"), Ok(in_values("tags", &[])));
+    assert_eq!(
+        from_dsl(r#"not segment("seg_1") or `segment` = "a" or user_list("vip")"#),
+        Ok(TargetFilter::Or {
+            fields: vec![
+                not(TargetFilter::InSegment {
+                    segment_id: String::from("seg_1"),
+                }),
+                select("segment", "a"),
+                TargetFilter::InUserList {
+                    list_id: String::from("vip"),
+                },
+            ],
+        })
+    );
+}
+
+#[test]
+fn test_from_dsl_error() {
+    let error = |input: &str| from_dsl(input).unwrap_err();
+
+    assert_eq!(
+        error("country in (KR)"),
+        ParseError {
+            line: 1,
+            column: 13,
+            message: String::from("expected string or number, found `KR`"),
+        }
+    );
+    let multi_line = error("country in (\"KR\")\n  and age >");
+    assert_eq!((multi_line.line, multi_line.column), (2, 12));
+    assert_eq!(multi_line.message, "expected number, found end of input");
+    assert_eq!(
+        multi_line.to_string(),
+        "expected number, found end of input at line 2, column 12"
+    );
+
+    assert_eq!(
+        error(r#"(a = "1""#).message,
+        "expected `)`, found end of input"
+    );
+    assert_eq!(
+        error(r#"a = "1" b = "2""#).message,
+        "expected `and`, `or` or end of input, found `b`"
+    );
+    assert_eq!(
+        error(r#"in = "1""#).message,
+        "expected dimension, found `in`"
+    );
+    assert_eq!(
+        error(r#"a = "1"#),
+        ParseError {
+            line: 1,
+            column: 5,
+            message: String::from("unterminated \""),
+        }
+    );
+    assert_eq!(error("a ~ 1").message, "unexpected character `~`");
+    assert_eq!(
+        error("segment(seg_1)").message,
+        "expected segment id string, found `seg_1`"
+    );
+    assert_eq!(
+        error(r#"age > "10""#).message,
+        r#"expected number, found string "10""#
+    );
+    assert_eq!(
+        error(r#"prefer(os = "ios", -1)"#).message,
+        "expected positive weight, found number -1"
+    );
+    assert_eq!(
+        error(r#"movies contains at least 1.5 of ("m1")"#).message,
+        "expected count, found number 1.5"
+    );
+    assert_eq!(
+        error(r#"movies contains any ("m1")"#).message,
+        "expected `all` or `at least`, found `any`"
+    );
+}
+
+#[test]
+fn test_from_dsl_depth() {
+    let nested = |depth: usize| format!("{}a = \"1\"{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(from_dsl(&nested(255)), Ok(select("a", "1")));
+    assert_eq!(
+        from_dsl(&nested(8000)),
+        Err(ParseError {
+            line: 1,
+            column: 257,
+            message: String::from("nesting is deeper than 256"),
+        })
+    );
+
+    let not_chain = format!("{}a = \"1\"", "not ".repeat(20000));
+    let error = from_dsl(&not_chain).unwrap_err();
+    assert_eq!((error.line, error.column), (1, 1025));
+    assert_eq!(error.message, "nesting is deeper than 256");
+}
+
+#[test]
+fn test_to_dsl() {
+    let filter = TargetFilter::And {
+        fields: vec![
+            in_values("country", &["US", "KR"]),
+            not(select("age", "10s")),
+            TargetFilter::Or {
+                fields: vec![
+                    not(in_values("user region", &["seoul"])),
+                    TargetFilter::Lt {
+                        dimension: String::from("score"),
+                        value: 0.5,
+                    },
+                ],
+            },
+            not(TargetFilter::And {
+                fields: vec![select("a", "x\"y"), select("b", "1")],
+            }),
+        ],
+    };
+    assert_eq!(
+        to_dsl(&filter),
+        r#"country in ("KR", "US") and age != "10s" and (`user region` not in ("seoul") or score < 0.5) and not (a = "x\"y" and b = "1")"#
+    );
+    assert_eq!(from_dsl(&to_dsl(&filter)), Ok(filter));
+
+    let prefer = TargetFilter::And {
+        fields: vec![
+            select("country", "KR"),
+            TargetFilter::Prefer {
+                field: Box::new(TargetFilter::Or {
+                    fields: vec![select("os", "ios"), select("os", "ipados")],
+                }),
+                weight: 2.5,
+            },
+        ],
+    };
+    assert_eq!(
+        to_dsl(&prefer),
+        r#"country = "KR" and prefer(os = "ios" or os = "ipados", 2.5)"#
+    );
+    assert_eq!(from_dsl(&to_dsl(&prefer)), Ok(prefer));
+
+    let contains = TargetFilter::And {
+        fields: vec![
+            TargetFilter::ContainsAll {
+                dimension: String::from("tags"),
+                values: HashSet::from([String::from("b"), String::from("a")]),
+            },
+            TargetFilter::ContainsAtLeast {
+                dimension: String::from("contains"),
+                values: HashSet::from([String::from("m1"), String::from("m2")]),
+                n: 2,
+            },
+        ],
+    };
+    assert_eq!(
+        to_dsl(&contains),
+        r#"tags contains all ("a", "b") and `contains` contains at least 2 of ("m1", "m2")"#
+    );
+    assert_eq!(from_dsl(&to_dsl(&contains)), Ok(contains));
+}
+
+fn dimension_strategy() -> impl Strategy<Value = String> {
+    prop::sample::select(vec!["a", "b", "user.age", "in", "with space", "tick`"])
+        .prop_map(String::from)
+}
+fn value_strategy() -> impl Strategy<Value = String> {
+    "[a-z0-9 \"\\\\]{0,3}"
+}
+fn number_strategy() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        (-100i64..100).prop_map(|v| v as f64),
+        -1.0e6f64..1.0e6f64,
+        Just(f64::INFINITY),
+        Just(f64::NEG_INFINITY),
+    ]
+}
+fn target_filter_strategy() -> impl Strategy<Value = TargetFilter> {
+    FilterDomain {
+        dimensions: dimension_strategy().boxed(),
+        values: value_strategy().boxed(),
+        numbers: number_strategy().boxed(),
+        segment_ids: Some(value_strategy().boxed()),
+        list_ids: value_strategy().boxed(),
+        shape: (4, 32, 4),
+    }
+    .target_filter_strategy()
+}
+
+proptest! {
+    #[test]
+    fn test_dsl_round_trip(filter in target_filter_strategy()) {
+        let text = to_dsl(&filter);
+        let parsed = from_dsl(&text);
+        prop_assert_eq!(&parsed, &Ok(collapse_all(&filter)), "text: {}", text);
+        // canonical text is fixed point.
+        prop_assert_eq!(to_dsl(&parsed.unwrap()), text);
+    }
+
+    #[test]
+    fn test_dsl_same_as_filter(
+        filter in crate::test_util::target_filter_strategy(),
+        user_infos in prop::collection::vec(user_info_strategy(), 1..8),
+    ) {
+        let parsed = from_dsl(&to_dsl(&filter)).unwrap();
+        for user_info in &user_infos {
+            prop_assert_eq!(parsed.apply(user_info), filter.apply(user_info));
+        }
+        prop_assert_eq!(
+            parsed.apply(&user_info_of(&[])),
+            filter.apply(&user_info_of(&[]))
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use common::types::{UserInfo, ValueKind};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::filter::{TargetFilter, TargetKey};
+use crate::normalize::Literal;
+
+/**
+ * How a predicate of target key is evaluated.
+ * Empty is implicit: dimension without positive literal is indexed on reserved empty key,
+ * so it passes for any value including missing dimension.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Predicate {
+    Empty,
+    Indexed { literal: Literal },
+    Residual { literal: Literal },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PredicateExplanation {
+    pub predicate: Predicate,
+    // user values that hit the literal. for Not, these are the values excluding the user.
+    pub matched_values: Vec<String>,
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DimensionExplanation {
+    pub dimension: String,
+    pub user_values: Vec<String>,
+    pub predicates: Vec<PredicateExplanation>,
+    pub passed: bool,
+}
+
+/**
+ * Single conjunction(target key) of filter. matched only when every dimension passed.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TargetKeyExplanation {
+    pub internal_id: String,
+    pub dimensions: Vec<DimensionExplanation>,
+    pub matched: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Explanation {
+    pub id: String,
+    // filter without targeting matches every user.
+    pub non_filter: bool,
+    // false when id is unknown, deleted or rejected on update.
+    pub indexed: bool,
+    pub filter: Option<TargetFilter>,
+    pub target_keys: Vec<TargetKeyExplanation>,
+    // result of FilterIndex::search for this id, not re-evaluation of target_keys.
+    pub matched: bool,
+}
+
+fn literals_of(target_key: &TargetKey) -> Vec<Literal> {
+    let mut literals = Vec::new();
+    let mut values: BTreeMap<(String, bool), BTreeSet<String>> = BTreeMap::new();
+    for dv in &target_key.dim_values {
+        if dv.kind == ValueKind::Exists {
+            literals.push(Literal::Exists {
+                dimension: dv.dimension.clone(),
+                is_not: dv.is_not,
+            });
+            continue;
+        }
+        values
+            .entry((dv.dimension.clone(), dv.is_not))
+            .or_default()
+            .insert(dv.value.clone());
+    }
+    for ((dimension, is_not), values) in values {
+        literals.push(Literal::Values {
+            dimension,
+            values,
+            is_not,
+        });
+    }
+    for dr in &target_key.dim_ranges {
+        literals.push(Literal::Range {
+            dimension: dr.dimension.clone(),
+            interval: dr.interval,
+            is_not: dr.is_not,
+        });
+    }
+    literals
+}
+
+fn explain_predicate(predicate: Predicate, user_info: &UserInfo) -> PredicateExplanation {
+    let (matched_values, passed) = match &predicate {
+        Predicate::Empty => (Vec::new(), true),
+        Predicate::Indexed { literal } | Predicate::Residual { literal } => {
+            let matched_values = literal.matched_values(user_info);
+            (matched_values, literal.apply(user_info))
+        }
+    };
+    PredicateExplanation {
+        predicate,
+        matched_values,
+        passed,
+    }
+}
+
+pub fn explain_target_key(
+    all_dimensions: &HashMap<String, HashSet<String>>,
+    internal_id: String,
+    target_key: &TargetKey,
+    user_info: &UserInfo,
+) -> TargetKeyExplanation {
+    let mut predicates: BTreeMap<String, Vec<Predicate>> = BTreeMap::new();
+    for dimension in all_dimensions.keys() {
+        predicates.entry(dimension.clone()).or_default();
+    }
+    for literal in literals_of(target_key) {
+        predicates
+            .entry(literal.dimension().to_string())
+            .or_default()
+            .push(Predicate::Indexed { literal });
+    }
+    for literal in &target_key.residuals {
+        predicates
+            .entry(literal.dimension().to_string())
+            .or_default()
+            .push(Predicate::Residual {
+                literal: literal.clone(),
+            });
+    }
+
+    let mut dimensions = Vec::new();
+    for (dimension, mut dimension_predicates) in predicates {
+        let has_positive = dimension_predicates
+            .iter()
+            .any(|predicate| match predicate {
+                Predicate::Indexed { literal } => !literal.is_not(),
+                _ => false,
+            });
+        if !has_positive {
+            dimension_predicates.insert(0, Predicate::Empty);
+        }
+        let predicates: Vec<PredicateExplanation> = dimension_predicates
+            .into_iter()
+            .map(|predicate| explain_predicate(predicate, user_info))
+            .collect();
+
+        let mut user_values: Vec<String> = user_info
+            .get(&dimension)
+            .map(|values| values.iter().cloned().collect())
+            .unwrap_or_default();
+        user_values.sort();
+
+        dimensions.push(DimensionExplanation {
+            passed: predicates.iter().all(|p| p.passed),
+            dimension,
+            user_values,
+            predicates,
+        });
+    }
+
+    TargetKeyExplanation {
+        internal_id,
+        matched: dimensions.iter().all(|d| d.passed),
+        dimensions,
+    }
+}
+
+#[cfg(test)]
+#[path = "./explain_test.rs"]
+mod explain_test;
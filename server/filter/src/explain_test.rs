@@ -0,0 +1,153 @@
+use super::*;
+
+use crate::index::FilterIndex;
+use crate::range::Interval;
+use crate::test_util::*;
+
+fn dimension<'a>(target_key: &'a TargetKeyExplanation, name: &str) -> &'a DimensionExplanation {
+    target_key
+        .dimensions
+        .iter()
+        .find(|d| d.dimension == name)
+        .unwrap()
+}
+fn build_index() -> FilterIndex {
+    // (gender = F AND NOT region = seoul) OR age >= 30
+    let ad_1 = TestFilter {
+        id: String::from("ad_1"),
+        filter: Some(TargetFilter::Or {
+            fields: vec![
+                TargetFilter::And {
+                    fields: vec![
+                        select("gender", "F"),
+                        TargetFilter::Not {
+                            field: Box::new(select("region", "seoul")),
+                        },
+                    ],
+                },
+                TargetFilter::Gte {
+                    dimension: String::from("age"),
+                    value: 30.0,
+                },
+            ],
+        }),
+    };
+    let ad_2 = TestFilter {
+        id: String::from("ad_2"),
+        filter: Some(select("interests", "L1")),
+    };
+    let ad_3 = TestFilter {
+        id: String::from("ad_3"),
+        filter: None,
+    };
+    let mut index = FilterIndex::default();
+    index.update(&[ad_1, ad_2, ad_3], &[]);
+    index
+}
+
+#[test]
+fn test_explain_not_matched() {
+    let index = build_index();
+    let user_info = user_info_of(&[("gender", &["F"]), ("region", &["seoul"]), ("age", &["25"])]);
+    let explanation = index.explain(&user_info, "ad_1");
+
+    assert!(!explanation.matched);
+    assert!(explanation.indexed);
+    assert_eq!(explanation.target_keys.len(), 2);
+    assert!(explanation.target_keys.iter().all(|t| !t.matched));
+
+    let gender_key = explanation
+        .target_keys
+        .iter()
+        .find(|t| dimension(t, "gender").predicates[0].predicate != Predicate::Empty)
+        .unwrap();
+    // gender passes, but region excludes the user.
+    assert!(dimension(gender_key, "gender").passed);
+    let region = dimension(gender_key, "region");
+    assert!(!region.passed);
+    assert_eq!(
+        region.predicates,
+        vec![
+            PredicateExplanation {
+                predicate: Predicate::Empty,
+                matched_values: vec![],
+                passed: true,
+            },
+            PredicateExplanation {
+                predicate: Predicate::Indexed {
+                    literal: Literal::Values {
+                        dimension: String::from("region"),
+                        values: BTreeSet::from([String::from("seoul")]),
+                        is_not: true,
+                    }
+                },
+                matched_values: vec![String::from("seoul")],
+                passed: false,
+            },
+        ]
+    );
+    // dimension only used by other filter is implicit empty.
+    assert_eq!(
+        dimension(gender_key, "interests").predicates[0].predicate,
+        Predicate::Empty
+    );
+    assert!(dimension(gender_key, "interests").passed);
+
+    let age_key = explanation
+        .target_keys
+        .iter()
+        .find(|t| t.internal_id != gender_key.internal_id)
+        .unwrap();
+    let age = dimension(age_key, "age");
+    assert_eq!(
+        age.predicates,
+        vec![PredicateExplanation {
+            predicate: Predicate::Indexed {
+                literal: Literal::Range {
+                    dimension: String::from("age"),
+                    interval: Interval::gte(30.0),
+                    is_not: false,
+                }
+            },
+            matched_values: vec![],
+            passed: false,
+        }]
+    );
+    assert_eq!(age.user_values, vec![String::from("25")]);
+}
+
+#[test]
+fn test_explain_matched() {
+    let index = build_index();
+    let user_info = user_info_of(&[("gender", &["M"]), ("age", &["25", "31"])]);
+    let explanation = index.explain(&user_info, "ad_1");
+
+    assert!(explanation.matched);
+    let matched: Vec<&TargetKeyExplanation> = explanation
+        .target_keys
+        .iter()
+        .filter(|t| t.matched)
+        .collect();
+    assert_eq!(matched.len(), 1);
+    assert_eq!(
+        dimension(matched[0], "age").predicates[0].matched_values,
+        vec![String::from("31")]
+    );
+}
+
+#[test]
+fn test_explain_non_filter_and_unknown() {
+    let index = build_index();
+    let user_info = user_info_of(&[]);
+
+    let explanation = index.explain(&user_info, "ad_3");
+    assert!(explanation.non_filter);
+    assert!(!explanation.indexed);
+    assert!(explanation.matched);
+
+    let explanation = index.explain(&user_info, "unknown");
+    assert!(!explanation.non_filter);
+    assert!(!explanation.indexed);
+    assert!(!explanation.matched);
+    assert!(explanation.target_keys.is_empty());
+}
@@ -1,13 +1,97 @@
+use crate::normalize::{build_conjunctions, Literal, NormalizeError};
+use crate::range::{parse_number, DimRange, Interval};
+use crate::user_list::USER_LIST_DIMENSION;
 use common::types::{DimValue, UserInfo};
 use serde::Serialize;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct TargetKey {
     pub dim_values: Vec<DimValue>,
+    pub dim_ranges: Vec<DimRange>,
+    // positive literals on a dimension that already has indexed one. checked on search.
+    pub residuals: Vec<Literal>,
 }
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+impl TargetKey {
+    /**
+     * Every positive values on the same dimension are stored together(any of),
+     * so In with many values stays as single target key.
+     * index only has one slot per dimension for positive literal, so the other
+     * positive literals on the same dimension are kept as residuals.
+     */
+    fn new(mut literals: Vec<Literal>) -> TargetKey {
+        // narrowest values first, then ranges.
+        literals.sort_by_key(|literal| match literal {
+            Literal::Values { values, .. } => (0, values.len()),
+            Literal::Range { .. } => (1, 0),
+            Literal::Exists { .. } => (2, 0),
+            Literal::AtLeast { values, .. } => (3, values.len()),
+        });
+        let mut indexed_dimensions = HashSet::new();
+        let mut target_key = TargetKey::default();
+        for literal in literals {
+            // negated AtLeast can't be on index, user with fewer than n values can have some.
+            let is_indexed = match &literal {
+                Literal::AtLeast { is_not: true, .. } => false,
+                _ if literal.is_not() => true,
+                _ => indexed_dimensions.insert(literal.dimension().to_string()),
+            };
+            if !is_indexed && !matches!(literal, Literal::AtLeast { .. }) {
+                target_key.residuals.push(literal);
+                continue;
+            }
+            match literal {
+                Literal::Values {
+                    dimension,
+                    values,
+                    is_not,
+                } => {
+                    for value in values {
+                        target_key
+                            .dim_values
+                            .push(DimValue::new(&dimension, &value, is_not));
+                    }
+                }
+                Literal::Range {
+                    dimension,
+                    interval,
+                    is_not,
+                } => {
+                    target_key
+                        .dim_ranges
+                        .push(DimRange::new(&dimension, interval, is_not));
+                }
+                Literal::Exists { dimension, is_not } => {
+                    target_key
+                        .dim_values
+                        .push(DimValue::exists(&dimension, is_not));
+                }
+                // count of values is always checked as residual. indexed one
+                // narrows candidates to users that have any of values.
+                Literal::AtLeast {
+                    ref dimension,
+                    ref values,
+                    ..
+                } => {
+                    if is_indexed {
+                        for value in values {
+                            target_key
+                                .dim_values
+                                .push(DimValue::new(dimension, value, false));
+                        }
+                    }
+                    target_key.residuals.push(literal.clone());
+                }
+            }
+        }
+        target_key.dim_values.sort();
+        target_key.dim_ranges.sort();
+        target_key.residuals.sort();
+        target_key
+    }
+}
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum TargetFilter {
     In {
         dimension: String,
@@ -26,134 +110,177 @@ pub enum TargetFilter {
     Not {
         field: Box<TargetFilter>,
     },
+    Gt {
+        dimension: String,
+        value: f64,
+    },
+    Gte {
+        dimension: String,
+        value: f64,
+    },
+    Lt {
+        dimension: String,
+        value: f64,
+    },
+    Lte {
+        dimension: String,
+        value: f64,
+    },
+    Between {
+        dimension: String,
+        min: f64,
+        max: f64,
+    },
+    // user has any value on dimension.
+    Exists {
+        dimension: String,
+    },
+    // user has no value on dimension.
+    Missing {
+        dimension: String,
+    },
+    // user is in Segment, whose filter is inlined by segment::resolve_segments.
+    InSegment {
+        segment_id: String,
+    },
+    // user id of request is on user list, membership is on USER_LIST_DIMENSION of user_info.
+    InUserList {
+        list_id: String,
+    },
+    // user has every one of values on dimension.
+    ContainsAll {
+        dimension: String,
+        values: HashSet<String>,
+    },
+    // user has n or more of values on dimension.
+    ContainsAtLeast {
+        dimension: String,
+        values: HashSet<String>,
+        n: usize,
+    },
+    // soft targeting. never excludes user, adds weight to match score when field holds.
+    Prefer {
+        field: Box<TargetFilter>,
+        weight: f64,
+    },
 }
 pub trait Filter {
     fn apply(&self, user_info: &UserInfo) -> bool;
 }
 
-impl TargetFilter {
-    fn explode<T>(ls_of_ls: &[Vec<T>], prev: &[T]) -> Vec<Vec<T>>
-    where
-        T: Clone,
-    {
-        if ls_of_ls.is_empty() {
-            vec![prev.to_vec()]
-        } else {
-            let head = &ls_of_ls[0];
-            let rest = head
-                .iter()
-                .flat_map(|s| {
-                    let next_prev: Vec<T> = prev.iter().chain([s]).cloned().collect();
-
-                    Self::explode(&ls_of_ls[1..], &next_prev[..])
-                })
-                .collect::<Vec<Vec<T>>>();
-            rest
+/**
+ * Reference semantics that FilterIndex::search must agree with.
+ * user_info has set of values per dimension, so In holds when user has any of values
+ * and range holds when any numeric value of dimension is in interval.
+ * missing dimension has no value, same as dimension with empty set.
+ */
+impl Filter for TargetFilter {
+    fn apply(&self, user_info: &UserInfo) -> bool {
+        match self {
+            TargetFilter::In {
+                dimension,
+                valid_values,
+            } => user_info
+                .get(dimension)
+                .map(|values| values.iter().any(|v| valid_values.contains(v)))
+                .unwrap_or(false),
+            TargetFilter::Select {
+                dimension,
+                valid_value,
+            } => user_info
+                .get(dimension)
+                .map(|values| values.contains(valid_value))
+                .unwrap_or(false),
+            TargetFilter::And { fields } => fields.iter().all(|field| field.apply(user_info)),
+            TargetFilter::Or { fields } => fields.iter().any(|field| field.apply(user_info)),
+            TargetFilter::Not { field } => !field.apply(user_info),
+            TargetFilter::Exists { dimension } => user_info
+                .get(dimension)
+                .map(|values| !values.is_empty())
+                .unwrap_or(false),
+            TargetFilter::Missing { dimension } => user_info
+                .get(dimension)
+                .map(|values| values.is_empty())
+                .unwrap_or(true),
+            // unresolved segment never holds, same as filter that failed to compile.
+            TargetFilter::InSegment { .. } => false,
+            TargetFilter::InUserList { list_id } => user_info
+                .get(USER_LIST_DIMENSION)
+                .map(|list_ids| list_ids.contains(list_id))
+                .unwrap_or(false),
+            TargetFilter::ContainsAll { dimension, values } => {
+                Self::count_values(user_info, dimension, values) == values.len()
+            }
+            TargetFilter::ContainsAtLeast {
+                dimension,
+                values,
+                n,
+            } => Self::count_values(user_info, dimension, values) >= *n,
+            TargetFilter::Prefer { .. } => true,
+            _ => match Self::range(self) {
+                Some((dimension, interval)) => user_info
+                    .get(dimension)
+                    .map(|values| {
+                        values
+                            .iter()
+                            .flat_map(|v| parse_number(v))
+                            .any(|number| interval.contains(number))
+                    })
+                    .unwrap_or(false),
+                None => false,
+            },
         }
     }
+}
+
+impl TargetFilter {
+    // number of values that user has on dimension.
+    pub fn count_values(user_info: &UserInfo, dimension: &str, values: &HashSet<String>) -> usize {
+        user_info
+            .get(dimension)
+            .map(|user_values| user_values.intersection(values).count())
+            .unwrap_or(0)
+    }
+    /**
+     * Target keys without limit on the number of conjunctions.
+     * FilterIndex uses try_build_target_keys with its max_target_keys.
+     */
     pub fn build_target_keys(current_filter: &TargetFilter) -> Vec<TargetKey> {
-        let mut target_keys: Vec<TargetKey> = Self::build_target_keys_inner(current_filter)
+        Self::try_build_target_keys(current_filter, usize::MAX).unwrap()
+    }
+    pub fn try_build_target_keys(
+        current_filter: &TargetFilter,
+        max_target_keys: usize,
+    ) -> Result<Vec<TargetKey>, NormalizeError> {
+        let mut target_keys: Vec<TargetKey> = build_conjunctions(current_filter, max_target_keys)?
             .into_iter()
-            .flatten()
+            .map(TargetKey::new)
             .collect();
 
         target_keys.sort();
-        target_keys
+        Ok(target_keys)
     }
 
-    fn build_target_keys_inner(current_filter: &TargetFilter) -> Vec<Vec<TargetKey>> {
+    /**
+     * dimension and interval for range predicates. None for the others.
+     */
+    pub fn range(current_filter: &TargetFilter) -> Option<(&String, Interval)> {
         match current_filter {
-            TargetFilter::Select {
+            TargetFilter::Gt { dimension, value } => Some((dimension, Interval::gt(*value))),
+            TargetFilter::Gte { dimension, value } => Some((dimension, Interval::gte(*value))),
+            TargetFilter::Lt { dimension, value } => Some((dimension, Interval::lt(*value))),
+            TargetFilter::Lte { dimension, value } => Some((dimension, Interval::lte(*value))),
+            TargetFilter::Between {
                 dimension,
-                valid_value,
-            } => {
-                let dvs = vec![vec![TargetKey {
-                    dim_values: vec![DimValue {
-                        dimension: dimension.to_string(),
-                        value: valid_value.to_string(),
-                        is_not: false,
-                    }],
-                }]];
-                // println!("Select: {:?}", dvs);
-                dvs
-            }
-            TargetFilter::In {
-                dimension,
-                valid_values,
-            } => {
-                let dvs = vec![valid_values
-                    .iter()
-                    .map(|v| TargetKey {
-                        dim_values: vec![DimValue {
-                            dimension: dimension.to_string(),
-                            value: v.to_string(),
-                            is_not: false,
-                        }],
-                    })
-                    .collect()];
-                // println!("In: {:?}", dvs);
-                dvs
-            }
-            TargetFilter::And { fields } => {
-                let childrens: Vec<Vec<TargetKey>> = fields
-                    .iter()
-                    .flat_map(|field| Self::build_target_keys_inner(field))
-                    .collect();
-                let dvs = Self::explode(&childrens, &vec![])
-                    .iter()
-                    .map(|ls| {
-                        let mut dim_values: Vec<DimValue> = ls
-                            .iter()
-                            .flat_map(|target_key| target_key.dim_values.clone())
-                            .collect();
-                        dim_values.sort();
-                        vec![TargetKey { dim_values }]
-                    })
-                    .collect();
-                // println!("And: {:?}", dvs);
-                dvs
-            }
-            TargetFilter::Or { fields } => {
-                let mut childrens: Vec<TargetKey> = fields
-                    .iter()
-                    .flat_map(|field| Self::build_target_keys_inner(field))
-                    .flatten()
-                    .collect();
-                childrens.sort();
-                let dvs = vec![childrens];
-                // println!("Or: {:?}", dvs);
-                dvs
-            }
-            TargetFilter::Not { field } => {
-                let child = Self::build_target_keys_inner(field);
-                let dvs = child
-                    .iter()
-                    .map(|vs| {
-                        vs.iter()
-                            .map(|v| {
-                                let mut dim_values: Vec<DimValue> = v
-                                    .dim_values
-                                    .iter()
-                                    .map(|dv| DimValue {
-                                        dimension: dv.dimension.to_string(),
-                                        value: dv.value.to_string(),
-                                        is_not: !dv.is_not,
-                                    })
-                                    .collect();
-                                dim_values.sort();
-                                TargetKey { dim_values }
-                            })
-                            .collect()
-                    })
-                    .collect();
-                dvs
-            }
+                min,
+                max,
+            } => Some((dimension, Interval::between(*min, *max))),
+            _ => None,
         }
     }
-
-    fn traverse<F>(filter: &TargetFilter, f: &mut F) -> ()
+    pub(crate) fn traverse<F>(filter: &TargetFilter, f: &mut F)
     where
-        F: FnMut(&TargetFilter) -> (),
+        F: FnMut(&TargetFilter),
     {
         match filter {
             TargetFilter::In {
@@ -176,10 +303,21 @@ impl TargetFilter {
                     Self::traverse(field, f);
                 }
             }
-            TargetFilter::Not { field } => {
+            TargetFilter::Not { field } | TargetFilter::Prefer { field, .. } => {
                 f(filter);
                 Self::traverse(field, f);
             }
+            TargetFilter::Gt { .. }
+            | TargetFilter::Gte { .. }
+            | TargetFilter::Lt { .. }
+            | TargetFilter::Lte { .. }
+            | TargetFilter::Between { .. }
+            | TargetFilter::Exists { .. }
+            | TargetFilter::Missing { .. }
+            | TargetFilter::InSegment { .. }
+            | TargetFilter::InUserList { .. }
+            | TargetFilter::ContainsAll { .. }
+            | TargetFilter::ContainsAtLeast { .. } => f(filter),
         }
     }
 
@@ -199,48 +337,184 @@ impl TargetFilter {
             } => {
                 dimensions.insert(dimension.clone());
             }
+            TargetFilter::Gt { dimension, .. }
+            | TargetFilter::Gte { dimension, .. }
+            | TargetFilter::Lt { dimension, .. }
+            | TargetFilter::Lte { dimension, .. }
+            | TargetFilter::Between { dimension, .. }
+            | TargetFilter::Exists { dimension }
+            | TargetFilter::Missing { dimension }
+            | TargetFilter::ContainsAll { dimension, .. }
+            | TargetFilter::ContainsAtLeast { dimension, .. } => {
+                dimensions.insert(dimension.clone());
+            }
+            TargetFilter::InUserList { .. } => {
+                dimensions.insert(String::from(USER_LIST_DIMENSION));
+            }
             _ => (),
         };
 
         Self::traverse(filter, &mut op);
         dimensions
     }
-    pub fn to_internal_id(dv: &DimValue, id: &str, index: usize) -> String {
-        if dv.is_not {
-            String::from(id)
-        } else {
-            format!("{id}_{seq}", id = id, seq = index)
+    /**
+     * segments that filter refers to directly, not through other segments.
+     */
+    pub fn extract_segment_ids(filter: &TargetFilter) -> BTreeSet<String> {
+        let mut segment_ids = BTreeSet::new();
+
+        let mut op = |current_filter: &TargetFilter| {
+            if let TargetFilter::InSegment { segment_id } = current_filter {
+                segment_ids.insert(segment_id.clone());
+            }
+        };
+
+        Self::traverse(filter, &mut op);
+        segment_ids
+    }
+    /**
+     * Prefer that are not under Not, with their weights.
+     * preference inside field of other Prefer only counts as part of that field.
+     */
+    pub fn extract_preferences(filter: &TargetFilter) -> Vec<(TargetFilter, f64)> {
+        let mut preferences = Vec::new();
+        let mut stack = vec![filter];
+        while let Some(current_filter) = stack.pop() {
+            match current_filter {
+                TargetFilter::And { fields } | TargetFilter::Or { fields } => {
+                    stack.extend(fields.iter().rev())
+                }
+                TargetFilter::Prefer { field, weight } => {
+                    preferences.push((field.as_ref().clone(), *weight))
+                }
+                _ => (),
+            }
         }
+        preferences
     }
-    pub fn build_index_key_wth_internal_ids(
+    // weight of Prefer is positive, so preference never lowers match score.
+    pub fn is_valid_weight(weight: f64) -> bool {
+        weight.is_finite() && weight > 0.0
+    }
+    /**
+     * sum of weights of preferences that hold for user. 0 without preferences.
+     */
+    pub fn match_score(preferences: &[(TargetFilter, f64)], user_info: &UserInfo) -> f64 {
+        preferences
+            .iter()
+            .filter(|(field, _)| field.apply(user_info))
+            .map(|(_, weight)| weight)
+            .sum()
+    }
+    /**
+     * Negative postings also carry seq of target key, since negation only
+     * excludes the target key it belongs to, not the other target keys of the same id.
+     */
+    pub fn to_internal_id(_dv: &DimValue, id: &str, index: usize) -> String {
+        Self::internal_id(id, index)
+    }
+    pub fn internal_id(id: &str, index: usize) -> String {
+        format!("{id}_{seq}", id = id, seq = index)
+    }
+    /**
+     * Index keys with seq of target key they belong to.
+     * backends turn seq into their own internal id, so both share the same postings.
+     */
+    pub fn build_index_keys_with_seqs(
         all_dimensions: &HashMap<String, HashSet<String>>,
-        target_filter: &TargetFilter,
-        id: &str,
-    ) -> Vec<(DimValue, String)> {
+        target_keys: &[TargetKey],
+    ) -> Vec<(DimValue, usize)> {
         let mut dim_value_seqs = Vec::new();
 
-        let target_keys = Self::build_target_keys(&target_filter);
+        // fill out index with dimension that has values.
+        // dimension with range is indexed by build_range_keys_with_seqs.
+        for (index, target_key) in target_keys.iter().enumerate() {
+            for dv in &target_key.dim_values {
+                dim_value_seqs.push((dv.clone(), index));
+            }
+        }
+        dim_value_seqs.extend(Self::build_empty_keys_with_seqs(
+            all_dimensions.keys(),
+            target_keys,
+        ));
+        dim_value_seqs
+    }
+    /**
+     * Reserved empty keys for dimensions that target key doesn't have positive
+     * values, range or Exists on.
+     */
+    pub fn build_empty_keys_with_seqs<'a, I>(
+        dimensions: I,
+        target_keys: &[TargetKey],
+    ) -> Vec<(DimValue, usize)>
+    where
+        I: IntoIterator<Item = &'a String> + Clone,
+    {
+        let mut dim_value_seqs = Vec::new();
 
         for (index, target_key) in target_keys.iter().enumerate() {
             let mut value_existing_dimensions = HashSet::new();
-
-            // fill out index with dimension that has values.
             for dv in &target_key.dim_values {
-                value_existing_dimensions.insert(dv.dimension.clone());
-
-                dim_value_seqs.push((dv.clone(), Self::to_internal_id(dv, id, index)));
+                if !dv.is_not {
+                    value_existing_dimensions.insert(&dv.dimension);
+                }
+            }
+            for dr in &target_key.dim_ranges {
+                if !dr.is_not {
+                    value_existing_dimensions.insert(&dr.dimension);
+                }
             }
-            // fill out index for dimension that don't have values. empty.
-            for (dimension, _ids) in all_dimensions {
+            for dimension in dimensions.clone() {
                 if !value_existing_dimensions.contains(dimension) {
-                    let dv = DimValue::new(&dimension, "empty", false);
-
-                    dim_value_seqs.push((dv.clone(), Self::to_internal_id(&dv, id, index)));
+                    dim_value_seqs.push((DimValue::empty(dimension), index));
                 }
             }
         }
         dim_value_seqs
     }
+    pub fn build_range_keys_with_seqs(target_keys: &[TargetKey]) -> Vec<(DimRange, usize)> {
+        let mut dim_range_seqs = Vec::new();
+
+        for (index, target_key) in target_keys.iter().enumerate() {
+            for dr in &target_key.dim_ranges {
+                dim_range_seqs.push((dr.clone(), index));
+            }
+        }
+        dim_range_seqs
+    }
+    pub fn build_index_key_wth_internal_ids(
+        all_dimensions: &HashMap<String, HashSet<String>>,
+        target_keys: &[TargetKey],
+        id: &str,
+    ) -> Vec<(DimValue, String)> {
+        Self::build_index_keys_with_seqs(all_dimensions, target_keys)
+            .into_iter()
+            .map(|(dv, index)| {
+                let internal_id = Self::to_internal_id(&dv, id, index);
+                (dv, internal_id)
+            })
+            .collect()
+    }
+    pub fn build_range_keys_with_internal_ids(
+        target_keys: &[TargetKey],
+        id: &str,
+    ) -> Vec<(DimRange, String)> {
+        Self::build_range_keys_with_seqs(target_keys)
+            .into_iter()
+            .map(|(dr, index)| (dr, Self::internal_id(id, index)))
+            .collect()
+    }
+    pub fn build_residuals_with_internal_ids(
+        target_keys: &[TargetKey],
+        id: &str,
+    ) -> Vec<(String, Vec<Literal>)> {
+        target_keys
+            .iter()
+            .enumerate()
+            .filter(|(_index, target_key)| !target_key.residuals.is_empty())
+            .map(|(index, target_key)| (Self::internal_id(id, index), target_key.residuals.clone()))
+            .collect()
+    }
 }
 
 #[cfg(test)]
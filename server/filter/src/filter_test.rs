@@ -98,167 +98,67 @@ fn test_extract_dimensions_for_single_filter() {
     assert_eq!(all_dimensions, expected_output);
 }
 
-#[test]
-fn test_explode() {
-    let input = vec![vec![1, 2, 3], vec![4, 5], vec![6]];
-    let expected_output = vec![
-        vec![1, 4, 6],
-        vec![1, 5, 6],
-        vec![2, 4, 6],
-        vec![2, 5, 6],
-        vec![3, 4, 6],
-        vec![3, 5, 6],
-    ];
-
-    assert_eq!(TargetFilter::explode(&input, &vec![][..]), expected_output);
-}
-
 #[test]
 fn test_build_target_keys_filter_1() {
-    /*
-    let expected_output = vec![vec![
-        String::from("age.10"),
-        String::from("age.20"),
-        String::from("gender.F"),
-        String::from("age.10_AND_NOT_interests.L2,L3"),
-        String::from("age.20_AND_NOT_interests.L2,L3"),
-    ]];
-    */
+    // values of In are kept together on a target key instead of being exploded.
     let expected_output = vec![
-        TargetKey {
-            dim_values: vec![DimValue {
-                dimension: String::from("age"),
-                value: String::from("10"),
-                is_not: false,
-            }],
-        },
         TargetKey {
             dim_values: vec![
-                DimValue {
-                    dimension: String::from("age"),
-                    value: String::from("10"),
-                    is_not: false,
-                },
-                DimValue {
-                    dimension: String::from("interests"),
-                    value: String::from("L2,L3"),
-                    is_not: true,
-                },
+                DimValue::new("age", "10", false),
+                DimValue::new("age", "20", false),
             ],
-        },
-        TargetKey {
-            dim_values: vec![DimValue {
-                dimension: String::from("age"),
-                value: String::from("20"),
-                is_not: false,
-            }],
+            ..Default::default()
         },
         TargetKey {
             dim_values: vec![
-                DimValue {
-                    dimension: String::from("age"),
-                    value: String::from("20"),
-                    is_not: false,
-                },
-                DimValue {
-                    dimension: String::from("interests"),
-                    value: String::from("L2,L3"),
-                    is_not: true,
-                },
+                DimValue::new("age", "10", false),
+                DimValue::new("age", "20", false),
+                DimValue::new("interests", "L2,L3", true),
             ],
+            ..Default::default()
         },
         TargetKey {
-            dim_values: vec![DimValue {
-                dimension: String::from("gender"),
-                value: String::from("F"),
-                is_not: false,
-            }],
+            dim_values: vec![DimValue::new("gender", "F", false)],
+            ..Default::default()
         },
     ];
 
     let output = TargetFilter::build_target_keys(&FILTER_1);
 
     println!("{:?}", output);
-    for (a, b) in output.iter().zip(expected_output.iter()) {
-        assert_eq!(*a, *b)
-    }
+    assert_eq!(output, expected_output);
 }
 #[test]
 fn test_build_target_keys_filter_2() {
     let output = TargetFilter::build_target_keys(&FILTER_2);
     let expected_output = vec![
-        TargetKey {
-            dim_values: vec![DimValue {
-                dimension: String::from("age"),
-                value: String::from("10"),
-                is_not: false,
-            }],
-        },
         TargetKey {
             dim_values: vec![
-                DimValue {
-                    dimension: String::from("age"),
-                    value: String::from("10"),
-                    is_not: false,
-                },
-                DimValue {
-                    dimension: String::from("gender"),
-                    value: String::from("F"),
-                    is_not: false,
-                },
-                DimValue {
-                    dimension: String::from("interests"),
-                    value: String::from("L2,L3"),
-                    is_not: true,
-                },
+                DimValue::new("age", "10", false),
+                DimValue::new("age", "20", false),
             ],
-        },
-        TargetKey {
-            dim_values: vec![DimValue {
-                dimension: String::from("age"),
-                value: String::from("20"),
-                is_not: false,
-            }],
+            ..Default::default()
         },
         TargetKey {
             dim_values: vec![
-                DimValue {
-                    dimension: String::from("age"),
-                    value: String::from("20"),
-                    is_not: false,
-                },
-                DimValue {
-                    dimension: String::from("gender"),
-                    value: String::from("F"),
-                    is_not: false,
-                },
-                DimValue {
-                    dimension: String::from("interests"),
-                    value: String::from("L2,L3"),
-                    is_not: true,
-                },
+                DimValue::new("age", "10", false),
+                DimValue::new("age", "20", false),
+                DimValue::new("gender", "F", false),
+                DimValue::new("interests", "L2,L3", true),
             ],
+            ..Default::default()
         },
         TargetKey {
             dim_values: vec![
-                DimValue {
-                    dimension: String::from("gender"),
-                    value: String::from("F"),
-                    is_not: false,
-                },
-                DimValue {
-                    dimension: String::from("interests"),
-                    value: String::from("L1"),
-                    is_not: false,
-                },
+                DimValue::new("gender", "F", false),
+                DimValue::new("interests", "L1", false),
             ],
+            ..Default::default()
         },
     ];
 
     println!("{:?}", output);
-    for (a, b) in output.iter().zip(expected_output.iter()) {
-        assert_eq!(*a, *b)
-    }
+    assert_eq!(output, expected_output);
 }
 
 #[test]
@@ -350,3 +250,119 @@ fn test_jsonlogic_serde() {
     println!("{:?}", value);
     assert_eq!(deserialized_filter, deserialized_filter_after);
 }
+#[test]
+fn test_range_serde() {
+    let raw_str = r#"
+    {
+        "and": [
+            {">": [{"var": "age"}, 19]},
+            {"<=": [{"var": "age"}, 40]},
+            {"<=": [25, {"var": "purchase_count"}, 34.5]},
+            {"!": {"<": [{"var": "score"}, 0.5]}},
+            {">=": [10, {"var": "visits"}]}
+        ]
+    }
+    "#;
+    let value: Value = serde_json::from_str(raw_str).unwrap();
+    let deserialized_filter = serde::from_jsonlogic(&value).unwrap();
+    let expected_filter = And {
+        fields: vec![
+            Gt {
+                dimension: String::from("age"),
+                value: 19.0,
+            },
+            Lte {
+                dimension: String::from("age"),
+                value: 40.0,
+            },
+            Between {
+                dimension: String::from("purchase_count"),
+                min: 25.0,
+                max: 34.5,
+            },
+            Not {
+                field: Box::new(Lt {
+                    dimension: String::from("score"),
+                    value: 0.5,
+                }),
+            },
+            Lte {
+                dimension: String::from("visits"),
+                value: 10.0,
+            },
+        ],
+    };
+    assert_eq!(deserialized_filter, expected_filter);
+
+    let jsonlogic = serde::to_jsonlogic(&deserialized_filter);
+    assert_eq!(
+        serde::from_jsonlogic(&jsonlogic).unwrap(),
+        deserialized_filter
+    );
+
+    let json = serde::to_json(&deserialized_filter);
+    assert_eq!(serde::from_json(&json).unwrap(), deserialized_filter);
+}
+
+#[test]
+fn test_apply() {
+    let user_info: UserInfo = HashMap::from([
+        (
+            String::from("interests"),
+            HashSet::from([String::from("L1"), String::from("L2")]),
+        ),
+        (
+            String::from("age"),
+            HashSet::from([String::from("25"), String::from("unknown")]),
+        ),
+    ]);
+    let select = |dimension: &str, value: &str| Select {
+        dimension: String::from(dimension),
+        valid_value: String::from(value),
+    };
+
+    assert!(select("interests", "L2").apply(&user_info));
+    assert!(In {
+        dimension: String::from("interests"),
+        valid_values: HashSet::from([String::from("L3"), String::from("L1")]),
+    }
+    .apply(&user_info));
+    // user can have both values on a dimension.
+    assert!(And {
+        fields: vec![select("interests", "L1"), select("interests", "L2")],
+    }
+    .apply(&user_info));
+    // missing dimension has no value.
+    assert!(!select("gender", "F").apply(&user_info));
+    assert!(Not {
+        field: Box::new(select("gender", "F")),
+    }
+    .apply(&user_info));
+    // non numeric values are ignored by ranges.
+    assert!(Between {
+        dimension: String::from("age"),
+        min: 20.0,
+        max: 25.0,
+    }
+    .apply(&user_info));
+    assert!(!Gt {
+        dimension: String::from("age"),
+        value: 25.0,
+    }
+    .apply(&user_info));
+    // dimension with empty set has no value.
+    let exists = |dimension: &str| Exists {
+        dimension: String::from(dimension),
+    };
+    let missing = |dimension: &str| Missing {
+        dimension: String::from(dimension),
+    };
+    let mut user_info = user_info;
+    user_info.insert(String::from("region"), HashSet::new());
+    assert!(exists("age").apply(&user_info));
+    assert!(!exists("region").apply(&user_info));
+    assert!(missing("region").apply(&user_info));
+    assert!(missing("gender").apply(&user_info));
+    assert!(And { fields: vec![] }.apply(&user_info));
+    assert!(!Or { fields: vec![] }.apply(&user_info));
+}
@@ -4,3 +4,15 @@ pub trait Filterable {
     fn id(&self) -> String;
     fn filter(&self) -> Option<TargetFilter>;
 }
+
+impl<F> Filterable for &F
+where
+    F: Filterable,
+{
+    fn id(&self) -> String {
+        (*self).id()
+    }
+    fn filter(&self) -> Option<TargetFilter> {
+        (*self).filter()
+    }
+}
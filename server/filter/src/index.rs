@@ -1,17 +1,56 @@
 use common::types::{DimValue, UserInfo};
 use serde_json::json;
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
+use crate::bitmap::BitmapIndex;
+use crate::explain::{explain_target_key, Explanation};
 use crate::filter::*;
 use crate::filterable::Filterable;
+use crate::normalize::{Literal, NormalizeError, DEFAULT_MAX_TARGET_KEYS};
+use crate::range::{parse_number, RangeIndex};
+use crate::taxonomy::Taxonomy;
+
+/**
+ * Storage of postings. HashSet keeps "{id}_{seq}" strings,
+ * Bitmap interns target keys to u32 and keeps RoaringBitmap.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexBackend {
+    #[default]
+    HashSet,
+    Bitmap,
+}
+impl IndexBackend {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "hashset" | "hash_set" => Some(IndexBackend::HashSet),
+            "bitmap" => Some(IndexBackend::Bitmap),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct FilterIndex {
     pub all_dimensions: HashMap<String, HashSet<String>>,
     pub filters: HashMap<String, TargetFilter>,
     pub index: HashMap<DimValue, HashSet<String>>,
+    // (dimension, is_not) -> sorted intervals for range predicates.
+    pub range_index: HashMap<(String, bool), RangeIndex>,
+    // internal_id -> literals that are not on index, checked after index lookup.
+    pub residuals: HashMap<String, Vec<Literal>>,
     pub non_filter_ids: HashSet<String>,
+    // filter that expands into more target keys than this is rejected on update.
+    pub max_target_keys: usize,
+    // index/range_index/residuals above are only used by HashSet backend.
+    pub backend: IndexBackend,
+    pub bitmap: BitmapIndex,
+    // user values are expanded with their ancestors before search.
+    pub taxonomy: Option<Arc<Taxonomy>>,
+    // id -> Prefer of its filter with weights. only ids that have any are kept.
+    pub preferences: HashMap<String, Vec<(TargetFilter, f64)>>,
 }
 impl Default for FilterIndex {
     fn default() -> Self {
@@ -19,11 +58,57 @@ impl Default for FilterIndex {
             all_dimensions: Default::default(),
             filters: Default::default(),
             index: Default::default(),
+            range_index: Default::default(),
+            residuals: Default::default(),
             non_filter_ids: Default::default(),
+            max_target_keys: DEFAULT_MAX_TARGET_KEYS,
+            backend: Default::default(),
+            bitmap: Default::default(),
+            taxonomy: None,
+            preferences: Default::default(),
         }
     }
 }
 impl FilterIndex {
+    pub fn with_max_target_keys(max_target_keys: usize) -> Self {
+        Self {
+            max_target_keys,
+            ..Default::default()
+        }
+    }
+    pub fn with_backend(backend: IndexBackend) -> Self {
+        Self {
+            backend,
+            ..Default::default()
+        }
+    }
+    /**
+     * Postings are moved to the other backend by re-indexing current filters.
+     */
+    pub fn set_backend(&mut self, backend: IndexBackend) {
+        if self.backend == backend {
+            return;
+        }
+        self.backend = backend;
+        self.index = Default::default();
+        self.range_index = Default::default();
+        self.residuals = Default::default();
+        self.bitmap = Default::default();
+
+        let filters: Vec<(String, TargetFilter)> = self
+            .filters
+            .iter()
+            .map(|(id, target_filter)| (id.clone(), target_filter.clone()))
+            .collect();
+        for (id, target_filter) in filters {
+            if let Ok(target_keys) =
+                TargetFilter::try_build_target_keys(&target_filter, self.max_target_keys)
+            {
+                self.insert_postings(&id, &target_keys);
+            }
+        }
+        self.rebuild_range_index();
+    }
     pub fn debug_index(&self) -> serde_json::Value {
         let mut index = HashMap::new();
         for (dim_value, ids) in self.index.iter() {
@@ -34,15 +119,35 @@ impl FilterIndex {
         }
         json!(index)
     }
+    pub fn debug_range_index(&self) -> serde_json::Value {
+        let mut index = HashMap::new();
+        for ((dimension, is_not), range_index) in self.range_index.iter() {
+            for (interval, ids) in range_index.postings.iter() {
+                let mut sorted_ids = Vec::from_iter(ids.iter());
+                sorted_ids.sort();
+
+                let key = format!("{is_not}.{dimension}.{}", interval.debug());
+                index.insert(key, sorted_ids);
+            }
+        }
+        json!(index)
+    }
     pub fn debug(&self) -> serde_json::Value {
+        let (index, range_index) = match self.backend {
+            IndexBackend::HashSet => (self.debug_index(), self.debug_range_index()),
+            IndexBackend::Bitmap => (self.bitmap.debug_index(), self.bitmap.debug_range_index()),
+        };
         json!({
+            "backend": format!("{:?}", self.backend),
             "all_dimensions": json!(&self.all_dimensions),
             "ids": json!(&self.filters),
-            "index": self.debug_index(),
+            "index": index,
+            "range_index": range_index,
+            "residuals": json!(&self.residuals),
             "non_filter_ids": json!(&self.non_filter_ids.borrow()),
         })
     }
-    fn build_all_dimensions<F>(filters: &Vec<F>) -> HashMap<String, HashSet<String>>
+    fn build_all_dimensions<F>(filters: &[F]) -> HashMap<String, HashSet<String>>
     where
         F: Filterable,
     {
@@ -59,7 +164,7 @@ impl FilterIndex {
         }
         dimensions
     }
-    fn add_all_dimensions<F>(&mut self, filters_to_insert: &Vec<F>)
+    fn add_all_dimensions<F>(&mut self, filters_to_insert: &[F])
     where
         F: Filterable,
     {
@@ -72,25 +177,37 @@ impl FilterIndex {
                 .extend(ids);
         }
     }
-    fn remove_all_dimensions<F>(&mut self, filters_to_delete: &Vec<F>)
+    /**
+     * ids are removed from dimensions of their previous version too, so dimension
+     * that no filter uses anymore leaves the universe.
+     */
+    fn remove_all_dimensions<F>(&mut self, filters_to_insert: &[F], filters_to_delete: &[F])
     where
         F: Filterable,
     {
         let all_dimensions = &mut self.all_dimensions;
 
+        for filter in filters_to_insert.iter().chain(filters_to_delete.iter()) {
+            let id = filter.id();
+            if let Some(prev_target_filter) = self.filters.get(&id) {
+                for dim in TargetFilter::extract_dimensions(prev_target_filter) {
+                    if let Some(prev_ids) = all_dimensions.get_mut(&dim) {
+                        prev_ids.remove(&id);
+                    }
+                }
+            }
+        }
         for (dim, ids) in Self::build_all_dimensions(filters_to_delete).iter() {
-            let prev_ids = all_dimensions
-                .entry(dim.to_string())
-                .or_insert_with(|| HashSet::new());
-
-            for id in ids {
-                prev_ids.remove(id);
+            if let Some(prev_ids) = all_dimensions.get_mut(dim) {
+                for id in ids {
+                    prev_ids.remove(id);
+                }
             }
         }
         all_dimensions.retain(|_dv, ids| !ids.is_empty());
     }
 
-    fn update_non_filter_ids<F>(&mut self, filters_to_insert: &Vec<F>, filters_to_delete: &Vec<F>)
+    fn update_non_filter_ids<F>(&mut self, filters_to_insert: &[F], filters_to_delete: &[F])
     where
         F: Filterable,
     {
@@ -109,48 +226,195 @@ impl FilterIndex {
             }
         }
     }
-    fn update_filters<F>(&mut self, filters_to_insert: &Vec<F>, filters_to_delete: &Vec<F>)
+    fn update_filters<F>(&mut self, filters_to_insert: &[F], filters_to_delete: &[F])
     where
         F: Filterable,
     {
         let filters = &mut self.filters;
+        let preferences = &mut self.preferences;
         for filter in filters_to_delete {
             filters.remove(&filter.id());
+            preferences.remove(&filter.id());
         }
         for filter in filters_to_insert {
+            preferences.remove(&filter.id());
             match filter.filter() {
                 None => {
                     filters.remove(&filter.id());
                 }
                 Some(target_filter) => {
+                    let filter_preferences = TargetFilter::extract_preferences(&target_filter);
+                    if !filter_preferences.is_empty() {
+                        preferences.insert(filter.id(), filter_preferences);
+                    }
                     filters.insert(filter.id(), target_filter);
                 }
             }
         }
     }
+    fn insert_postings(&mut self, id: &str, target_keys: &[TargetKey]) {
+        if self.backend == IndexBackend::Bitmap {
+            self.bitmap.insert(&self.all_dimensions, id, target_keys);
+            return;
+        }
+        let index = &mut self.index;
+        let range_index = &mut self.range_index;
+
+        let index_key_with_internal_ids =
+            TargetFilter::build_index_key_wth_internal_ids(&self.all_dimensions, target_keys, id);
+        for (dv, internal_id) in index_key_with_internal_ids {
+            index
+                .entry(dv)
+                .or_default()
+                .insert(internal_id);
+        }
+
+        let range_keys_with_internal_ids =
+            TargetFilter::build_range_keys_with_internal_ids(target_keys, id);
+        for (dr, internal_id) in range_keys_with_internal_ids {
+            range_index
+                .entry((dr.dimension, dr.is_not))
+                .or_default()
+                .insert(dr.interval, internal_id);
+        }
+
+        self.residuals
+            .extend(TargetFilter::build_residuals_with_internal_ids(
+                target_keys,
+                id,
+            ));
+    }
+    fn remove_postings(&mut self, id: &str, target_keys: &[TargetKey]) {
+        if self.backend == IndexBackend::Bitmap {
+            self.bitmap.remove(&self.all_dimensions, id, target_keys);
+            return;
+        }
+        let index = &mut self.index;
+        let range_index = &mut self.range_index;
+        let residuals = &mut self.residuals;
+
+        let index_key_with_internal_ids =
+            TargetFilter::build_index_key_wth_internal_ids(&self.all_dimensions, target_keys, id);
+        for (dv, internal_id) in index_key_with_internal_ids {
+            if let Some(prev_ids) = index.get_mut(&dv) {
+                prev_ids.remove(&internal_id);
+            }
+        }
+
+        let range_keys_with_internal_ids =
+            TargetFilter::build_range_keys_with_internal_ids(target_keys, id);
+        for (dr, internal_id) in range_keys_with_internal_ids {
+            if let Some(prev_ranges) = range_index.get_mut(&(dr.dimension, dr.is_not)) {
+                prev_ranges.remove(&dr.interval, &internal_id);
+            }
+        }
+
+        // residuals of an older version can be on target key that has none now.
+        for index in 0..target_keys.len() {
+            residuals.remove(&TargetFilter::internal_id(id, index));
+        }
+    }
+    fn insert_empty_postings(
+        &mut self,
+        id: &str,
+        target_keys: &[TargetKey],
+        dimensions: &HashSet<String>,
+    ) {
+        let empty_keys = TargetFilter::build_empty_keys_with_seqs(dimensions, target_keys);
+        if self.backend == IndexBackend::Bitmap {
+            self.bitmap.insert_index_keys(id, target_keys, empty_keys);
+            return;
+        }
+        for (dv, index) in empty_keys {
+            self.index
+                .entry(dv)
+                .or_default()
+                .insert(TargetFilter::internal_id(id, index));
+        }
+    }
+    fn remove_dimension_postings(&mut self, dimension: &str) {
+        self.index.retain(|dv, _ids| dv.dimension != dimension);
+        self.range_index
+            .retain(|(range_dimension, _is_not), _ranges| range_dimension != dimension);
+        self.bitmap.remove_dimension(dimension);
+    }
+    /**
+     * Every indexed filter has empty posting on each dimension of universe that it
+     * doesn't target. when universe changes, filters untouched by this update are
+     * backfilled on added dimensions, and postings of removed dimensions are dropped.
+     * inserted filters are already indexed with the new universe.
+     */
+    fn reindex_dimension_changes<F>(
+        &mut self,
+        prev_dimensions: &HashSet<String>,
+        filters_to_insert: &[F],
+        filters_to_delete: &[F],
+    ) where
+        F: Filterable,
+    {
+        let removed_dimensions: Vec<String> = prev_dimensions
+            .iter()
+            .filter(|dimension| !self.all_dimensions.contains_key(*dimension))
+            .cloned()
+            .collect();
+        for dimension in removed_dimensions {
+            self.remove_dimension_postings(&dimension);
+        }
+
+        let added_dimensions: HashSet<String> = self
+            .all_dimensions
+            .keys()
+            .filter(|dimension| !prev_dimensions.contains(*dimension))
+            .cloned()
+            .collect();
+        if added_dimensions.is_empty() {
+            return;
+        }
+        let touched_ids: HashSet<String> = filters_to_insert
+            .iter()
+            .chain(filters_to_delete.iter())
+            .map(|filter| filter.id())
+            .collect();
+        let untouched_filters: Vec<(String, TargetFilter)> = self
+            .filters
+            .iter()
+            .filter(|(id, _target_filter)| !touched_ids.contains(*id))
+            .map(|(id, target_filter)| (id.clone(), target_filter.clone()))
+            .collect();
+        for (id, target_filter) in untouched_filters {
+            if let Ok(target_keys) =
+                TargetFilter::try_build_target_keys(&target_filter, self.max_target_keys)
+            {
+                self.insert_empty_postings(&id, &target_keys, &added_dimensions);
+            }
+        }
+    }
+    fn rebuild_range_index(&mut self) {
+        // intervals changed by cleanup or insert are applied to tree at once.
+        for ranges in self.range_index.values_mut() {
+            ranges.rebuild();
+        }
+        self.bitmap.rebuild();
+    }
     fn remove_filter_from_index<F>(&mut self, filter: &F)
     where
         F: Filterable,
     {
-        let all_dimensions = &self.all_dimensions;
-        let filters = &self.filters;
-        let index = &mut self.index;
+        let prev_target_filter = self.filters.get(&filter.id()).cloned().or(filter.filter());
 
-        if let Some(prev_target_filter) = filters.get(&filter.id()).or(filter.filter().as_ref()) {
-            let index_key_with_internal_ids = TargetFilter::build_index_key_wth_internal_ids(
-                &all_dimensions,
-                prev_target_filter,
-                &filter.id(),
-            );
-
-            for (dv, internal_id) in index_key_with_internal_ids {
-                if let Some(prev_ids) = index.get_mut(&dv) {
-                    prev_ids.remove(&internal_id);
-                }
-            }
+        if let Some(prev_target_filter) = prev_target_filter {
+            // filter that has been rejected by max_target_keys has nothing on index.
+            let target_keys = match TargetFilter::try_build_target_keys(
+                &prev_target_filter,
+                self.max_target_keys,
+            ) {
+                Ok(target_keys) => target_keys,
+                Err(_) => return,
+            };
+            self.remove_postings(&filter.id(), &target_keys);
         }
     }
-    fn cleaup_index<F>(&mut self, filters_to_insert: &Vec<F>, filters_to_delete: &Vec<F>)
+    fn cleaup_index<F>(&mut self, filters_to_insert: &[F], filters_to_delete: &[F])
     where
         F: Filterable,
     {
@@ -164,61 +428,110 @@ impl FilterIndex {
 
         let index = &mut self.index;
         index.retain(|_dv, internal_ids| !internal_ids.is_empty());
+
+        let range_index = &mut self.range_index;
+        range_index.retain(|_key, ranges| !ranges.is_empty());
+
+        self.bitmap.cleanup();
     }
-    fn update_index<F>(&mut self, filters_to_insert: &Vec<F>)
+    fn update_index<F>(&mut self, filters_to_insert: &[F])
     where
         F: Filterable,
     {
-        let all_dimensons = &mut self.all_dimensions;
-        let index = &mut self.index;
-
         for filter in filters_to_insert {
-            if let Some(target_filter) = filter.filter() {
-                let index_key_with_internal_ids = TargetFilter::build_index_key_wth_internal_ids(
-                    &all_dimensons,
-                    &target_filter,
-                    &filter.id(),
-                );
-                for (dv, internal_id) in index_key_with_internal_ids {
-                    index
-                        .entry(dv)
-                        .or_insert_with(|| HashSet::new())
-                        .insert(internal_id);
-                }
+            let target_keys = filter.filter().and_then(|target_filter| {
+                TargetFilter::try_build_target_keys(&target_filter, self.max_target_keys).ok()
+            });
+            if let Some(target_keys) = target_keys {
+                self.insert_postings(&filter.id(), &target_keys);
             }
         }
+        self.rebuild_range_index();
     }
 
-    pub fn update<F>(&mut self, filters_to_insert: &Vec<F>, filters_to_delete: &Vec<F>) -> ()
+    /**
+     * Filter that expands into more than max_target_keys target keys is not indexed.
+     * it is removed from index as if it was deleted, and returned with the reason.
+     */
+    pub fn update<F>(
+        &mut self,
+        filters_to_insert: &[F],
+        filters_to_delete: &[F],
+    ) -> Vec<(String, NormalizeError)>
     where
         F: Filterable,
     {
+        // when id is duplicated in a batch, only the last insert is applied and it wins over delete.
+        let last_positions: HashMap<String, usize> = filters_to_insert
+            .iter()
+            .enumerate()
+            .map(|(position, filter)| (filter.id(), position))
+            .collect();
+        let mut rejected = Vec::new();
+        let mut inserts = Vec::new();
+        let mut deletes: Vec<&F> = filters_to_delete
+            .iter()
+            .filter(|filter| !last_positions.contains_key(&filter.id()))
+            .collect();
+        for (position, filter) in filters_to_insert.iter().enumerate() {
+            if last_positions.get(&filter.id()) != Some(&position) {
+                continue;
+            }
+            let target_keys = filter.filter().map(|target_filter| {
+                TargetFilter::try_build_target_keys(&target_filter, self.max_target_keys)
+            });
+            match target_keys {
+                Some(Err(e)) => {
+                    rejected.push((filter.id(), e));
+                    deletes.push(filter);
+                }
+                _ => inserts.push(filter),
+            }
+        }
+        let filters_to_insert = &inserts;
+        let filters_to_delete = &deletes;
+
+        let prev_dimensions: HashSet<String> = self.all_dimensions.keys().cloned().collect();
+
         // order of function calls is important.
-        self.add_all_dimensions(filters_to_insert);
         self.update_non_filter_ids(filters_to_insert, filters_to_delete);
 
+        // previous versions are removed with the universe they have been indexed with.
         self.cleaup_index(filters_to_insert, filters_to_delete);
+        self.remove_all_dimensions(filters_to_insert, filters_to_delete);
+        self.add_all_dimensions(filters_to_insert);
         self.update_index(filters_to_insert);
 
         self.update_filters(filters_to_insert, filters_to_delete);
-        self.remove_all_dimensions(filters_to_delete);
+        self.reindex_dimension_changes(&prev_dimensions, filters_to_insert, filters_to_delete);
+
+        rejected
     }
 
     fn generate_dimension_candidates<'a>(
+        &'a self,
         user_info: &UserInfo,
         dimension: &String,
-        index: &'a HashMap<DimValue, HashSet<String>>,
         is_not: bool,
     ) -> HashSet<&'a str> {
+        let index = &self.index;
         let mut union: HashSet<&str> = HashSet::new();
-        // empty key need to be lookup.
-        let empty_dim_value = DimValue::new(dimension, "empty", is_not);
-        if let Some(internal_ids) = index.get(&empty_dim_value) {
-            for internal_id in internal_ids {
-                union.insert(internal_id);
+        // reserved empty key need to be lookup. only positive postings have it.
+        if !is_not {
+            if let Some(internal_ids) = index.get(&DimValue::empty(dimension)) {
+                for internal_id in internal_ids {
+                    union.insert(internal_id);
+                }
             }
         }
         if let Some(values) = user_info.get(dimension) {
+            if !values.is_empty() {
+                if let Some(internal_ids) = index.get(&DimValue::exists(dimension, is_not)) {
+                    for internal_id in internal_ids {
+                        union.insert(internal_id);
+                    }
+                }
+            }
             for value in values {
                 let dim_value = DimValue::new(dimension, value, is_not);
                 if let Some(internal_ids) = index.get(&dim_value) {
@@ -227,6 +540,16 @@ impl FilterIndex {
                     }
                 }
             }
+            // numeric values are looked up on range index.
+            if let Some(range_index) = self.range_index.get(&(dimension.clone(), is_not)) {
+                for number in values.iter().flat_map(|value| parse_number(value)) {
+                    for internal_ids in range_index.search(number) {
+                        for internal_id in internal_ids {
+                            union.insert(internal_id);
+                        }
+                    }
+                }
+            }
         }
         //println!("{:?} {:?} candidates {:?}", is_not, dimension, union);
         union
@@ -242,12 +565,10 @@ impl FilterIndex {
     }
     fn search_positive_internal_ids(&self, user_info: &UserInfo) -> Option<HashSet<&str>> {
         let all_dimensions = &self.all_dimensions;
-        let true_index = &self.index;
         let mut positive_candidates: Option<HashSet<&str>> = None;
 
         for (dimension, _ids) in all_dimensions.iter() {
-            let dim_candidates =
-                Self::generate_dimension_candidates(user_info, dimension, true_index, false);
+            let dim_candidates = self.generate_dimension_candidates(user_info, dimension, false);
             let intersections = positive_candidates
                 .map(|prev| {
                     let mut intersections = HashSet::new();
@@ -263,30 +584,123 @@ impl FilterIndex {
         //println!("positive candidates: {:?}", positive_candidates);
         positive_candidates
     }
-    fn search_negative_ids(&self, user_info: &UserInfo) -> HashSet<&str> {
+    fn search_negative_internal_ids(&self, user_info: &UserInfo) -> HashSet<&str> {
         let all_dimensions = &self.all_dimensions;
-        let index = &self.index;
         let mut union = HashSet::new();
 
         for (dimension, _ids) in all_dimensions.iter() {
-            let dim_candidates =
-                Self::generate_dimension_candidates(user_info, dimension, &index, true);
+            let dim_candidates = self.generate_dimension_candidates(user_info, dimension, true);
             union.extend(dim_candidates);
         }
 
         union
     }
+    fn check_residuals(&self, internal_id: &str, user_info: &UserInfo) -> bool {
+        match self.residuals.get(internal_id) {
+            None => true,
+            Some(literals) => literals.iter().all(|literal| literal.apply(user_info)),
+        }
+    }
+    /**
+     * Why id does or doesn't match user_info. every target key of stored filter is
+     * evaluated per dimension, including implicit empty and Not exclusions.
+     */
+    pub fn explain(&self, user_info: &UserInfo, id: &str) -> Explanation {
+        let user_info = &self.expand(user_info);
+        let non_filter = self.non_filter_ids.contains(id);
+        let filter = self.filters.get(id).cloned();
+        let target_keys = filter
+            .as_ref()
+            .and_then(|target_filter| {
+                TargetFilter::try_build_target_keys(target_filter, self.max_target_keys).ok()
+            })
+            .unwrap_or_default();
+
+        Explanation {
+            id: id.to_string(),
+            non_filter,
+            indexed: filter.is_some(),
+            target_keys: target_keys
+                .iter()
+                .enumerate()
+                .map(|(index, target_key)| {
+                    let internal_id = TargetFilter::internal_id(id, index);
+                    explain_target_key(&self.all_dimensions, internal_id, target_key, user_info)
+                })
+                .collect(),
+            matched: non_filter || self.search(user_info).contains(id),
+            filter,
+        }
+    }
+    /**
+     * Taxonomy is consulted on search instead of on indexing, so it can be changed
+     * without re-indexing.
+     */
+    pub fn set_taxonomy(&mut self, taxonomy: Option<Arc<Taxonomy>>) {
+        self.taxonomy = taxonomy;
+    }
+    fn expand<'a>(&self, user_info: &'a UserInfo) -> Cow<'a, UserInfo> {
+        match &self.taxonomy {
+            Some(taxonomy) if !taxonomy.is_empty() => Cow::Owned(taxonomy.expand(user_info)),
+            _ => Cow::Borrowed(user_info),
+        }
+    }
     pub fn search(&self, user_info: &UserInfo) -> HashSet<&str> {
+        let user_info = &self.expand(user_info);
+        // without any dimension every filter is constant(ex: And[]) and nothing is on
+        // dimension index, so filters are evaluated directly.
+        if self.all_dimensions.is_empty() {
+            return self
+                .filters
+                .iter()
+                .filter(|(_id, target_filter)| target_filter.apply(user_info))
+                .map(|(id, _target_filter)| id.as_str())
+                .collect();
+        }
+        if self.backend == IndexBackend::Bitmap {
+            return self.bitmap.search(&self.all_dimensions, user_info);
+        }
         let positive_candidates = self
             .search_positive_internal_ids(user_info)
             .unwrap_or(HashSet::default());
-        let negative_candidates = self.search_negative_ids(user_info);
-        let ids = &positive_candidates - &negative_candidates;
+        let negative_candidates = self.search_negative_internal_ids(user_info);
+        let ids: HashSet<&str> = (&positive_candidates - &negative_candidates)
+            .into_iter()
+            .filter(|internal_id| self.check_residuals(internal_id, user_info))
+            .collect();
         let matched_ids = Self::to_ids(&ids);
 
         //println!("index search: {:?}", matched_ids);
         matched_ids
     }
+    /**
+     * Match score of each id, as sum of weights of its Prefer that hold for user.
+     * id without preferences(including non filter ids) scores 0.
+     */
+    pub fn match_scores<'a, I>(&self, user_info: &UserInfo, ids: I) -> HashMap<&'a str, f64>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let user_info = &self.expand(user_info);
+        ids.into_iter()
+            .map(|id| {
+                let score = self
+                    .preferences
+                    .get(id)
+                    .map(|preferences| TargetFilter::match_score(preferences, user_info))
+                    .unwrap_or(0.0);
+                (id, score)
+            })
+            .collect()
+    }
+    /**
+     * search with match score of each matched id. boolean matching is the same as search,
+     * preferences only order the matched ids.
+     */
+    pub fn search_with_scores(&self, user_info: &UserInfo) -> HashMap<&str, f64> {
+        let ids = self.search(user_info);
+        self.match_scores(user_info, ids)
+    }
 }
 
 #[cfg(test)]
@@ -1,30 +1,19 @@
 use super::*;
 
+use crate::normalize::{Literal, NormalizeError};
+use crate::range::{DimRange, Interval};
 use crate::serde;
+use crate::test_util::{
+    self, step_strategy, to_updates, user_info_of, user_info_strategy, TestFilter,
+};
+use proptest::prelude::*;
 use serde_json::Value;
 use std::collections::HashSet;
 
-struct TestFilter {
-    id: String,
-    filter: String,
-}
-impl TestFilter {
-    fn from(other: &TestFilter) -> Self {
-        Self {
-            id: String::from(&other.id),
-            filter: String::from(&other.filter),
-        }
-    }
-}
-impl Filterable for TestFilter {
-    fn id(&self) -> String {
-        String::from(&self.id)
-    }
-
-    fn filter(&self) -> Option<TargetFilter> {
-        let value: Value = serde_json::from_str(&self.filter).ok()?;
-        serde::from_json(&value)
-    }
+// filter as it is stored on db, None when it is not a valid filter.
+fn json_filter(filter: &str) -> Option<TargetFilter> {
+    let value: Value = serde_json::from_str(filter).ok()?;
+    serde::from_json(&value)
 }
 const FILTER_1_STR: &str = r#"
     {
@@ -79,69 +68,62 @@ const FILTER_1_STR: &str = r#"
 fn test_filter_1(id: &str) -> TestFilter {
     TestFilter {
         id: String::from(id),
-        filter: String::from(FILTER_1_STR),
+        filter: json_filter(FILTER_1_STR),
     }
 }
 fn test_filter_1_expected_target_keys() -> Vec<TargetKey> {
     vec![
-        TargetKey {
-            dim_values: vec![DimValue::new("age", "10", false)],
-        },
         TargetKey {
             dim_values: vec![
                 DimValue::new("age", "10", false),
-                DimValue::new("gender", "F", false),
-                DimValue::new("interests", "L2,L3", true),
+                DimValue::new("age", "20", false),
             ],
-        },
-        TargetKey {
-            dim_values: vec![DimValue::new("age", "20", false)],
+            ..Default::default()
         },
         TargetKey {
             dim_values: vec![
+                DimValue::new("age", "10", false),
                 DimValue::new("age", "20", false),
                 DimValue::new("gender", "F", false),
                 DimValue::new("interests", "L2,L3", true),
             ],
+            ..Default::default()
         },
         TargetKey {
             dim_values: vec![
                 DimValue::new("gender", "F", false),
                 DimValue::new("interests", "L1", false),
             ],
+            ..Default::default()
         },
     ]
 }
 fn test_filter_1_expected_true_index(id: &str) -> HashMap<DimValue, HashSet<String>> {
     HashMap::from([
         (
-            DimValue::new("gender", "empty", false),
-            HashSet::from([
-                format!("{id}_{seq}", id = id, seq = "0"),
-                format!("{id}_{seq}", id = id, seq = "2"),
-            ]),
+            DimValue::empty("gender"),
+            HashSet::from([format!("{id}_{seq}", id = id, seq = "0")]),
         ),
         (
-            DimValue::new("age", "empty", false),
-            HashSet::from([format!("{id}_{seq}", id = id, seq = "4")]),
+            DimValue::empty("age"),
+            HashSet::from([format!("{id}_{seq}", id = id, seq = "2")]),
         ),
         (
             DimValue::new("interests", "L1", false),
-            HashSet::from([format!("{id}_{seq}", id = id, seq = "4")]),
+            HashSet::from([format!("{id}_{seq}", id = id, seq = "2")]),
         ),
         (
             DimValue::new("age", "20", false),
             HashSet::from([
-                format!("{id}_{seq}", id = id, seq = "2"),
-                format!("{id}_{seq}", id = id, seq = "3"),
+                format!("{id}_{seq}", id = id, seq = "0"),
+                format!("{id}_{seq}", id = id, seq = "1"),
             ]),
         ),
         (
             DimValue::new("gender", "F", false),
             HashSet::from([
                 format!("{id}_{seq}", id = id, seq = "1"),
-                format!("{id}_{seq}", id = id, seq = "3"),
-                format!("{id}_{seq}", id = id, seq = "4"),
+                format!("{id}_{seq}", id = id, seq = "2"),
             ]),
         ),
         (
@@ -151,16 +133,17 @@ fn test_filter_1_expected_true_index(id: &str) -> HashMap<DimValue, HashSet<Stri
                 format!("{id}_{seq}", id = id, seq = "1"),
             ]),
         ),
+        // dimension that only has negative values on target key is empty on positive side.
         (
-            DimValue::new("interests", "empty", false),
+            DimValue::empty("interests"),
             HashSet::from([
                 format!("{id}_{seq}", id = id, seq = "0"),
-                format!("{id}_{seq}", id = id, seq = "2"),
+                format!("{id}_{seq}", id = id, seq = "1"),
             ]),
         ),
         (
             DimValue::new("interests", "L2,L3", true),
-            HashSet::from([format!("{id}", id = id)]),
+            HashSet::from([format!("{id}_{seq}", id = id, seq = "1")]),
         ),
     ])
 }
@@ -175,7 +158,7 @@ fn test_filter_1_index() {
     let filters: Vec<TestFilter> = vec![filter];
 
     let mut filter_index = FilterIndex::default();
-    filter_index.update(&filters, &vec![]);
+    filter_index.update(&filters, &[]);
 
     let id = &filters[0].id;
     let expected_true_index = test_filter_1_expected_true_index(id);
@@ -188,7 +171,7 @@ fn test_new_filter_index() {
     let filter = test_filter_1(id);
     let filters: Vec<TestFilter> = vec![filter];
     let mut filter_index = FilterIndex::default();
-    filter_index.update(&filters, &vec![]);
+    filter_index.update(&filters, &[]);
     let user_info = HashMap::from([(
         String::from("age"),
         HashSet::from([String::from("10"), String::from("20")]),
@@ -197,7 +180,7 @@ fn test_new_filter_index() {
     let internal_ids = filter_index
         .search_positive_internal_ids(&user_info)
         .unwrap_or(HashSet::new());
-    let expected_internal_ids = HashSet::from(["AD_1_0", "AD_1_2"]);
+    let expected_internal_ids = HashSet::from(["AD_1_0"]);
     println!("{:?}", internal_ids);
     assert_eq!(internal_ids, expected_internal_ids);
 }
@@ -210,7 +193,7 @@ fn test_update_index_when_filter_changed() {
     )]);
     let filter = TestFilter {
         id: String::from(id),
-        filter: String::from(
+        filter: json_filter(
             r#"
             {"type": "in", "dimension": "age", "values": ["10"]}
             "#,
@@ -218,13 +201,13 @@ fn test_update_index_when_filter_changed() {
     };
     let no_filter = TestFilter {
         id: String::from(id),
-        filter: String::from(r#"{}"#),
+        filter: json_filter(r#"{}"#),
     };
 
     // prev_filter: None, current_filter: Some
     let mut index = FilterIndex::default();
     {
-        index.update(&vec![TestFilter::from(&no_filter)], &vec![]);
+        index.update(std::slice::from_ref(&no_filter), &[]);
 
         let result = index.search(&user_info);
 
@@ -233,7 +216,7 @@ fn test_update_index_when_filter_changed() {
         assert_eq!(index.filters.contains_key(id), false);
     }
     {
-        index.update(&vec![filter], &vec![]);
+        index.update(&[filter], &[]);
 
         let result = index.search(&user_info);
         assert_eq!(result.contains(id), true);
@@ -242,7 +225,7 @@ fn test_update_index_when_filter_changed() {
     }
     {
         // prev_filter: Some, current_filter: None
-        index.update(&vec![TestFilter::from(&no_filter)], &vec![]);
+        index.update(std::slice::from_ref(&no_filter), &[]);
         let result = index.search(&user_info);
 
         assert_eq!(result.contains(id), false);
@@ -255,7 +238,7 @@ fn test_update_index_when_filter_changed_prev_current_both_exist() {
     let id = "AD_1";
     let mut filter = TestFilter {
         id: String::from(id),
-        filter: String::from(
+        filter: json_filter(
             r#"
         {"type": "in", "dimension": "age", "values": ["10"]}
         "#,
@@ -265,7 +248,7 @@ fn test_update_index_when_filter_changed_prev_current_both_exist() {
     let user_info = HashMap::from([(String::from("age"), HashSet::from([String::from("10")]))]);
     let mut index = FilterIndex::default();
     {
-        index.update(&filters, &vec![]);
+        index.update(&filters, &[]);
 
         println!("[BEFORE]: {:?}", index);
         let result = index.search(&user_info);
@@ -275,13 +258,13 @@ fn test_update_index_when_filter_changed_prev_current_both_exist() {
     {
         filter = TestFilter {
             id: String::from(id),
-            filter: String::from(
+            filter: json_filter(
                 r#"
                 {"type": "in", "dimension": "age", "values": ["30"]}
                 "#,
             ),
         };
-        index.update(&vec![filter], &vec![]);
+        index.update(&[filter], &[]);
         println!("[AFTER]: {:?}", index);
 
         let result = index.search(&user_info);
@@ -293,7 +276,7 @@ fn test_update_index_when_filter_changed_prev_current_both_exist() {
 fn test_filter_index() {
     let filter_1 = TestFilter {
         id: String::from("AD_1"),
-        filter: String::from(
+        filter: json_filter(
             r#"
             {"type": "in", "dimension": "age", "values": ["10"]}
             "#,
@@ -301,7 +284,7 @@ fn test_filter_index() {
     };
     let filter_2 = TestFilter {
         id: String::from("AD_2"),
-        filter: String::from(
+        filter: json_filter(
             r#"
             {"type": "in", "dimension": "gender", "values": ["F"]}
             "#,
@@ -317,14 +300,8 @@ fn test_filter_index() {
             ),
         ]),
         filters: HashMap::from([
-            (
-                String::from("AD_1"),
-                TestFilter::from(&filter_1).filter().unwrap(),
-            ),
-            (
-                String::from("AD_2"),
-                TestFilter::from(&filter_2).filter().unwrap(),
-            ),
+            (String::from("AD_1"), filter_1.filter().unwrap()),
+            (String::from("AD_2"), filter_2.filter().unwrap()),
         ]),
         index: HashMap::from([
             (
@@ -336,9 +313,9 @@ fn test_filter_index() {
                 )]),
             ),
             (
-                DimValue::new("age", "empty", false),
+                DimValue::empty("age"),
                 HashSet::from([TargetFilter::to_internal_id(
-                    &DimValue::new("age", "empty", false),
+                    &DimValue::empty("age"),
                     "AD_2",
                     0,
                 )]),
@@ -352,28 +329,24 @@ fn test_filter_index() {
                 )]),
             ),
             (
-                DimValue::new("gender", "empty", false),
+                DimValue::empty("gender"),
                 HashSet::from([TargetFilter::to_internal_id(
-                    &DimValue::new("gender", "empty", false),
+                    &DimValue::empty("gender"),
                     "AD_1",
                     0,
                 )]),
             ),
         ]),
+        range_index: HashMap::new(),
         non_filter_ids: HashSet::new(),
+        ..Default::default()
     };
     let mut filter_index = FilterIndex::default();
-    filter_index.update(
-        &vec![TestFilter::from(&filter_1), TestFilter::from(&filter_2)],
-        &vec![],
-    );
+    filter_index.update(&[filter_1.clone(), filter_2.clone()], &[]);
 
     check_equal(&filter_index, &expected_filter_index);
 
-    filter_index.update(
-        &vec![],
-        &vec![TestFilter::from(&filter_1), TestFilter::from(&filter_2)],
-    );
+    filter_index.update(&[], &[filter_1.clone(), filter_2.clone()]);
     check_equal(&filter_index, &FilterIndex::default());
 }
 
@@ -389,3 +362,565 @@ fn check_equal(filter_index: &FilterIndex, expected_filter_index: &FilterIndex)
         expected_filter_index.non_filter_ids
     );
 }
+
+#[test]
+fn test_range_filter_search() {
+    let between = TestFilter {
+        id: String::from("AD_1"),
+        filter: json_filter(r#"{"type": "between", "dimension": "age", "min": 25, "max": 34}"#),
+    };
+    let gte_and_in = TestFilter {
+        id: String::from("AD_2"),
+        filter: json_filter(
+            r#"
+            {
+                "type": "and",
+                "fields": [
+                    {"type": "gte", "dimension": "purchase_count", "value": 3},
+                    {"type": "in", "dimension": "gender", "values": ["F"]}
+                ]
+            }
+            "#,
+        ),
+    };
+    let mut index = FilterIndex::default();
+    index.update(&[between, gte_and_in], &[]);
+
+    let result = index.search(&user_info_of(&[("age", &["30"])]));
+    assert_eq!(result, HashSet::from(["AD_1"]));
+
+    let result = index.search(&user_info_of(&[("age", &["35"])]));
+    assert_eq!(result, HashSet::new());
+
+    // non numeric value never matches on range.
+    let result = index.search(&user_info_of(&[("age", &["thirty"])]));
+    assert_eq!(result, HashSet::new());
+
+    let result = index.search(&user_info_of(&[
+        ("age", &["25"]),
+        ("purchase_count", &["3"]),
+        ("gender", &["F"]),
+    ]));
+    assert_eq!(result, HashSet::from(["AD_1", "AD_2"]));
+
+    let result = index.search(&user_info_of(&[
+        ("purchase_count", &["2"]),
+        ("gender", &["F"]),
+    ]));
+    assert_eq!(result, HashSet::new());
+}
+
+#[test]
+fn test_range_filter_on_same_dimension() {
+    let filter = TestFilter {
+        id: String::from("AD_1"),
+        filter: json_filter(
+            r#"
+            {
+                "type": "and",
+                "fields": [
+                    {"type": "gt", "dimension": "age", "value": 20},
+                    {"type": "lte", "dimension": "age", "value": 30}
+                ]
+            }
+            "#,
+        ),
+    };
+    // only one range per dimension is on index. the other is checked after lookup.
+    let target_keys = TargetFilter::build_target_keys(&filter.filter().unwrap());
+    assert_eq!(
+        target_keys,
+        vec![TargetKey {
+            dim_values: vec![],
+            dim_ranges: vec![DimRange::new("age", Interval::lte(30.0), false)],
+            residuals: vec![Literal::Range {
+                dimension: String::from("age"),
+                interval: Interval::gt(20.0),
+                is_not: false,
+            }],
+        }]
+    );
+
+    let mut index = FilterIndex::default();
+    index.update(&[filter], &[]);
+
+    assert_eq!(index.search(&user_info_of(&[("age", &["20"])])).len(), 0);
+    assert_eq!(index.search(&user_info_of(&[("age", &["21"])])).len(), 1);
+    assert_eq!(index.search(&user_info_of(&[("age", &["30"])])).len(), 1);
+    assert_eq!(index.search(&user_info_of(&[("age", &["31"])])).len(), 0);
+    // each predicate can be satisfied by different value of the dimension.
+    assert_eq!(
+        index.search(&user_info_of(&[("age", &["10", "50"])])).len(),
+        1
+    );
+}
+
+#[test]
+fn test_range_filter_update_and_delete() {
+    let id = "AD_1";
+    let user_info = user_info_of(&[("age", &["40"])]);
+    let filter = TestFilter {
+        id: String::from(id),
+        filter: json_filter(r#"{"type": "gt", "dimension": "age", "value": 30}"#),
+    };
+    let mut index = FilterIndex::default();
+    index.update(std::slice::from_ref(&filter), &[]);
+    assert!(index.search(&user_info).contains(id));
+
+    let changed = TestFilter {
+        id: String::from(id),
+        filter: json_filter(r#"{"type": "lt", "dimension": "age", "value": 30}"#),
+    };
+    index.update(&[changed], &[]);
+    assert!(!index.search(&user_info).contains(id));
+    assert!(index
+        .search(&user_info_of(&[("age", &["29.5"])]))
+        .contains(id));
+
+    index.update(&[], &[filter]);
+    assert!(index.range_index.is_empty());
+    assert!(index.all_dimensions.is_empty());
+}
+
+#[test]
+fn test_negation_only_excludes_its_target_key() {
+    let filter = TestFilter {
+        id: String::from("AD_1"),
+        filter: json_filter(
+            r#"
+            {
+                "type": "or",
+                "fields": [
+                    {"type": "select", "dimension": "gender", "value": "F"},
+                    {"type": "not", "field": {"type": "in", "dimension": "age", "values": ["10"]}}
+                ]
+            }
+            "#,
+        ),
+    };
+    let mut index = FilterIndex::default();
+    index.update(&[filter], &[]);
+
+    let result = index.search(&user_info_of(&[("gender", &["F"]), ("age", &["10"])]));
+    assert_eq!(result, HashSet::from(["AD_1"]));
+
+    let result = index.search(&user_info_of(&[("gender", &["M"]), ("age", &["20"])]));
+    assert_eq!(result, HashSet::from(["AD_1"]));
+
+    let result = index.search(&user_info_of(&[("gender", &["M"]), ("age", &["10"])]));
+    assert_eq!(result, HashSet::new());
+}
+
+#[test]
+fn test_in_is_not_exploded() {
+    let fields: Vec<TargetFilter> = (0..5)
+        .map(|d| TargetFilter::In {
+            dimension: format!("dim_{}", d),
+            valid_values: (0..20).map(|v| format!("{}", v)).collect(),
+        })
+        .collect();
+    let target_filter = TargetFilter::And { fields };
+    let filter = TestFilter {
+        id: String::from("AD_1"),
+        filter: Some(target_filter.clone()),
+    };
+    assert_eq!(TargetFilter::build_target_keys(&target_filter).len(), 1);
+
+    let mut index = FilterIndex::default();
+    index.update(&[filter], &[]);
+    assert_eq!(index.index.len(), 5 * 20);
+
+    let user_info = user_info_of(&[
+        ("dim_0", &["1"]),
+        ("dim_1", &["3"]),
+        ("dim_2", &["5"]),
+        ("dim_3", &["7"]),
+        ("dim_4", &["19"]),
+    ]);
+    assert_eq!(index.search(&user_info), HashSet::from(["AD_1"]));
+}
+
+#[test]
+fn test_filter_over_max_target_keys_is_rejected() {
+    // And of Or on different dimensions can't be merged, so it expands into 2^4 target keys.
+    let fields: Vec<TargetFilter> = (0..4)
+        .map(|d| TargetFilter::Or {
+            fields: vec![
+                TargetFilter::Select {
+                    dimension: format!("a_{}", d),
+                    valid_value: String::from("x"),
+                },
+                TargetFilter::Select {
+                    dimension: format!("b_{}", d),
+                    valid_value: String::from("x"),
+                },
+            ],
+        })
+        .collect();
+    let huge_filter = TestFilter {
+        id: String::from("AD_1"),
+        filter: Some(TargetFilter::And { fields }),
+    };
+    let small_filter = TestFilter {
+        id: String::from("AD_1"),
+        filter: json_filter(r#"{"type": "select", "dimension": "a_0", "value": "x"}"#),
+    };
+    let user_info = user_info_of(&[
+        ("a_0", &["x"]),
+        ("a_1", &["x"]),
+        ("a_2", &["x"]),
+        ("a_3", &["x"]),
+    ]);
+
+    let mut index = FilterIndex::with_max_target_keys(8);
+    let rejected = index.update(std::slice::from_ref(&small_filter), &[]);
+    assert_eq!(rejected, vec![]);
+    assert_eq!(index.search(&user_info), HashSet::from(["AD_1"]));
+
+    // previous version is removed as well, so rejected filter never matches.
+    let rejected = index.update(&[huge_filter], &[]);
+    assert_eq!(
+        rejected,
+        vec![(
+            String::from("AD_1"),
+            NormalizeError::TooManyTargetKeys { limit: 8 }
+        )]
+    );
+    assert_eq!(index.search(&user_info), HashSet::new());
+    assert!(!index.filters.contains_key("AD_1"));
+    assert!(!index.non_filter_ids.contains("AD_1"));
+    assert!(index.index.is_empty());
+}
+
+#[test]
+fn test_filter_without_dimension() {
+    let always_true = TestFilter {
+        id: String::from("AD_1"),
+        filter: Some(TargetFilter::And { fields: vec![] }),
+    };
+    let always_false = TestFilter {
+        id: String::from("AD_2"),
+        filter: Some(TargetFilter::Or { fields: vec![] }),
+    };
+    let mut index = FilterIndex::default();
+    index.update(&[always_true, always_false], &[]);
+
+    assert!(index.all_dimensions.is_empty());
+    assert_eq!(index.search(&user_info_of(&[])), HashSet::from(["AD_1"]));
+}
+
+#[test]
+fn test_duplicated_id_in_batch() {
+    let filter_of = |value: &str| TestFilter {
+        id: String::from("AD_1"),
+        filter: Some(test_util::select("gender", value)),
+    };
+    let mut index = FilterIndex::default();
+    // last one wins, and insert wins over delete of the same id.
+    index.update(
+        &[filter_of("F"), filter_of("M")],
+        &[TestFilter {
+            id: String::from("AD_1"),
+            filter: None,
+        }],
+    );
+
+    assert_eq!(
+        index.search(&user_info_of(&[("gender", &["F"])])),
+        HashSet::new()
+    );
+    assert_eq!(
+        index.search(&user_info_of(&[("gender", &["M"])])),
+        HashSet::from(["AD_1"])
+    );
+}
+
+#[test]
+fn test_dimension_added_and_removed() {
+    let filter_of = |id: &str, target_filter: TargetFilter| TestFilter {
+        id: String::from(id),
+        filter: Some(target_filter),
+    };
+    let gender_f = filter_of("AD_1", test_util::select("gender", "F"));
+    let age_10 = filter_of("AD_2", test_util::select("age", "10"));
+    let user_info = user_info_of(&[("gender", &["F"]), ("age", &["20"])]);
+
+    let mut index = FilterIndex::default();
+    index.update(&[gender_f], &[]);
+    assert_eq!(index.search(&user_info), HashSet::from(["AD_1"]));
+
+    // age is new to universe. AD_1 is backfilled with empty on age.
+    index.update(&[age_10], &[]);
+    assert_eq!(
+        index.index.get(&DimValue::empty("age")),
+        Some(&HashSet::from([String::from("AD_1_0")]))
+    );
+    assert_eq!(index.search(&user_info), HashSet::from(["AD_1"]));
+
+    // new version of AD_2 doesn't use age anymore, so age leaves universe.
+    let interests = filter_of("AD_2", test_util::select("interests", "L1"));
+    index.update(&[interests], &[]);
+    assert!(!index.all_dimensions.contains_key("age"));
+    assert!(!(index.index.keys().any(|dv| dv.dimension == "age")));
+    assert_eq!(index.search(&user_info), HashSet::from(["AD_1"]));
+
+    // age comes back with a range, and gender leaves with AD_1.
+    let age_range = filter_of(
+        "AD_3",
+        TargetFilter::Gte {
+            dimension: String::from("age"),
+            value: 20.0,
+        },
+    );
+    let delete = TestFilter {
+        id: String::from("AD_1"),
+        filter: None,
+    };
+    index.update(&[age_range], &[delete]);
+    assert_eq!(
+        index.all_dimensions.keys().cloned().collect::<HashSet<_>>(),
+        HashSet::from([String::from("age"), String::from("interests")])
+    );
+    assert_eq!(index.search(&user_info), HashSet::from(["AD_3"]));
+    assert_eq!(
+        index.search(&user_info_of(&[("interests", &["L1"])])),
+        HashSet::from(["AD_2"])
+    );
+}
+
+#[test]
+fn test_exists_and_missing() {
+    let filter_of = |id: &str, target_filter: TargetFilter| TestFilter {
+        id: String::from(id),
+        filter: Some(target_filter),
+    };
+    let filters = vec![
+        // user value "empty" is an ordinary value.
+        filter_of("AD_1", test_util::select("gender", "empty")),
+        filter_of(
+            "AD_2",
+            TargetFilter::Missing {
+                dimension: String::from("region"),
+            },
+        ),
+        filter_of(
+            "AD_3",
+            TargetFilter::And {
+                fields: vec![
+                    TargetFilter::Exists {
+                        dimension: String::from("region"),
+                    },
+                    TargetFilter::Not {
+                        field: Box::new(test_util::select("region", "seoul")),
+                    },
+                ],
+            },
+        ),
+    ];
+    for backend in [IndexBackend::HashSet, IndexBackend::Bitmap] {
+        let mut index = FilterIndex::with_backend(backend);
+        index.update(&filters, &[]);
+
+        assert_eq!(index.search(&user_info_of(&[])), HashSet::from(["AD_2"]));
+        assert_eq!(
+            index.search(&user_info_of(&[("gender", &["empty"]), ("region", &[])])),
+            HashSet::from(["AD_1", "AD_2"])
+        );
+        assert_eq!(
+            index.search(&user_info_of(&[("region", &["busan"])])),
+            HashSet::from(["AD_3"])
+        );
+        assert_eq!(
+            index.search(&user_info_of(&[("region", &["seoul"])])),
+            HashSet::new()
+        );
+    }
+}
+
+#[test]
+fn test_contains_all_and_at_least() {
+    let values = |values: &[&str]| values.iter().map(|v| String::from(*v)).collect();
+    let filter_of = |id: &str, target_filter: TargetFilter| TestFilter {
+        id: String::from(id),
+        filter: Some(target_filter),
+    };
+    let contains_all = TargetFilter::ContainsAll {
+        dimension: String::from("tags"),
+        values: values(&["a", "b"]),
+    };
+    let filters = vec![
+        filter_of(
+            "AD_1",
+            TargetFilter::ContainsAtLeast {
+                dimension: String::from("movies"),
+                values: values(&["m1", "m2", "m3", "m4"]),
+                n: 2,
+            },
+        ),
+        filter_of("AD_2", contains_all.clone()),
+        filter_of(
+            "AD_3",
+            TargetFilter::Not {
+                field: Box::new(contains_all),
+            },
+        ),
+    ];
+    for backend in [IndexBackend::HashSet, IndexBackend::Bitmap] {
+        let mut index = FilterIndex::with_backend(backend);
+        index.update(&filters, &[]);
+
+        assert_eq!(
+            index.search(&user_info_of(&[
+                ("movies", &["m1", "m4", "m9"]),
+                ("tags", &["a", "b", "c"])
+            ])),
+            HashSet::from(["AD_1", "AD_2"])
+        );
+        assert_eq!(
+            index.search(&user_info_of(&[
+                ("movies", &["m1", "m9"]),
+                ("tags", &["a"])
+            ])),
+            HashSet::from(["AD_3"])
+        );
+        assert_eq!(index.search(&user_info_of(&[])), HashSet::from(["AD_3"]));
+
+        let explanation = index.explain(&user_info_of(&[("movies", &["m2", "m3"])]), "AD_1");
+        assert!(explanation.matched);
+    }
+}
+
+#[test]
+fn test_prefer_scores_without_excluding() {
+    let filter_of = |id: &str, target_filter: TargetFilter| TestFilter {
+        id: String::from(id),
+        filter: Some(target_filter),
+    };
+    let prefer = |field: TargetFilter, weight: f64| TargetFilter::Prefer {
+        field: Box::new(field),
+        weight,
+    };
+    let filters = vec![
+        // require country=KR, prefer ios and young users.
+        filter_of(
+            "AD_1",
+            TargetFilter::And {
+                fields: vec![
+                    test_util::select("country", "KR"),
+                    prefer(test_util::select("os", "ios"), 2.0),
+                    prefer(
+                        TargetFilter::Lt {
+                            dimension: String::from("age"),
+                            value: 30.0,
+                        },
+                        0.5,
+                    ),
+                ],
+            },
+        ),
+        filter_of("AD_2", test_util::select("country", "KR")),
+        // preference under Not is not scored, and Not(Prefer) never holds.
+        filter_of(
+            "AD_3",
+            TargetFilter::Not {
+                field: Box::new(prefer(test_util::select("os", "ios"), 1.0)),
+            },
+        ),
+    ];
+    for backend in [IndexBackend::HashSet, IndexBackend::Bitmap] {
+        let mut index = FilterIndex::with_backend(backend);
+        index.update(&filters, &[]);
+        assert_eq!(index.preferences.keys().collect::<Vec<_>>(), vec!["AD_1"]);
+
+        assert_eq!(
+            index.search_with_scores(&user_info_of(&[
+                ("country", &["KR"]),
+                ("os", &["ios"]),
+                ("age", &["20"])
+            ])),
+            HashMap::from([("AD_1", 2.5), ("AD_2", 0.0)])
+        );
+        assert_eq!(
+            index.search_with_scores(&user_info_of(&[("country", &["KR"]), ("os", &["android"])])),
+            HashMap::from([("AD_1", 0.0), ("AD_2", 0.0)])
+        );
+        assert_eq!(
+            index.search_with_scores(&user_info_of(&[("country", &["US"]), ("os", &["ios"])])),
+            HashMap::new()
+        );
+        // unknown id scores 0.
+        assert_eq!(
+            index.match_scores(&user_info_of(&[("os", &["ios"])]), ["AD_1", "AD_9"]),
+            HashMap::from([("AD_1", 2.0), ("AD_9", 0.0)])
+        );
+
+        // new version without preference drops its scores.
+        index.update(
+            &[filter_of("AD_1", test_util::select("country", "KR"))],
+            &[],
+        );
+        assert!(index.preferences.is_empty());
+    }
+}
+
+proptest! {
+    /**
+     * search must return exactly the ids whose current filter holds for user_info,
+     * and postings must be the same as index built from scratch with current filters,
+     * while dimensions come and go over update cycles.
+     */
+    #[test]
+    fn test_search_same_as_apply(
+        steps in prop::collection::vec(step_strategy(), 1..6),
+        user_infos in prop::collection::vec(user_info_strategy(), 1..8),
+        backend in prop_oneof![Just(IndexBackend::HashSet), Just(IndexBackend::Bitmap)],
+    ) {
+        let mut index = FilterIndex::with_backend(backend);
+        let mut filters: HashMap<String, TargetFilter> = HashMap::new();
+
+        for step in steps {
+            let (inserts, deletes) = to_updates(step);
+            for filter in &deletes {
+                filters.remove(&filter.id);
+            }
+            for filter in &inserts {
+                match &filter.filter {
+                    None => filters.remove(&filter.id),
+                    Some(target_filter) => filters.insert(filter.id.clone(), target_filter.clone()),
+                };
+            }
+            index.update(&inserts, &deletes);
+
+            for user_info in &user_infos {
+                let expected: HashSet<&str> = filters
+                    .iter()
+                    .filter(|(_id, target_filter)| target_filter.apply(user_info))
+                    .map(|(id, _target_filter)| id.as_str())
+                    .collect();
+                prop_assert_eq!(
+                    index.search(user_info),
+                    expected,
+                    "user_info: {:?}, filters: {:?}",
+                    user_info,
+                    &filters
+                );
+            }
+
+            let mut fresh_index = FilterIndex::with_backend(backend);
+            let current: Vec<TestFilter> = filters
+                .iter()
+                .map(|(id, target_filter)| TestFilter {
+                    id: id.clone(),
+                    filter: Some(target_filter.clone()),
+                })
+                .collect();
+            fresh_index.update(&current, &[]);
+            prop_assert_eq!(&index.all_dimensions, &fresh_index.all_dimensions);
+            prop_assert_eq!(&index.debug()["index"], &fresh_index.debug()["index"]);
+            prop_assert_eq!(
+                &index.debug()["range_index"],
+                &fresh_index.debug()["range_index"]
+            );
+        }
+    }
+}
@@ -1,4 +1,21 @@
+pub mod bitmap;
+pub mod catalog;
+pub mod derived;
+pub mod dsl;
+pub mod explain;
 pub mod filter;
 pub mod filterable;
 pub mod index;
+pub mod normalize;
+pub mod overlap;
+pub mod range;
+pub mod segment;
 pub mod serde;
+pub mod sql;
+pub mod taxonomy;
+pub mod user_list;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(test)]
+mod test_util;
@@ -0,0 +1,715 @@
+use common::types::UserInfo;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::filter::TargetFilter;
+use crate::range::{parse_number, Interval};
+use crate::user_list::USER_LIST_DIMENSION;
+
+pub const DEFAULT_MAX_TARGET_KEYS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NormalizeError {
+    // filter can't be indexed without materializing more than limit conjunctions.
+    TooManyTargetKeys { limit: usize },
+    // InSegment has to be inlined by segment::resolve_segments before indexing.
+    UnresolvedSegment { segment_id: String },
+}
+impl std::fmt::Display for NormalizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NormalizeError::TooManyTargetKeys { limit } => write!(
+                f,
+                "filter expands into more than {} target keys(conjunctions)",
+                limit
+            ),
+            NormalizeError::UnresolvedSegment { segment_id } => {
+                write!(f, "segment {} is not resolved", segment_id)
+            }
+        }
+    }
+}
+impl std::error::Error for NormalizeError {}
+
+/**
+ * Single predicate on one dimension after negations are pushed down.
+ * user_info has set of values per dimension, so
+ * Values holds when user has any of values, Range holds when any numeric value is in interval
+ * and Exists holds when user has any value at all. Missing is negated Exists.
+ * AtLeast holds when user has n or more of values, which is checked on search as residual.
+ */
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Literal {
+    Values {
+        dimension: String,
+        values: BTreeSet<String>,
+        is_not: bool,
+    },
+    Range {
+        dimension: String,
+        interval: Interval,
+        is_not: bool,
+    },
+    Exists {
+        dimension: String,
+        is_not: bool,
+    },
+    AtLeast {
+        dimension: String,
+        values: BTreeSet<String>,
+        n: usize,
+        is_not: bool,
+    },
+}
+impl Literal {
+    // having one of values is any of values, which can be on index.
+    fn at_least(dimension: &str, values: &HashSet<String>, n: usize) -> Literal {
+        let values: BTreeSet<String> = values.iter().cloned().collect();
+        if n == 1 {
+            Literal::Values {
+                dimension: dimension.to_string(),
+                values,
+                is_not: false,
+            }
+        } else {
+            Literal::AtLeast {
+                dimension: dimension.to_string(),
+                values,
+                n,
+                is_not: false,
+            }
+        }
+    }
+    pub fn from_filter(filter: &TargetFilter) -> Option<Literal> {
+        match filter {
+            TargetFilter::In {
+                dimension,
+                valid_values,
+            } => Some(Literal::Values {
+                dimension: dimension.clone(),
+                values: valid_values.iter().cloned().collect(),
+                is_not: false,
+            }),
+            TargetFilter::Select {
+                dimension,
+                valid_value,
+            } => Some(Literal::Values {
+                dimension: dimension.clone(),
+                values: BTreeSet::from([valid_value.clone()]),
+                is_not: false,
+            }),
+            TargetFilter::Exists { dimension } => Some(Literal::Exists {
+                dimension: dimension.clone(),
+                is_not: false,
+            }),
+            TargetFilter::Missing { dimension } => Some(Literal::Exists {
+                dimension: dimension.clone(),
+                is_not: true,
+            }),
+            TargetFilter::InUserList { list_id } => Some(Literal::Values {
+                dimension: String::from(USER_LIST_DIMENSION),
+                values: BTreeSet::from([list_id.clone()]),
+                is_not: false,
+            }),
+            TargetFilter::ContainsAll { dimension, values } => {
+                Some(Self::at_least(dimension, values, values.len()))
+            }
+            TargetFilter::ContainsAtLeast {
+                dimension,
+                values,
+                n,
+            } => Some(Self::at_least(dimension, values, *n)),
+            TargetFilter::Not { field } => Self::from_filter(field).map(|literal| literal.negate()),
+            TargetFilter::And { .. }
+            | TargetFilter::Or { .. }
+            | TargetFilter::InSegment { .. }
+            | TargetFilter::Prefer { .. } => None,
+            _ => {
+                let (dimension, interval) = TargetFilter::range(filter)?;
+                Some(Literal::Range {
+                    dimension: dimension.clone(),
+                    interval,
+                    is_not: false,
+                })
+            }
+        }
+    }
+    pub fn to_filter(&self) -> TargetFilter {
+        let positive = match self {
+            Literal::Exists { dimension, is_not } => {
+                let dimension = dimension.clone();
+                return if *is_not {
+                    TargetFilter::Missing { dimension }
+                } else {
+                    TargetFilter::Exists { dimension }
+                };
+            }
+            Literal::Values {
+                dimension, values, ..
+            } => TargetFilter::In {
+                dimension: dimension.clone(),
+                valid_values: values.iter().cloned().collect(),
+            },
+            Literal::Range {
+                dimension,
+                interval,
+                ..
+            } => Self::range_to_filter(dimension, interval),
+            Literal::AtLeast {
+                dimension,
+                values,
+                n,
+                ..
+            } => TargetFilter::ContainsAtLeast {
+                dimension: dimension.clone(),
+                values: values.iter().cloned().collect(),
+                n: *n,
+            },
+        };
+        if self.is_not() {
+            TargetFilter::Not {
+                field: Box::new(positive),
+            }
+        } else {
+            positive
+        }
+    }
+    fn range_to_filter(dimension: &str, interval: &Interval) -> TargetFilter {
+        let dimension = dimension.to_string();
+        let lower = if interval.lower == f64::NEG_INFINITY {
+            None
+        } else if interval.lower_inclusive {
+            Some(TargetFilter::Gte {
+                dimension: dimension.clone(),
+                value: interval.lower,
+            })
+        } else {
+            Some(TargetFilter::Gt {
+                dimension: dimension.clone(),
+                value: interval.lower,
+            })
+        };
+        let upper = if interval.upper == f64::INFINITY {
+            None
+        } else if interval.upper_inclusive {
+            Some(TargetFilter::Lte {
+                dimension: dimension.clone(),
+                value: interval.upper,
+            })
+        } else {
+            Some(TargetFilter::Lt {
+                dimension: dimension.clone(),
+                value: interval.upper,
+            })
+        };
+        match (lower, upper) {
+            (Some(lower), None) => lower,
+            (None, Some(upper)) => upper,
+            (None, None) => TargetFilter::Gte {
+                dimension,
+                value: f64::NEG_INFINITY,
+            },
+            (Some(_), Some(_)) if interval.lower_inclusive && interval.upper_inclusive => {
+                TargetFilter::Between {
+                    dimension,
+                    min: interval.lower,
+                    max: interval.upper,
+                }
+            }
+            (Some(lower), Some(upper)) => TargetFilter::And {
+                fields: vec![lower, upper],
+            },
+        }
+    }
+    pub fn dimension(&self) -> &str {
+        match self {
+            Literal::Values { dimension, .. } => dimension,
+            Literal::Range { dimension, .. } => dimension,
+            Literal::Exists { dimension, .. } => dimension,
+            Literal::AtLeast { dimension, .. } => dimension,
+        }
+    }
+    pub fn is_not(&self) -> bool {
+        match self {
+            Literal::Values { is_not, .. } => *is_not,
+            Literal::Range { is_not, .. } => *is_not,
+            Literal::Exists { is_not, .. } => *is_not,
+            Literal::AtLeast { is_not, .. } => *is_not,
+        }
+    }
+    pub fn negate(&self) -> Literal {
+        let mut literal = self.clone();
+        match &mut literal {
+            Literal::Values { is_not, .. } => *is_not = !*is_not,
+            Literal::Range { is_not, .. } => *is_not = !*is_not,
+            Literal::Exists { is_not, .. } => *is_not = !*is_not,
+            Literal::AtLeast { is_not, .. } => *is_not = !*is_not,
+        }
+        literal
+    }
+    pub fn apply(&self, user_info: &UserInfo) -> bool {
+        let user_values = user_info.get(self.dimension());
+        let matched = match self {
+            Literal::Values { values, .. } => user_values
+                .map(|user_values| user_values.iter().any(|v| values.contains(v)))
+                .unwrap_or(false),
+            Literal::Range { interval, .. } => user_values
+                .map(|user_values| {
+                    user_values
+                        .iter()
+                        .flat_map(|v| parse_number(v))
+                        .any(|number| interval.contains(number))
+                })
+                .unwrap_or(false),
+            Literal::Exists { .. } => user_values
+                .map(|user_values| !user_values.is_empty())
+                .unwrap_or(false),
+            Literal::AtLeast { values, n, .. } => user_values
+                .map(|user_values| user_values.iter().filter(|v| values.contains(*v)).count() >= *n)
+                .unwrap_or(false),
+        };
+        matched != self.is_not()
+    }
+    /**
+     * user values that satisfy literal ignoring is_not. for negative literal,
+     * these are the values that exclude the user. AtLeast gives values of user
+     * that it counts, even when they are fewer than n.
+     */
+    pub fn matched_values(&self, user_info: &UserInfo) -> Vec<String> {
+        let mut matched: Vec<String> = user_info
+            .get(self.dimension())
+            .map(|user_values| {
+                user_values
+                    .iter()
+                    .filter(|v| match self {
+                        Literal::Values { values, .. } | Literal::AtLeast { values, .. } => {
+                            values.contains(*v)
+                        }
+                        Literal::Range { interval, .. } => parse_number(v)
+                            .map(|number| interval.contains(number))
+                            .unwrap_or(false),
+                        Literal::Exists { .. } => true,
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        matched.sort();
+        matched
+    }
+    // value of literal that doesn't depend on user_info. empty In or empty range never holds.
+    fn constant(&self) -> Option<bool> {
+        let is_empty = match self {
+            Literal::Values { values, .. } => values.is_empty(),
+            Literal::Range { interval, .. } => interval.is_empty(),
+            Literal::Exists { .. } => false,
+            // every user has 0 of values, and no user has more than all of them.
+            Literal::AtLeast { n: 0, .. } => return Some(!self.is_not()),
+            Literal::AtLeast { values, n, .. } => *n > values.len(),
+        };
+        if is_empty {
+            Some(self.is_not())
+        } else {
+            None
+        }
+    }
+}
+
+// And of nothing is true, Or of nothing is false.
+fn constant(value: bool) -> TargetFilter {
+    if value {
+        TargetFilter::And { fields: vec![] }
+    } else {
+        TargetFilter::Or { fields: vec![] }
+    }
+}
+pub fn as_constant(filter: &TargetFilter) -> Option<bool> {
+    match filter {
+        TargetFilter::And { fields } if fields.is_empty() => Some(true),
+        TargetFilter::Or { fields } if fields.is_empty() => Some(false),
+        _ => None,
+    }
+}
+
+/**
+ * Simplify literals that must hold at the same time.
+ * returns None when they can't, under the semantics that a dimension can have multiple values.
+ * so `A=x AND A=y` is kept as it is(user can have both), while
+ * `A in X AND NOT A in Y` is contradiction when X is subset of Y.
+ */
+pub fn simplify_conjunction(literals: Vec<Literal>) -> Option<Vec<Literal>> {
+    let mut by_dimension: BTreeMap<String, Vec<Literal>> = BTreeMap::new();
+    for literal in literals {
+        match literal.constant() {
+            Some(true) => continue,
+            Some(false) => return None,
+            None => {}
+        }
+        by_dimension
+            .entry(literal.dimension().to_string())
+            .or_default()
+            .push(literal);
+    }
+
+    let mut simplified = Vec::new();
+    for (dimension, literals) in by_dimension {
+        let mut positive_values: Vec<BTreeSet<String>> = Vec::new();
+        let mut positive_ranges: Vec<Interval> = Vec::new();
+        let mut negative_values: BTreeSet<String> = BTreeSet::new();
+        let mut negative_ranges: Vec<Interval> = Vec::new();
+        let mut exists = false;
+        let mut missing = false;
+        let mut at_leasts: Vec<Literal> = Vec::new();
+        for literal in literals {
+            match literal {
+                Literal::Values { values, is_not, .. } => {
+                    if is_not {
+                        negative_values.extend(values);
+                    } else {
+                        positive_values.push(values);
+                    }
+                }
+                Literal::Range {
+                    interval, is_not, ..
+                } => {
+                    if is_not {
+                        negative_ranges.push(interval);
+                    } else {
+                        positive_ranges.push(interval);
+                    }
+                }
+                Literal::Exists { is_not, .. } => {
+                    if is_not {
+                        missing = true;
+                    } else {
+                        exists = true;
+                    }
+                }
+                // kept as it is.
+                Literal::AtLeast { .. } => at_leasts.push(literal),
+            }
+        }
+        let positive_at_least = at_leasts.iter().any(|literal| !literal.is_not());
+        // user without value can't have any of values or range, and it is never excluded.
+        if missing {
+            if exists
+                || !positive_values.is_empty()
+                || !positive_ranges.is_empty()
+                || positive_at_least
+            {
+                return None;
+            }
+            simplified.push(Literal::Exists {
+                dimension,
+                is_not: true,
+            });
+            continue;
+        }
+        let excluded = |value: &String| {
+            negative_values.contains(value)
+                || parse_number(value)
+                    .map(|number| negative_ranges.iter().any(|r| r.contains(number)))
+                    .unwrap_or(false)
+        };
+        // values that are excluded by negatives can't be the matched one.
+        for values in positive_values.iter_mut() {
+            values.retain(|value| !excluded(value));
+            if values.is_empty() {
+                return None;
+            }
+        }
+        // user need a value in range, but none of value in wider negative range.
+        for interval in positive_ranges.iter() {
+            if negative_ranges.iter().any(|r| is_subset(interval, r)) {
+                return None;
+            }
+        }
+        // having any of X implies having any of superset of X.
+        positive_values.sort_by_key(|values| values.len());
+        let mut kept_values: Vec<BTreeSet<String>> = Vec::new();
+        for values in positive_values {
+            if !kept_values.iter().any(|kept| kept.is_subset(&values)) {
+                kept_values.push(values);
+            }
+        }
+        let implied_by_values = |interval: &Interval| {
+            kept_values.iter().any(|values| {
+                values.iter().all(|value| {
+                    parse_number(value)
+                        .map(|number| interval.contains(number))
+                        .unwrap_or(false)
+                })
+            })
+        };
+        let mut kept_ranges: Vec<Interval> = Vec::new();
+        positive_ranges.sort();
+        positive_ranges.dedup();
+        for (i, interval) in positive_ranges.iter().enumerate() {
+            let implied_by_range = positive_ranges
+                .iter()
+                .enumerate()
+                .any(|(j, other)| i != j && is_subset(other, interval));
+            if !implied_by_range && !implied_by_values(interval) {
+                kept_ranges.push(*interval);
+            }
+        }
+        // negative values inside of negative range are redundant.
+        negative_values.retain(|value| {
+            !parse_number(value)
+                .map(|number| negative_ranges.iter().any(|r| r.contains(number)))
+                .unwrap_or(false)
+        });
+        negative_ranges.sort();
+        negative_ranges.dedup();
+        let negative_ranges: Vec<Interval> = negative_ranges
+            .iter()
+            .enumerate()
+            .filter(|(i, interval)| {
+                !negative_ranges
+                    .iter()
+                    .enumerate()
+                    .any(|(j, other)| *i != j && is_subset(interval, other))
+            })
+            .map(|(_, interval)| *interval)
+            .collect();
+
+        // any of values, range or AtLeast already implies that user has a value.
+        if exists && kept_values.is_empty() && kept_ranges.is_empty() && !positive_at_least {
+            simplified.push(Literal::Exists {
+                dimension: dimension.clone(),
+                is_not: false,
+            });
+        }
+        for values in kept_values {
+            simplified.push(Literal::Values {
+                dimension: dimension.clone(),
+                values,
+                is_not: false,
+            });
+        }
+        for interval in kept_ranges {
+            simplified.push(Literal::Range {
+                dimension: dimension.clone(),
+                interval,
+                is_not: false,
+            });
+        }
+        if !negative_values.is_empty() {
+            simplified.push(Literal::Values {
+                dimension: dimension.clone(),
+                values: negative_values,
+                is_not: true,
+            });
+        }
+        for interval in negative_ranges {
+            simplified.push(Literal::Range {
+                dimension: dimension.clone(),
+                interval,
+                is_not: true,
+            });
+        }
+        simplified.extend(at_leasts);
+    }
+    simplified.sort();
+    Some(simplified)
+}
+fn is_subset(interval: &Interval, other: &Interval) -> bool {
+    interval.intersect(other) == *interval
+}
+/**
+ * Simplify literals where any of them need to hold.
+ * returns None when one of them always holds.
+ */
+fn simplify_disjunction(literals: Vec<Literal>) -> Option<Vec<Literal>> {
+    let mut positive_values: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut simplified = BTreeSet::new();
+    for literal in literals {
+        match literal.constant() {
+            Some(true) => return None,
+            Some(false) => continue,
+            None => {}
+        }
+        match literal {
+            // any of X or any of Y is any of X and Y.
+            Literal::Values {
+                dimension,
+                values,
+                is_not: false,
+            } => positive_values.entry(dimension).or_default().extend(values),
+            _ => {
+                simplified.insert(literal);
+            }
+        }
+    }
+    for (dimension, values) in positive_values {
+        simplified.insert(Literal::Values {
+            dimension,
+            values,
+            is_not: false,
+        });
+    }
+    // literal or its negation always holds.
+    if simplified.iter().any(|l| simplified.contains(&l.negate())) {
+        return None;
+    }
+    Some(simplified.into_iter().collect())
+}
+
+/**
+ * Push negations down to leaves, flatten nested And/Or, fold constants and
+ * dedupe children. always true filter becomes And([]), always false becomes Or([]).
+ */
+pub fn normalize(filter: &TargetFilter) -> TargetFilter {
+    normalize_inner(filter, false)
+}
+fn normalize_inner(filter: &TargetFilter, negated: bool) -> TargetFilter {
+    match filter {
+        TargetFilter::Not { field } => normalize_inner(field, !negated),
+        TargetFilter::And { fields } => {
+            let childrens = fields.iter().map(|f| normalize_inner(f, negated));
+            combine(!negated, childrens)
+        }
+        TargetFilter::Or { fields } => {
+            let childrens = fields.iter().map(|f| normalize_inner(f, negated));
+            combine(negated, childrens)
+        }
+        // kept as it is, build_conjunctions refuses it.
+        TargetFilter::InSegment { .. } if negated => TargetFilter::Not {
+            field: Box::new(filter.clone()),
+        },
+        TargetFilter::InSegment { .. } => filter.clone(),
+        // preference only scores, so it is always true for matching.
+        TargetFilter::Prefer { .. } => constant(!negated),
+        _ => {
+            let literal = Literal::from_filter(filter).unwrap();
+            let literal = if negated { literal.negate() } else { literal };
+            match literal.constant() {
+                Some(value) => constant(value),
+                None => literal.to_filter(),
+            }
+        }
+    }
+}
+fn combine<I>(is_and: bool, childrens: I) -> TargetFilter
+where
+    I: Iterator<Item = TargetFilter>,
+{
+    let mut literals = Vec::new();
+    let mut others: Vec<TargetFilter> = Vec::new();
+    let mut push =
+        |child: TargetFilter, others: &mut Vec<TargetFilter>| match Literal::from_filter(&child) {
+            Some(literal) => literals.push(literal),
+            None => {
+                if !others.contains(&child) {
+                    others.push(child)
+                }
+            }
+        };
+    for child in childrens {
+        match as_constant(&child) {
+            Some(value) if value == is_and => continue,
+            Some(value) => return constant(value),
+            None => {}
+        }
+        match child {
+            TargetFilter::And { fields } if is_and => {
+                for field in fields {
+                    push(field, &mut others);
+                }
+            }
+            TargetFilter::Or { fields } if !is_and => {
+                for field in fields {
+                    push(field, &mut others);
+                }
+            }
+            _ => push(child, &mut others),
+        }
+    }
+
+    let literals = if is_and {
+        simplify_conjunction(literals)
+    } else {
+        simplify_disjunction(literals)
+    };
+    let literals = match literals {
+        Some(literals) => literals,
+        None => return constant(!is_and),
+    };
+    let mut fields: Vec<TargetFilter> = literals.iter().map(|l| l.to_filter()).collect();
+    fields.extend(others);
+
+    if fields.len() == 1 {
+        fields.pop().unwrap()
+    } else if is_and {
+        TargetFilter::And { fields }
+    } else {
+        TargetFilter::Or { fields }
+    }
+}
+
+/**
+ * Disjunctive normal form of filter. each conjunction becomes one target key.
+ * And over Or is distributed one child at a time and unsatisfiable conjunctions are
+ * dropped on the way, so the work is bounded by max_target_keys instead of the
+ * size of full cross product.
+ */
+pub fn build_conjunctions(
+    filter: &TargetFilter,
+    max_target_keys: usize,
+) -> Result<Vec<Vec<Literal>>, NormalizeError> {
+    if let Some(segment_id) = TargetFilter::extract_segment_ids(filter).into_iter().next() {
+        return Err(NormalizeError::UnresolvedSegment { segment_id });
+    }
+    conjunctions(&normalize(filter), max_target_keys)
+}
+fn conjunctions(
+    filter: &TargetFilter,
+    max_target_keys: usize,
+) -> Result<Vec<Vec<Literal>>, NormalizeError> {
+    let too_many = NormalizeError::TooManyTargetKeys {
+        limit: max_target_keys,
+    };
+    match filter {
+        TargetFilter::And { fields } => {
+            let mut prevs: Vec<Vec<Literal>> = vec![vec![]];
+            for field in fields {
+                let childrens = conjunctions(field, max_target_keys)?;
+                let mut nexts = BTreeSet::new();
+                for prev in prevs.iter() {
+                    for child in childrens.iter() {
+                        let merged = prev.iter().chain(child.iter()).cloned().collect();
+                        if let Some(conjunction) = simplify_conjunction(merged) {
+                            nexts.insert(conjunction);
+                        }
+                        if nexts.len() > max_target_keys {
+                            return Err(too_many);
+                        }
+                    }
+                }
+                prevs = nexts.into_iter().collect();
+            }
+            Ok(prevs)
+        }
+        TargetFilter::Or { fields } => {
+            let mut nexts = BTreeSet::new();
+            for field in fields {
+                nexts.extend(conjunctions(field, max_target_keys)?);
+                if nexts.len() > max_target_keys {
+                    return Err(too_many);
+                }
+            }
+            Ok(nexts.into_iter().collect())
+        }
+        _ => {
+            let literal = Literal::from_filter(filter).unwrap();
+            if max_target_keys == 0 {
+                return Err(too_many);
+            }
+            Ok(vec![vec![literal]])
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./normalize_test.rs"]
+mod normalize_test;
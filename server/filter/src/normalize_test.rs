@@ -0,0 +1,378 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+use TargetFilter::*;
+
+fn select(dimension: &str, value: &str) -> TargetFilter {
+    Select {
+        dimension: String::from(dimension),
+        valid_value: String::from(value),
+    }
+}
+fn in_values(dimension: &str, values: &[&str]) -> TargetFilter {
+    In {
+        dimension: String::from(dimension),
+        valid_values: values.iter().map(|v| String::from(*v)).collect(),
+    }
+}
+fn not(filter: TargetFilter) -> TargetFilter {
+    Not {
+        field: Box::new(filter),
+    }
+}
+
+#[test]
+fn test_normalize_flatten_and_dedupe() {
+    let filter = And {
+        fields: vec![
+            select("gender", "F"),
+            And {
+                fields: vec![
+                    select("gender", "F"),
+                    Or {
+                        fields: vec![
+                            select("age", "10"),
+                            Or {
+                                fields: vec![select("age", "20")],
+                            },
+                        ],
+                    },
+                ],
+            },
+        ],
+    };
+    // Or of values on the same dimension is merged into single In.
+    assert_eq!(
+        normalize(&filter),
+        And {
+            fields: vec![in_values("age", &["10", "20"]), in_values("gender", &["F"])],
+        }
+    );
+}
+
+#[test]
+fn test_normalize_push_down_not() {
+    let filter = not(Or {
+        fields: vec![select("age", "10"), not(select("gender", "F"))],
+    });
+    assert_eq!(
+        normalize(&filter),
+        And {
+            fields: vec![not(in_values("age", &["10"])), in_values("gender", &["F"])],
+        }
+    );
+}
+
+#[test]
+fn test_normalize_constant_fold() {
+    let always_false = Or { fields: vec![] };
+    let always_true = And { fields: vec![] };
+
+    assert_eq!(normalize(&in_values("age", &[])), always_false);
+    assert_eq!(normalize(&not(in_values("age", &[]))), always_true);
+    assert_eq!(
+        normalize(&And {
+            fields: vec![select("gender", "F"), in_values("age", &[])],
+        }),
+        always_false
+    );
+    assert_eq!(
+        normalize(&Or {
+            fields: vec![select("gender", "F"), in_values("age", &[])],
+        }),
+        in_values("gender", &["F"])
+    );
+    assert_eq!(
+        normalize(&Between {
+            dimension: String::from("age"),
+            min: 30.0,
+            max: 20.0,
+        }),
+        always_false
+    );
+    assert_eq!(
+        normalize(&Or {
+            fields: vec![select("gender", "F"), not(select("gender", "F"))],
+        }),
+        always_true
+    );
+}
+
+#[test]
+fn test_normalize_contradiction() {
+    let always_false = Or { fields: vec![] };
+
+    assert_eq!(
+        normalize(&And {
+            fields: vec![select("gender", "F"), not(select("gender", "F"))],
+        }),
+        always_false
+    );
+    assert_eq!(
+        normalize(&And {
+            fields: vec![
+                in_values("age", &["10", "20"]),
+                not(in_values("age", &["10", "20", "30"])),
+            ],
+        }),
+        always_false
+    );
+    // value inside of negative range can't be matched.
+    assert_eq!(
+        normalize(&And {
+            fields: vec![
+                select("age", "25"),
+                not(Between {
+                    dimension: String::from("age"),
+                    min: 20.0,
+                    max: 30.0,
+                }),
+            ],
+        }),
+        always_false
+    );
+    assert_eq!(
+        normalize(&And {
+            fields: vec![
+                Gt {
+                    dimension: String::from("age"),
+                    value: 25.0,
+                },
+                not(Gte {
+                    dimension: String::from("age"),
+                    value: 20.0,
+                }),
+            ],
+        }),
+        always_false
+    );
+    // user can have both values on a dimension, so this is not contradiction.
+    assert_eq!(
+        normalize(&And {
+            fields: vec![select("interests", "L1"), select("interests", "L2")],
+        }),
+        And {
+            fields: vec![
+                in_values("interests", &["L1"]),
+                in_values("interests", &["L2"])
+            ],
+        }
+    );
+}
+
+#[test]
+fn test_simplify_conjunction() {
+    let values = |values: &[&str], is_not: bool| Literal::Values {
+        dimension: String::from("age"),
+        values: values.iter().map(|v| String::from(*v)).collect(),
+        is_not,
+    };
+    // negative values are removed from positive values.
+    assert_eq!(
+        simplify_conjunction(vec![values(&["10", "20"], false), values(&["20"], true)]),
+        Some(vec![values(&["10"], false), values(&["20"], true)])
+    );
+    // any of superset is implied by any of subset.
+    assert_eq!(
+        simplify_conjunction(vec![values(&["10", "20"], false), values(&["10"], false)]),
+        Some(vec![values(&["10"], false)])
+    );
+    // range is implied by values inside of it.
+    assert_eq!(
+        simplify_conjunction(vec![
+            values(&["10"], false),
+            Literal::Range {
+                dimension: String::from("age"),
+                interval: Interval::lt(20.0),
+                is_not: false,
+            },
+        ]),
+        Some(vec![values(&["10"], false)])
+    );
+    assert_eq!(
+        simplify_conjunction(vec![values(&["10"], false), values(&["10"], true)]),
+        None
+    );
+}
+
+#[test]
+fn test_simplify_exists_and_missing() {
+    let exists = |is_not: bool| Literal::Exists {
+        dimension: String::from("age"),
+        is_not,
+    };
+    let values = |is_not: bool| Literal::Values {
+        dimension: String::from("age"),
+        values: BTreeSet::from([String::from("10")]),
+        is_not,
+    };
+    // having any of values implies existence.
+    assert_eq!(
+        simplify_conjunction(vec![exists(false), values(false)]),
+        Some(vec![values(false)])
+    );
+    assert_eq!(
+        simplify_conjunction(vec![exists(false), values(true)]),
+        Some(vec![values(true), exists(false)])
+    );
+    // user without value can't have any of values, and is never excluded by them.
+    assert_eq!(
+        simplify_conjunction(vec![exists(true), values(false)]),
+        None
+    );
+    assert_eq!(
+        simplify_conjunction(vec![exists(true), values(true)]),
+        Some(vec![exists(true)])
+    );
+    assert_eq!(
+        simplify_conjunction(vec![exists(true), exists(false)]),
+        None
+    );
+    assert_eq!(
+        normalize(&Not {
+            field: Box::new(Missing {
+                dimension: String::from("age"),
+            }),
+        }),
+        Exists {
+            dimension: String::from("age"),
+        }
+    );
+    assert_eq!(
+        normalize(&Or {
+            fields: vec![
+                Exists {
+                    dimension: String::from("age"),
+                },
+                Missing {
+                    dimension: String::from("age"),
+                },
+            ],
+        }),
+        And { fields: vec![] }
+    );
+}
+
+#[test]
+fn test_build_conjunctions() {
+    let filter = And {
+        fields: vec![
+            Or {
+                fields: vec![select("a", "1"), select("b", "1")],
+            },
+            Or {
+                fields: vec![select("a", "1"), not(select("a", "1"))],
+            },
+            Or {
+                fields: vec![select("c", "1"), not(select("b", "1"))],
+            },
+        ],
+    };
+    let literal = |dimension: &str, is_not: bool| Literal::Values {
+        dimension: String::from(dimension),
+        values: BTreeSet::from([String::from("1")]),
+        is_not,
+    };
+    // (a or b) and (c or not b). b and not b is dropped.
+    assert_eq!(
+        build_conjunctions(&filter, DEFAULT_MAX_TARGET_KEYS),
+        Ok(vec![
+            vec![literal("a", false), literal("b", true)],
+            vec![literal("a", false), literal("c", false)],
+            vec![literal("b", false), literal("c", false)],
+        ])
+    );
+}
+
+#[test]
+fn test_build_conjunctions_limit() {
+    let fields: Vec<TargetFilter> = (0..11)
+        .map(|d| Or {
+            fields: vec![
+                select(&format!("a_{}", d), "x"),
+                select(&format!("b_{}", d), "x"),
+            ],
+        })
+        .collect();
+    let filter = And { fields };
+
+    let error = build_conjunctions(&filter, DEFAULT_MAX_TARGET_KEYS).unwrap_err();
+    assert_eq!(
+        error,
+        NormalizeError::TooManyTargetKeys {
+            limit: DEFAULT_MAX_TARGET_KEYS
+        }
+    );
+    assert!(error.to_string().contains("1024"));
+    assert_eq!(build_conjunctions(&filter, 2048).map(|c| c.len()), Ok(2048));
+}
+
+#[test]
+fn test_literal_apply() {
+    let user_info = HashMap::from([
+        (
+            String::from("interests"),
+            HashSet::from([String::from("L1"), String::from("L2")]),
+        ),
+        (String::from("age"), HashSet::from([String::from("25")])),
+    ]);
+    let literal = Literal::from_filter(&in_values("interests", &["L2", "L3"])).unwrap();
+    assert!(literal.apply(&user_info));
+    assert!(!literal.negate().apply(&user_info));
+
+    let literal = Literal::from_filter(&Lt {
+        dimension: String::from("age"),
+        value: 20.0,
+    })
+    .unwrap();
+    assert!(!literal.apply(&user_info));
+    assert!(literal.negate().apply(&user_info));
+
+    // missing dimension has no value.
+    let literal = Literal::from_filter(&select("gender", "F")).unwrap();
+    assert!(!literal.apply(&user_info));
+    assert!(literal.negate().apply(&user_info));
+}
+
+#[test]
+fn test_contains_literals() {
+    let values = |values: &[&str]| values.iter().map(|v| String::from(*v)).collect();
+    let at_least = |n: usize| ContainsAtLeast {
+        dimension: String::from("movies"),
+        values: values(&["m1", "m2", "m3"]),
+        n,
+    };
+    // 1 of values is any of them, 0 always holds and more than all never does.
+    assert_eq!(
+        normalize(&at_least(1)),
+        in_values("movies", &["m1", "m2", "m3"])
+    );
+    assert_eq!(normalize(&at_least(0)), And { fields: vec![] });
+    assert_eq!(normalize(&not(at_least(4))), And { fields: vec![] });
+    assert_eq!(
+        normalize(&ContainsAll {
+            dimension: String::from("movies"),
+            values: values(&["m1", "m2", "m3"]),
+        }),
+        at_least(3)
+    );
+    // user without value can't have 2 of them.
+    assert_eq!(
+        normalize(&And {
+            fields: vec![
+                at_least(2),
+                Missing {
+                    dimension: String::from("movies"),
+                },
+            ],
+        }),
+        Or { fields: vec![] }
+    );
+
+    let user_info = HashMap::from([(String::from("movies"), values(&["m1", "m3", "m9"]))]);
+    let literal = Literal::from_filter(&at_least(2)).unwrap();
+    assert!(literal.apply(&user_info));
+    assert_eq!(literal.matched_values(&user_info), vec!["m1", "m3"]);
+    assert!(!Literal::from_filter(&at_least(3))
+        .unwrap()
+        .apply(&user_info));
+}
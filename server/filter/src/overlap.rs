@@ -0,0 +1,507 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use common::types::UserInfo;
+use serde::Serialize;
+
+use crate::filter::{Filter, TargetFilter};
+use crate::index::FilterIndex;
+use crate::normalize::{
+    build_conjunctions, simplify_conjunction, Literal, DEFAULT_MAX_TARGET_KEYS,
+};
+use crate::range::{parse_number, Interval};
+use crate::taxonomy::Taxonomy;
+
+/**
+ * How the users matched by a relate to the ones matched by b.
+ * proven symbolically, Unknown when neither could be proven.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    Equal,
+    // every user of a is matched by b.
+    ContainedIn,
+    // every user of b is matched by a.
+    Contains,
+    // no user can be matched by both.
+    Disjoint,
+    // some user can be matched by both, and neither contains the other.
+    Intersecting,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Overlap {
+    pub a: String,
+    pub b: String,
+    pub relation: Relation,
+    // users on sample matched by both.
+    pub sampled: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OverlapReport {
+    pub sample_size: usize,
+    // id -> users on sample matched by it.
+    pub matched: BTreeMap<String, usize>,
+    pub overlaps: Vec<Overlap>,
+}
+
+/**
+ * DNF of filter. None when it has too many conjunctions to be compared.
+ * with taxonomy, conjunction also has ancestor values its values imply.
+ */
+fn conjunctions_of(
+    filter: &TargetFilter,
+    taxonomy: Option<&Taxonomy>,
+) -> Option<Vec<Vec<Literal>>> {
+    let conjunctions = build_conjunctions(filter, DEFAULT_MAX_TARGET_KEYS).ok()?;
+    Some(
+        conjunctions
+            .into_iter()
+            .map(|conjunction| match taxonomy {
+                Some(taxonomy) => with_ancestors(conjunction, taxonomy),
+                None => conjunction,
+            })
+            .flat_map(simplify_conjunction)
+            .collect(),
+    )
+}
+/**
+ * user is expanded with ancestors of its values before search, so city = Seoul
+ * also means country = KR. literal is implied only on dimension every value has
+ * an ancestor on, otherwise user may have none of them.
+ */
+fn with_ancestors(conjunction: Vec<Literal>, taxonomy: &Taxonomy) -> Vec<Literal> {
+    let mut implied = Vec::new();
+    for literal in conjunction.iter() {
+        let (dimension, values) = match literal {
+            Literal::Values {
+                dimension,
+                values,
+                is_not: false,
+            } => (dimension, values),
+            _ => continue,
+        };
+        // ancestor dimension -> ancestor values, for each value.
+        let ancestors: Vec<BTreeMap<String, BTreeSet<String>>> = values
+            .iter()
+            .map(|value| {
+                let mut by_dimension: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+                for (ancestor_dimension, ancestor_value) in taxonomy.ancestors(dimension, value) {
+                    by_dimension
+                        .entry(ancestor_dimension)
+                        .or_default()
+                        .insert(ancestor_value);
+                }
+                by_dimension
+            })
+            .collect();
+        let first = match ancestors.first() {
+            Some(first) => first,
+            None => continue,
+        };
+        for ancestor_dimension in first.keys() {
+            if !ancestors
+                .iter()
+                .all(|by_dimension| by_dimension.contains_key(ancestor_dimension))
+            {
+                continue;
+            }
+            implied.push(Literal::Values {
+                dimension: ancestor_dimension.clone(),
+                values: ancestors
+                    .iter()
+                    .flat_map(|by_dimension| by_dimension[ancestor_dimension].iter().cloned())
+                    .collect(),
+                is_not: false,
+            });
+        }
+    }
+    conjunction.into_iter().chain(implied).collect()
+}
+fn is_subset(interval: &Interval, other: &Interval) -> bool {
+    interval.is_empty() || interval.intersect(other) == *interval
+}
+/**
+ * literal x alone implies literal l, under the semantics that a dimension can have
+ * multiple values. only the cases that are cheap to prove, so false is "not proven".
+ */
+fn literal_implies(x: &Literal, l: &Literal) -> bool {
+    if x == l {
+        return true;
+    }
+    if x.dimension() != l.dimension() {
+        return false;
+    }
+    let is_missing = matches!(x, Literal::Exists { is_not: true, .. });
+    match l {
+        Literal::Values {
+            values,
+            is_not: false,
+            ..
+        } => match x {
+            Literal::Values {
+                values: x_values,
+                is_not: false,
+                ..
+            } => x_values.is_subset(values),
+            // n of x_values can't all be outside of values when too few are outside.
+            Literal::AtLeast {
+                values: x_values,
+                n,
+                is_not: false,
+                ..
+            } => x_values.difference(values).count() < *n,
+            _ => false,
+        },
+        Literal::Values {
+            values,
+            is_not: true,
+            ..
+        } => match x {
+            Literal::Values {
+                values: x_values,
+                is_not: true,
+                ..
+            } => values.is_subset(x_values),
+            _ => is_missing,
+        },
+        Literal::Range {
+            interval,
+            is_not: false,
+            ..
+        } => match x {
+            Literal::Range {
+                interval: x_interval,
+                is_not: false,
+                ..
+            } => is_subset(x_interval, interval),
+            Literal::Values {
+                values,
+                is_not: false,
+                ..
+            } => values.iter().all(|value| {
+                parse_number(value)
+                    .map(|number| interval.contains(number))
+                    .unwrap_or(false)
+            }),
+            _ => false,
+        },
+        Literal::Range {
+            interval,
+            is_not: true,
+            ..
+        } => match x {
+            Literal::Range {
+                interval: x_interval,
+                is_not: true,
+                ..
+            } => is_subset(interval, x_interval),
+            _ => is_missing,
+        },
+        Literal::Exists { is_not: false, .. } => match x {
+            Literal::AtLeast { n, is_not, .. } => !*is_not && *n > 0,
+            _ => !x.is_not(),
+        },
+        Literal::Exists { is_not: true, .. } => is_missing,
+        Literal::AtLeast {
+            values,
+            n,
+            is_not: false,
+            ..
+        } => match x {
+            // n of subset are n of superset.
+            Literal::AtLeast {
+                values: x_values,
+                n: x_n,
+                is_not: false,
+                ..
+            } => x_values.is_subset(values) && x_n >= n,
+            _ => false,
+        },
+        Literal::AtLeast {
+            values,
+            n,
+            is_not: true,
+            ..
+        } => match x {
+            // fewer than x_n of superset are fewer than x_n of subset.
+            Literal::AtLeast {
+                values: x_values,
+                n: x_n,
+                is_not: true,
+                ..
+            } => values.is_subset(x_values) && x_n <= n,
+            _ => is_missing,
+        },
+    }
+}
+fn conjunction_implies(conjunction: &[Literal], other: &[Literal]) -> bool {
+    other
+        .iter()
+        .all(|l| conjunction.iter().any(|x| literal_implies(x, l)))
+}
+// every conjunction of a implies one of b.
+fn is_contained(a: &[Vec<Literal>], b: &[Vec<Literal>]) -> bool {
+    a.iter()
+        .all(|ca| b.iter().any(|cb| conjunction_implies(ca, cb)))
+}
+fn is_disjoint(a: &[Vec<Literal>], b: &[Vec<Literal>]) -> bool {
+    a.iter().all(|ca| {
+        b.iter().all(|cb| {
+            let merged = ca.iter().chain(cb.iter()).cloned().collect();
+            simplify_conjunction(merged).is_none()
+        })
+    })
+}
+/**
+ * user_info built to satisfy conjunction. it is only a candidate, caller checks it
+ * against the filters since AtLeast and ranges are not fully taken into account.
+ */
+fn witness(conjunction: &[Literal]) -> UserInfo {
+    let mut by_dimension: BTreeMap<&str, Vec<&Literal>> = BTreeMap::new();
+    for literal in conjunction {
+        by_dimension
+            .entry(literal.dimension())
+            .or_default()
+            .push(literal);
+    }
+    let mut user_info = UserInfo::new();
+    for (dimension, literals) in by_dimension {
+        if literals
+            .iter()
+            .any(|literal| matches!(literal, Literal::Exists { is_not: true, .. }))
+        {
+            continue;
+        }
+        let excluded = |value: &String| {
+            literals.iter().any(|literal| match literal {
+                Literal::Values {
+                    values,
+                    is_not: true,
+                    ..
+                } => values.contains(value),
+                Literal::Range {
+                    interval,
+                    is_not: true,
+                    ..
+                } => parse_number(value)
+                    .map(|number| interval.contains(number))
+                    .unwrap_or(false),
+                _ => false,
+            })
+        };
+        let mut chosen: BTreeSet<String> = BTreeSet::new();
+        let mut needs_value = false;
+        for literal in literals.iter() {
+            match literal {
+                Literal::Values {
+                    values,
+                    is_not: false,
+                    ..
+                } if values.is_disjoint(&chosen) => {
+                    if let Some(value) = values.iter().find(|value| !excluded(value)) {
+                        chosen.insert(value.clone());
+                    }
+                }
+                Literal::Range {
+                    interval,
+                    is_not: false,
+                    ..
+                } => {
+                    let in_range = chosen.iter().any(|value| {
+                        parse_number(value)
+                            .map(|number| interval.contains(number))
+                            .unwrap_or(false)
+                    });
+                    if !in_range {
+                        if let Some(value) = numbers_in(interval)
+                            .into_iter()
+                            .map(|number| number.to_string())
+                            .find(|value| !excluded(value))
+                        {
+                            chosen.insert(value);
+                        }
+                    }
+                }
+                Literal::AtLeast {
+                    values,
+                    n,
+                    is_not: false,
+                    ..
+                } => {
+                    let have = values.intersection(&chosen).count();
+                    let more: Vec<String> = values
+                        .iter()
+                        .filter(|value| !chosen.contains(*value) && !excluded(value))
+                        .take(n.saturating_sub(have))
+                        .cloned()
+                        .collect();
+                    chosen.extend(more);
+                }
+                Literal::Exists { is_not: false, .. } => needs_value = true,
+                _ => {}
+            }
+        }
+        if needs_value && chosen.is_empty() {
+            // value no literal refers to.
+            let mut value = String::from("*");
+            while excluded(&value) {
+                value.push('*');
+            }
+            chosen.insert(value);
+        }
+        if !chosen.is_empty() {
+            user_info.insert(dimension.to_string(), chosen.into_iter().collect());
+        }
+    }
+    user_info
+}
+// a few numbers in interval, including its bounds when they are inclusive.
+fn numbers_in(interval: &Interval) -> Vec<f64> {
+    let (lower, upper) = (interval.lower, interval.upper);
+    let candidates = match (lower.is_finite(), upper.is_finite()) {
+        (true, true) => vec![lower, upper, (lower + upper) / 2.0],
+        (true, false) => vec![lower, lower + 1.0],
+        (false, true) => vec![upper, upper - 1.0],
+        (false, false) => vec![0.0],
+    };
+    candidates
+        .into_iter()
+        .filter(|number| interval.contains(*number))
+        .collect()
+}
+/**
+ * Relation between two filters. filter that matches nobody is disjoint with everything.
+ * None filter(non filter id) matches everyone.
+ * taxonomy is the one of index, so that relation holds for users as they are on search.
+ */
+pub fn relation(
+    a: Option<&TargetFilter>,
+    b: Option<&TargetFilter>,
+    taxonomy: Option<&Taxonomy>,
+) -> Relation {
+    let always = TargetFilter::And { fields: vec![] };
+    let a = a.unwrap_or(&always);
+    let b = b.unwrap_or(&always);
+    relation_of(
+        (a, conjunctions_of(a, taxonomy).as_deref()),
+        (b, conjunctions_of(b, taxonomy).as_deref()),
+        taxonomy,
+    )
+}
+// relation of filters with their conjunctions already built.
+fn relation_of(
+    (a, a_conjunctions): (&TargetFilter, Option<&[Vec<Literal>]>),
+    (b, b_conjunctions): (&TargetFilter, Option<&[Vec<Literal>]>),
+    taxonomy: Option<&Taxonomy>,
+) -> Relation {
+    let (a_conjunctions, b_conjunctions) = match (a_conjunctions, b_conjunctions) {
+        (Some(a_conjunctions), Some(b_conjunctions)) => (a_conjunctions, b_conjunctions),
+        _ => return Relation::Unknown,
+    };
+    if a_conjunctions.is_empty() || b_conjunctions.is_empty() {
+        return Relation::Disjoint;
+    }
+    match (
+        is_contained(a_conjunctions, b_conjunctions),
+        is_contained(b_conjunctions, a_conjunctions),
+    ) {
+        (true, true) => return Relation::Equal,
+        (true, false) => return Relation::ContainedIn,
+        (false, true) => return Relation::Contains,
+        _ => {}
+    }
+    if is_disjoint(a_conjunctions, b_conjunctions) {
+        return Relation::Disjoint;
+    }
+    let is_both = |user_info: &UserInfo| {
+        let user_info = match taxonomy {
+            Some(taxonomy) => taxonomy.expand(user_info),
+            None => user_info.clone(),
+        };
+        a.apply(&user_info) && b.apply(&user_info)
+    };
+    for ca in a_conjunctions.iter() {
+        for cb in b_conjunctions.iter() {
+            let merged: Vec<Literal> = ca.iter().chain(cb.iter()).cloned().collect();
+            if let Some(merged) = simplify_conjunction(merged) {
+                if is_both(&witness(&merged)) {
+                    return Relation::Intersecting;
+                }
+            }
+        }
+    }
+    Relation::Unknown
+}
+/**
+ * Pairwise relation of every id on index, with the number of users on sample that
+ * each id and each pair match, the same way as search.
+ */
+pub fn analyze(index: &FilterIndex, sample: &[UserInfo]) -> OverlapReport {
+    let mut ids: Vec<&str> = index
+        .filters
+        .keys()
+        .chain(index.non_filter_ids.iter())
+        .map(|id| id.as_str())
+        .collect();
+    ids.sort();
+    ids.dedup();
+
+    let mut matched: BTreeMap<String, usize> = ids.iter().map(|id| (id.to_string(), 0)).collect();
+    let mut sampled: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    for user_info in sample {
+        let found = index.search(user_info);
+        let mut user_ids: Vec<&str> = ids
+            .iter()
+            .copied()
+            .filter(|id| index.non_filter_ids.contains(*id) || found.contains(id))
+            .collect();
+        user_ids.sort();
+        for (i, a) in user_ids.iter().enumerate() {
+            *matched.entry(a.to_string()).or_default() += 1;
+            for b in user_ids[i + 1..].iter() {
+                *sampled.entry((*a, *b)).or_default() += 1;
+            }
+        }
+    }
+
+    // conjunctions are built once per id, not once per pair.
+    let taxonomy = index.taxonomy.as_deref();
+    let always = TargetFilter::And { fields: vec![] };
+    let filters: Vec<(&TargetFilter, Option<Vec<Vec<Literal>>>)> = ids
+        .iter()
+        .map(|id| {
+            let filter = index.filters.get(*id).unwrap_or(&always);
+            (filter, conjunctions_of(filter, taxonomy))
+        })
+        .collect();
+
+    let mut overlaps = Vec::new();
+    for (i, a) in ids.iter().enumerate() {
+        for (j, b) in ids.iter().enumerate().skip(i + 1) {
+            let (a_filter, a_conjunctions) = &filters[i];
+            let (b_filter, b_conjunctions) = &filters[j];
+            overlaps.push(Overlap {
+                a: a.to_string(),
+                b: b.to_string(),
+                relation: relation_of(
+                    (a_filter, a_conjunctions.as_deref()),
+                    (b_filter, b_conjunctions.as_deref()),
+                    taxonomy,
+                ),
+                sampled: sampled.get(&(*a, *b)).copied().unwrap_or(0),
+            });
+        }
+    }
+    OverlapReport {
+        sample_size: sample.len(),
+        matched,
+        overlaps,
+    }
+}
+
+#[cfg(test)]
+#[path = "./overlap_test.rs"]
+mod overlap_test;
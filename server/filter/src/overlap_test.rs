@@ -0,0 +1,228 @@
+use super::*;
+
+use crate::dsl::from_dsl;
+use crate::test_util::{target_filter_strategy, user_info_of, user_info_strategy, TestFilter};
+use proptest::prelude::*;
+
+fn relation_of(a: &str, b: &str) -> Relation {
+    relation(
+        Some(&from_dsl(a).unwrap()),
+        Some(&from_dsl(b).unwrap()),
+        None,
+    )
+}
+
+#[test]
+fn test_relation() {
+    assert_eq!(
+        relation_of(r#"country = "KR""#, r#"country in ("KR", "JP")"#),
+        Relation::ContainedIn
+    );
+    assert_eq!(
+        relation_of(r#"exists(country)"#, r#"country = "KR""#),
+        Relation::Contains
+    );
+    assert_eq!(
+        relation_of(
+            r#"country = "KR" or country = "JP""#,
+            r#"country in ("JP", "KR")"#
+        ),
+        Relation::Equal
+    );
+    assert_eq!(
+        relation_of(r#"age between 20 and 30"#, r#"age between 10 and 40"#),
+        Relation::ContainedIn
+    );
+    assert_eq!(
+        relation_of(r#"not country in ("KR", "JP")"#, r#"not country = "KR""#),
+        Relation::ContainedIn
+    );
+    assert_eq!(
+        relation_of(
+            r#"movies contains all ("1", "2")"#,
+            r#"movies in ("1", "3")"#
+        ),
+        Relation::ContainedIn
+    );
+    assert_eq!(
+        relation_of(r#"country = "KR""#, r#"not country = "KR""#),
+        Relation::Disjoint
+    );
+    assert_eq!(
+        relation_of(r#"missing(country)"#, r#"country = "KR" and age > 20"#),
+        Relation::Disjoint
+    );
+    assert_eq!(
+        relation_of(r#"country = "KR""#, r#"age > 20"#),
+        Relation::Intersecting
+    );
+    // user can have both values, so they are not disjoint.
+    assert_eq!(
+        relation_of(r#"country = "KR""#, r#"country = "JP""#),
+        Relation::Intersecting
+    );
+    // 5 and 50 are on both, but none of them is between 10 and 40.
+    assert_eq!(
+        relation_of(r#"age >= 20 and age < 30"#, r#"age between 10 and 40"#),
+        Relation::Intersecting
+    );
+
+    // no filter matches everyone, and filter that matches nobody is disjoint.
+    let filter = from_dsl(r#"country = "KR""#).unwrap();
+    assert_eq!(relation(None, Some(&filter), None), Relation::Contains);
+    assert_eq!(relation(None, None, None), Relation::Equal);
+    let never = TargetFilter::Or { fields: vec![] };
+    assert_eq!(relation(Some(&never), None, None), Relation::Disjoint);
+}
+
+#[test]
+fn test_relation_with_taxonomy() {
+    let taxonomy = Taxonomy::from_json(&serde_json::json!({
+        "region": {"APAC": {"country": {"KR": {}}}}
+    }))
+    .unwrap();
+    let a = from_dsl(r#"country = "KR""#).unwrap();
+    let b = from_dsl(r#"not region = "APAC""#).unwrap();
+    // user of KR is always in APAC after expansion.
+    assert_eq!(relation(Some(&a), Some(&b), None), Relation::Intersecting);
+    assert_eq!(
+        relation(Some(&a), Some(&b), Some(&taxonomy)),
+        Relation::Disjoint
+    );
+
+    let taxonomy = Taxonomy::from_json(&serde_json::json!({
+        "country": {"KR": {"city": {"Seoul": {}, "Busan": {}}}, "JP": {"city": {"Tokyo": {}}}}
+    }))
+    .unwrap();
+    let relation_with_taxonomy = |a: &str, b: &str| {
+        relation(
+            Some(&from_dsl(a).unwrap()),
+            Some(&from_dsl(b).unwrap()),
+            Some(&taxonomy),
+        )
+    };
+    assert_eq!(
+        relation_with_taxonomy(r#"city = "Seoul""#, r#"country = "KR""#),
+        Relation::ContainedIn
+    );
+    assert_eq!(
+        relation_with_taxonomy(r#"country = "KR""#, r#"city in ("Seoul", "Tokyo")"#),
+        Relation::Intersecting
+    );
+    assert_eq!(
+        relation_with_taxonomy(
+            r#"city in ("Seoul", "Tokyo")"#,
+            r#"country in ("KR", "JP")"#
+        ),
+        Relation::ContainedIn
+    );
+    assert_eq!(
+        relation_with_taxonomy(r#"city = "Busan""#, r#"exists(country)"#),
+        Relation::ContainedIn
+    );
+    assert_eq!(
+        relation_with_taxonomy(r#"city = "Tokyo""#, r#"not country = "JP""#),
+        Relation::Disjoint
+    );
+    // Osaka has no country, so user of it may have none.
+    assert_eq!(
+        relation_with_taxonomy(r#"city in ("Seoul", "Osaka")"#, r#"country = "KR""#),
+        Relation::Intersecting
+    );
+}
+
+#[test]
+fn test_analyze() {
+    let mut index = FilterIndex::default();
+    let filters = [
+        ("ad_1", Some(r#"country = "KR""#)),
+        ("ad_2", Some(r#"country in ("KR", "JP")"#)),
+        ("ad_3", Some(r#"not country = "KR""#)),
+        ("ad_4", None),
+    ];
+    let inserts: Vec<TestFilter> = filters
+        .iter()
+        .map(|(id, filter)| TestFilter {
+            id: id.to_string(),
+            filter: filter.map(|filter| from_dsl(filter).unwrap()),
+        })
+        .collect();
+    index.update(&inserts, &[]);
+    let sample = [
+        user_info_of(&[("country", &["KR"])]),
+        user_info_of(&[("country", &["JP"])]),
+        user_info_of(&[("country", &["KR", "JP"])]),
+        user_info_of(&[]),
+    ];
+    let report = analyze(&index, &sample);
+
+    assert_eq!(report.sample_size, 4);
+    assert_eq!(report.matched["ad_1"], 2);
+    assert_eq!(report.matched["ad_2"], 3);
+    assert_eq!(report.matched["ad_3"], 2);
+    assert_eq!(report.matched["ad_4"], 4);
+    assert_eq!(report.overlaps.len(), 6);
+    let overlap = |a: &str, b: &str| {
+        report
+            .overlaps
+            .iter()
+            .find(|overlap| overlap.a == a && overlap.b == b)
+            .map(|overlap| (overlap.relation, overlap.sampled))
+            .unwrap()
+    };
+    assert_eq!(overlap("ad_1", "ad_2"), (Relation::ContainedIn, 2));
+    assert_eq!(overlap("ad_1", "ad_3"), (Relation::Disjoint, 0));
+    assert_eq!(overlap("ad_2", "ad_3"), (Relation::Intersecting, 1));
+    assert_eq!(overlap("ad_3", "ad_4"), (Relation::ContainedIn, 2));
+}
+
+proptest! {
+    /**
+     * relations that are proven must hold for every user_info.
+     */
+    #[test]
+    fn test_relation_is_sound(
+        a in target_filter_strategy(),
+        b in target_filter_strategy(),
+        user_infos in prop::collection::vec(user_info_strategy(), 1..16),
+    ) {
+        let relation = relation(Some(&a), Some(&b), None);
+        for user_info in user_infos.iter() {
+            let (in_a, in_b) = (a.apply(user_info), b.apply(user_info));
+            match relation {
+                Relation::Equal => prop_assert_eq!(in_a, in_b),
+                Relation::ContainedIn => prop_assert!(!in_a || in_b),
+                Relation::Contains => prop_assert!(!in_b || in_a),
+                Relation::Disjoint => prop_assert!(!(in_a && in_b)),
+                Relation::Intersecting | Relation::Unknown => {}
+            }
+        }
+    }
+
+    /**
+     * same with taxonomy, on users expanded as they are on search.
+     */
+    #[test]
+    fn test_relation_with_taxonomy_is_sound(
+        a in target_filter_strategy(),
+        b in target_filter_strategy(),
+        user_infos in prop::collection::vec(user_info_strategy(), 1..16),
+    ) {
+        let taxonomy = Taxonomy::from_json(&serde_json::json!({
+            "c": {"1": {"b": {"1": {"a": {"1": {}, "2": {}}}, "2": {}}}, "x": {"b": {"3": {}}}}
+        }))
+        .unwrap();
+        let relation = relation(Some(&a), Some(&b), Some(&taxonomy));
+        for user_info in user_infos.iter() {
+            let user_info = taxonomy.expand(user_info);
+            let (in_a, in_b) = (a.apply(&user_info), b.apply(&user_info));
+            match relation {
+                Relation::Equal => prop_assert_eq!(in_a, in_b),
+                Relation::ContainedIn => prop_assert!(!in_a || in_b),
+                Relation::Contains => prop_assert!(!in_b || in_a),
+                Relation::Disjoint => prop_assert!(!(in_a && in_b)),
+                Relation::Intersecting | Relation::Unknown => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use roaring::RoaringBitmap;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/**
+ * Numeric interval used by range predicates(Gt/Gte/Lt/Lte/Between).
+ * unbounded side is represented with +/- infinity.
+ */
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Interval {
+    pub lower: f64,
+    pub lower_inclusive: bool,
+    pub upper: f64,
+    pub upper_inclusive: bool,
+}
+
+impl Interval {
+    pub fn new(lower: f64, lower_inclusive: bool, upper: f64, upper_inclusive: bool) -> Self {
+        Interval {
+            lower,
+            lower_inclusive,
+            upper,
+            upper_inclusive,
+        }
+    }
+    pub fn gt(value: f64) -> Self {
+        Self::new(value, false, f64::INFINITY, true)
+    }
+    pub fn gte(value: f64) -> Self {
+        Self::new(value, true, f64::INFINITY, true)
+    }
+    pub fn lt(value: f64) -> Self {
+        Self::new(f64::NEG_INFINITY, true, value, false)
+    }
+    pub fn lte(value: f64) -> Self {
+        Self::new(f64::NEG_INFINITY, true, value, true)
+    }
+    pub fn between(min: f64, max: f64) -> Self {
+        Self::new(min, true, max, true)
+    }
+    pub fn is_empty(&self) -> bool {
+        if self.lower.is_nan() || self.upper.is_nan() {
+            return true;
+        }
+        match self.lower.partial_cmp(&self.upper) {
+            Some(Ordering::Less) => false,
+            Some(Ordering::Equal) => !(self.lower_inclusive && self.upper_inclusive),
+            _ => true,
+        }
+    }
+    pub fn contains(&self, x: f64) -> bool {
+        let lower_ok = self.lower < x || (self.lower == x && self.lower_inclusive);
+        let upper_ok = x < self.upper || (x == self.upper && self.upper_inclusive);
+        lower_ok && upper_ok
+    }
+    pub fn intersect(&self, other: &Interval) -> Interval {
+        let (lower, lower_inclusive) = if self.lower > other.lower {
+            (self.lower, self.lower_inclusive)
+        } else if self.lower < other.lower {
+            (other.lower, other.lower_inclusive)
+        } else {
+            (self.lower, self.lower_inclusive && other.lower_inclusive)
+        };
+        let (upper, upper_inclusive) = if self.upper < other.upper {
+            (self.upper, self.upper_inclusive)
+        } else if self.upper > other.upper {
+            (other.upper, other.upper_inclusive)
+        } else {
+            (self.upper, self.upper_inclusive && other.upper_inclusive)
+        };
+        Interval::new(lower, lower_inclusive, upper, upper_inclusive)
+    }
+    // a point that is guaranteed to be inside of non-empty interval.
+    fn midpoint(&self) -> f64 {
+        match (self.lower.is_finite(), self.upper.is_finite()) {
+            (true, true) => self.lower + (self.upper - self.lower) / 2.0,
+            (true, false) => self.lower + 1.0,
+            (false, true) => self.upper - 1.0,
+            (false, false) => 0.0,
+        }
+    }
+    pub fn debug(&self) -> String {
+        format!(
+            "{open}{lower},{upper}{close}",
+            open = if self.lower_inclusive { "[" } else { "(" },
+            lower = self.lower,
+            upper = self.upper,
+            close = if self.upper_inclusive { "]" } else { ")" },
+        )
+    }
+    fn cmp_lower(&self, other: &Interval) -> Ordering {
+        // inclusive lower bound comes first on tie.
+        self.lower
+            .total_cmp(&other.lower)
+            .then(other.lower_inclusive.cmp(&self.lower_inclusive))
+    }
+    fn cmp_upper(&self, other: &Interval) -> Ordering {
+        // inclusive upper bound comes last on tie.
+        self.upper
+            .total_cmp(&other.upper)
+            .then(self.upper_inclusive.cmp(&other.upper_inclusive))
+    }
+}
+impl PartialEq for Interval {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Interval {}
+impl PartialOrd for Interval {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Interval {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_lower(other).then(self.cmp_upper(other))
+    }
+}
+impl Hash for Interval {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lower.to_bits().hash(state);
+        self.lower_inclusive.hash(state);
+        self.upper.to_bits().hash(state);
+        self.upper_inclusive.hash(state);
+    }
+}
+
+pub fn parse_number(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| !number.is_nan())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct DimRange {
+    pub dimension: String,
+    pub interval: Interval,
+    pub is_not: bool,
+}
+impl DimRange {
+    pub fn new(dim: &str, interval: Interval, is_not: bool) -> Self {
+        DimRange {
+            dimension: String::from(dim),
+            interval,
+            is_not,
+        }
+    }
+    pub fn debug(&self) -> String {
+        format!(
+            "{is_not}.{dimension}.{interval}",
+            is_not = self.is_not,
+            dimension = self.dimension,
+            interval = self.interval.debug()
+        )
+    }
+}
+
+/**
+ * Centered interval tree. every node keeps intervals containing its center
+ * sorted by lower bound(ascending) and upper bound(descending), so a point
+ * query only visits one path from root to leaf plus the matched intervals.
+ */
+#[derive(Debug, Clone)]
+struct IntervalNode {
+    center: f64,
+    linear: bool,
+    by_lower: Vec<Interval>,
+    by_upper: Vec<Interval>,
+    left: Option<Box<IntervalNode>>,
+    right: Option<Box<IntervalNode>>,
+}
+
+impl IntervalNode {
+    fn build(intervals: Vec<Interval>) -> Option<Box<IntervalNode>> {
+        if intervals.is_empty() {
+            return None;
+        }
+        let mut midpoints: Vec<f64> = intervals.iter().map(|i| i.midpoint()).collect();
+        midpoints.sort_by(|a, b| a.total_cmp(b));
+        let center = midpoints[midpoints.len() / 2];
+
+        let mut overlaps = Vec::new();
+        let mut lefts = Vec::new();
+        let mut rights = Vec::new();
+        for interval in intervals {
+            if interval.contains(center) {
+                overlaps.push(interval);
+            } else if interval.upper <= center {
+                lefts.push(interval);
+            } else {
+                rights.push(interval);
+            }
+        }
+        // only happens with precision loss on midpoint. fallback to linear scan.
+        let linear = overlaps.is_empty();
+        if linear {
+            overlaps.append(&mut lefts);
+            overlaps.append(&mut rights);
+        }
+
+        let mut by_lower = overlaps.clone();
+        by_lower.sort_by(|a, b| a.cmp_lower(b));
+        let mut by_upper = overlaps;
+        by_upper.sort_by(|a, b| b.cmp_upper(a));
+
+        Some(Box::new(IntervalNode {
+            center,
+            linear,
+            by_lower,
+            by_upper,
+            left: Self::build(lefts),
+            right: Self::build(rights),
+        }))
+    }
+
+    fn query<'a>(&'a self, x: f64, out: &mut Vec<&'a Interval>) {
+        if self.linear {
+            out.extend(self.by_lower.iter().filter(|interval| interval.contains(x)));
+            return;
+        }
+        // every interval on this node contains center, so only one side of
+        // bound need to be checked and scan can stop on first miss.
+        if x < self.center {
+            for interval in &self.by_lower {
+                if !interval.contains(x) {
+                    break;
+                }
+                out.push(interval);
+            }
+            if let Some(left) = &self.left {
+                left.query(x, out);
+            }
+        } else if x > self.center {
+            for interval in &self.by_upper {
+                if !interval.contains(x) {
+                    break;
+                }
+                out.push(interval);
+            }
+            if let Some(right) = &self.right {
+                right.query(x, out);
+            }
+        } else {
+            out.extend(self.by_lower.iter());
+        }
+    }
+}
+
+/**
+ * Posting list stored per interval. HashSet<String> keeps internal ids as is,
+ * RoaringBitmap keeps interned target key numbers.
+ */
+pub trait Postings: Default {
+    type Id;
+    type Key: ?Sized;
+
+    fn insert(&mut self, id: Self::Id);
+    fn remove(&mut self, id: &Self::Key);
+    fn is_empty(&self) -> bool;
+}
+impl Postings for HashSet<String> {
+    type Id = String;
+    type Key = str;
+
+    fn insert(&mut self, id: String) {
+        HashSet::insert(self, id);
+    }
+    fn remove(&mut self, id: &str) {
+        HashSet::remove(self, id);
+    }
+    fn is_empty(&self) -> bool {
+        HashSet::is_empty(self)
+    }
+}
+impl Postings for RoaringBitmap {
+    type Id = u32;
+    type Key = u32;
+
+    fn insert(&mut self, id: u32) {
+        RoaringBitmap::insert(self, id);
+    }
+    fn remove(&mut self, id: &u32) {
+        RoaringBitmap::remove(self, *id);
+    }
+    fn is_empty(&self) -> bool {
+        RoaringBitmap::is_empty(self)
+    }
+}
+
+/**
+ * Per dimension index for range predicates.
+ * postings are the source of truth and tree is rebuilt from postings
+ * once per FilterIndex::update.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct IntervalIndex<P> {
+    pub postings: BTreeMap<Interval, P>,
+    tree: Option<Box<IntervalNode>>,
+    dirty: bool,
+}
+pub type RangeIndex = IntervalIndex<HashSet<String>>;
+pub type BitmapRangeIndex = IntervalIndex<RoaringBitmap>;
+
+impl<P: Postings> IntervalIndex<P> {
+    pub fn insert(&mut self, interval: Interval, internal_id: P::Id) {
+        self.postings
+            .entry(interval)
+            .or_default()
+            .insert(internal_id);
+        self.dirty = true;
+    }
+    pub fn remove(&mut self, interval: &Interval, internal_id: &P::Key) {
+        if let Some(ids) = self.postings.get_mut(interval) {
+            ids.remove(internal_id);
+            if ids.is_empty() {
+                self.postings.remove(interval);
+            }
+            self.dirty = true;
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.postings.is_empty()
+    }
+    pub fn rebuild(&mut self) {
+        if self.dirty {
+            self.tree = IntervalNode::build(self.postings.keys().cloned().collect());
+            self.dirty = false;
+        }
+    }
+    pub fn search(&self, x: f64) -> Vec<&P> {
+        let mut intervals = Vec::new();
+        if let Some(tree) = &self.tree {
+            tree.query(x, &mut intervals);
+        }
+        intervals
+            .into_iter()
+            .flat_map(|interval| self.postings.get(interval))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[path = "./range_test.rs"]
+mod range_test;
@@ -0,0 +1,120 @@
+use super::*;
+
+fn brute_force(intervals: &[Interval], x: f64) -> Vec<Interval> {
+    let mut matched: Vec<Interval> = intervals
+        .iter()
+        .filter(|interval| interval.contains(x))
+        .cloned()
+        .collect();
+    matched.sort();
+    matched
+}
+
+#[test]
+fn test_interval_contains() {
+    assert!(!Interval::gt(10.0).contains(10.0));
+    assert!(Interval::gt(10.0).contains(10.5));
+    assert!(Interval::gte(10.0).contains(10.0));
+    assert!(!Interval::lt(10.0).contains(10.0));
+    assert!(Interval::lt(10.0).contains(-100.0));
+    assert!(Interval::lte(10.0).contains(10.0));
+    assert!(Interval::between(25.0, 34.0).contains(25.0));
+    assert!(Interval::between(25.0, 34.0).contains(34.0));
+    assert!(!Interval::between(25.0, 34.0).contains(34.1));
+}
+
+#[test]
+fn test_interval_intersect() {
+    let interval = Interval::gte(25.0).intersect(&Interval::lt(35.0));
+    assert_eq!(interval, Interval::new(25.0, true, 35.0, false));
+    assert!(!interval.is_empty());
+
+    let interval = Interval::gt(10.0).intersect(&Interval::lte(10.0));
+    assert!(interval.is_empty());
+
+    let interval = Interval::gte(10.0).intersect(&Interval::lte(10.0));
+    assert!(!interval.is_empty());
+    assert!(interval.contains(10.0));
+
+    assert!(Interval::between(10.0, 5.0).is_empty());
+}
+
+#[test]
+fn test_parse_number() {
+    assert_eq!(parse_number("10"), Some(10.0));
+    assert_eq!(parse_number(" 3.5 "), Some(3.5));
+    assert_eq!(parse_number("-2"), Some(-2.0));
+    assert_eq!(parse_number("10s"), None);
+    assert_eq!(parse_number("NaN"), None);
+    assert_eq!(parse_number("empty"), None);
+}
+
+#[test]
+fn test_range_index_matches_brute_force() {
+    let mut intervals = Vec::new();
+    for i in 0..20 {
+        let v = i as f64;
+        intervals.push(Interval::gt(v));
+        intervals.push(Interval::gte(v * 2.0));
+        intervals.push(Interval::lt(v - 3.0));
+        intervals.push(Interval::lte(v / 2.0));
+        intervals.push(Interval::between(v, v + (i % 5) as f64));
+        intervals.push(Interval::new(v, false, v + 1.5, false));
+    }
+    let mut range_index = RangeIndex::default();
+    for (seq, interval) in intervals.iter().enumerate() {
+        range_index.insert(*interval, format!("id_{}", seq));
+    }
+    range_index.rebuild();
+
+    let mut points = vec![f64::NEG_INFINITY, f64::INFINITY];
+    for i in -10..90 {
+        points.push(i as f64 / 2.0);
+    }
+    for x in points {
+        let mut matched: Vec<Interval> = Vec::new();
+        if let Some(tree) = &range_index.tree {
+            let mut out = Vec::new();
+            tree.query(x, &mut out);
+            matched = out.into_iter().cloned().collect();
+        }
+        matched.sort();
+
+        let mut expected = brute_force(&intervals, x);
+        expected.dedup();
+        assert_eq!(matched, expected, "x = {}", x);
+    }
+}
+
+#[test]
+fn test_range_index_insert_remove() {
+    let mut range_index = RangeIndex::default();
+    range_index.insert(Interval::between(25.0, 34.0), String::from("AD_1_0"));
+    range_index.insert(Interval::gte(30.0), String::from("AD_2_0"));
+    range_index.insert(Interval::gte(30.0), String::from("AD_3_0"));
+    range_index.rebuild();
+
+    let ids: HashSet<&str> = range_index
+        .search(31.0)
+        .into_iter()
+        .flatten()
+        .map(|id| id.as_str())
+        .collect();
+    assert_eq!(ids, HashSet::from(["AD_1_0", "AD_2_0", "AD_3_0"]));
+
+    range_index.remove(&Interval::gte(30.0), "AD_2_0");
+    range_index.remove(&Interval::between(25.0, 34.0), "AD_1_0");
+    range_index.rebuild();
+
+    let ids: HashSet<&str> = range_index
+        .search(31.0)
+        .into_iter()
+        .flatten()
+        .map(|id| id.as_str())
+        .collect();
+    assert_eq!(ids, HashSet::from(["AD_3_0"]));
+    assert_eq!(range_index.search(26.0).len(), 0);
+
+    range_index.remove(&Interval::gte(30.0), "AD_3_0");
+    assert!(range_index.is_empty());
+}
@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::filter::TargetFilter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentError {
+    // InSegment refers to segment that is not loaded.
+    NotFound { segment_id: String },
+    // segments refer to each other. path starts and ends with the same segment.
+    Cycle { path: Vec<String> },
+}
+impl std::fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SegmentError::NotFound { segment_id } => {
+                write!(f, "segment {} does not exist", segment_id)
+            }
+            SegmentError::Cycle { path } => {
+                write!(f, "segment reference cycle: {}", path.join(" -> "))
+            }
+        }
+    }
+}
+impl std::error::Error for SegmentError {}
+
+/**
+ * Inline every InSegment with the filter of the segment, which can refer to other segments.
+ * segments is segment id -> its filter as it is stored, before resolving.
+ */
+pub fn resolve_segments(
+    filter: &TargetFilter,
+    segments: &HashMap<String, TargetFilter>,
+) -> Result<TargetFilter, SegmentError> {
+    resolve(filter, segments, &mut Vec::new())
+}
+// path is the segments being inlined from outermost, to detect cycle.
+fn resolve(
+    filter: &TargetFilter,
+    segments: &HashMap<String, TargetFilter>,
+    path: &mut Vec<String>,
+) -> Result<TargetFilter, SegmentError> {
+    match filter {
+        TargetFilter::InSegment { segment_id } => {
+            if let Some(position) = path.iter().position(|id| id == segment_id) {
+                let mut cycle = path[position..].to_vec();
+                cycle.push(segment_id.clone());
+                return Err(SegmentError::Cycle { path: cycle });
+            }
+            let segment = segments
+                .get(segment_id)
+                .ok_or_else(|| SegmentError::NotFound {
+                    segment_id: segment_id.clone(),
+                })?;
+            path.push(segment_id.clone());
+            let resolved = resolve(segment, segments, path);
+            path.pop();
+            resolved
+        }
+        TargetFilter::And { fields } => Ok(TargetFilter::And {
+            fields: fields
+                .iter()
+                .map(|field| resolve(field, segments, path))
+                .collect::<Result<Vec<TargetFilter>, SegmentError>>()?,
+        }),
+        TargetFilter::Or { fields } => Ok(TargetFilter::Or {
+            fields: fields
+                .iter()
+                .map(|field| resolve(field, segments, path))
+                .collect::<Result<Vec<TargetFilter>, SegmentError>>()?,
+        }),
+        TargetFilter::Not { field } => Ok(TargetFilter::Not {
+            field: Box::new(resolve(field, segments, path)?),
+        }),
+        TargetFilter::Prefer { field, weight } => Ok(TargetFilter::Prefer {
+            field: Box::new(resolve(field, segments, path)?),
+            weight: *weight,
+        }),
+        _ => Ok(filter.clone()),
+    }
+}
+
+/**
+ * Segments that filter depends on, directly or through other segments.
+ * cycles and missing segments are skipped, resolve_segments reports them.
+ */
+pub fn dependent_segment_ids(
+    filter: &TargetFilter,
+    segments: &HashMap<String, TargetFilter>,
+) -> HashSet<String> {
+    let mut visited = HashSet::new();
+    let mut stack: Vec<String> = TargetFilter::extract_segment_ids(filter)
+        .into_iter()
+        .collect();
+    while let Some(segment_id) = stack.pop() {
+        if !visited.insert(segment_id.clone()) {
+            continue;
+        }
+        if let Some(segment) = segments.get(&segment_id) {
+            stack.extend(TargetFilter::extract_segment_ids(segment));
+        }
+    }
+    visited
+}
+
+#[cfg(test)]
+#[path = "./segment_test.rs"]
+mod segment_test;
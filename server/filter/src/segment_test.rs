@@ -0,0 +1,125 @@
+use super::*;
+
+use crate::filter::Filter;
+use crate::index::FilterIndex;
+use crate::normalize::NormalizeError;
+use crate::test_util::{select, user_info_of, TestFilter};
+
+fn in_segment(segment_id: &str) -> TargetFilter {
+    TargetFilter::InSegment {
+        segment_id: String::from(segment_id),
+    }
+}
+
+#[test]
+fn test_resolve_segments() {
+    let segments = HashMap::from([
+        (String::from("adults"), select("age", "20s")),
+        (
+            String::from("korean_adults"),
+            TargetFilter::And {
+                fields: vec![in_segment("adults"), select("country", "KR")],
+            },
+        ),
+    ]);
+    let filter = TargetFilter::Or {
+        fields: vec![
+            TargetFilter::Not {
+                field: Box::new(in_segment("korean_adults")),
+            },
+            select("gender", "F"),
+        ],
+    };
+    assert_eq!(
+        resolve_segments(&filter, &segments),
+        Ok(TargetFilter::Or {
+            fields: vec![
+                TargetFilter::Not {
+                    field: Box::new(TargetFilter::And {
+                        fields: vec![select("age", "20s"), select("country", "KR")],
+                    }),
+                },
+                select("gender", "F"),
+            ],
+        })
+    );
+    assert_eq!(
+        dependent_segment_ids(&filter, &segments),
+        HashSet::from([String::from("korean_adults"), String::from("adults")])
+    );
+    assert_eq!(
+        resolve_segments(&in_segment("teens"), &segments),
+        Err(SegmentError::NotFound {
+            segment_id: String::from("teens")
+        })
+    );
+    // the same segment twice side by side is not a cycle.
+    let twice = TargetFilter::And {
+        fields: vec![in_segment("adults"), in_segment("korean_adults")],
+    };
+    assert!(resolve_segments(&twice, &segments).is_ok());
+}
+
+#[test]
+fn test_segment_cycle() {
+    let segments = HashMap::from([
+        (String::from("a"), in_segment("b")),
+        (
+            String::from("b"),
+            TargetFilter::Or {
+                fields: vec![select("age", "10s"), in_segment("c")],
+            },
+        ),
+        (String::from("c"), in_segment("a")),
+        (String::from("self"), in_segment("self")),
+    ]);
+    let error = resolve_segments(&in_segment("a"), &segments).unwrap_err();
+    assert_eq!(
+        error,
+        SegmentError::Cycle {
+            path: vec!["a", "b", "c", "a"]
+                .into_iter()
+                .map(String::from)
+                .collect()
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "segment reference cycle: a -> b -> c -> a"
+    );
+    assert_eq!(
+        resolve_segments(&in_segment("self"), &segments)
+            .unwrap_err()
+            .to_string(),
+        "segment reference cycle: self -> self"
+    );
+    // dependencies are still found on cycle.
+    assert_eq!(dependent_segment_ids(&in_segment("c"), &segments).len(), 3);
+}
+
+#[test]
+fn test_unresolved_segment_is_not_indexed() {
+    let filter = TargetFilter::And {
+        fields: vec![select("age", "10s"), in_segment("adults")],
+    };
+    assert!(!filter.apply(&user_info_of(&[("age", &["10s"])])));
+
+    let mut index = FilterIndex::default();
+    let rejected = index.update(
+        &[TestFilter {
+            id: String::from("ad_1"),
+            filter: Some(filter),
+        }],
+        &[],
+    );
+    assert_eq!(
+        rejected,
+        vec![(
+            String::from("ad_1"),
+            NormalizeError::UnresolvedSegment {
+                segment_id: String::from("adults")
+            }
+        )]
+    );
+    assert!(index.search(&user_info_of(&[("age", &["10s"])])).is_empty());
+}
@@ -44,6 +44,72 @@ pub fn to_json(filter: &TargetFilter) -> serde_json::Value {
                 "field": to_json(field)
             })
         }
+        TargetFilter::Gt { dimension, value } => json!({
+            "type": "gt",
+            "dimension": dimension,
+            "value": value,
+        }),
+        TargetFilter::Gte { dimension, value } => json!({
+            "type": "gte",
+            "dimension": dimension,
+            "value": value,
+        }),
+        TargetFilter::Lt { dimension, value } => json!({
+            "type": "lt",
+            "dimension": dimension,
+            "value": value,
+        }),
+        TargetFilter::Lte { dimension, value } => json!({
+            "type": "lte",
+            "dimension": dimension,
+            "value": value,
+        }),
+        TargetFilter::Between {
+            dimension,
+            min,
+            max,
+        } => json!({
+            "type": "between",
+            "dimension": dimension,
+            "min": min,
+            "max": max,
+        }),
+        TargetFilter::Exists { dimension } => json!({
+            "type": "exists",
+            "dimension": dimension,
+        }),
+        TargetFilter::Missing { dimension } => json!({
+            "type": "missing",
+            "dimension": dimension,
+        }),
+        TargetFilter::InSegment { segment_id } => json!({
+            "type": "in_segment",
+            "segment_id": segment_id,
+        }),
+        TargetFilter::InUserList { list_id } => json!({
+            "type": "in_user_list",
+            "list_id": list_id,
+        }),
+        TargetFilter::ContainsAll { dimension, values } => json!({
+            "type": "contains_all",
+            "dimension": dimension,
+            "values": values,
+        }),
+        TargetFilter::ContainsAtLeast {
+            dimension,
+            values,
+            n,
+        } => json!({
+            "type": "contains_at_least",
+            "dimension": dimension,
+            "values": values,
+            "n": n,
+        }),
+        TargetFilter::Prefer { field, weight } => json!({
+            "type": "prefer",
+            "field": to_json(field),
+            "weight": weight,
+        }),
     }
 }
 pub fn to_jsonlogic(filter: &TargetFilter) -> serde_json::Value {
@@ -73,42 +139,383 @@ pub fn to_jsonlogic(filter: &TargetFilter) -> serde_json::Value {
         TargetFilter::Not { field } => {
             json!({ "!": to_jsonlogic(field) })
         }
+        TargetFilter::Gt { dimension, value } => json!({
+            ">": [{"var": dimension}, value]
+        }),
+        TargetFilter::Gte { dimension, value } => json!({
+            ">=": [{"var": dimension}, value]
+        }),
+        TargetFilter::Lt { dimension, value } => json!({
+            "<": [{"var": dimension}, value]
+        }),
+        TargetFilter::Lte { dimension, value } => json!({
+            "<=": [{"var": dimension}, value]
+        }),
+        TargetFilter::Between {
+            dimension,
+            min,
+            max,
+        } => json!({
+            "<=": [min, {"var": dimension}, max]
+        }),
+        TargetFilter::Exists { dimension } => json!({
+            "!!": {"var": dimension}
+        }),
+        TargetFilter::Missing { dimension } => json!({
+            "missing": [dimension]
+        }),
+        TargetFilter::InSegment { segment_id } => json!({
+            "in_segment": [segment_id]
+        }),
+        TargetFilter::InUserList { list_id } => json!({
+            "in_user_list": [list_id]
+        }),
+        TargetFilter::ContainsAll { dimension, values } => json!({
+            "contains_all": [{"var": dimension}, values]
+        }),
+        TargetFilter::ContainsAtLeast {
+            dimension,
+            values,
+            n,
+        } => json!({
+            "contains_at_least": [{"var": dimension}, values, n]
+        }),
+        TargetFilter::Prefer { field, weight } => json!({
+            "prefer": [to_jsonlogic(field), weight]
+        }),
     }
 }
-pub fn from_jsonlogic(value: &Value) -> Option<TargetFilter> {
-    if !&value["!"].is_null() {
-        let field = Box::new(from_jsonlogic(&value["!"])?);
-        return Some(TargetFilter::Not { field });
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonLogicError {
+    // node is not an object with single operator.
+    InvalidNode { node: Value },
+    // operator outside of supported subset.
+    UnsupportedOperator { operator: String, node: Value },
+    // supported operator with arguments that can't be compiled into TargetFilter.
+    InvalidArguments { operator: String, node: Value },
+}
+impl std::fmt::Display for JsonLogicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonLogicError::InvalidNode { node } => {
+                write!(f, "invalid jsonlogic node: {}", node)
+            }
+            JsonLogicError::UnsupportedOperator { operator, node } => {
+                write!(f, "unsupported jsonlogic operator `{}`: {}", operator, node)
+            }
+            JsonLogicError::InvalidArguments { operator, node } => {
+                write!(f, "invalid arguments for `{}`: {}", operator, node)
+            }
+        }
     }
-    if let Value::Array(childrens) = &value["and"] {
-        let fields: Vec<TargetFilter> = childrens
-            .iter()
-            .flat_map(|child| from_jsonlogic(child))
-            .collect();
-        return Some(TargetFilter::And { fields });
+}
+impl std::error::Error for JsonLogicError {}
+
+fn invalid_arguments(operator: &str, node: &Value) -> JsonLogicError {
+    JsonLogicError::InvalidArguments {
+        operator: operator.to_string(),
+        node: node.clone(),
     }
-    if let Value::Array(childrens) = &value["or"] {
-        let fields: Vec<TargetFilter> = childrens
-            .iter()
-            .flat_map(|child| from_jsonlogic(child))
-            .collect();
-        return Some(TargetFilter::Or { fields });
+}
+// jsonlogic allows unary operator's argument with or without array.
+fn unary_argument<'a>(
+    operator: &str,
+    node: &Value,
+    args: &'a Value,
+) -> Result<&'a Value, JsonLogicError> {
+    match args {
+        Value::Array(values) if values.len() == 1 => Ok(&values[0]),
+        Value::Array(_) => Err(invalid_arguments(operator, node)),
+        _ => Ok(args),
     }
-    if let Value::Array(op_values) = &value["in"] {
-        let dimension = String::from(op_values[0]["var"].as_str()?);
-        let valid_values: HashSet<String> = op_values[1]
-            .as_array()?
-            .iter()
-            .flat_map(|v| v.as_str())
-            .map(|v| String::from(v))
-            .collect();
-        return Some(TargetFilter::In {
-            dimension,
-            valid_values,
-        });
+}
+// {"var": dim} or {"var": [dim]}. default of {"var": [dim, default]} would match users
+// without dim, which index can't, so it is not a var that filter compiles.
+fn var_name(value: &Value) -> Option<String> {
+    match &value["var"] {
+        Value::String(name) => Some(name.clone()),
+        Value::Array(values) if values.len() == 1 => values[0].as_str().map(String::from),
+        _ => None,
+    }
+}
+// user_info only has string values, so scalar literal is compared as string.
+fn literal_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+/**
+ * {"op": [{"var": dim}, number]}, {"op": [number, {"var": dim}]} and
+ * jsonlogic's between form {"op": [number, {"var": dim}, number]}.
+ */
+fn from_jsonlogic_comparison(
+    operator: &str,
+    node: &Value,
+    args: &Value,
+) -> Result<TargetFilter, JsonLogicError> {
+    let range = |op: &str, dimension: String, value: f64| match op {
+        ">" => TargetFilter::Gt { dimension, value },
+        ">=" => TargetFilter::Gte { dimension, value },
+        "<" => TargetFilter::Lt { dimension, value },
+        _ => TargetFilter::Lte { dimension, value },
+    };
+    let flip = |op: &str| match op {
+        ">" => "<",
+        ">=" => "<=",
+        "<" => ">",
+        _ => ">=",
+    };
+    let op_values = args
+        .as_array()
+        .ok_or_else(|| invalid_arguments(operator, node))?;
+
+    match op_values.as_slice() {
+        [lhs, rhs] => {
+            if let (Some(dimension), Some(value)) = (var_name(lhs), rhs.as_f64()) {
+                Ok(range(operator, dimension, value))
+            } else if let (Some(value), Some(dimension)) = (lhs.as_f64(), var_name(rhs)) {
+                Ok(range(flip(operator), dimension, value))
+            } else {
+                Err(invalid_arguments(operator, node))
+            }
+        }
+        [min, var, max] => {
+            let (min, dimension, max) = match (min.as_f64(), var_name(var), max.as_f64()) {
+                (Some(min), Some(dimension), Some(max)) => (min, dimension, max),
+                _ => return Err(invalid_arguments(operator, node)),
+            };
+            match operator {
+                "<=" => Ok(TargetFilter::Between {
+                    dimension,
+                    min,
+                    max,
+                }),
+                "<" => Ok(TargetFilter::And {
+                    fields: vec![
+                        TargetFilter::Gt {
+                            dimension: dimension.clone(),
+                            value: min,
+                        },
+                        TargetFilter::Lt {
+                            dimension,
+                            value: max,
+                        },
+                    ],
+                }),
+                _ => Err(invalid_arguments(operator, node)),
+            }
+        }
+        _ => Err(invalid_arguments(operator, node)),
     }
+}
+fn from_jsonlogic_equality(
+    operator: &str,
+    node: &Value,
+    args: &Value,
+) -> Result<TargetFilter, JsonLogicError> {
+    let select = match args.as_array().map(|values| values.as_slice()) {
+        Some([lhs, rhs]) => match (var_name(lhs), var_name(rhs)) {
+            (Some(dimension), None) => literal_to_string(rhs).map(|v| (dimension, v)),
+            (None, Some(dimension)) => literal_to_string(lhs).map(|v| (dimension, v)),
+            _ => None,
+        },
+        _ => None,
+    };
+    let (dimension, valid_value) = select.ok_or_else(|| invalid_arguments(operator, node))?;
+    let filter = TargetFilter::Select {
+        dimension,
+        valid_value,
+    };
 
-    None
+    match operator {
+        "==" | "===" => Ok(filter),
+        _ => Ok(TargetFilter::Not {
+            field: Box::new(filter),
+        }),
+    }
+}
+/**
+ * {"missing": [dims..]} is truthy when any of dims is missing.
+ */
+fn from_jsonlogic_missing(
+    operator: &str,
+    node: &Value,
+    args: &Value,
+) -> Result<TargetFilter, JsonLogicError> {
+    let names = match args {
+        Value::Array(names) => names.clone(),
+        _ => vec![args.clone()],
+    };
+    let mut fields = Vec::new();
+    for name in names {
+        let dimension = name
+            .as_str()
+            .ok_or_else(|| invalid_arguments(operator, node))?
+            .to_string();
+        fields.push(TargetFilter::Missing { dimension });
+    }
+    match fields.len() {
+        0 => Err(invalid_arguments(operator, node)),
+        1 => Ok(fields.pop().unwrap()),
+        _ => Ok(TargetFilter::Or { fields }),
+    }
+}
+fn from_jsonlogic_in(
+    operator: &str,
+    node: &Value,
+    args: &Value,
+) -> Result<TargetFilter, JsonLogicError> {
+    let (dimension, values) = match args.as_array().map(|values| values.as_slice()) {
+        Some([var, Value::Array(values)]) => match var_name(var) {
+            Some(dimension) => (dimension, values),
+            None => return Err(invalid_arguments(operator, node)),
+        },
+        // {"in": ["a", "abc"]} is substring match which is not supported.
+        _ => return Err(invalid_arguments(operator, node)),
+    };
+    let mut valid_values = HashSet::new();
+    for value in values {
+        let v = literal_to_string(value).ok_or_else(|| invalid_arguments(operator, node))?;
+        valid_values.insert(v);
+    }
+    Ok(TargetFilter::In {
+        dimension,
+        valid_values,
+    })
+}
+/**
+ * {"contains_all": [{"var": dim}, [values..]]} and
+ * {"contains_at_least": [{"var": dim}, [values..], n]}.
+ */
+fn from_jsonlogic_contains(
+    operator: &str,
+    node: &Value,
+    args: &Value,
+) -> Result<TargetFilter, JsonLogicError> {
+    let (var, values, n) = match (operator, args.as_array().map(|values| values.as_slice())) {
+        ("contains_all", Some([var, Value::Array(values)])) => (var, values, None),
+        ("contains_at_least", Some([var, Value::Array(values), n])) => {
+            let n = n
+                .as_u64()
+                .ok_or_else(|| invalid_arguments(operator, node))?;
+            (var, values, Some(n as usize))
+        }
+        _ => return Err(invalid_arguments(operator, node)),
+    };
+    let dimension = var_name(var).ok_or_else(|| invalid_arguments(operator, node))?;
+    let mut valid_values = HashSet::new();
+    for value in values {
+        let v = literal_to_string(value).ok_or_else(|| invalid_arguments(operator, node))?;
+        valid_values.insert(v);
+    }
+    Ok(match n {
+        None => TargetFilter::ContainsAll {
+            dimension,
+            values: valid_values,
+        },
+        Some(n) => TargetFilter::ContainsAtLeast {
+            dimension,
+            values: valid_values,
+            n,
+        },
+    })
+}
+/**
+ * Compile jsonlogic into TargetFilter.
+ * supported subset is `and`, `or`, `!`, `!!`, `in`, `==`, `!=`, `>`, `>=`, `<`, `<=`, `missing`
+ * and custom `in_segment`, `in_user_list` which refer to Segment and user list(Customset) by id,
+ * `contains_all`, `contains_at_least` on multi-valued dimension,
+ * and `prefer` as {"prefer": [node, weight]} which only scores.
+ * `!!` and `!` on bare var are Exists and Missing of the dimension.
+ * any other node fails the whole compile, so that a filter is never loosen silently
+ * by dropping a node that can't be understood.
+ */
+pub fn from_jsonlogic(value: &Value) -> Result<TargetFilter, JsonLogicError> {
+    let (operator, args) = match value.as_object() {
+        Some(object) if object.len() == 1 => object.iter().next().unwrap(),
+        _ => {
+            return Err(JsonLogicError::InvalidNode {
+                node: value.clone(),
+            })
+        }
+    };
+    let operator = operator.as_str();
+
+    match operator {
+        "and" | "or" => {
+            let childrens = args
+                .as_array()
+                .ok_or_else(|| invalid_arguments(operator, value))?;
+            let fields = childrens
+                .iter()
+                .map(from_jsonlogic)
+                .collect::<Result<Vec<TargetFilter>, JsonLogicError>>()?;
+            if operator == "and" {
+                Ok(TargetFilter::And { fields })
+            } else {
+                Ok(TargetFilter::Or { fields })
+            }
+        }
+        "!" => {
+            let arg = unary_argument(operator, value, args)?;
+            if let Some(dimension) = var_name(arg) {
+                return Ok(TargetFilter::Missing { dimension });
+            }
+            if arg.get("var").is_some() {
+                return Err(invalid_arguments(operator, value));
+            }
+            let field = from_jsonlogic(arg)?;
+            Ok(TargetFilter::Not {
+                field: Box::new(field),
+            })
+        }
+        // truthiness of boolean expression is the expression itself.
+        "!!" => {
+            let arg = unary_argument(operator, value, args)?;
+            match var_name(arg) {
+                Some(dimension) => Ok(TargetFilter::Exists { dimension }),
+                None if arg.get("var").is_some() => Err(invalid_arguments(operator, value)),
+                None => from_jsonlogic(arg),
+            }
+        }
+        "in" => from_jsonlogic_in(operator, value, args),
+        "contains_all" | "contains_at_least" => from_jsonlogic_contains(operator, value, args),
+        "missing" => from_jsonlogic_missing(operator, value, args),
+        "in_segment" => match unary_argument(operator, value, args)?.as_str() {
+            Some(segment_id) => Ok(TargetFilter::InSegment {
+                segment_id: segment_id.to_string(),
+            }),
+            None => Err(invalid_arguments(operator, value)),
+        },
+        "in_user_list" => match unary_argument(operator, value, args)?.as_str() {
+            Some(list_id) => Ok(TargetFilter::InUserList {
+                list_id: list_id.to_string(),
+            }),
+            None => Err(invalid_arguments(operator, value)),
+        },
+        "prefer" => match args.as_array().map(|values| values.as_slice()) {
+            Some([field, weight]) => match weight
+                .as_f64()
+                .filter(|w| TargetFilter::is_valid_weight(*w))
+            {
+                Some(weight) => Ok(TargetFilter::Prefer {
+                    field: Box::new(from_jsonlogic(field)?),
+                    weight,
+                }),
+                None => Err(invalid_arguments(operator, value)),
+            },
+            _ => Err(invalid_arguments(operator, value)),
+        },
+        "==" | "===" | "!=" | "!==" => from_jsonlogic_equality(operator, value, args),
+        ">" | ">=" | "<" | "<=" => from_jsonlogic_comparison(operator, value, args),
+        _ => Err(JsonLogicError::UnsupportedOperator {
+            operator: operator.to_string(),
+            node: value.clone(),
+        }),
+    }
 }
 pub fn from_json(value: &Value) -> Option<TargetFilter> {
     match value["type"].as_str() {
@@ -155,7 +562,68 @@ pub fn from_json(value: &Value) -> Option<TargetFilter> {
             "not" => Some(TargetFilter::Not {
                 field: Box::new(from_json(&value["field"])?),
             }),
+            "gt" => Some(TargetFilter::Gt {
+                dimension: value["dimension"].as_str()?.to_string(),
+                value: value["value"].as_f64()?,
+            }),
+            "gte" => Some(TargetFilter::Gte {
+                dimension: value["dimension"].as_str()?.to_string(),
+                value: value["value"].as_f64()?,
+            }),
+            "lt" => Some(TargetFilter::Lt {
+                dimension: value["dimension"].as_str()?.to_string(),
+                value: value["value"].as_f64()?,
+            }),
+            "lte" => Some(TargetFilter::Lte {
+                dimension: value["dimension"].as_str()?.to_string(),
+                value: value["value"].as_f64()?,
+            }),
+            "between" => Some(TargetFilter::Between {
+                dimension: value["dimension"].as_str()?.to_string(),
+                min: value["min"].as_f64()?,
+                max: value["max"].as_f64()?,
+            }),
+            "exists" => Some(TargetFilter::Exists {
+                dimension: value["dimension"].as_str()?.to_string(),
+            }),
+            "missing" => Some(TargetFilter::Missing {
+                dimension: value["dimension"].as_str()?.to_string(),
+            }),
+            "in_segment" => Some(TargetFilter::InSegment {
+                segment_id: value["segment_id"].as_str()?.to_string(),
+            }),
+            "in_user_list" => Some(TargetFilter::InUserList {
+                list_id: value["list_id"].as_str()?.to_string(),
+            }),
+            "contains_all" | "contains_at_least" => {
+                let dimension = value["dimension"].as_str()?.to_string();
+                let mut values = HashSet::new();
+                for value in value["values"].as_array()? {
+                    if let Some(v) = value.as_str() {
+                        values.insert(v.to_string());
+                    }
+                }
+                if t == "contains_all" {
+                    Some(TargetFilter::ContainsAll { dimension, values })
+                } else {
+                    Some(TargetFilter::ContainsAtLeast {
+                        dimension,
+                        values,
+                        n: value["n"].as_u64()? as usize,
+                    })
+                }
+            }
+            "prefer" => Some(TargetFilter::Prefer {
+                field: Box::new(from_json(&value["field"])?),
+                weight: value["weight"]
+                    .as_f64()
+                    .filter(|w| TargetFilter::is_valid_weight(*w))?,
+            }),
             _ => None,
         },
     }
 }
+
+#[cfg(test)]
+#[path = "./serde_test.rs"]
+mod serde_test;
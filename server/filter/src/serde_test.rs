@@ -0,0 +1,314 @@
+use super::*;
+use crate::test_util::FilterDomain;
+use proptest::prelude::*;
+
+fn target_filter_strategy() -> impl Strategy<Value = TargetFilter> {
+    FilterDomain {
+        dimensions: prop::sample::select(vec!["age", "gender", "interests", "region"])
+            .prop_map(String::from)
+            .boxed(),
+        values: "[a-zA-Z0-9_]{1,4}".boxed(),
+        numbers: prop_oneof![(-1000i64..1000).prop_map(|v| v as f64), -1.0e6f64..1.0e6f64,].boxed(),
+        segment_ids: Some("[a-z_]{1,8}".boxed()),
+        list_ids: "[a-z_]{1,8}".boxed(),
+        shape: (4, 32, 4),
+    }
+    .target_filter_strategy()
+}
+
+proptest! {
+    #[test]
+    fn test_jsonlogic_round_trip(filter in target_filter_strategy()) {
+        let jsonlogic = to_jsonlogic(&filter);
+        prop_assert_eq!(from_jsonlogic(&jsonlogic), Ok(filter.clone()));
+
+        // round trip through serialized string as it is stored on db.
+        let raw = serde_json::to_string(&jsonlogic).unwrap();
+        let value: Value = serde_json::from_str(&raw).unwrap();
+        prop_assert_eq!(from_jsonlogic(&value), Ok(filter));
+    }
+
+    #[test]
+    fn test_json_round_trip(filter in target_filter_strategy()) {
+        prop_assert_eq!(from_json(&to_json(&filter)), Some(filter));
+    }
+}
+
+#[test]
+fn test_select_is_not_dropped() {
+    let value = json!({
+        "and": [
+            {"==": [{"var": "gender"}, "F"]},
+            {"in": [{"var": "age"}, ["10", "20"]]}
+        ]
+    });
+    let expected = TargetFilter::And {
+        fields: vec![
+            TargetFilter::Select {
+                dimension: String::from("gender"),
+                valid_value: String::from("F"),
+            },
+            TargetFilter::In {
+                dimension: String::from("age"),
+                valid_values: HashSet::from([String::from("10"), String::from("20")]),
+            },
+        ],
+    };
+    assert_eq!(from_jsonlogic(&value), Ok(expected));
+}
+
+#[test]
+fn test_equality_and_negation() {
+    let select = TargetFilter::Select {
+        dimension: String::from("gender"),
+        valid_value: String::from("F"),
+    };
+    let not_select = TargetFilter::Not {
+        field: Box::new(select.clone()),
+    };
+
+    assert_eq!(
+        from_jsonlogic(&json!({"==": ["F", {"var": "gender"}]})),
+        Ok(select.clone())
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"===": [{"var": "gender"}, "F"]})),
+        Ok(select.clone())
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"!=": [{"var": "gender"}, "F"]})),
+        Ok(not_select.clone())
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"!": [{"==": [{"var": "gender"}, "F"]}]})),
+        Ok(not_select)
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"!!": [{"==": [{"var": "gender"}, "F"]}]})),
+        Ok(select)
+    );
+    // literal is compared as string since user_info only has string values.
+    assert_eq!(
+        from_jsonlogic(&json!({"==": [{"var": "age"}, 10]})),
+        Ok(TargetFilter::Select {
+            dimension: String::from("age"),
+            valid_value: String::from("10"),
+        })
+    );
+}
+
+#[test]
+fn test_unsupported_node_is_error() {
+    let value = json!({
+        "and": [
+            {"in": [{"var": "age"}, ["10"]]},
+            {"if": [{"var": "vip"}, true, false]}
+        ]
+    });
+    let error = from_jsonlogic(&value).unwrap_err();
+    assert_eq!(
+        error,
+        JsonLogicError::UnsupportedOperator {
+            operator: String::from("if"),
+            node: json!({"if": [{"var": "vip"}, true, false]}),
+        }
+    );
+    assert!(error.to_string().contains("`if`"));
+
+    // substring form of `in` is not supported.
+    assert!(matches!(
+        from_jsonlogic(&json!({"in": ["a", {"var": "name"}]})),
+        Err(JsonLogicError::InvalidArguments { .. })
+    ));
+    // var compared with var.
+    assert!(matches!(
+        from_jsonlogic(&json!({"==": [{"var": "a"}, {"var": "b"}]})),
+        Err(JsonLogicError::InvalidArguments { .. })
+    ));
+    // comparison with non numeric literal.
+    assert!(matches!(
+        from_jsonlogic(&json!({">": [{"var": "age"}, "10"]})),
+        Err(JsonLogicError::InvalidArguments { .. })
+    ));
+    // var with default matches users without dimension, which filter can't express.
+    assert_eq!(
+        from_jsonlogic(&json!({"==": [{"var": ["country", "KR"]}, "KR"]})),
+        Err(JsonLogicError::InvalidArguments {
+            operator: String::from("=="),
+            node: json!({"==": [{"var": ["country", "KR"]}, "KR"]}),
+        })
+    );
+    for node in [
+        json!({"!!": {"var": ["vip", true]}}),
+        json!({"!": [{"var": ["vip", false]}]}),
+        json!({"in": [{"var": ["age", "10"]}, ["10"]]}),
+    ] {
+        assert!(matches!(
+            from_jsonlogic(&node),
+            Err(JsonLogicError::InvalidArguments { .. })
+        ));
+    }
+    // var without default is the same as plain var.
+    assert_eq!(
+        from_jsonlogic(&json!({"==": [{"var": ["country"]}, "KR"]})),
+        from_jsonlogic(&json!({"==": [{"var": "country"}, "KR"]}))
+    );
+    assert!(matches!(
+        from_jsonlogic(&json!({"and": [], "or": []})),
+        Err(JsonLogicError::InvalidNode { .. })
+    ));
+    assert!(matches!(
+        from_jsonlogic(&json!(true)),
+        Err(JsonLogicError::InvalidNode { .. })
+    ));
+}
+
+#[test]
+fn test_exists_and_missing() {
+    let exists = TargetFilter::Exists {
+        dimension: String::from("region"),
+    };
+    let missing = TargetFilter::Missing {
+        dimension: String::from("region"),
+    };
+    assert_eq!(
+        from_json(&json!({"type": "exists", "dimension": "region"})),
+        Some(exists.clone())
+    );
+    assert_eq!(
+        from_json(&json!({"type": "missing", "dimension": "region"})),
+        Some(missing.clone())
+    );
+
+    assert_eq!(
+        from_jsonlogic(&json!({"!!": {"var": "region"}})),
+        Ok(exists.clone())
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"!": [{"var": "region"}]})),
+        Ok(missing.clone())
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"missing": ["region"]})),
+        Ok(missing.clone())
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"missing": "region"})),
+        Ok(missing.clone())
+    );
+    // missing is truthy when any of dimensions is missing.
+    assert_eq!(
+        from_jsonlogic(&json!({"missing": ["region", "age"]})),
+        Ok(TargetFilter::Or {
+            fields: vec![
+                missing,
+                TargetFilter::Missing {
+                    dimension: String::from("age"),
+                },
+            ],
+        })
+    );
+    assert!(matches!(
+        from_jsonlogic(&json!({"missing": []})),
+        Err(JsonLogicError::InvalidArguments { .. })
+    ));
+    assert!(matches!(
+        from_jsonlogic(&json!({"missing": [{"var": "region"}]})),
+        Err(JsonLogicError::InvalidArguments { .. })
+    ));
+}
+
+#[test]
+fn test_in_segment() {
+    let in_segment = TargetFilter::InSegment {
+        segment_id: String::from("seg_1"),
+    };
+    assert_eq!(
+        from_jsonlogic(&json!({"in_segment": ["seg_1"]})),
+        Ok(in_segment.clone())
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"in_segment": "seg_1"})),
+        Ok(in_segment.clone())
+    );
+    assert_eq!(
+        from_json(&json!({"type": "in_segment", "segment_id": "seg_1"})),
+        Some(in_segment)
+    );
+    assert!(matches!(
+        from_jsonlogic(&json!({"in_segment": [1]})),
+        Err(JsonLogicError::InvalidArguments { .. })
+    ));
+    assert_eq!(
+        from_jsonlogic(&json!({"in_user_list": ["vip"]})),
+        Ok(TargetFilter::InUserList {
+            list_id: String::from("vip")
+        })
+    );
+}
+
+#[test]
+fn test_prefer() {
+    let prefer = TargetFilter::Prefer {
+        field: Box::new(TargetFilter::InUserList {
+            list_id: String::from("vip"),
+        }),
+        weight: 1.5,
+    };
+    assert_eq!(
+        from_jsonlogic(&json!({"prefer": [{"in_user_list": ["vip"]}, 1.5]})),
+        Ok(prefer.clone())
+    );
+    assert_eq!(
+        from_json(
+            &json!({"type": "prefer", "field": {"type": "in_user_list", "list_id": "vip"}, "weight": 1.5})
+        ),
+        Some(prefer)
+    );
+    // weight is required and positive.
+    for args in [
+        json!([{"in_user_list": ["vip"]}]),
+        json!([{"in_user_list": ["vip"]}, 0]),
+    ] {
+        assert!(matches!(
+            from_jsonlogic(&json!({ "prefer": args })),
+            Err(JsonLogicError::InvalidArguments { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_contains() {
+    let at_least = TargetFilter::ContainsAtLeast {
+        dimension: String::from("movies"),
+        values: HashSet::from([String::from("1"), String::from("m2")]),
+        n: 2,
+    };
+    assert_eq!(
+        from_jsonlogic(&json!({"contains_at_least": [{"var": "movies"}, [1, "m2"], 2]})),
+        Ok(at_least.clone())
+    );
+    assert_eq!(
+        from_json(
+            &json!({"type": "contains_at_least", "dimension": "movies", "values": ["1", "m2"], "n": 2})
+        ),
+        Some(at_least)
+    );
+    assert_eq!(
+        from_jsonlogic(&json!({"contains_all": [{"var": "tags"}, ["a"]]})),
+        Ok(TargetFilter::ContainsAll {
+            dimension: String::from("tags"),
+            values: HashSet::from([String::from("a")]),
+        })
+    );
+    // count is required and not negative.
+    for args in [
+        json!([{"var": "movies"}, ["m1"]]),
+        json!([{"var": "movies"}, ["m1"], -1]),
+    ] {
+        assert!(matches!(
+            from_jsonlogic(&json!({ "contains_at_least": args })),
+            Err(JsonLogicError::InvalidArguments { .. })
+        ));
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::filter::TargetFilter;
+use crate::range::Interval;
+
+/**
+ * Database that keeps user features as json object on a column,
+ * such as `UserFeature.feature`(jsonb on Postgres, JSON on DuckDB).
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Postgres,
+    DuckDb,
+}
+
+/**
+ * WHERE clause with $1, $2.. placeholders. every dimension and value is bound as
+ * text parameter, so nothing from filter is written into sql itself.
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SqlWhere {
+    pub sql: String,
+    pub params: Vec<String>,
+}
+impl SqlWhere {
+    /**
+     * bind one more parameter after the ones of filter and return its placeholder,
+     * to combine the clause with other conditions(ex: version).
+     */
+    pub fn push_param(&mut self, value: &str) -> String {
+        self.params.push(value.to_string());
+        format!("${}", self.params.len())
+    }
+}
+
+// what parse_number accepts, except surrounding whitespace which is trimmed in sql.
+const NUMBER_PATTERN: &str = r"^[+-]?(([0-9]+\.?[0-9]*|\.[0-9]+)(e[+-]?[0-9]+)?|inf|infinity)$";
+
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+// json pointer(RFC 6901) of top level key, which DuckDB json functions take as path.
+fn json_pointer(key: &str) -> String {
+    format!("/{}", key.replace('~', "~0").replace('/', "~1"))
+}
+// bound as text and cast in sql, with the spelling of infinity both databases accept.
+fn number_param(value: f64) -> String {
+    if value == f64::INFINITY {
+        String::from("Infinity")
+    } else if value == f64::NEG_INFINITY {
+        String::from("-Infinity")
+    } else {
+        value.to_string()
+    }
+}
+
+struct SqlCompiler {
+    dialect: Dialect,
+    column: String,
+    params: Vec<String>,
+    // dimension -> expression of its key, so each key is bound once.
+    keys: HashMap<String, String>,
+}
+impl SqlCompiler {
+    fn param(&mut self, value: String) -> String {
+        self.params.push(value);
+        format!("${}", self.params.len())
+    }
+    fn key(&mut self, dimension: &str) -> String {
+        if let Some(placeholder) = self.keys.get(dimension) {
+            return placeholder.clone();
+        }
+        // `jsonb -> unknown` is ambiguous between key and index on Postgres.
+        let placeholder = match self.dialect {
+            Dialect::Postgres => {
+                let placeholder = self.param(dimension.to_string());
+                format!("CAST({} AS TEXT)", placeholder)
+            }
+            Dialect::DuckDb => self.param(json_pointer(dimension)),
+        };
+        self.keys.insert(dimension.to_string(), placeholder.clone());
+        placeholder
+    }
+    fn double(&self) -> &str {
+        match self.dialect {
+            Dialect::Postgres => "DOUBLE PRECISION",
+            Dialect::DuckDb => "DOUBLE",
+        }
+    }
+    /**
+     * true when any value of dimension satisfies condition on its text `value_text`.
+     * condition is built after key is bound, so key comes before its values on params.
+     * same as parse_user_info: array is set of its scalar elements, scalar is single value,
+     * json null is "null" and object has no value.
+     */
+    fn any_value<F>(&mut self, dimension: &str, condition: F) -> String
+    where
+        F: FnOnce(&mut Self) -> String,
+    {
+        let key = self.key(dimension);
+        let condition = condition(self);
+        let column = &self.column;
+        let (field, elements, scalar, text) = match self.dialect {
+            Dialect::Postgres => (
+                format!("({} -> {})", column, key),
+                format!(
+                    "jsonb_array_elements(CASE jsonb_typeof({column} -> {key}) WHEN 'array' THEN {column} -> {key} ELSE jsonb_build_array({column} -> {key}) END) AS e(value)",
+                    column = column,
+                    key = key
+                ),
+                "jsonb_typeof(e.value) NOT IN ('object', 'array')",
+                "(CASE jsonb_typeof(e.value) WHEN 'null' THEN 'null' ELSE e.value #>> '{}' END)",
+            ),
+            Dialect::DuckDb => (
+                format!("json_extract({}, {})", column, key),
+                format!(
+                    "(SELECT unnest(CASE WHEN json_type(json_extract({column}, {key})) = 'ARRAY' THEN CAST(json_extract({column}, {key}) AS JSON[]) ELSE [json_extract({column}, {key})] END) AS value) AS e",
+                    column = column,
+                    key = key
+                ),
+                "json_type(e.value) NOT IN ('OBJECT', 'ARRAY')",
+                "(CASE WHEN json_type(e.value) = 'NULL' THEN 'null' ELSE json_extract_string(e.value, '$') END)",
+            ),
+        };
+        format!(
+            "({field} IS NOT NULL AND EXISTS (SELECT 1 FROM {elements} WHERE {scalar} AND {condition}))",
+            field = field,
+            elements = elements,
+            scalar = scalar,
+            condition = condition.replace("value_text", text)
+        )
+    }
+    fn number(&self) -> String {
+        let matches = match self.dialect {
+            Dialect::Postgres => format!("TRIM(value_text) ~* '{}'", NUMBER_PATTERN),
+            Dialect::DuckDb => format!(
+                "regexp_matches(TRIM(value_text), '{}', 'i')",
+                NUMBER_PATTERN
+            ),
+        };
+        format!(
+            "(CASE WHEN {} THEN CAST(TRIM(value_text) AS {}) END)",
+            matches,
+            self.double()
+        )
+    }
+    fn range(&mut self, interval: Interval) -> String {
+        let number = self.number();
+        let mut bounds = Vec::new();
+        // infinite inclusive bound holds for every number.
+        if !(interval.lower == f64::NEG_INFINITY && interval.lower_inclusive) {
+            let op = if interval.lower_inclusive { ">=" } else { ">" };
+            let placeholder = self.param(number_param(interval.lower));
+            bounds.push(format!(
+                "{} {} CAST({} AS {})",
+                number,
+                op,
+                placeholder,
+                self.double()
+            ));
+        }
+        if !(interval.upper == f64::INFINITY && interval.upper_inclusive) {
+            let op = if interval.upper_inclusive { "<=" } else { "<" };
+            let placeholder = self.param(number_param(interval.upper));
+            bounds.push(format!(
+                "{} {} CAST({} AS {})",
+                number,
+                op,
+                placeholder,
+                self.double()
+            ));
+        }
+        if bounds.is_empty() {
+            bounds.push(format!("{} IS NOT NULL", number));
+        }
+        bounds.join(" AND ")
+    }
+    // user has n or more of values on dimension, counted one value at a time.
+    fn count_values(&mut self, dimension: &str, values: &HashSet<String>, n: usize) -> String {
+        if n == 0 {
+            return String::from("TRUE");
+        }
+        if n > values.len() {
+            return String::from("FALSE");
+        }
+        let values: BTreeSet<&String> = values.iter().collect();
+        let counts: Vec<String> = values
+            .into_iter()
+            .map(|value| {
+                let has_value = self.any_value(dimension, |compiler| {
+                    format!("value_text = {}", compiler.param(value.clone()))
+                });
+                format!("(CASE WHEN {} THEN 1 ELSE 0 END)", has_value)
+            })
+            .collect();
+        let n = self.param(n.to_string());
+        format!("({}) >= CAST({} AS INTEGER)", counts.join(" + "), n)
+    }
+    fn compile(&mut self, filter: &TargetFilter) -> String {
+        match filter {
+            TargetFilter::In {
+                dimension,
+                valid_values,
+            } => {
+                if valid_values.is_empty() {
+                    return String::from("FALSE");
+                }
+                // sorted, so the same filter always gives the same sql.
+                let valid_values: BTreeSet<&String> = valid_values.iter().collect();
+                self.any_value(dimension, |compiler| {
+                    let placeholders: Vec<String> = valid_values
+                        .into_iter()
+                        .map(|value| compiler.param(value.clone()))
+                        .collect();
+                    format!("value_text IN ({})", placeholders.join(", "))
+                })
+            }
+            TargetFilter::Select {
+                dimension,
+                valid_value,
+            } => self.any_value(dimension, |compiler| {
+                format!("value_text = {}", compiler.param(valid_value.clone()))
+            }),
+            TargetFilter::And { fields } if fields.is_empty() => String::from("TRUE"),
+            TargetFilter::Or { fields } if fields.is_empty() => String::from("FALSE"),
+            TargetFilter::And { fields } => {
+                let fields: Vec<String> = fields.iter().map(|f| self.compile(f)).collect();
+                format!("({})", fields.join(" AND "))
+            }
+            TargetFilter::Or { fields } => {
+                let fields: Vec<String> = fields.iter().map(|f| self.compile(f)).collect();
+                format!("({})", fields.join(" OR "))
+            }
+            TargetFilter::Not { field } => format!("(NOT {})", self.compile(field)),
+            TargetFilter::Exists { dimension } => {
+                self.any_value(dimension, |_compiler| String::from("TRUE"))
+            }
+            TargetFilter::Missing { dimension } => format!(
+                "(NOT {})",
+                self.any_value(dimension, |_compiler| String::from("TRUE"))
+            ),
+            // segments are resolved before compile, unresolved one never holds as on apply.
+            TargetFilter::InSegment { .. } => String::from("FALSE"),
+            // user lists are not on UserFeature, so no user is counted in them.
+            TargetFilter::InUserList { .. } => String::from("FALSE"),
+            TargetFilter::ContainsAll { dimension, values } => {
+                self.count_values(dimension, values, values.len())
+            }
+            TargetFilter::ContainsAtLeast {
+                dimension,
+                values,
+                n,
+            } => self.count_values(dimension, values, *n),
+            // preference never excludes user.
+            TargetFilter::Prefer { .. } => String::from("TRUE"),
+            _ => match TargetFilter::range(filter) {
+                Some((dimension, interval)) => {
+                    self.any_value(dimension, |compiler| compiler.range(interval))
+                }
+                None => String::from("FALSE"),
+            },
+        }
+    }
+}
+
+/**
+ * Compile filter into WHERE clause over json `column`, with the same semantics as
+ * Filter::apply on user_info parsed from the column by parse_user_info.
+ * numbers and bools are compared by their text as stored in json.
+ */
+pub fn to_sql_where(filter: &TargetFilter, dialect: Dialect, column: &str) -> SqlWhere {
+    let mut compiler = SqlCompiler {
+        dialect,
+        column: quote_identifier(column),
+        params: Vec::new(),
+        keys: HashMap::new(),
+    };
+    let sql = compiler.compile(filter);
+    SqlWhere {
+        sql,
+        params: compiler.params,
+    }
+}
+
+#[cfg(test)]
+#[path = "./sql_test.rs"]
+mod sql_test;
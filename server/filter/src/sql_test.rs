@@ -0,0 +1,146 @@
+use super::*;
+
+use std::collections::HashSet;
+
+fn select(dimension: &str, value: &str) -> TargetFilter {
+    TargetFilter::Select {
+        dimension: String::from(dimension),
+        valid_value: String::from(value),
+    }
+}
+
+#[test]
+fn test_postgres_where() {
+    let filter = TargetFilter::And {
+        fields: vec![
+            TargetFilter::In {
+                dimension: String::from("country"),
+                valid_values: HashSet::from([String::from("US"), String::from("KR")]),
+            },
+            TargetFilter::Not {
+                field: Box::new(select("country", "JP")),
+            },
+        ],
+    };
+    let mut sql_where = to_sql_where(&filter, Dialect::Postgres, "feature");
+    let country_values = |condition: &str| {
+        format!(
+            "((\"feature\" -> CAST($1 AS TEXT)) IS NOT NULL AND EXISTS (SELECT 1 FROM jsonb_array_elements(CASE jsonb_typeof(\"feature\" -> CAST($1 AS TEXT)) WHEN 'array' THEN \"feature\" -> CAST($1 AS TEXT) ELSE jsonb_build_array(\"feature\" -> CAST($1 AS TEXT)) END) AS e(value) WHERE jsonb_typeof(e.value) NOT IN ('object', 'array') AND {}))",
+            condition
+        )
+    };
+    let text = "(CASE jsonb_typeof(e.value) WHEN 'null' THEN 'null' ELSE e.value #>> '{}' END)";
+    assert_eq!(
+        sql_where.sql,
+        format!(
+            "({} AND (NOT {}))",
+            country_values(&format!("{} IN ($2, $3)", text)),
+            country_values(&format!("{} = $4", text)),
+        )
+    );
+    // key of the same dimension is bound once.
+    assert_eq!(sql_where.params, vec!["country", "KR", "US", "JP"]);
+
+    assert_eq!(sql_where.push_param("20230701"), "$5");
+    assert_eq!(sql_where.params.len(), 5);
+}
+
+#[test]
+fn test_duckdb_where() {
+    let filter = TargetFilter::Gt {
+        dimension: String::from("a/b~c"),
+        value: 20.0,
+    };
+    let sql_where = to_sql_where(&filter, Dialect::DuckDb, "feature");
+    // top level key is addressed by json pointer.
+    assert_eq!(sql_where.params, vec!["/a~1b~0c", "20"]);
+    assert!(sql_where.sql.starts_with(
+        "(json_extract(\"feature\", $1) IS NOT NULL AND EXISTS (SELECT 1 FROM (SELECT unnest("
+    ));
+    assert!(sql_where
+        .sql
+        .contains("AS DOUBLE) END) > CAST($2 AS DOUBLE)"));
+}
+
+#[test]
+fn test_values_are_only_bound() {
+    let injection = "x' OR 1=1 --";
+    let filter = TargetFilter::Or {
+        fields: vec![
+            select(injection, injection),
+            TargetFilter::Missing {
+                dimension: String::from("\"region\""),
+            },
+            TargetFilter::Between {
+                dimension: String::from("age"),
+                min: f64::NEG_INFINITY,
+                max: 30.5,
+            },
+        ],
+    };
+    for dialect in [Dialect::Postgres, Dialect::DuckDb] {
+        let sql_where = to_sql_where(&filter, dialect, "feature");
+        assert!(!sql_where.sql.contains(injection));
+        assert!(!sql_where.sql.contains("region"));
+        assert!(!sql_where.sql.contains("age"));
+        assert!(sql_where.params.contains(&String::from(injection)));
+        assert!(sql_where.params.contains(&String::from("30.5")));
+        // inclusive infinite bound is dropped.
+        assert!(!sql_where.params.contains(&String::from("-Infinity")));
+    }
+    assert_eq!(
+        to_sql_where(
+            &TargetFilter::Gt {
+                dimension: String::from("age"),
+                value: f64::NEG_INFINITY,
+            },
+            Dialect::Postgres,
+            "feature",
+        )
+        .params,
+        vec!["age", "-Infinity"]
+    );
+    assert_eq!(quote_identifier("fea\"ture"), "\"fea\"\"ture\"");
+}
+
+#[test]
+fn test_constant_where() {
+    let sql = |filter: TargetFilter| to_sql_where(&filter, Dialect::Postgres, "feature").sql;
+    assert_eq!(sql(TargetFilter::And { fields: vec![] }), "TRUE");
+    assert_eq!(sql(TargetFilter::Or { fields: vec![] }), "FALSE");
+    assert_eq!(
+        sql(TargetFilter::In {
+            dimension: String::from("country"),
+            valid_values: HashSet::new(),
+        }),
+        "FALSE"
+    );
+}
+
+#[test]
+fn test_contains_where() {
+    let values: HashSet<String> = HashSet::from([String::from("m2"), String::from("m1")]);
+    let at_least = to_sql_where(
+        &TargetFilter::ContainsAtLeast {
+            dimension: String::from("movies"),
+            values: values.clone(),
+            n: 2,
+        },
+        Dialect::Postgres,
+        "feature",
+    );
+    // each value is counted on its own, and count is bound too.
+    assert_eq!(at_least.params, vec!["movies", "m1", "m2", "2"]);
+    assert!(at_least.sql.ends_with(">= CAST($4 AS INTEGER)"));
+    assert_eq!(
+        to_sql_where(
+            &TargetFilter::ContainsAll {
+                dimension: String::from("movies"),
+                values,
+            },
+            Dialect::Postgres,
+            "feature",
+        ),
+        at_least
+    );
+}
@@ -0,0 +1,140 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use common::types::UserInfo;
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaxonomyError {
+    // node at path(ex: region.APAC.country) is not an object of children.
+    InvalidNode { path: String },
+}
+impl std::fmt::Display for TaxonomyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaxonomyError::InvalidNode { path } => {
+                write!(f, "taxonomy node {} is not an object", path)
+            }
+        }
+    }
+}
+impl std::error::Error for TaxonomyError {}
+
+/**
+ * Parent/child trees of dimension values. user that has a child value is expanded
+ * with every ancestor value before search, so filter on region.APAC matches
+ * user with country.KR or city.Seoul without listing every leaf on the filter.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Taxonomy {
+    // (dimension, value) -> its parents.
+    parents: HashMap<(String, String), BTreeSet<(String, String)>>,
+}
+impl Taxonomy {
+    /**
+     * Nested dimension -> value -> children, where children has the same shape.
+     * ex) {"region": {"APAC": {"country": {"KR": {"city": {"Seoul": {}}}, "JP": {}}}}}
+     * leaf can be {} or null. value can have parents on more than one tree.
+     */
+    pub fn from_json(value: &Value) -> Result<Self, TaxonomyError> {
+        let mut taxonomy = Taxonomy::default();
+        taxonomy.add_children(None, value, "")?;
+        Ok(taxonomy)
+    }
+    fn add_children(
+        &mut self,
+        parent: Option<&(String, String)>,
+        children: &Value,
+        path: &str,
+    ) -> Result<(), TaxonomyError> {
+        let invalid = |path: &str| TaxonomyError::InvalidNode {
+            path: String::from(path),
+        };
+        let dimensions = match children {
+            Value::Null => return Ok(()),
+            Value::Object(dimensions) => dimensions,
+            _ => return Err(invalid(path)),
+        };
+        for (dimension, values) in dimensions {
+            let dimension_path = join_path(path, dimension);
+            let values = values.as_object().ok_or_else(|| invalid(&dimension_path))?;
+            for (value, grand_children) in values {
+                let node = (dimension.clone(), value.clone());
+                if let Some(parent) = parent {
+                    self.add(node.clone(), parent.clone());
+                }
+                self.add_children(
+                    Some(&node),
+                    grand_children,
+                    &join_path(&dimension_path, value),
+                )?;
+            }
+        }
+        Ok(())
+    }
+    pub fn add(&mut self, child: (String, String), parent: (String, String)) {
+        self.parents.entry(child).or_default().insert(parent);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+    // dimensions of every node, ancestors are added to user_info on expansion.
+    pub fn dimensions(&self) -> HashSet<String> {
+        self.parents
+            .iter()
+            .flat_map(|(child, parents)| std::iter::once(child).chain(parents.iter()))
+            .map(|(dimension, _)| dimension.clone())
+            .collect()
+    }
+    /**
+     * every ancestor of dimension value, nearest first.
+     */
+    pub fn ancestors(&self, dimension: &str, value: &str) -> Vec<(String, String)> {
+        let mut ancestors = Vec::new();
+        let mut visited = HashSet::new();
+        let mut nexts = vec![(dimension.to_string(), value.to_string())];
+        while !nexts.is_empty() {
+            let mut parents = Vec::new();
+            for node in nexts {
+                for parent in self.parents.get(&node).into_iter().flatten() {
+                    if visited.insert(parent.clone()) {
+                        ancestors.push(parent.clone());
+                        parents.push(parent.clone());
+                    }
+                }
+            }
+            nexts = parents;
+        }
+        ancestors
+    }
+    /**
+     * user_info with ancestors of its values added.
+     */
+    pub fn expand(&self, user_info: &UserInfo) -> UserInfo {
+        let mut expanded = user_info.clone();
+        if self.is_empty() {
+            return expanded;
+        }
+        for (dimension, values) in user_info {
+            for value in values {
+                for (ancestor_dimension, ancestor_value) in self.ancestors(dimension, value) {
+                    expanded
+                        .entry(ancestor_dimension)
+                        .or_default()
+                        .insert(ancestor_value);
+                }
+            }
+        }
+        expanded
+    }
+}
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+#[path = "./taxonomy_test.rs"]
+mod taxonomy_test;
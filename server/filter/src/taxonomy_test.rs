@@ -0,0 +1,115 @@
+use super::*;
+
+use crate::filter::TargetFilter;
+use crate::index::{FilterIndex, IndexBackend};
+use crate::test_util::{select, user_info_of, TestFilter};
+use serde_json::json;
+use std::sync::Arc;
+
+fn geo() -> Taxonomy {
+    Taxonomy::from_json(&json!({
+        "region": {
+            "APAC": {
+                "country": {
+                    "KR": {"city": {"Seoul": {}, "Busan": null}},
+                    "JP": {}
+                }
+            },
+            "EMEA": {"country": {"FR": {}}}
+        }
+    }))
+    .unwrap()
+}
+fn pair(dimension: &str, value: &str) -> (String, String) {
+    (String::from(dimension), String::from(value))
+}
+
+#[test]
+fn test_ancestors_and_expand() {
+    let taxonomy = geo();
+    assert_eq!(
+        taxonomy.ancestors("city", "Seoul"),
+        vec![pair("country", "KR"), pair("region", "APAC")]
+    );
+    assert_eq!(taxonomy.ancestors("region", "APAC"), vec![]);
+    assert_eq!(taxonomy.ancestors("country", "US"), vec![]);
+
+    let expanded = taxonomy.expand(&user_info_of(&[
+        ("city", &["Seoul"]),
+        ("country", &["FR"]),
+        ("age", &["10"]),
+    ]));
+    assert_eq!(
+        expanded,
+        user_info_of(&[
+            ("city", &["Seoul"]),
+            ("country", &["FR", "KR"]),
+            ("region", &["APAC", "EMEA"]),
+            ("age", &["10"]),
+        ])
+    );
+
+    // value can be under itself on the same dimension, and have more than one parent.
+    let mut taxonomy = Taxonomy::from_json(&json!({
+        "category": {"sports": {"category": {"soccer": {}}}}
+    }))
+    .unwrap();
+    taxonomy.add(pair("category", "soccer"), pair("category", "outdoor"));
+    taxonomy.add(pair("category", "outdoor"), pair("category", "soccer"));
+    assert_eq!(
+        taxonomy.expand(&user_info_of(&[("category", &["soccer"])])),
+        user_info_of(&[("category", &["soccer", "sports", "outdoor"])])
+    );
+}
+
+#[test]
+fn test_invalid_taxonomy() {
+    assert_eq!(
+        Taxonomy::from_json(&json!({"region": {"APAC": {"country": ["KR"]}}})),
+        Err(TaxonomyError::InvalidNode {
+            path: String::from("region.APAC.country")
+        })
+    );
+    assert_eq!(
+        Taxonomy::from_json(&json!({"region": {"APAC": 1}}))
+            .unwrap_err()
+            .to_string(),
+        "taxonomy node region.APAC is not an object"
+    );
+}
+
+#[test]
+fn test_search_with_taxonomy() {
+    let filters = vec![
+        TestFilter {
+            id: String::from("ad_apac"),
+            filter: Some(select("region", "APAC")),
+        },
+        TestFilter {
+            id: String::from("ad_kr"),
+            filter: Some(select("country", "KR")),
+        },
+        TestFilter {
+            id: String::from("ad_not_kr"),
+            filter: Some(TargetFilter::Not {
+                field: Box::new(select("country", "KR")),
+            }),
+        },
+    ];
+    for backend in [IndexBackend::HashSet, IndexBackend::Bitmap] {
+        let mut index = FilterIndex::with_backend(backend);
+        index.update(&filters, &[]);
+        index.set_taxonomy(Some(Arc::new(geo())));
+
+        let seoul = user_info_of(&[("city", &["Seoul"])]);
+        assert_eq!(index.search(&seoul), HashSet::from(["ad_apac", "ad_kr"]));
+        assert_eq!(
+            index.search(&user_info_of(&[("country", &["JP"])])),
+            HashSet::from(["ad_apac", "ad_not_kr"])
+        );
+        assert!(index.explain(&seoul, "ad_kr").matched);
+
+        index.set_taxonomy(None);
+        assert_eq!(index.search(&seoul), HashSet::from(["ad_not_kr"]));
+    }
+}
@@ -0,0 +1,223 @@
+// fixtures and proptest strategies shared by tests.
+use common::types::UserInfo;
+use proptest::prelude::*;
+use std::collections::HashSet;
+
+use crate::filter::TargetFilter;
+use crate::filterable::Filterable;
+use crate::user_list::USER_LIST_DIMENSION;
+
+#[derive(Debug, Clone)]
+pub struct TestFilter {
+    pub id: String,
+    pub filter: Option<TargetFilter>,
+}
+impl Filterable for TestFilter {
+    fn id(&self) -> String {
+        String::from(&self.id)
+    }
+
+    fn filter(&self) -> Option<TargetFilter> {
+        self.filter.clone()
+    }
+}
+
+pub fn select(dimension: &str, value: &str) -> TargetFilter {
+    TargetFilter::Select {
+        dimension: String::from(dimension),
+        valid_value: String::from(value),
+    }
+}
+pub fn user_info_of(kvs: &[(&str, &[&str])]) -> UserInfo {
+    kvs.iter()
+        .map(|(k, vs)| {
+            (
+                String::from(*k),
+                vs.iter().map(|v| String::from(*v)).collect::<HashSet<_>>(),
+            )
+        })
+        .collect()
+}
+
+// small domain so that generated filters and user_infos actually match each other.
+pub fn dimension_strategy() -> impl Strategy<Value = String> {
+    prop::sample::select(vec!["a", "b", "c", "d"]).prop_map(String::from)
+}
+pub fn value_strategy() -> impl Strategy<Value = String> {
+    prop::sample::select(vec!["1", "2", "3", "x"]).prop_map(String::from)
+}
+pub fn number_strategy() -> impl Strategy<Value = f64> {
+    (0i64..6).prop_map(|v| v as f64)
+}
+pub fn list_id_strategy() -> impl Strategy<Value = String> {
+    prop::sample::select(vec!["l1", "l2"]).prop_map(String::from)
+}
+/**
+ * Where leaves of generated filters take their operands from and how deep the tree is.
+ * default is the small domain of index tests, serde and DSL tests bring their own.
+ */
+pub struct FilterDomain {
+    pub dimensions: BoxedStrategy<String>,
+    pub values: BoxedStrategy<String>,
+    pub numbers: BoxedStrategy<f64>,
+    // None when the test can't resolve segments.
+    pub segment_ids: Option<BoxedStrategy<String>>,
+    pub list_ids: BoxedStrategy<String>,
+    // (depth, desired size, max fields of And/Or) of prop_recursive.
+    pub shape: (u32, u32, u32),
+}
+impl Default for FilterDomain {
+    fn default() -> Self {
+        Self {
+            dimensions: dimension_strategy().boxed(),
+            values: value_strategy().boxed(),
+            numbers: number_strategy().boxed(),
+            segment_ids: None,
+            list_ids: list_id_strategy().boxed(),
+            shape: (3, 16, 3),
+        }
+    }
+}
+impl FilterDomain {
+    pub fn target_filter_strategy(self) -> impl Strategy<Value = TargetFilter> {
+        let FilterDomain {
+            dimensions,
+            values,
+            numbers,
+            segment_ids,
+            list_ids,
+            shape: (depth, size, fields),
+        } = self;
+        let mut leaves = vec![
+            (
+                dimensions.clone(),
+                prop::collection::hash_set(values.clone(), 0..4),
+            )
+                .prop_map(|(dimension, valid_values)| TargetFilter::In {
+                    dimension,
+                    valid_values,
+                })
+                .boxed(),
+            (dimensions.clone(), values.clone())
+                .prop_map(|(dimension, valid_value)| TargetFilter::Select {
+                    dimension,
+                    valid_value,
+                })
+                .boxed(),
+            (dimensions.clone(), numbers.clone())
+                .prop_map(|(dimension, value)| TargetFilter::Gt { dimension, value })
+                .boxed(),
+            (dimensions.clone(), numbers.clone())
+                .prop_map(|(dimension, value)| TargetFilter::Gte { dimension, value })
+                .boxed(),
+            (dimensions.clone(), numbers.clone())
+                .prop_map(|(dimension, value)| TargetFilter::Lt { dimension, value })
+                .boxed(),
+            (dimensions.clone(), numbers.clone())
+                .prop_map(|(dimension, value)| TargetFilter::Lte { dimension, value })
+                .boxed(),
+            (dimensions.clone(), numbers.clone(), numbers)
+                .prop_map(|(dimension, min, max)| TargetFilter::Between {
+                    dimension,
+                    min,
+                    max,
+                })
+                .boxed(),
+            dimensions
+                .clone()
+                .prop_map(|dimension| TargetFilter::Exists { dimension })
+                .boxed(),
+            dimensions
+                .clone()
+                .prop_map(|dimension| TargetFilter::Missing { dimension })
+                .boxed(),
+            list_ids
+                .prop_map(|list_id| TargetFilter::InUserList { list_id })
+                .boxed(),
+            (
+                dimensions.clone(),
+                prop::collection::hash_set(values.clone(), 0..4),
+            )
+                .prop_map(|(dimension, values)| TargetFilter::ContainsAll { dimension, values })
+                .boxed(),
+            (
+                dimensions,
+                prop::collection::hash_set(values, 0..4),
+                0usize..5,
+            )
+                .prop_map(|(dimension, values, n)| TargetFilter::ContainsAtLeast {
+                    dimension,
+                    values,
+                    n,
+                })
+                .boxed(),
+        ];
+        if let Some(segment_ids) = segment_ids {
+            leaves.push(
+                segment_ids
+                    .prop_map(|segment_id| TargetFilter::InSegment { segment_id })
+                    .boxed(),
+            );
+        }
+        let leaf = prop::strategy::Union::new(leaves);
+        leaf.prop_recursive(depth, size, fields, move |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..fields as usize)
+                    .prop_map(|fields| TargetFilter::And { fields }),
+                prop::collection::vec(inner.clone(), 0..fields as usize)
+                    .prop_map(|fields| TargetFilter::Or { fields }),
+                inner.clone().prop_map(|field| TargetFilter::Not {
+                    field: Box::new(field)
+                }),
+                (inner, 0.5f64..100.0).prop_map(|(field, weight)| TargetFilter::Prefer {
+                    field: Box::new(field),
+                    weight,
+                }),
+            ]
+        })
+    }
+}
+pub fn target_filter_strategy() -> impl Strategy<Value = TargetFilter> {
+    FilterDomain::default().target_filter_strategy()
+}
+// some users are on user lists, as user_info is after user lists are applied.
+pub fn user_info_strategy() -> impl Strategy<Value = UserInfo> {
+    (
+        prop::collection::hash_map(
+            dimension_strategy(),
+            prop::collection::hash_set(value_strategy(), 0..3),
+            0..4,
+        ),
+        prop::option::of(prop::collection::hash_set(list_id_strategy(), 0..3)),
+    )
+        .prop_map(|(mut user_info, list_ids)| {
+            if let Some(list_ids) = list_ids {
+                user_info.insert(String::from(USER_LIST_DIMENSION), list_ids);
+            }
+            user_info
+        })
+}
+// (id, None: delete, Some(None): non filter, Some(Some(filter)): insert)
+pub fn step_strategy() -> impl Strategy<Value = Vec<(String, Option<Option<TargetFilter>>)>> {
+    let id = prop::sample::select(vec!["ad_1", "ad_2", "ad_3", "ad_4_1"]).prop_map(String::from);
+    let op = prop_oneof![
+        1 => Just(None),
+        1 => Just(Some(None)),
+        4 => target_filter_strategy().prop_map(|filter| Some(Some(filter))),
+    ];
+    prop::collection::vec((id, op), 1..4)
+}
+
+pub fn to_updates(
+    step: Vec<(String, Option<Option<TargetFilter>>)>,
+) -> (Vec<TestFilter>, Vec<TestFilter>) {
+    let mut inserts = Vec::new();
+    let mut deletes = Vec::new();
+    for (id, op) in step {
+        match op {
+            None => deletes.push(TestFilter { id, filter: None }),
+            Some(filter) => inserts.push(TestFilter { id, filter }),
+        }
+    }
+    (inserts, deletes)
+}
@@ -0,0 +1,88 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use common::types::UserInfo;
+
+/**
+ * Reserved dimension that has the ids of user lists the user of request is in.
+ * InUserList(list_id) is In on this dimension, so the index only has one posting
+ * per list instead of one per user id.
+ */
+pub const USER_LIST_DIMENSION: &str = "$user_list";
+
+/**
+ * Membership of large user id list(ex: CRM upload on Customset).
+ * ids are kept sorted and looked up with binary search.
+ */
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct UserList {
+    ids: Vec<String>,
+}
+impl UserList {
+    pub fn new<I>(ids: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut ids: Vec<String> = ids.into_iter().collect();
+        ids.sort();
+        ids.dedup();
+        ids.shrink_to_fit();
+        UserList { ids }
+    }
+    /**
+     * one user id per line. surrounding whitespace and blank lines are ignored.
+     */
+    pub fn parse(text: &str) -> Self {
+        Self::new(
+            text.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        )
+    }
+    pub fn contains(&self, user_id: &str) -> bool {
+        self.ids
+            .binary_search_by(|id| id.as_str().cmp(user_id))
+            .is_ok()
+    }
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+// ids are not printed, list can have hundreds of thousands of them.
+impl std::fmt::Debug for UserList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UserList({} ids)", self.ids.len())
+    }
+}
+
+/**
+ * Put lists that user_id is in on USER_LIST_DIMENSION of user_info.
+ * it is always overwritten, so request can't claim membership by sending the dimension.
+ */
+pub fn insert_user_lists(
+    user_info: &mut UserInfo,
+    user_id: Option<&str>,
+    user_lists: &HashMap<String, Arc<UserList>>,
+) {
+    let list_ids: HashSet<String> = match user_id {
+        None => HashSet::new(),
+        Some(user_id) => user_lists
+            .iter()
+            .filter(|(_, user_list)| user_list.contains(user_id))
+            .map(|(list_id, _)| list_id.clone())
+            .collect(),
+    };
+    if list_ids.is_empty() {
+        user_info.remove(USER_LIST_DIMENSION);
+    } else {
+        user_info.insert(String::from(USER_LIST_DIMENSION), list_ids);
+    }
+}
+
+#[cfg(test)]
+#[path = "./user_list_test.rs"]
+mod user_list_test;
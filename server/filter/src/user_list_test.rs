@@ -0,0 +1,90 @@
+use super::*;
+
+use crate::filter::{Filter, TargetFilter};
+use crate::index::{FilterIndex, IndexBackend};
+use crate::test_util::{select, user_info_of, TestFilter};
+
+fn in_user_list(list_id: &str) -> TargetFilter {
+    TargetFilter::InUserList {
+        list_id: String::from(list_id),
+    }
+}
+
+#[test]
+fn test_user_list() {
+    let user_list = UserList::parse("u3\n  u1 \n\nu2\nu1\n");
+    assert_eq!(user_list.len(), 3);
+    assert!(user_list.contains("u1"));
+    assert!(user_list.contains("u3"));
+    assert!(!user_list.contains("u4"));
+    assert!(!user_list.contains(""));
+    assert_eq!(format!("{:?}", user_list), "UserList(3 ids)");
+    assert!(UserList::new(vec![]).is_empty());
+}
+
+#[test]
+fn test_insert_user_lists() {
+    let user_lists = HashMap::from([
+        (
+            String::from("vip"),
+            Arc::new(UserList::new(vec![String::from("u1"), String::from("u2")])),
+        ),
+        (
+            String::from("churned"),
+            Arc::new(UserList::new(vec![String::from("u2")])),
+        ),
+    ]);
+    let mut user_info = user_info_of(&[("age", &["10"])]);
+    insert_user_lists(&mut user_info, Some("u2"), &user_lists);
+    assert_eq!(
+        user_info[USER_LIST_DIMENSION],
+        HashSet::from([String::from("vip"), String::from("churned")])
+    );
+
+    // membership sent on request is not trusted.
+    let mut user_info = user_info_of(&[(USER_LIST_DIMENSION, &["vip"])]);
+    insert_user_lists(&mut user_info, Some("u3"), &user_lists);
+    assert!(!user_info.contains_key(USER_LIST_DIMENSION));
+
+    let mut user_info = user_info_of(&[(USER_LIST_DIMENSION, &["vip"])]);
+    insert_user_lists(&mut user_info, None, &user_lists);
+    assert!(!user_info.contains_key(USER_LIST_DIMENSION));
+}
+
+#[test]
+fn test_search_user_list() {
+    let user_lists = HashMap::from([(
+        String::from("vip"),
+        Arc::new(UserList::new((0..1000).map(|i| format!("u{}", i)))),
+    )]);
+    let filters = vec![
+        TestFilter {
+            id: String::from("ad_vip"),
+            filter: Some(TargetFilter::And {
+                fields: vec![in_user_list("vip"), select("age", "10")],
+            }),
+        },
+        TestFilter {
+            id: String::from("ad_not_vip"),
+            filter: Some(TargetFilter::Not {
+                field: Box::new(in_user_list("vip")),
+            }),
+        },
+    ];
+    for backend in [IndexBackend::HashSet, IndexBackend::Bitmap] {
+        let mut index = FilterIndex::with_backend(backend);
+        index.update(&filters, &[]);
+
+        for (user_id, expected) in [(Some("u10"), "ad_vip"), (Some("x"), "ad_not_vip")] {
+            let mut user_info = user_info_of(&[("age", &["10"])]);
+            insert_user_lists(&mut user_info, user_id, &user_lists);
+            assert_eq!(index.search(&user_info), HashSet::from([expected]));
+            for filter in &filters {
+                assert_eq!(
+                    filter.filter.as_ref().unwrap().apply(&user_info),
+                    filter.id == expected
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::dsl::{from_dsl, to_dsl};
+use crate::filter::{Filter, TargetFilter};
+use crate::serde::{from_jsonlogic, to_jsonlogic};
+use common::util::parse_user_info;
+
+// bindings for dashboard, so that targeting preview runs the same code as server.
+// values cross the boundary as JSON text, and errors are thrown as JSON text of
+// {"message"}, with "line" and "column" when DSL fails to parse.
+fn error(message: String) -> String {
+    json!({ "message": message }).to_string()
+}
+fn parse_json(text: &str) -> Result<Value, String> {
+    serde_json::from_str(text).map_err(|e| error(e.to_string()))
+}
+fn parse_filter(jsonlogic: &str) -> Result<TargetFilter, String> {
+    from_jsonlogic(&parse_json(jsonlogic)?).map_err(|e| error(e.to_string()))
+}
+// same as response of POST /filters/parse.
+fn to_parsed(filter: &TargetFilter) -> String {
+    json!({
+        "text": to_dsl(filter),
+        "jsonlogic": to_jsonlogic(filter),
+    })
+    .to_string()
+}
+
+/**
+ * jsonlogic compiled into filter, returned as {"text", "jsonlogic"} in the form server stores.
+ */
+#[wasm_bindgen(js_name = fromJsonLogic)]
+pub fn from_jsonlogic_text(jsonlogic: &str) -> Result<String, String> {
+    parse_filter(jsonlogic).map(|filter| to_parsed(&filter))
+}
+
+/**
+ * targeting text parsed into {"text", "jsonlogic"}.
+ */
+#[wasm_bindgen(js_name = parseDsl)]
+pub fn parse_dsl(text: &str) -> Result<String, String> {
+    match from_dsl(text) {
+        Ok(filter) => Ok(to_parsed(&filter)),
+        Err(e) => Err(serde_json::to_string(&e).unwrap()),
+    }
+}
+
+/**
+ * whether user matches filter. user_info is an object of dimension to value or values.
+ * segments are not resolved here, so InSegment never matches.
+ */
+#[wasm_bindgen]
+pub fn evaluate(jsonlogic: &str, user_info: &str) -> Result<bool, String> {
+    let filter = parse_filter(jsonlogic)?;
+    let user_info = parse_user_info(&parse_json(user_info)?)
+        .ok_or_else(|| error(String::from("user_info is not an object")))?;
+
+    Ok(filter.apply(&user_info))
+}
+
+#[cfg(test)]
+#[path = "./wasm_test.rs"]
+mod wasm_test;
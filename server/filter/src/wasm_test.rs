@@ -0,0 +1,62 @@
+use super::*;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::wasm_bindgen_test;
+
+// same tests run natively and on node with wasm-bindgen-test-runner.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn test_from_jsonlogic_text() {
+    let parsed: Value = serde_json::from_str(
+        &from_jsonlogic_text(r#"{"in": [{"var": "country"}, ["KR", "JP"]]}"#).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(parsed["text"], json!(r#"country in ("JP", "KR")"#));
+    // stored jsonlogic compiles into the same filter.
+    assert_eq!(
+        parse_filter(&parsed["jsonlogic"].to_string()).unwrap(),
+        from_dsl(r#"country in ("KR", "JP")"#).unwrap()
+    );
+
+    let error: Value =
+        serde_json::from_str(&from_jsonlogic_text(r#"{"merge": []}"#).unwrap_err()).unwrap();
+    assert_eq!(error["message"].as_str().is_some(), true);
+    assert_eq!(from_jsonlogic_text("{").is_err(), true);
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn test_parse_dsl() {
+    let parsed: Value =
+        serde_json::from_str(&parse_dsl(r#"country = "KR" and age >= 20"#).unwrap()).unwrap();
+    assert_eq!(
+        parsed["jsonlogic"],
+        json!({"and": [
+            {"==": [{"var": "country"}, "KR"]},
+            {">=": [{"var": "age"}, 20.0]}
+        ]})
+    );
+
+    let error: Value = serde_json::from_str(&parse_dsl("country = ").unwrap_err()).unwrap();
+    assert_eq!(error["line"], json!(1));
+    assert_eq!(error["message"].as_str().is_some(), true);
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn test_evaluate() {
+    let jsonlogic = r#"{"and": [
+        {"in": [{"var": "country"}, ["KR", "JP"]]},
+        {">=": [{"var": "age"}, 20]}
+    ]}"#;
+    assert_eq!(
+        evaluate(jsonlogic, r#"{"country": "KR", "age": 30}"#).unwrap(),
+        true
+    );
+    assert_eq!(
+        evaluate(jsonlogic, r#"{"country": ["US", "JP"], "age": "20"}"#).unwrap(),
+        true
+    );
+    assert_eq!(evaluate(jsonlogic, r#"{"country": "KR"}"#).unwrap(), false);
+    assert_eq!(evaluate(jsonlogic, "[]").is_err(), true);
+}
@@ -10,6 +10,7 @@ impl AdSetThompsonSamplingRanker {
         &self,
         ad_sets_stat: &'a HashMap<String, Stat>,
         candidates: Vec<&'a ad_set::Data>,
+        match_scores: &HashMap<&str, f64>,
         k: usize,
     ) -> Vec<(&'a ad_set::Data, f32)> {
         let mut top_candidates = Vec::new();
@@ -22,8 +23,13 @@ impl AdSetThompsonSamplingRanker {
             let default_stat = Stat::default();
             let stat = ad_sets_stat.get(&candidate.id).unwrap_or(&default_stat);
             let score = stat.score().unwrap_or(0.0);
+            // ad set that is preferred by user is boosted by its match score.
+            let match_score = match_scores
+                .get(candidate.id.as_str())
+                .copied()
+                .unwrap_or(0.0);
 
-            top_candidates.push((candidate, score))
+            top_candidates.push((candidate, score * (1.0 + match_score as f32)))
         }
         top_candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
         top_candidates.truncate(k);
@@ -233,44 +233,63 @@ impl Integrations {
         self.get_integration(placement_id, Self::is_ad_set_ranker_integration)
     }
 
+    /**
+     * match_scores is ad_group_id -> match score of soft targeting(Prefer) on its filter.
+     * without ranker, candidates are ordered by match score alone.
+     */
     pub fn rank<'a>(
         &'a self,
         placement_id: &str,
         creatives_stat: &'a HashMap<String, Stat>,
         candidates: Vec<CreativeWithContent<'a>>,
+        match_scores: &HashMap<&str, f64>,
         k: usize,
     ) -> Vec<(CreativeWithContent<'a>, f32)> {
         match self.get_ranker_function(placement_id) {
             Some(Function::ThompsonSamplingRanker { function }) => {
-                function.apply(creatives_stat, candidates, k)
+                function.apply(creatives_stat, candidates, match_scores, k)
             }
             _ => {
                 let mut top_candidates = Vec::new();
                 for candidate in candidates {
-                    top_candidates.push((candidate, 0.0));
+                    let match_score = match_scores
+                        .get(candidate.creative.ad_group_id.as_str())
+                        .copied()
+                        .unwrap_or(0.0);
+                    top_candidates.push((candidate, match_score as f32));
                 }
+                top_candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
                 top_candidates
             }
         }
     }
+    /**
+     * match_scores is ad_set_id -> match score of soft targeting(Prefer) on its filter.
+     */
     pub fn rank_ad_sets<'a>(
         &'a self,
         placement_id: &str,
         ad_sets_stat: &'a HashMap<String, Stat>,
         candidates: Vec<&'a ad_set::Data>,
+        match_scores: &HashMap<&str, f64>,
         k: usize,
     ) -> Vec<(&'a ad_set::Data, f32)> {
         match self.get_ad_set_ranker_function(placement_id) {
             Some(Function::AdSetThompsonSamplingRanker { function }) => {
-                function.apply(ad_sets_stat, candidates, k)
+                function.apply(ad_sets_stat, candidates, match_scores, k)
             }
             _ => {
                 let mut top_candidates = Vec::new();
                 for candidate in candidates {
                     if is_active_ad_set(candidate) {
-                        top_candidates.push((candidate, 0.0));
+                        let match_score = match_scores
+                            .get(candidate.id.as_str())
+                            .copied()
+                            .unwrap_or(0.0);
+                        top_candidates.push((candidate, match_score as f32));
                     }
                 }
+                top_candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
                 top_candidates
             }
         }
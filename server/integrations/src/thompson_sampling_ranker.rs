@@ -10,6 +10,7 @@ impl ThompsonSamplingRanker {
         &self,
         creatives_stat: &'a HashMap<String, Stat>,
         candidates: Vec<CreativeWithContent<'a>>,
+        match_scores: &HashMap<&str, f64>,
         k: usize,
     ) -> Vec<(CreativeWithContent<'a>, f32)> {
         let mut top_candidates = Vec::new();
@@ -20,8 +21,13 @@ impl ThompsonSamplingRanker {
                 .get(&candidate.creative.id)
                 .unwrap_or(&default_stat);
             let score = stat.score().unwrap_or(0.0);
+            // ad group that is preferred by user is boosted by its match score.
+            let match_score = match_scores
+                .get(candidate.creative.ad_group_id.as_str())
+                .copied()
+                .unwrap_or(0.0);
 
-            top_candidates.push((candidate, score))
+            top_candidates.push((candidate, score * (1.0 + match_score as f32)))
         }
         top_candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
         top_candidates.truncate(k);
@@ -1,12 +1,17 @@
 use std::sync::Arc;
 
-use prisma_client_rust::{raw, QueryError};
+use prisma_client_rust::{raw, PrismaValue, QueryError, Raw};
+use serde::Deserialize;
 
 use common::{
     db::{user_feature, PrismaClient},
     types::UserInfo,
     util::parse_user_info,
 };
+use filter::{
+    filter::TargetFilter,
+    sql::{to_sql_where, Dialect},
+};
 use futures::future::join_all;
 
 #[derive(Debug, Clone)]
@@ -69,3 +74,75 @@ impl UserFeatureDatabase {
         Some(queries)
     }
 }
+
+#[derive(Debug, Deserialize)]
+struct PopulationCount {
+    count: i32,
+}
+
+/**
+ * Number of users on `version` of UserFeature whose features match filter.
+ */
+pub async fn count_population(
+    client: &PrismaClient,
+    version: &str,
+    filter: &TargetFilter,
+) -> Result<i64, QueryError> {
+    let mut sql_where = to_sql_where(filter, Dialect::Postgres, "feature");
+    let version = sql_where.push_param(version);
+    let sql = format!(
+        r#"
+            SELECT  CAST(COUNT(*) AS INTEGER) AS "count"
+            FROM    "UserFeature"
+            WHERE   "version" = {}
+            AND     {}
+        "#,
+        version, sql_where.sql
+    );
+    let params = sql_where
+        .params
+        .into_iter()
+        .map(PrismaValue::String)
+        .collect();
+
+    let counts: Vec<PopulationCount> = client._query_raw(Raw::new(&sql, params)).exec().await?;
+    Ok(counts.first().map(|c| c.count as i64).unwrap_or(0))
+}
+
+#[derive(Debug, Deserialize)]
+struct SampledFeature {
+    feature: serde_json::Value,
+}
+
+/**
+ * Random users of `version` of UserFeature, the latest version when it is not given.
+ */
+pub async fn sample_user_features(
+    client: &PrismaClient,
+    version: Option<&str>,
+    size: usize,
+) -> Result<Vec<UserInfo>, QueryError> {
+    let mut params = vec![PrismaValue::Int(size as i64)];
+    let version = match version {
+        Some(version) => {
+            params.push(PrismaValue::String(String::from(version)));
+            "$2"
+        }
+        None => r#"(SELECT MAX("version") FROM "UserFeature")"#,
+    };
+    let sql = format!(
+        r#"
+            SELECT  "feature"
+            FROM    "UserFeature"
+            WHERE   "version" = {}
+            ORDER BY RANDOM()
+            LIMIT   $1
+        "#,
+        version
+    );
+    let features: Vec<SampledFeature> = client._query_raw(Raw::new(&sql, params)).exec().await?;
+    Ok(features
+        .iter()
+        .flat_map(|feature| parse_user_info(&feature.feature))
+        .collect())
+}